@@ -0,0 +1,163 @@
+//! The back/forward cache keeps the documents of recently visited history entries alive, so that
+//! going back or forward to them is instant and preserves their state (scroll positions, form
+//! contents and DOM changes). Documents evicted from the cache have their state saved to their
+//! history entries instead (see [`HistoryEntry::state`](crate::history::HistoryEntry::state)).
+//!
+//! Cached documents are kept intact so that they can be shown again without relayout. Only when
+//! the cache uses more than [`MAX_CACHED_BYTES`] are the least recently cached documents
+//! [discarded](blitz_dom::BaseDocument::discard).
+
+use std::collections::VecDeque;
+
+use blitz_dom::Document;
+
+/// The maximum number of documents each tab keeps alive for back/forward navigation
+const MAX_CACHED_DOCUMENTS: usize = 4;
+
+/// The memory (as estimated by [`BaseDocument::memory_report`]) which each tab's cached documents
+/// may use before the least recently cached of them are discarded
+///
+/// [`BaseDocument::memory_report`]: blitz_dom::BaseDocument::memory_report
+const MAX_CACHED_BYTES: usize = 256 * 1024 * 1024;
+
+pub struct CachedDocument {
+    pub document: Box<dyn Document>,
+    pub html_source: String,
+    pub title: String,
+}
+
+#[derive(Default)]
+pub struct BackForwardCache {
+    /// Cached documents by history document id, least recently cached first
+    documents: VecDeque<(u64, CachedDocument)>,
+}
+
+impl BackForwardCache {
+    /// Cache a document, returning the documents which were evicted to make room for it
+    pub fn insert(
+        &mut self,
+        document_id: u64,
+        document: CachedDocument,
+    ) -> Vec<(u64, CachedDocument)> {
+        self.documents.retain(|(id, _)| *id != document_id);
+        self.documents.push_back((document_id, document));
+        let excess = self.documents.len().saturating_sub(MAX_CACHED_DOCUMENTS);
+        let evicted = self.documents.drain(..excess).collect();
+        self.discard_over(MAX_CACHED_BYTES);
+        evicted
+    }
+
+    /// Discard the least recently cached documents until the cache uses at most `max_bytes`
+    fn discard_over(&mut self, max_bytes: usize) {
+        let mut total: usize = self
+            .documents
+            .iter()
+            .map(|(_, cached)| cached.document.inner().memory_report().total())
+            .sum();
+        for (_, cached) in &mut self.documents {
+            if total <= max_bytes {
+                break;
+            }
+            let mut document = cached.document.inner_mut();
+            if document.is_discarded() {
+                continue;
+            }
+            let before = document.memory_report().total();
+            document.discard();
+            total -= before.saturating_sub(document.memory_report().total());
+        }
+    }
+
+    pub fn take(&mut self, document_id: u64) -> Option<CachedDocument> {
+        let index = self
+            .documents
+            .iter()
+            .position(|(id, _)| *id == document_id)?;
+        self.documents.remove(index).map(|(_, document)| document)
+    }
+
+    /// Drop the cached documents for which `keep` returns false (such as those whose history
+    /// entries no longer exist)
+    pub fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.documents.retain(|(id, _)| keep(*id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::{BaseDocument, DocumentConfig, PlainDocument, qual_name};
+
+    fn cached() -> CachedDocument {
+        CachedDocument {
+            document: Box::new(PlainDocument(BaseDocument::new(DocumentConfig::default()))),
+            html_source: String::new(),
+            title: String::new(),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_cached() {
+        let mut cache = BackForwardCache::default();
+        for id in 0..MAX_CACHED_DOCUMENTS as u64 {
+            assert!(cache.insert(id, cached()).is_empty());
+        }
+
+        // Re-caching a document makes it the most recent
+        assert!(cache.insert(0, cached()).is_empty());
+        let evicted = cache.insert(100, cached());
+        assert_eq!(
+            evicted.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        assert!(cache.take(0).is_some());
+        assert!(cache.take(0).is_none());
+        cache.retain(|id| id != 2);
+        assert!(cache.take(2).is_none());
+        assert!(cache.take(3).is_some());
+    }
+
+    /// A cached document with a long paragraph of text, most of whose memory is freed by
+    /// discarding it
+    fn cached_with_text() -> CachedDocument {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let mut mutator = doc.mutate();
+        let text = mutator.create_text_node(&"hello world ".repeat(2000));
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[text]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        CachedDocument {
+            document: Box::new(PlainDocument(doc)),
+            ..cached()
+        }
+    }
+
+    #[test]
+    fn discards_least_recently_cached_over_memory_limit() {
+        let mut cache = BackForwardCache::default();
+        for id in 0..2 {
+            cache.insert(id, cached_with_text());
+        }
+        let is_discarded = |cache: &BackForwardCache, index: usize| {
+            cache.documents[index].1.document.inner().is_discarded()
+        };
+        assert!(!is_discarded(&cache, 0));
+
+        // Documents are discarded oldest first, until the cache is within the limit
+        let document_bytes = cache.documents[1]
+            .1
+            .document
+            .inner()
+            .memory_report()
+            .total();
+        cache.discard_over(document_bytes * 3 / 2);
+        assert!(is_discarded(&cache, 0));
+        assert!(!is_discarded(&cache, 1));
+    }
+}
@@ -98,6 +98,15 @@ fn render_scene(
     (render_width, render_height)
 }
 
+/// Write the frames recorded by the document's profiler to the specified path as a Chrome trace
+#[cfg(feature = "capture")]
+pub(crate) fn save_frame_trace(doc: &blitz_dom::BaseDocument, path: &Path) {
+    let trace = doc.profiler().to_chrome_trace_json();
+    if std::fs::write(path, trace).is_ok() {
+        println!("Frame trace saved to {}", path.display());
+    }
+}
+
 /// Open an RFD file dialog to get a path to save a file to
 pub(crate) async fn try_get_save_path(file_type_name: &str, ext: &str) -> Option<PathBuf> {
     let timestamp = std::time::SystemTime::now()
@@ -1,21 +1,30 @@
+use std::cell::RefCell;
 use std::sync::Arc;
 
-use blitz_dom::{DocumentConfig, FontContext};
+use blitz_dom::{BaseDocument, DocumentConfig, FontContext, MemoryLimits};
 use blitz_html::{HtmlDocument, HtmlProvider};
 use blitz_traits::{net::Request, shell::ShellProvider};
 use dioxus_native::{SubDocumentAttr, prelude::*};
 use linebender_resource_handle::Blob;
 
 use crate::StdNetProvider;
-use crate::history::{BrowserNavProvider, History, SyncStore};
+use crate::bfcache::BackForwardCache;
+use crate::history::{
+    BrowserNavProvider, History, HistoryEntry, HistoryNav, NewTabRequests, SyncStore,
+};
 
 pub enum DocumentLoaderStatus {
     Loading,
     Idle,
 }
 
+/// The maximum size of the decoded images kept in memory by each tab
+const MAX_TAB_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct LoadedDocument {
+    /// The history document id (see [`HistoryEntry::document_id`]) of the document
+    pub document_id: u64,
     pub document: SubDocumentAttr,
     pub html_source: String,
     pub title: String,
@@ -27,6 +36,7 @@ pub struct DocumentLoader {
     pub status: Signal<DocumentLoaderStatus>,
     pub history: SyncStore<History>,
     pub reload_generation: Signal<u64>,
+    pub bfcache: RefCell<BackForwardCache>,
 }
 
 pub fn make_doc_config(
@@ -40,11 +50,18 @@ pub fn make_doc_config(
         base_url,
         ua_stylesheets: None,
         net_provider: Some(net_provider as _),
-        navigation_provider: Some(Arc::new(BrowserNavProvider { history })),
+        navigation_provider: Some(Arc::new(BrowserNavProvider {
+            history,
+            new_tab_requests: consume_context::<NewTabRequests>(),
+        })),
         shell_provider: Some(consume_context::<Arc<dyn ShellProvider>>()),
         html_parser_provider: Some(Arc::new(HtmlProvider)),
         font_ctx: Some(font_ctx),
         media_type: None,
+        memory_limits: Some(MemoryLimits {
+            max_image_bytes: Some(MAX_TAB_IMAGE_BYTES),
+        }),
+        sync_image_decoding: false,
     }
 }
 
@@ -61,6 +78,7 @@ impl DocumentLoader {
             status: Signal::new(DocumentLoaderStatus::Idle),
             history,
             reload_generation: Signal::new(0),
+            bfcache: RefCell::new(BackForwardCache::default()),
         }
     }
 
@@ -73,7 +91,47 @@ impl DocumentLoader {
         *self.reload_generation.read()
     }
 
-    pub async fn load_document(&self, req: Request) -> LoadedDocument {
+    /// Load the document for a history entry: from the back/forward cache if it is there, or else
+    /// from the network. Unless reloading, the state saved when the entry's document was evicted
+    /// from the cache (and the entry's scroll position) is restored.
+    ///
+    /// Returns `None` if loading the document would re-submit a form, which the user must confirm
+    /// first (see [`HistoryEntry::needs_resubmission_prompt`]).
+    pub async fn load_entry(&self, entry: HistoryEntry, is_reload: bool) -> Option<LoadedDocument> {
+        let cached = match is_reload {
+            true => None,
+            false => self.bfcache.borrow_mut().take(entry.document_id),
+        };
+        if let Some(cached) = cached {
+            let mut document = cached.document;
+            if let Some(scroll) = entry.scroll {
+                document.inner_mut().set_viewport_scroll(scroll);
+            }
+            return Some(LoadedDocument {
+                document_id: entry.document_id,
+                document: SubDocumentAttr::from_document(document),
+                html_source: cached.html_source,
+                title: cached.title,
+            });
+        }
+        if entry.needs_resubmission_prompt() {
+            return None;
+        }
+        self.history.mark_request_sent(entry.id);
+
+        let restore_from = (!is_reload).then_some(&entry);
+        let loaded = self
+            .load_document(entry.request.clone(), entry.document_id, restore_from)
+            .await;
+        Some(loaded)
+    }
+
+    async fn load_document(
+        &self,
+        req: Request,
+        document_id: u64,
+        restore_from: Option<&HistoryEntry>,
+    ) -> LoadedDocument {
         let net_provider = Arc::clone(&self.net_provider);
         let font_ctx = self.font_ctx.clone();
         let history = self.history;
@@ -93,12 +151,16 @@ impl DocumentLoader {
                     &bytes_str
                 };
 
-                let document = HtmlDocument::from_html(html, config).into_inner();
+                let mut document = HtmlDocument::from_html(html, config).into_inner();
+                if let Some(entry) = restore_from {
+                    restore_entry_state(&mut document, entry);
+                }
                 let parsed_title = document
                     .find_title_node()
                     .map(|n| n.text_content())
                     .unwrap_or_default();
                 LoadedDocument {
+                    document_id,
                     document: SubDocumentAttr::new(document),
                     html_source: html.to_string(),
                     title: parsed_title,
@@ -120,6 +182,7 @@ impl DocumentLoader {
                     document.mutate().set_node_text(text_node, &error_msg);
                 }
                 LoadedDocument {
+                    document_id,
                     document: SubDocumentAttr::new(document),
                     html_source: error_html.to_string(),
                     title: String::new(),
@@ -128,3 +191,14 @@ impl DocumentLoader {
         }
     }
 }
+
+fn restore_entry_state(document: &mut BaseDocument, entry: &HistoryEntry) {
+    if let Some(state) = &entry.state {
+        if let Err(err) = document.restore_state(state) {
+            tracing::warn!("Couldn't restore document state: {:?}", err);
+        }
+    }
+    if let Some(scroll) = entry.scroll {
+        document.set_viewport_scroll(scroll);
+    }
+}
@@ -1,24 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use blitz_dom::{DocumentState, Point};
 use blitz_traits::navigation::{NavigationOptions, NavigationProvider};
-use blitz_traits::net::Request;
+use blitz_traits::net::{Method, Request, Url};
 use dioxus_native::prelude::*;
 
 pub type SyncStore<T> = Store<T, CopyValue<T, SyncStorage>>;
 
+/// URLs that documents have asked to open in new tabs (e.g. from a link's context menu). Documents
+/// don't own the tab list, so the requests are queued here and opened by the app.
+pub type NewTabRequests = SyncSignal<Vec<Url>>;
+
+static ENTRY_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn next_entry_id() -> u64 {
+    ENTRY_ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Store, Clone)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Entries created by fragment navigations share the document (and `document_id`) of the
+    /// entry they were navigated from
+    pub document_id: u64,
+    pub request: Request,
+    /// The viewport scroll position when the entry was last navigated away from
+    pub scroll: Option<Point<f64>>,
+    /// The state of the entry's document, saved when it was evicted from the back/forward cache
+    pub state: Option<DocumentState>,
+    /// Whether the entry's request can be sent once without asking the user. It is cleared when
+    /// the request is sent, so `POST` requests are only re-submitted (on reload, or when returning
+    /// to an entry whose document isn't cached) once confirmed again.
+    pub is_resubmission_confirmed: bool,
+}
+
+impl HistoryEntry {
+    fn new(request: Request, document_id: u64) -> Self {
+        Self {
+            id: next_entry_id(),
+            document_id,
+            request,
+            scroll: None,
+            state: None,
+            is_resubmission_confirmed: true,
+        }
+    }
+
+    /// Whether loading the entry's document (rather than restoring it from the back/forward
+    /// cache) requires the user to confirm re-submitting a form
+    pub fn needs_resubmission_prompt(&self) -> bool {
+        self.request.method == Method::POST && !self.is_resubmission_confirmed
+    }
+}
+
 #[derive(Store)]
 pub struct History {
-    urls: Vec<Request>,
+    entries: Vec<HistoryEntry>,
     current: usize,
 }
 
 impl History {
     pub fn new(initial_request: Request) -> Self {
+        let id = next_entry_id();
         Self {
-            urls: vec![initial_request],
+            entries: vec![HistoryEntry::new(initial_request, id)],
             current: 0,
         }
     }
 }
 
+/// Whether navigating from `from` to `to` only changes the URL's fragment, in which case the
+/// current document is scrolled to the fragment rather than reloaded
+pub fn is_fragment_navigation(from: &Request, to: &Request) -> bool {
+    if to.method != Method::GET || to.url.fragment().is_none() {
+        return false;
+    }
+    let mut from_url = from.url.clone();
+    let mut to_url = to.url.clone();
+    from_url.set_fragment(None);
+    to_url.set_fragment(None);
+    from_url == to_url
+}
+
 #[store]
 impl<Lens> Store<History, Lens> {
     fn current_idx(&self) -> usize {
@@ -26,7 +89,41 @@ impl<Lens> Store<History, Lens> {
     }
 
     fn current_url(&self) -> impl Readable<Target = Request> {
-        self.urls().get(self.current_idx()).unwrap()
+        self.entries().get(self.current_idx()).unwrap().request()
+    }
+
+    fn current_entry_id(&self) -> u64 {
+        *self.entries().get(self.current_idx()).unwrap().id().read()
+    }
+
+    fn current_document_id(&self) -> u64 {
+        *self
+            .entries()
+            .get(self.current_idx())
+            .unwrap()
+            .document_id()
+            .read()
+    }
+
+    /// The current entry, without subscribing to changes
+    fn peek_current_entry(&self) -> HistoryEntry {
+        self.entries().peek()[*self.current().peek()].clone()
+    }
+
+    /// An entry by id, without subscribing to changes
+    fn peek_entry(&self, id: u64) -> Option<HistoryEntry> {
+        self.entries()
+            .peek()
+            .iter()
+            .find(|entry| entry.id == id)
+            .cloned()
+    }
+
+    fn has_document(&self, document_id: u64) -> bool {
+        self.entries()
+            .peek()
+            .iter()
+            .any(|entry| entry.document_id == document_id)
     }
 
     fn has_back(&self) -> bool {
@@ -34,7 +131,7 @@ impl<Lens> Store<History, Lens> {
     }
 
     fn has_forward(&self) -> bool {
-        self.current_idx() < self.urls().len() - 1
+        self.current_idx() < self.entries().len() - 1
     }
 
     fn go_back(&mut self) {
@@ -54,10 +151,63 @@ impl<Lens> Store<History, Lens> {
         Lens: Writable,
     {
         let idx = self.current_idx();
-        self.urls().write().truncate(idx + 1);
-        self.urls().push(req);
+        let current = self.peek_current_entry();
+        let document_id = if is_fragment_navigation(&current.request, &req) {
+            current.document_id
+        } else {
+            next_entry_id()
+        };
+
+        self.entries().write().truncate(idx + 1);
+        self.entries().push(HistoryEntry::new(req, document_id));
         *self.current().write() += 1;
     }
+
+    /// Record that an entry has been navigated away from
+    fn leave_entry(&self, id: u64, scroll: Option<Point<f64>>)
+    where
+        Lens: Writable,
+    {
+        let mut entries = self.entries().write_unchecked();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.id == id) {
+            entry.scroll = scroll;
+        }
+    }
+
+    /// Record that an entry's request has been sent, so that sending it again must be confirmed
+    fn mark_request_sent(&self, id: u64)
+    where
+        Lens: Writable,
+    {
+        let mut entries = self.entries().write_unchecked();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.id == id) {
+            entry.is_resubmission_confirmed = false;
+        }
+    }
+
+    fn confirm_resubmission(&self, id: u64)
+    where
+        Lens: Writable,
+    {
+        let mut entries = self.entries().write_unchecked();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.id == id) {
+            entry.is_resubmission_confirmed = true;
+        }
+    }
+
+    /// Save (or clear) the state of a document for each of the entries which display it
+    fn set_document_state(&self, document_id: u64, state: Option<DocumentState>)
+    where
+        Lens: Writable,
+    {
+        let mut entries = self.entries().write_unchecked();
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| entry.document_id == document_id)
+        {
+            entry.state = state.clone();
+        }
+    }
 }
 
 /// Public extension trait exposing history navigation for use across modules.
@@ -65,11 +215,20 @@ impl<Lens> Store<History, Lens> {
 /// which is only accessible within this module.
 pub trait HistoryNav {
     fn current_url(&self) -> impl Readable<Target = Request>;
+    fn current_entry_id(&self) -> u64;
+    fn current_document_id(&self) -> u64;
+    fn peek_current_entry(&self) -> HistoryEntry;
+    fn peek_entry(&self, id: u64) -> Option<HistoryEntry>;
+    fn has_document(&self, document_id: u64) -> bool;
     fn has_back(&self) -> bool;
     fn has_forward(&self) -> bool;
     fn go_back(&mut self);
     fn go_forward(&mut self);
     fn navigate(&self, req: Request);
+    fn leave_entry(&self, id: u64, scroll: Option<Point<f64>>);
+    fn mark_request_sent(&self, id: u64);
+    fn confirm_resubmission(&self, id: u64);
+    fn set_document_state(&self, document_id: u64, state: Option<DocumentState>);
 }
 
 impl HistoryNav for SyncStore<History> {
@@ -77,6 +236,26 @@ impl HistoryNav for SyncStore<History> {
         HistoryStoreImplExt::current_url(self)
     }
 
+    fn current_entry_id(&self) -> u64 {
+        HistoryStoreImplExt::current_entry_id(self)
+    }
+
+    fn current_document_id(&self) -> u64 {
+        HistoryStoreImplExt::current_document_id(self)
+    }
+
+    fn peek_current_entry(&self) -> HistoryEntry {
+        HistoryStoreImplExt::peek_current_entry(self)
+    }
+
+    fn peek_entry(&self, id: u64) -> Option<HistoryEntry> {
+        HistoryStoreImplExt::peek_entry(self, id)
+    }
+
+    fn has_document(&self, document_id: u64) -> bool {
+        HistoryStoreImplExt::has_document(self, document_id)
+    }
+
     fn has_back(&self) -> bool {
         HistoryStoreImplExt::has_back(self)
     }
@@ -96,14 +275,177 @@ impl HistoryNav for SyncStore<History> {
     fn navigate(&self, req: Request) {
         HistoryStoreImplExt::navigate(self, req)
     }
+
+    fn leave_entry(&self, id: u64, scroll: Option<Point<f64>>) {
+        HistoryStoreImplExt::leave_entry(self, id, scroll)
+    }
+
+    fn mark_request_sent(&self, id: u64) {
+        HistoryStoreImplExt::mark_request_sent(self, id)
+    }
+
+    fn confirm_resubmission(&self, id: u64) {
+        HistoryStoreImplExt::confirm_resubmission(self, id)
+    }
+
+    fn set_document_state(&self, document_id: u64, state: Option<DocumentState>) {
+        HistoryStoreImplExt::set_document_state(self, document_id, state)
+    }
 }
 
 pub struct BrowserNavProvider {
     pub history: SyncStore<History>,
+    pub new_tab_requests: NewTabRequests,
 }
 
 impl NavigationProvider for BrowserNavProvider {
     fn navigate_to(&self, options: NavigationOptions) {
-        HistoryNav::navigate(&self.history, options.into_request());
+        if options.open_in_new_tab {
+            let mut new_tab_requests = self.new_tab_requests;
+            new_tab_requests.write().push(options.url);
+        } else {
+            HistoryNav::navigate(&self.history, options.into_request());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_dom::{BaseDocument, DocumentConfig, Point};
+    use blitz_traits::net::{Method, Request, Url};
+    use dioxus_native::prelude::{Element, ScopeId, Store, VNode, VirtualDom};
+
+    // The store's own (private) methods are not imported, as they share their names with those of
+    // `HistoryNav`
+    use super::{History, HistoryNav, SyncStore, is_fragment_navigation};
+
+    fn get(url: &str) -> Request {
+        Request::get(Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn detects_fragment_navigations() {
+        let page = get("https://example.com/page");
+        assert!(is_fragment_navigation(
+            &page,
+            &get("https://example.com/page#section")
+        ));
+        assert!(is_fragment_navigation(
+            &get("https://example.com/page#a"),
+            &get("https://example.com/page#b")
+        ));
+        assert!(!is_fragment_navigation(
+            &page,
+            &get("https://example.com/other#section")
+        ));
+        assert!(!is_fragment_navigation(
+            &get("https://example.com/page#a"),
+            &page
+        ));
+    }
+
+    fn empty_app() -> Element {
+        VNode::empty()
+    }
+
+    /// Run `f` with a history starting at `https://example.com/`. Stores need a Dioxus runtime.
+    fn with_history(f: impl FnOnce(SyncStore<History>)) {
+        let dom = VirtualDom::new(empty_app);
+        dom.in_scope(ScopeId::ROOT, || {
+            f(Store::new_maybe_sync(History::new(get(
+                "https://example.com/",
+            ))))
+        });
+    }
+
+    fn post(url: &str) -> Request {
+        let mut request = get(url);
+        request.method = Method::POST;
+        request
+    }
+
+    #[test]
+    fn navigate_assigns_document_ids() {
+        with_history(|history| {
+            let first = history.peek_current_entry();
+
+            history.navigate(get("https://example.com/#section"));
+            let fragment = history.peek_current_entry();
+            assert_ne!(fragment.id, first.id);
+            assert_eq!(fragment.document_id, first.document_id);
+
+            history.navigate(get("https://example.com/other"));
+            let other = history.peek_current_entry();
+            assert_ne!(other.document_id, first.document_id);
+            assert_eq!(history.current_document_id(), other.document_id);
+
+            // Navigating from an earlier entry drops the entries after it
+            let mut history = history;
+            history.go_back();
+            history.go_back();
+            history.navigate(get("https://example.com/new"));
+            assert!(history.peek_entry(fragment.id).is_none());
+            assert!(!history.has_document(other.document_id));
+            assert!(history.has_document(first.document_id));
+        });
+    }
+
+    #[test]
+    fn leave_entry_records_scroll_position() {
+        with_history(|history| {
+            let first = history.peek_current_entry();
+            history.navigate(get("https://example.com/other"));
+            let scroll = Point { x: 0.0, y: 120.0 };
+            history.leave_entry(first.id, Some(scroll));
+
+            assert_eq!(history.peek_entry(first.id).unwrap().scroll, Some(scroll));
+            assert_eq!(history.peek_current_entry().scroll, None);
+        });
+    }
+
+    #[test]
+    fn set_document_state_applies_to_entries_of_the_document() {
+        with_history(|history| {
+            let first = history.peek_current_entry();
+            history.navigate(get("https://example.com/#section"));
+            let fragment = history.peek_current_entry();
+            history.navigate(get("https://example.com/other"));
+            let other = history.peek_current_entry();
+
+            let doc = BaseDocument::new(DocumentConfig::default());
+            let state = doc.save_state();
+            history.set_document_state(first.document_id, Some(state.clone()));
+            assert_eq!(
+                history.peek_entry(first.id).unwrap().state,
+                Some(state.clone())
+            );
+            assert_eq!(history.peek_entry(fragment.id).unwrap().state, Some(state));
+            assert_eq!(history.peek_entry(other.id).unwrap().state, None);
+
+            history.set_document_state(first.document_id, None);
+            assert_eq!(history.peek_entry(first.id).unwrap().state, None);
+        });
+    }
+
+    #[test]
+    fn resubmitting_requires_confirmation() {
+        with_history(|history| {
+            history.navigate(post("https://example.com/form"));
+            let entry_id = history.current_entry_id();
+            assert!(!history.peek_current_entry().needs_resubmission_prompt());
+
+            // Once the form has been submitted, reloading it must be confirmed (every time)
+            history.mark_request_sent(entry_id);
+            assert!(history.peek_current_entry().needs_resubmission_prompt());
+            history.confirm_resubmission(entry_id);
+            assert!(!history.peek_current_entry().needs_resubmission_prompt());
+            history.mark_request_sent(entry_id);
+            assert!(history.peek_current_entry().needs_resubmission_prompt());
+
+            // GET requests are never prompted for
+            history.navigate(get("https://example.com/other"));
+            history.mark_request_sent(history.current_entry_id());
+            assert!(!history.peek_current_entry().needs_resubmission_prompt());
+        });
     }
 }
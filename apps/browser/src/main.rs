@@ -19,6 +19,7 @@ use winit::platform::macos::WindowAttributesMacOS;
 pub(crate) type StdNetProvider = blitz_net::Provider;
 
 mod about_pages;
+mod bfcache;
 #[cfg(any(feature = "screenshot", feature = "capture"))]
 mod capture;
 mod document_loader;
@@ -33,6 +34,7 @@ mod tab_strip;
 mod toolbar;
 
 use about_pages::AboutPage;
+use history::NewTabRequests;
 use status_bar::StatusBar;
 use tab::{Tab, TabId, TabStoreImplExt, TabWebView, active_tab, open_tab, tab_title_or_url};
 use tab_strip::TabStrip;
@@ -72,11 +74,27 @@ fn app() -> Element {
     let url_input_value = use_signal(|| home_url.to_string());
 
     let tabs: Store<Vec<Tab>> = use_store(Vec::new);
+    let new_tab_requests: NewTabRequests =
+        use_context_provider(|| Signal::new_maybe_sync(Vec::new()));
     let mut active_tab_id: Signal<TabId> = use_hook(|| {
         let tab = open_tab(tabs, home_url.clone(), net_provider.clone());
         Signal::new(tab.tab_id())
     });
 
+    // Links that documents open in new tabs are opened in the background
+    use_effect({
+        let net_provider = net_provider.clone();
+        move || {
+            let mut new_tab_requests = new_tab_requests;
+            if new_tab_requests.read().is_empty() {
+                return;
+            }
+            for url in std::mem::take(&mut *new_tab_requests.write()) {
+                open_tab(tabs, url, net_provider.clone());
+            }
+        }
+    });
+
     let open_new_tab = use_callback(move |url: Url| {
         let new_id = open_tab(tabs, url, net_provider.clone());
         active_tab_id.set(new_id.tab_id());
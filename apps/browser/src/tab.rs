@@ -6,11 +6,13 @@ use std::{
     },
 };
 
+use blitz_dom::{BaseDocument, Document, ScrollBehavior};
 use blitz_traits::net::{Request, Url};
 use dioxus_native::{NodeHandle, SubDocumentAttr, prelude::*};
 
 use crate::StdNetProvider;
 use crate::about_pages::{AboutPage, AboutPageView};
+use crate::bfcache::CachedDocument;
 use crate::document_loader::{DocumentLoader, DocumentLoaderStatus, LoadedDocument};
 use crate::history::{History, HistoryNav, SyncStore};
 
@@ -31,6 +33,11 @@ pub struct Tab {
     pub node_handle: Option<NodeHandle>,
     pub html_source: String,
     pub title: String,
+    /// The id of the history entry which was last shown
+    pub displayed_entry: Option<u64>,
+    /// The history document id (see [`HistoryEntry::document_id`](crate::history::HistoryEntry))
+    /// of the document in the tab's `web-view`
+    pub displayed_document: Option<u64>,
 }
 
 #[store(pub)]
@@ -63,16 +70,150 @@ impl<Lens> Store<Tab, Lens> {
         self.nav_history().go_forward();
     }
 
+    /// Free the layout and decoded images of the tab's document while it is in the background.
+    /// They are recomputed when the tab is next shown.
+    fn discard_document(&self) {
+        let node_handle = self.node_handle().peek_unchecked();
+        let document = self.document().peek_unchecked();
+        with_web_view_document(&node_handle, &document, |inner| {
+            if inner.is_discarded() {
+                return;
+            }
+            let before = inner.memory_report().total();
+            inner.discard();
+            let after = inner.memory_report().total();
+            tracing::info!("Discarded background tab: {before} -> {after} bytes");
+        });
+    }
+
+    /// Record the scroll position of the entry being navigated away from, and scroll the
+    /// document if the new entry shows the same document (as with fragment navigations, and
+    /// going back or forward between them)
+    fn show_entry(&self, entry_id: u64)
+    where
+        Lens: Writable,
+    {
+        let previous = *self.displayed_entry().peek();
+        if previous == Some(entry_id) {
+            return;
+        }
+        *self.displayed_entry().write_unchecked() = Some(entry_id);
+
+        let history = self.nav_history();
+        let displayed_document = *self.displayed_document().peek();
+        let node_handle = self.node_handle().peek_unchecked();
+        let document = self.document().peek_unchecked();
+
+        if let Some(previous) = previous.and_then(|id| history.peek_entry(id)) {
+            let scroll = (displayed_document == Some(previous.document_id))
+                .then(|| {
+                    with_web_view_document(&node_handle, &document, |doc| doc.viewport_scroll())
+                })
+                .flatten();
+            history.leave_entry(previous.id, scroll);
+        }
+
+        let Some(entry) = history.peek_entry(entry_id) else {
+            return;
+        };
+        if displayed_document == Some(entry.document_id) {
+            with_web_view_document(&node_handle, &document, |doc| match entry.scroll {
+                Some(scroll) => doc.scroll_to(None, scroll, ScrollBehavior::Instant),
+                None => {
+                    doc.scroll_to_fragment(entry.request.url.fragment().unwrap_or_default());
+                }
+            });
+        }
+    }
+
+    /// Move the document in the tab's `web-view` into the back/forward cache, unless it is the
+    /// document with id `replaced_by` (which is being reloaded)
+    fn cache_displayed_document(&self, replaced_by: Option<u64>)
+    where
+        Lens: Writable,
+    {
+        let Some(document_id) = self
+            .displayed_document()
+            .peek()
+            .filter(|id| Some(*id) != replaced_by)
+        else {
+            return;
+        };
+        *self.displayed_document().write_unchecked() = None;
+
+        let Some(document) = self.take_displayed_document() else {
+            return;
+        };
+
+        let history = self.nav_history();
+        let loader = self.loader_rc();
+        let mut bfcache = loader.bfcache.borrow_mut();
+        bfcache.retain(|id| history.has_document(id));
+        if !history.has_document(document_id) {
+            return;
+        }
+        let cached = CachedDocument {
+            document,
+            html_source: String::clone(&self.html_source().peek()),
+            title: String::clone(&self.title().peek()),
+        };
+        for (evicted_id, evicted) in bfcache.insert(document_id, cached) {
+            let state = evicted.document.inner().save_state();
+            history.set_document_state(evicted_id, Some(state));
+        }
+    }
+
+    /// Remove the tab's document from its `web-view` without dropping it
+    fn take_displayed_document(&self) -> Option<Box<dyn Document>> {
+        let node_handle = self.node_handle().peek_unchecked();
+        let handle = (*node_handle).as_ref()?;
+        let document_id = self.document().peek_unchecked().as_ref()?.id()?;
+        let node_id = handle.node_id();
+        let mut doc = handle.doc_mut();
+        let displayed_id = doc.get_node(node_id)?.element_data()?.sub_doc_data()?.id();
+        if displayed_id != document_id {
+            return None;
+        }
+        doc.take_sub_document(node_id)
+    }
+
     fn apply_loaded_document(&self, loaded: LoadedDocument)
     where
         Lens: Writable,
     {
+        self.cache_displayed_document(Some(loaded.document_id));
+        *self.displayed_document().write_unchecked() = Some(loaded.document_id);
+        // The document's state is kept alive by the document itself until it is next evicted
+        self.nav_history()
+            .set_document_state(loaded.document_id, None);
+
         *self.html_source().write_unchecked() = loaded.html_source;
         *self.title().write_unchecked() = loaded.title;
         *self.document().write_unchecked() = Some(loaded.document);
     }
 }
 
+/// Call `f` with the document in a tab's `web-view`, if the `web-view` is mounted and is
+/// displaying the tab's `document` (rather than a previous one)
+fn with_web_view_document<R>(
+    node_handle: &Option<NodeHandle>,
+    document: &Option<SubDocumentAttr>,
+    f: impl FnOnce(&mut BaseDocument) -> R,
+) -> Option<R> {
+    let handle = node_handle.as_ref()?;
+    let document_id = document.as_ref()?.id()?;
+    let mut doc = handle.doc_mut();
+    let sub_doc = doc
+        .get_node_mut(handle.node_id())
+        .and_then(|node| node.element_data_mut())
+        .and_then(|el| el.sub_doc_data_mut())?;
+    if sub_doc.id() != document_id {
+        return None;
+    }
+    let mut inner = sub_doc.inner_mut();
+    Some(f(&mut inner))
+}
+
 pub fn open_tab(
     mut tabs: Store<Vec<Tab>>,
     url: Url,
@@ -90,6 +231,8 @@ pub fn open_tab(
         node_handle: None,
         html_source: String::new(),
         title: String::new(),
+        displayed_entry: None,
+        displayed_document: None,
     });
 
     let len = tabs.len();
@@ -113,21 +256,40 @@ pub fn active_tab(tabs: Store<Vec<Tab>>, active_id: TabId) -> Store<Tab> {
 pub fn TabWebView(tab: Store<Tab>, active_tab_id: Signal<TabId>) -> Element {
     let about = use_memo(move || AboutPage::from_url(&tab.nav_history().current_url().read().url));
 
+    // Fragment navigations create new history entries for the same document, which are shown by
+    // `show_entry` without loading anything
+    let document_id = use_memo(move || tab.nav_history().current_document_id());
+    let mut awaiting_resubmission = use_signal(|| false);
+
     let loader = tab.loader_rc();
     let loaded_document = use_resource(move || {
-        let req = (*tab.nav_history().current_url().read()).clone();
+        let _document_id = document_id();
+        let entry = tab.nav_history().peek_current_entry();
+        let is_reload = *tab.displayed_document().peek() == Some(entry.document_id);
         let _reload_generation = loader.reload_generation();
         let loader = loader.clone();
         let is_about = about().is_some();
         async move {
             if is_about {
-                None
-            } else {
-                Some(loader.load_document(req).await)
+                // The about page replaces the web-view
+                tab.cache_displayed_document(None);
+                awaiting_resubmission.set(false);
+                return None;
             }
+            let loaded = loader.load_entry(entry, is_reload).await;
+            if loaded.is_none() {
+                tab.cache_displayed_document(None);
+            }
+            awaiting_resubmission.set(loaded.is_none());
+            loaded
         }
     });
 
+    use_effect(move || {
+        let entry_id = tab.nav_history().current_entry_id();
+        tab.show_entry(entry_id);
+    });
+
     use_effect(move || {
         let loader = tab.loader_rc();
         let mut status = loader.status;
@@ -158,6 +320,13 @@ pub fn TabWebView(tab: Store<Tab>, active_tab_id: Signal<TabId>) -> Element {
         }
     });
 
+    // Background tabs aren't resolved or painted, so their layout and images can be freed
+    use_effect(move || {
+        if active_tab_id() != tab.tab_id() {
+            tab.discard_document();
+        }
+    });
+
     let id = tab.tab_id();
     let document = tab.document().cloned();
     let mut node_handle_lens = tab.node_handle();
@@ -171,6 +340,12 @@ pub fn TabWebView(tab: Store<Tab>, active_tab_id: Signal<TabId>) -> Element {
         tab.navigate(req);
     });
 
+    let on_confirm_resubmission = use_callback(move |_| {
+        let history = tab.nav_history();
+        history.confirm_resubmission(history.peek_current_entry().id);
+        tab.reload();
+    });
+
     rsx!(
         if let Some(page) = about() {
             div {
@@ -179,6 +354,13 @@ pub fn TabWebView(tab: Store<Tab>, active_tab_id: Signal<TabId>) -> Element {
                 style: visibility,
                 AboutPageView { page, on_navigate }
             }
+        } else if awaiting_resubmission() {
+            div {
+                key: "{id}",
+                class: "tab-content",
+                style: visibility,
+                ResubmitPrompt { on_confirm: on_confirm_resubmission }
+            }
         } else {
             web-view {
                 key: "{id}",
@@ -194,6 +376,20 @@ pub fn TabWebView(tab: Store<Tab>, active_tab_id: Signal<TabId>) -> Element {
     )
 }
 
+/// Shown instead of re-submitting a form when navigating back or forward to the page it loaded
+#[component]
+fn ResubmitPrompt(on_confirm: Callback<()>) -> Element {
+    rsx!(
+        div { class: "resubmit-prompt",
+            h1 { "Confirm Form Resubmission" }
+            p {
+                "Loading this page again will re-submit a form, repeating any action it performed."
+            }
+            button { onclick: move |_| on_confirm(()), "Resubmit" }
+        }
+    )
+}
+
 pub fn tab_title_or_url<L>(tab: Store<Tab, L>) -> String
 where
     L: Copy + Readable<Target = Tab> + 'static,
@@ -103,6 +103,8 @@ pub fn Toolbar(
             html_parser_provider: Some(Arc::new(HtmlProvider)),
             font_ctx: Some(tab.loader_rc().font_ctx.clone()),
             media_type: None,
+            memory_limits: None,
+            sync_image_decoding: false,
         };
         let mut document = HtmlDocument::from_html(view_source_html, config).into_inner();
         if let Some(parent_id) = document.get_element_by_id("source") {
@@ -110,6 +112,8 @@ pub fn Toolbar(
             let text_node = mutator.create_text_node(&source);
             mutator.append_children(parent_id, &[text_node]);
         }
+        // Keep the page alive so that reloading or navigating back to it doesn't refetch it
+        tab.cache_displayed_document(None);
         *tab.document().write_unchecked() = Some(SubDocumentAttr::new(document));
     });
 
@@ -174,6 +178,46 @@ pub fn Toolbar(
         }
     });
 
+    let profiling_action = use_callback(move |_| {
+        menu_open.set(false);
+        let node_handle = active_tab(tabs, active_tab_id()).node_handle();
+        if let Some(handle) = node_handle.cloned() {
+            let node_id = handle.node_id();
+            let mut doc = handle.doc_mut();
+            if let Some(sub_doc) = doc
+                .get_node_mut(node_id)
+                .and_then(|node| node.element_data_mut())
+                .and_then(|el| el.sub_doc_data_mut())
+            {
+                let sub_doc = sub_doc.inner();
+                let profiler = sub_doc.profiler();
+                profiler.set_enabled(!profiler.is_enabled());
+            }
+        }
+    });
+
+    #[cfg(feature = "capture")]
+    let export_trace_action = use_callback(move |_| {
+        menu_open.set(false);
+        let node_handle = active_tab(tabs, active_tab_id()).node_handle();
+        async move {
+            let Some(path) = crate::capture::try_get_save_path("Chrome Trace", "json").await else {
+                return;
+            };
+            if let Some(handle) = node_handle.cloned() {
+                let node_id = handle.node_id();
+                let mut doc = handle.doc_mut();
+                if let Some(sub_doc) = doc
+                    .get_node_mut(node_id)
+                    .and_then(|node| node.element_data_mut())
+                    .and_then(|el| el.sub_doc_data_mut())
+                {
+                    crate::capture::save_frame_trace(&sub_doc.inner(), &path);
+                }
+            }
+        }
+    });
+
     #[cfg(feature = "screenshot")]
     let screenshot_item = rsx!(
         div { class: "menu-item", onclick: move |_| screenshot_action(()),
@@ -194,6 +238,13 @@ pub fn Toolbar(
     #[cfg(not(feature = "capture"))]
     let capture_item = rsx!();
 
+    #[cfg(feature = "capture")]
+    let export_trace_item = rsx!(
+        div { class: "menu-item", onclick: move |_| export_trace_action(()), "Export Frame Trace" }
+    );
+    #[cfg(not(feature = "capture"))]
+    let export_trace_item = rsx!();
+
     #[cfg(feature = "vello")]
     let fps_toggle_item = rsx!(
         div { class: "menu-item", onclick: move |_| {
@@ -321,6 +372,8 @@ pub fn Toolbar(
                         {screenshot_item}
                         {capture_item}
                         div { class: "menu-item", onclick: move |_| devtools_action(()), "Toggle DevTools" }
+                        div { class: "menu-item", onclick: move |_| profiling_action(()), "Toggle Profiling" }
+                        {export_trace_item}
                         {fps_toggle_item}
                         {clear_cache_item}
                     }
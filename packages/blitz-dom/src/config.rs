@@ -1,4 +1,4 @@
-use crate::HtmlParserProvider;
+use crate::{HtmlParserProvider, MemoryLimits};
 use blitz_traits::{
     navigation::NavigationProvider,
     net::NetProvider,
@@ -30,4 +30,10 @@ pub struct DocumentConfig {
     /// The CSS media type used to evaluate `@media` rules.
     /// Defaults to [`MediaType::screen`].
     pub media_type: Option<MediaType>,
+    /// Caps on the memory used by the document. Defaults to no limits.
+    pub memory_limits: Option<MemoryLimits>,
+    /// Decode every image on the thread which receives it, as if it had `decoding="sync"`. Lets
+    /// callers which wait for their net provider to finish all requests (such as test runners)
+    /// know that every image has been applied. Defaults to `false`.
+    pub sync_image_decoding: bool,
 }
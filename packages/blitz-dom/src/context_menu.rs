@@ -0,0 +1,321 @@
+//! The default action of the `contextmenu` event
+//!
+//! A menu is built for the node under the pointer (taking into account any text selection,
+//! editable text input, link or image) and passed to the [`ShellProvider`] to be shown. The
+//! command chosen by the user is dispatched back to the document as a
+//! [`UiEvent::ContextMenuCommand`](blitz_traits::events::UiEvent::ContextMenuCommand).
+//!
+//! [`ShellProvider`]: blitz_traits::shell::ShellProvider
+
+use blitz_traits::events::{BlitzInputEvent, BlitzPointerEvent, DomEvent, DomEventData};
+use blitz_traits::navigation::NavigationOptions;
+use blitz_traits::shell::{ContextMenu, ContextMenuCommand, ContextMenuItem};
+use markup5ever::local_name;
+use url::Url;
+
+use crate::BaseDocument;
+
+impl BaseDocument {
+    /// Build the context menu for `target` and ask the shell to show it at the pointer position
+    pub(crate) fn open_context_menu(&mut self, target: usize, event: &BlitzPointerEvent) {
+        let editable = self.editable_ancestor(target);
+        let menu = self.build_context_menu(
+            target,
+            editable,
+            event.coords.client_x,
+            event.coords.client_y,
+        );
+        self.context_menu_editable = editable;
+        self.shell_provider.show_context_menu(menu);
+    }
+
+    fn build_context_menu(
+        &self,
+        target: usize,
+        editable: Option<usize>,
+        x: f32,
+        y: f32,
+    ) -> ContextMenu {
+        let mut items = Vec::new();
+        let command = |command| ContextMenuItem::Command {
+            command,
+            enabled: true,
+        };
+
+        if let Some(href) = self.link_ancestor_href(target) {
+            items.push(command(ContextMenuCommand::OpenLinkInNewTab(href.clone())));
+            items.push(command(ContextMenuCommand::CopyLink(href)));
+            items.push(ContextMenuItem::Separator);
+        }
+
+        let image_source = self.nodes[target]
+            .data
+            .is_element_with_tag_name(&local_name!("img"))
+            .then(|| self.select_image_source(target))
+            .flatten();
+        if let Some(source) = image_source {
+            let url = source.url.to_string();
+            items.push(command(ContextMenuCommand::OpenImageInNewTab(url.clone())));
+            items.push(command(ContextMenuCommand::CopyImageAddress(url)));
+            items.push(ContextMenuItem::Separator);
+        }
+
+        match editable {
+            Some(node_id) => {
+                let has_selection = self.nodes[node_id]
+                    .element_data()
+                    .and_then(|el| el.text_input_data())
+                    .is_some_and(|input| input.editor.selected_text().is_some());
+                items.push(ContextMenuItem::Command {
+                    command: ContextMenuCommand::Cut,
+                    enabled: has_selection,
+                });
+                items.push(ContextMenuItem::Command {
+                    command: ContextMenuCommand::Copy,
+                    enabled: has_selection,
+                });
+                items.push(command(ContextMenuCommand::Paste));
+                items.push(ContextMenuItem::Separator);
+                items.push(command(ContextMenuCommand::SelectAll));
+            }
+            None => {
+                items.push(ContextMenuItem::Command {
+                    command: ContextMenuCommand::Copy,
+                    enabled: self.has_text_selection(),
+                });
+                items.push(command(ContextMenuCommand::SelectAll));
+            }
+        }
+
+        ContextMenu { x, y, items }
+    }
+
+    /// Execute a command chosen from a context menu opened by this document
+    pub fn execute_context_menu_command(
+        &mut self,
+        command: ContextMenuCommand,
+        mut dispatch_event: impl FnMut(DomEvent),
+    ) {
+        let editable = self
+            .context_menu_editable
+            .take()
+            .filter(|node_id| self.editable_ancestor(*node_id) == Some(*node_id));
+
+        match command {
+            ContextMenuCommand::CopyLink(url) | ContextMenuCommand::CopyImageAddress(url) => {
+                let _ = self.shell_provider.set_clipboard_text(url);
+            }
+            ContextMenuCommand::OpenLinkInNewTab(url)
+            | ContextMenuCommand::OpenImageInNewTab(url) => {
+                if let Ok(url) = Url::parse(&url) {
+                    let options =
+                        NavigationOptions::new(url, None, self.id()).set_open_in_new_tab(true);
+                    self.navigation_provider.navigate_to(options);
+                }
+            }
+            command => match editable {
+                Some(node_id) => self.apply_editing_command(node_id, command, &mut dispatch_event),
+                None => match command {
+                    ContextMenuCommand::Copy => {
+                        if let Some(text) = self.get_selected_text() {
+                            let _ = self.shell_provider.set_clipboard_text(text);
+                        }
+                    }
+                    ContextMenuCommand::SelectAll => {
+                        self.select_all_text();
+                        self.shell_provider.request_redraw();
+                    }
+                    // Nothing to cut from or paste into
+                    _ => {}
+                },
+            },
+        }
+    }
+
+    fn apply_editing_command(
+        &mut self,
+        node_id: usize,
+        command: ContextMenuCommand,
+        dispatch_event: &mut dyn FnMut(DomEvent),
+    ) {
+        let shell_provider = self.shell_provider.clone();
+        let Some(input_data) = self.nodes[node_id]
+            .element_data_mut()
+            .and_then(|el| el.text_input_data_mut())
+        else {
+            return;
+        };
+
+        let mut font_ctx = self.font_ctx.lock().unwrap();
+        let mut driver = input_data
+            .editor
+            .driver(&mut font_ctx, &mut self.layout_ctx);
+        let is_input = match command {
+            ContextMenuCommand::Cut => match driver.editor.selected_text() {
+                Some(text) => {
+                    let _ = shell_provider.set_clipboard_text(text.to_owned());
+                    driver.delete_selection();
+                    true
+                }
+                None => false,
+            },
+            ContextMenuCommand::Copy => {
+                if let Some(text) = driver.editor.selected_text() {
+                    let _ = shell_provider.set_clipboard_text(text.to_owned());
+                }
+                false
+            }
+            ContextMenuCommand::Paste => match shell_provider.get_clipboard_text() {
+                Ok(text) => {
+                    driver.insert_or_replace_selection(&text);
+                    true
+                }
+                Err(_) => false,
+            },
+            ContextMenuCommand::SelectAll => {
+                driver.select_all();
+                false
+            }
+            _ => false,
+        };
+        drop(font_ctx);
+
+        if is_input {
+            let value = input_data.editor.raw_text().to_string();
+            dispatch_event(DomEvent::new(
+                node_id,
+                DomEventData::Input(BlitzInputEvent { value }),
+            ));
+        }
+        shell_provider.request_redraw();
+    }
+
+    /// The nearest text input which is `node_id` or one of its ancestors
+    fn editable_ancestor(&self, node_id: usize) -> Option<usize> {
+        self.node_chain(node_id).into_iter().find(|id| {
+            self.nodes[*id].element_data().is_some_and(|el| {
+                el.text_input_data().is_some() && !el.has_attr(local_name!("disabled"))
+            })
+        })
+    }
+
+    /// The resolved `href` of the nearest link which is `node_id` or one of its ancestors
+    fn link_ancestor_href(&self, node_id: usize) -> Option<String> {
+        self.node_chain(node_id).into_iter().find_map(|id| {
+            let el = self.nodes[id].element_data()?;
+            if el.name.local != local_name!("a") {
+                return None;
+            }
+            let href = el.attr(local_name!("href"))?;
+            self.url.resolve_relative(href).map(|url| url.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use blitz_traits::navigation::NavigationProvider;
+    use blitz_traits::net::Method;
+    use blitz_traits::shell::{ClipboardError, ShellProvider};
+
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+
+    #[derive(Default)]
+    struct RecordingProvider {
+        navigations: Mutex<Vec<NavigationOptions>>,
+        clipboard: Mutex<Option<String>>,
+    }
+
+    impl NavigationProvider for RecordingProvider {
+        fn navigate_to(&self, options: NavigationOptions) {
+            self.navigations.lock().unwrap().push(options);
+        }
+    }
+
+    impl ShellProvider for RecordingProvider {
+        fn set_clipboard_text(&self, text: String) -> Result<(), ClipboardError> {
+            *self.clipboard.lock().unwrap() = Some(text);
+            Ok(())
+        }
+    }
+
+    /// A document containing `<a href="/page"><span></span></a>`, returning the span
+    fn link_document(provider: &Arc<RecordingProvider>) -> (BaseDocument, usize) {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            base_url: Some("https://example.com/dir/".to_string()),
+            navigation_provider: Some(provider.clone() as _),
+            shell_provider: Some(provider.clone() as _),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let span = mutator.create_element(qual_name!("span", html), Vec::new());
+        let link = mutator.create_element(
+            qual_name!("a", html),
+            vec![Attribute {
+                name: qual_name!("href"),
+                value: "/page".to_string(),
+            }],
+        );
+        mutator.append_children(link, &[span]);
+        mutator.append_children(0, &[link]);
+        drop(mutator);
+        (doc, span)
+    }
+
+    #[test]
+    fn test_link_context_menu() {
+        let provider = Arc::new(RecordingProvider::default());
+        let (doc, span) = link_document(&provider);
+
+        let menu = doc.build_context_menu(span, None, 10.0, 20.0);
+        let url = "https://example.com/page".to_string();
+        assert_eq!((menu.x, menu.y), (10.0, 20.0));
+        assert_eq!(
+            menu.items,
+            vec![
+                ContextMenuItem::Command {
+                    command: ContextMenuCommand::OpenLinkInNewTab(url.clone()),
+                    enabled: true,
+                },
+                ContextMenuItem::Command {
+                    command: ContextMenuCommand::CopyLink(url),
+                    enabled: true,
+                },
+                ContextMenuItem::Separator,
+                // Nothing is selected
+                ContextMenuItem::Command {
+                    command: ContextMenuCommand::Copy,
+                    enabled: false,
+                },
+                ContextMenuItem::Command {
+                    command: ContextMenuCommand::SelectAll,
+                    enabled: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_commands() {
+        let provider = Arc::new(RecordingProvider::default());
+        let (mut doc, _) = link_document(&provider);
+        let url = "https://example.com/page";
+
+        doc.execute_context_menu_command(
+            ContextMenuCommand::OpenLinkInNewTab(url.to_string()),
+            |_| {},
+        );
+        let navigations = provider.navigations.lock().unwrap().clone();
+        assert_eq!(navigations.len(), 1);
+        assert_eq!(navigations[0].url.as_str(), url);
+        assert_eq!(navigations[0].method, Method::GET);
+        assert!(navigations[0].open_in_new_tab);
+
+        doc.execute_context_menu_command(ContextMenuCommand::CopyLink(url.to_string()), |_| {});
+        assert_eq!(provider.clipboard.lock().unwrap().as_deref(), Some(url));
+        assert_eq!(provider.navigations.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,266 @@
+//! A remote inspector for live documents, speaking a subset of the
+//! [Chrome DevTools Protocol](https://chromedevtools.github.io/devtools-protocol/)
+//!
+//! The server accepts WebSocket connections on background threads. Messages are forwarded to the
+//! document's thread and handled at the start of [`BaseDocument::resolve`], so inspectors see (and
+//! edit) the document between frames. The `DOM`, `CSS` and `Overlay` domains are partially
+//! supported (see [`protocol`]).
+//!
+//! Chrome's inspector can be connected by opening
+//! `devtools://devtools/bundled/inspector.html?ws=127.0.0.1:9222/devtools/page/blitz`
+//! (substituting the address the server was started on), or found through `chrome://inspect`
+//! which uses the HTTP discovery endpoints (`/json/list` and `/json/version`).
+
+use std::io::{self, ErrorKind, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use blitz_traits::shell::ShellProvider;
+use serde_json::{Value, json};
+use tungstenite::Message;
+
+use crate::BaseDocument;
+
+mod protocol;
+
+/// How long connection threads wait for a message from the inspector before checking for replies
+const READ_TIMEOUT: Duration = Duration::from_millis(15);
+
+/// The id of the (only) target listed by the discovery endpoints
+const TARGET_ID: &str = "blitz";
+
+/// The shell provider of the document, used by connection threads to wake the document's thread.
+/// Shared so that it can be updated when the document is attached to a window.
+type SharedShellProvider = Arc<Mutex<Arc<dyn ShellProvider>>>;
+
+/// A protocol message from an inspector, along with the means to reply to it
+struct Request {
+    connection_id: usize,
+    message: String,
+    reply: Sender<String>,
+}
+
+pub(crate) struct DevtoolsServer {
+    addr: SocketAddr,
+    requests: Receiver<Request>,
+    shell_provider: SharedShellProvider,
+    /// Connections that have sent a request, for broadcasting events
+    connections: Vec<(usize, Sender<String>)>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl DevtoolsServer {
+    fn start(addr: impl ToSocketAddrs, shell_provider: Arc<dyn ShellProvider>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let (request_tx, request_rx) = channel();
+        let shell_provider = Arc::new(Mutex::new(shell_provider));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_shell_provider = shell_provider.clone();
+        let thread_shutdown = shutdown.clone();
+        thread::Builder::new()
+            .name(String::from("blitz-devtools"))
+            .spawn(move || {
+                for (connection_id, stream) in listener.incoming().enumerate() {
+                    if thread_shutdown.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let requests = request_tx.clone();
+                    let shell_provider = thread_shell_provider.clone();
+                    let shutdown = thread_shutdown.clone();
+                    let _ = thread::Builder::new()
+                        .name(format!("blitz-devtools-{connection_id}"))
+                        .spawn(move || {
+                            let _ = serve_connection(
+                                stream,
+                                addr,
+                                connection_id,
+                                requests,
+                                shell_provider,
+                                shutdown,
+                            );
+                        });
+                }
+            })?;
+
+        Ok(Self {
+            addr,
+            requests: request_rx,
+            shell_provider,
+            connections: Vec::new(),
+            shutdown,
+        })
+    }
+
+    pub(crate) fn set_shell_provider(&self, shell_provider: Arc<dyn ShellProvider>) {
+        *self.shell_provider.lock().unwrap() = shell_provider;
+    }
+
+    /// Send an event to every connected inspector
+    fn broadcast(&mut self, event: &Value) {
+        let message = event.to_string();
+        self.connections
+            .retain(|(_, connection)| connection.send(message.clone()).is_ok());
+    }
+}
+
+impl Drop for DevtoolsServer {
+    fn drop(&mut self) {
+        // Wake the listener thread (which is blocked accepting connections) so that it exits.
+        // Connection threads notice the flag the next time their read times out.
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+impl BaseDocument {
+    /// Start a server which remote inspectors can connect to in order to inspect and edit this
+    /// document. Returns the address the server is listening on (which is useful when binding to
+    /// port 0).
+    ///
+    /// Any server which is already running is stopped.
+    pub fn start_devtools_server(&mut self, addr: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+        self.devtools_server = None;
+        let server = DevtoolsServer::start(addr, self.shell_provider.clone())?;
+        let addr = server.addr;
+        self.devtools_server = Some(server);
+        Ok(addr)
+    }
+
+    /// Stop the devtools server (if one is running), disconnecting any inspectors
+    pub fn stop_devtools_server(&mut self) {
+        self.devtools_server = None;
+        self.devtool_settings.highlighted_node = None;
+    }
+
+    /// Handle the messages received from inspectors since the last call
+    pub(crate) fn handle_devtools_requests(&mut self) {
+        let Some(server) = &mut self.devtools_server else {
+            return;
+        };
+        let requests: Vec<Request> = server.requests.try_iter().collect();
+        for request in &requests {
+            if !server
+                .connections
+                .iter()
+                .any(|(id, _)| *id == request.connection_id)
+            {
+                server
+                    .connections
+                    .push((request.connection_id, request.reply.clone()));
+            }
+        }
+
+        for request in requests {
+            let mut events = Vec::new();
+            let reply = self.handle_devtools_message(&request.message, &mut events);
+            for message in events.iter().chain(&reply) {
+                let _ = request.reply.send(message.to_string());
+            }
+        }
+    }
+
+    /// Notify inspectors that the user picked a node while in inspect mode
+    pub(crate) fn devtools_inspect_node(&mut self, node_id: usize) {
+        if let Some(server) = &mut self.devtools_server {
+            server.broadcast(&json!({
+                "method": "Overlay.inspectNodeRequested",
+                "params": { "backendNodeId": protocol::to_protocol_id(node_id) },
+            }));
+        }
+    }
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    connection_id: usize,
+    requests: Sender<Request>,
+    shell_provider: SharedShellProvider,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    // Plain HTTP requests are for the discovery endpoints
+    let mut head = [0; 4096];
+    let len = stream.peek(&mut head)?;
+    let head = String::from_utf8_lossy(&head[..len]);
+    if !head.to_ascii_lowercase().contains("upgrade: websocket") {
+        return serve_discovery(stream, &head, addr);
+    }
+
+    let mut socket = tungstenite::accept(stream).map_err(io::Error::other)?;
+    socket.get_ref().set_read_timeout(Some(READ_TIMEOUT))?;
+    let (reply_tx, reply_rx) = channel();
+
+    while !shutdown.load(Ordering::Relaxed) {
+        match socket.read() {
+            Ok(Message::Text(message)) => {
+                let request = Request {
+                    connection_id,
+                    message: message.as_str().to_owned(),
+                    reply: reply_tx.clone(),
+                };
+                if requests.send(request).is_err() {
+                    // The server has been stopped
+                    break;
+                }
+                shell_provider.lock().unwrap().request_redraw();
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+
+        while let Ok(reply) = reply_rx.try_recv() {
+            if socket.send(Message::text(reply)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    let _ = socket.close(None);
+    Ok(())
+}
+
+/// Answer the HTTP endpoints which inspectors use to list debugging targets
+fn serve_discovery(mut stream: TcpStream, head: &str, addr: SocketAddr) -> io::Result<()> {
+    let path = head.split_whitespace().nth(1).unwrap_or("/");
+    let body = match path.trim_end_matches('/') {
+        "/json" | "/json/list" => json!([{
+            "id": TARGET_ID,
+            "type": "page",
+            "title": "Blitz",
+            "description": "Blitz document",
+            "url": "",
+            "webSocketDebuggerUrl": format!("ws://{addr}/devtools/page/{TARGET_ID}"),
+            "devtoolsFrontendUrl": format!(
+                "devtools://devtools/bundled/inspector.html?ws={addr}/devtools/page/{TARGET_ID}"
+            ),
+        }]),
+        "/json/version" => json!({
+            "Browser": concat!("Blitz/", env!("CARGO_PKG_VERSION")),
+            "Protocol-Version": "1.3",
+        }),
+        _ => {
+            return stream.write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+        }
+    };
+
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,675 @@
+//! Handlers for the supported Chrome DevTools Protocol methods
+//!
+//! - `DOM`: `getDocument`, `requestChildNodes`, `describeNode`, `querySelector`,
+//!   `querySelectorAll`, `getOuterHTML`, `getBoxModel`, `getNodeForLocation`,
+//!   `setAttributeValue`, `removeAttribute` and `setNodeValue`.
+//! - `CSS`: `getComputedStyleForNode`, `getMatchedStylesForNode`, `getInlineStylesForNode` and
+//!   `setStyleTexts` (for inline styles only).
+//! - `Overlay`: `highlightNode`, `hideHighlight` and `setInspectMode`.
+//!
+//! Protocol node ids are Blitz node ids plus one (as inspectors treat `0` as "no node"). Edits are
+//! reported back as events, but changes made by the application are not: inspectors should call
+//! `DOM.getDocument` to refresh.
+
+use cssparser::ToCss as _;
+use markup5ever::{LocalName, QualName, local_name, ns};
+use selectors::SelectorList;
+use serde_json::{Value, json};
+use style::dom_apis::element_matches;
+use style::properties::PropertyDeclarationBlock;
+use style::shared_lock::SharedRwLockReadGuard;
+use style::stylesheets::CssRule;
+
+use crate::{BaseDocument, Node, NodeData};
+
+/// The computed properties reported by `CSS.getComputedStyleForNode`
+const COMPUTED_STYLE_PROPERTIES: &[&str] = &[
+    "display",
+    "position",
+    "top",
+    "right",
+    "bottom",
+    "left",
+    "z-index",
+    "float",
+    "box-sizing",
+    "width",
+    "height",
+    "min-width",
+    "min-height",
+    "max-width",
+    "max-height",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+    "border-top-style",
+    "border-right-style",
+    "border-bottom-style",
+    "border-left-style",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+    "border-top-left-radius",
+    "border-top-right-radius",
+    "border-bottom-right-radius",
+    "border-bottom-left-radius",
+    "overflow-x",
+    "overflow-y",
+    "flex-direction",
+    "flex-wrap",
+    "flex-grow",
+    "flex-shrink",
+    "flex-basis",
+    "justify-content",
+    "align-items",
+    "align-self",
+    "align-content",
+    "order",
+    "row-gap",
+    "column-gap",
+    "grid-template-columns",
+    "grid-template-rows",
+    "grid-auto-flow",
+    "grid-column-start",
+    "grid-column-end",
+    "grid-row-start",
+    "grid-row-end",
+    "color",
+    "background-color",
+    "background-image",
+    "opacity",
+    "visibility",
+    "transform",
+    "font-family",
+    "font-size",
+    "font-weight",
+    "font-style",
+    "line-height",
+    "letter-spacing",
+    "text-align",
+    "text-decoration-line",
+    "text-transform",
+    "white-space",
+    "word-break",
+    "overflow-wrap",
+    "vertical-align",
+    "cursor",
+    "pointer-events",
+    "outline-style",
+    "outline-width",
+    "outline-color",
+];
+
+pub(super) fn to_protocol_id(node_id: usize) -> usize {
+    node_id + 1
+}
+
+enum ProtocolError {
+    MethodNotFound(String),
+    InvalidParams(&'static str),
+    NodeNotFound,
+}
+
+impl ProtocolError {
+    fn to_json(&self) -> Value {
+        let (code, message) = match self {
+            Self::MethodNotFound(method) => (-32601, format!("'{method}' wasn't found")),
+            Self::InvalidParams(message) => (-32602, message.to_string()),
+            Self::NodeNotFound => (-32000, String::from("Could not find node with given id")),
+        };
+        json!({ "code": code, "message": message })
+    }
+}
+
+type ProtocolResult = Result<Value, ProtocolError>;
+
+fn str_param<'a>(params: &'a Value, name: &'static str) -> Result<&'a str, ProtocolError> {
+    params[name]
+        .as_str()
+        .ok_or(ProtocolError::InvalidParams(name))
+}
+
+fn f32_param(params: &Value, name: &'static str) -> Result<f32, ProtocolError> {
+    params[name]
+        .as_f64()
+        .map(|value| value as f32)
+        .ok_or(ProtocolError::InvalidParams(name))
+}
+
+/// A `DOM.Quad` (the four corners of a box) from its position and size
+fn quad(x: f32, y: f32, width: f32, height: f32) -> Value {
+    json!([x, y, x + width, y, x + width, y + height, x, y + height])
+}
+
+impl BaseDocument {
+    /// Handle a protocol message, returning the reply (if any) and pushing any events to `events`
+    pub(super) fn handle_devtools_message(
+        &mut self,
+        message: &str,
+        events: &mut Vec<Value>,
+    ) -> Option<Value> {
+        let message: Value = serde_json::from_str(message).ok()?;
+        let id = message.get("id")?.clone();
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        Some(match self.handle_devtools_method(method, params, events) {
+            Ok(result) => json!({ "id": id, "result": result }),
+            Err(err) => json!({ "id": id, "error": err.to_json() }),
+        })
+    }
+
+    fn handle_devtools_method(
+        &mut self,
+        method: &str,
+        params: &Value,
+        events: &mut Vec<Value>,
+    ) -> ProtocolResult {
+        match method {
+            "DOM.enable" | "DOM.disable" | "CSS.enable" | "CSS.disable" | "Overlay.enable"
+            | "Overlay.disable" => Ok(json!({})),
+
+            "DOM.getDocument" => {
+                let depth = params["depth"].as_i64().unwrap_or(1);
+                Ok(json!({ "root": self.devtools_node(0, depth) }))
+            }
+            "DOM.requestChildNodes" => {
+                let node_id = self.devtools_node_param(params)?;
+                let depth = params["depth"].as_i64().unwrap_or(1);
+                let nodes: Vec<Value> = self
+                    .devtools_children(node_id)
+                    .map(|child_id| self.devtools_node(child_id, depth - 1))
+                    .collect();
+                events.push(json!({
+                    "method": "DOM.setChildNodes",
+                    "params": { "parentId": to_protocol_id(node_id), "nodes": nodes },
+                }));
+                Ok(json!({}))
+            }
+            "DOM.describeNode" => {
+                let node_id = self.devtools_node_param(params)?;
+                let depth = params["depth"].as_i64().unwrap_or(0);
+                Ok(json!({ "node": self.devtools_node(node_id, depth) }))
+            }
+            "DOM.querySelector" | "DOM.querySelectorAll" => {
+                let node_id = self.devtools_node_param(params)?;
+                let selector = str_param(params, "selector")?;
+                let matches = self
+                    .query_selector_all(selector)
+                    .map_err(|_| ProtocolError::InvalidParams("selector"))?;
+                let mut node_ids = matches
+                    .into_iter()
+                    .filter(|id| {
+                        *id != node_id && (node_id == 0 || self.node_chain(*id).contains(&node_id))
+                    })
+                    .map(to_protocol_id);
+                match method {
+                    "DOM.querySelector" => Ok(json!({ "nodeId": node_ids.next().unwrap_or(0) })),
+                    _ => Ok(json!({ "nodeIds": node_ids.collect::<Vec<_>>() })),
+                }
+            }
+            "DOM.getOuterHTML" => {
+                let node_id = self.devtools_node_param(params)?;
+                Ok(json!({ "outerHTML": self.nodes[node_id].outer_html() }))
+            }
+            "DOM.getBoxModel" => {
+                let node_id = self.devtools_node_param(params)?;
+                Ok(json!({ "model": self.devtools_box_model(node_id) }))
+            }
+            "DOM.getNodeForLocation" => {
+                let scroll = self.viewport_scroll();
+                let x = f32_param(params, "x")? + scroll.x as f32;
+                let y = f32_param(params, "y")? + scroll.y as f32;
+                let hit = self.hit(x, y).ok_or(ProtocolError::NodeNotFound)?;
+                let node_id = to_protocol_id(hit.node_id);
+                Ok(json!({ "backendNodeId": node_id, "nodeId": node_id }))
+            }
+            "DOM.setAttributeValue" => {
+                let node_id = self.devtools_element_param(params)?;
+                let name = str_param(params, "name")?;
+                let value = str_param(params, "value")?;
+                let qual_name = QualName::new(None, ns!(), LocalName::from(name));
+                self.mutate().set_attribute(node_id, qual_name, value);
+                self.shell_provider.request_redraw();
+                events.push(json!({
+                    "method": "DOM.attributeModified",
+                    "params": { "nodeId": to_protocol_id(node_id), "name": name, "value": value },
+                }));
+                Ok(json!({}))
+            }
+            "DOM.removeAttribute" => {
+                let node_id = self.devtools_element_param(params)?;
+                let name = str_param(params, "name")?;
+                let qual_name = QualName::new(None, ns!(), LocalName::from(name));
+                self.mutate().clear_attribute(node_id, qual_name);
+                self.shell_provider.request_redraw();
+                events.push(json!({
+                    "method": "DOM.attributeRemoved",
+                    "params": { "nodeId": to_protocol_id(node_id), "name": name },
+                }));
+                Ok(json!({}))
+            }
+            "DOM.setNodeValue" => {
+                let node_id = self.devtools_node_param(params)?;
+                let value = str_param(params, "value")?;
+                if !self.nodes[node_id].is_text_node() {
+                    return Err(ProtocolError::InvalidParams("nodeId"));
+                }
+                self.mutate().set_node_text(node_id, value);
+                self.shell_provider.request_redraw();
+                events.push(json!({
+                    "method": "DOM.characterDataModified",
+                    "params": { "nodeId": to_protocol_id(node_id), "characterData": value },
+                }));
+                Ok(json!({}))
+            }
+
+            "CSS.getComputedStyleForNode" => {
+                let node_id = self.devtools_node_param(params)?;
+                let node = &self.nodes[node_id];
+                let computed_style: Vec<Value> = COMPUTED_STYLE_PROPERTIES
+                    .iter()
+                    .filter_map(|name| {
+                        let value = node.computed_style_value(name)?;
+                        Some(json!({ "name": name, "value": value }))
+                    })
+                    .collect();
+                Ok(json!({ "computedStyle": computed_style }))
+            }
+            "CSS.getMatchedStylesForNode" => {
+                let node_id = self.devtools_node_param(params)?;
+                Ok(json!({
+                    "inlineStyle": self.devtools_inline_style(node_id),
+                    "matchedCSSRules": self.devtools_matched_rules(node_id),
+                }))
+            }
+            "CSS.getInlineStylesForNode" => {
+                let node_id = self.devtools_node_param(params)?;
+                Ok(json!({ "inlineStyle": self.devtools_inline_style(node_id) }))
+            }
+            "CSS.setStyleTexts" => {
+                let edits = params["edits"]
+                    .as_array()
+                    .ok_or(ProtocolError::InvalidParams("edits"))?;
+                let mut styles = Vec::new();
+                for edit in edits {
+                    // Only inline styles (whose ids are "inline:<nodeId>") are editable. The
+                    // whole attribute is replaced, regardless of the edit's range.
+                    let node_id = str_param(edit, "styleSheetId")?
+                        .strip_prefix("inline:")
+                        .and_then(|id| id.parse::<usize>().ok())
+                        .and_then(|id| id.checked_sub(1))
+                        .filter(|id| self.get_node(*id).is_some_and(|node| node.is_element()))
+                        .ok_or(ProtocolError::InvalidParams("styleSheetId"))?;
+                    let text = str_param(edit, "text")?;
+                    self.mutate().set_attribute(
+                        node_id,
+                        QualName::new(None, ns!(), local_name!("style")),
+                        text,
+                    );
+                    styles.push(self.devtools_inline_style(node_id));
+                }
+                self.shell_provider.request_redraw();
+                Ok(json!({ "styles": styles }))
+            }
+
+            "Overlay.highlightNode" => {
+                let node_id = self.devtools_node_param(params)?;
+                self.devtool_settings.highlighted_node = Some(node_id);
+                self.shell_provider.request_redraw();
+                Ok(json!({}))
+            }
+            "Overlay.hideHighlight" => {
+                self.devtool_settings.highlighted_node = None;
+                self.shell_provider.request_redraw();
+                Ok(json!({}))
+            }
+            "Overlay.setInspectMode" => {
+                let mode = str_param(params, "mode")?;
+                self.devtool_settings.highlight_hover = mode != "none";
+                self.shell_provider.request_redraw();
+                Ok(json!({}))
+            }
+
+            _ => Err(ProtocolError::MethodNotFound(method.to_string())),
+        }
+    }
+
+    /// The node identified by the `nodeId` (or `backendNodeId`) parameter
+    fn devtools_node_param(&self, params: &Value) -> Result<usize, ProtocolError> {
+        let id = params["nodeId"]
+            .as_u64()
+            .or_else(|| params["backendNodeId"].as_u64())
+            .ok_or(ProtocolError::InvalidParams("nodeId"))?;
+        (id as usize)
+            .checked_sub(1)
+            .filter(|node_id| self.get_node(*node_id).is_some())
+            .ok_or(ProtocolError::NodeNotFound)
+    }
+
+    fn devtools_element_param(&self, params: &Value) -> Result<usize, ProtocolError> {
+        let node_id = self.devtools_node_param(params)?;
+        match self.nodes[node_id].is_element() {
+            true => Ok(node_id),
+            false => Err(ProtocolError::InvalidParams("nodeId")),
+        }
+    }
+
+    /// The DOM children of a node which are shown by inspectors (which excludes whitespace)
+    fn devtools_children(&self, node_id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.nodes[node_id]
+            .children
+            .iter()
+            .copied()
+            .filter(|child_id| match &self.nodes[*child_id].data {
+                NodeData::Text(data) => !data.content.trim().is_empty(),
+                NodeData::AnonymousBlock(_) => false,
+                _ => true,
+            })
+    }
+
+    /// Serialize a node as a `DOM.Node`, including its descendants to the given depth (or all of
+    /// them if `depth` is negative)
+    fn devtools_node(&self, node_id: usize, depth: i64) -> Value {
+        let node = &self.nodes[node_id];
+        let protocol_id = to_protocol_id(node_id);
+        let child_count = self.devtools_children(node_id).count();
+        let mut value = json!({
+            "nodeId": protocol_id,
+            "backendNodeId": protocol_id,
+            "childNodeCount": child_count,
+            "localName": "",
+            "nodeValue": "",
+        });
+        if let Some(parent_id) = node.parent {
+            value["parentId"] = json!(to_protocol_id(parent_id));
+        }
+
+        match &node.data {
+            NodeData::Document => {
+                value["nodeType"] = json!(9);
+                value["nodeName"] = json!("#document");
+                value["documentURL"] = json!(self.url.as_str());
+                value["baseURL"] = json!(self.url.as_str());
+            }
+            NodeData::Element(el) | NodeData::AnonymousBlock(el) => {
+                let attributes: Vec<&str> = el
+                    .attrs()
+                    .iter()
+                    .flat_map(|attr| [&*attr.name.local, attr.value.as_str()])
+                    .collect();
+                value["nodeType"] = json!(1);
+                value["nodeName"] = json!(el.name.local.to_ascii_uppercase());
+                value["localName"] = json!(&*el.name.local);
+                value["attributes"] = json!(attributes);
+            }
+            NodeData::Text(data) => {
+                value["nodeType"] = json!(3);
+                value["nodeName"] = json!("#text");
+                value["nodeValue"] = json!(data.content);
+            }
+            NodeData::Comment => {
+                value["nodeType"] = json!(8);
+                value["nodeName"] = json!("#comment");
+            }
+        }
+
+        if depth != 0 && child_count > 0 {
+            let children: Vec<Value> = self
+                .devtools_children(node_id)
+                .map(|child_id| self.devtools_node(child_id, depth - 1))
+                .collect();
+            value["children"] = json!(children);
+        }
+
+        value
+    }
+
+    /// The `DOM.BoxModel` of a node in CSS pixels relative to the viewport
+    fn devtools_box_model(&self, node_id: usize) -> Value {
+        let node = &self.nodes[node_id];
+        let layout = &node.final_layout;
+        let scroll = self.viewport_scroll();
+        let position = node.absolute_position(0.0, 0.0);
+        let x = position.x - scroll.x as f32;
+        let y = position.y - scroll.y as f32;
+        let (width, height) = (layout.size.width, layout.size.height);
+        let (border, padding, margin) = (layout.border, layout.padding, layout.margin);
+
+        let padding_x = x + border.left;
+        let padding_y = y + border.top;
+        let padding_width = width - border.left - border.right;
+        let padding_height = height - border.top - border.bottom;
+
+        json!({
+            "margin": quad(
+                x - margin.left,
+                y - margin.top,
+                width + margin.left + margin.right,
+                height + margin.top + margin.bottom,
+            ),
+            "border": quad(x, y, width, height),
+            "padding": quad(padding_x, padding_y, padding_width, padding_height),
+            "content": quad(
+                padding_x + padding.left,
+                padding_y + padding.top,
+                padding_width - padding.left - padding.right,
+                padding_height - padding.top - padding.bottom,
+            ),
+            "width": width,
+            "height": height,
+        })
+    }
+
+    fn devtools_inline_style(&self, node_id: usize) -> Value {
+        let Some(el) = self.nodes[node_id].element_data() else {
+            return Value::Null;
+        };
+        let guard = self.guard.read();
+        let style_sheet_id = format!("inline:{}", to_protocol_id(node_id));
+        match &el.style_attribute {
+            Some(block) => devtools_style(block.read_with(&guard), Some(style_sheet_id)),
+            None => devtools_style(&PropertyDeclarationBlock::new(), Some(style_sheet_id)),
+        }
+    }
+
+    /// The style rules which match a node, as `CSS.RuleMatch`es (user-agent rules first and
+    /// author rules in document order)
+    fn devtools_matched_rules(&self, node_id: usize) -> Vec<Value> {
+        let node = &self.nodes[node_id];
+        if !node.is_element() {
+            return Vec::new();
+        }
+
+        let guard = self.guard.read();
+        let mut matched_rules = Vec::new();
+        let ua_sheets = self
+            .ua_stylesheets
+            .values()
+            .map(|sheet| (sheet, "user-agent"));
+        let author_sheets = self
+            .nodes_to_stylesheet
+            .values()
+            .map(|sheet| (sheet, "regular"));
+        for (sheet, origin) in ua_sheets.chain(author_sheets) {
+            let rules = sheet.0.contents(&guard).rules(&guard);
+            self.collect_matched_rules(node, rules, origin, &guard, &mut matched_rules);
+        }
+        matched_rules
+    }
+
+    fn collect_matched_rules(
+        &self,
+        node: &Node,
+        rules: &[CssRule],
+        origin: &str,
+        guard: &SharedRwLockReadGuard,
+        matched_rules: &mut Vec<Value>,
+    ) {
+        let quirks_mode = self.stylist.quirks_mode();
+        for rule in rules {
+            match rule {
+                CssRule::Style(style_rule) => {
+                    let style_rule = style_rule.read_with(guard);
+                    let selectors = style_rule.selectors.slice();
+                    let matching_selectors: Vec<usize> = (0..selectors.len())
+                        .filter(|&i| {
+                            let selector = SelectorList::from_one(selectors[i].clone());
+                            element_matches(&node, &selector, quirks_mode)
+                        })
+                        .collect();
+                    if !matching_selectors.is_empty() {
+                        let selector_texts: Vec<Value> = selectors
+                            .iter()
+                            .map(|selector| json!({ "text": selector.to_css_string() }))
+                            .collect();
+                        matched_rules.push(json!({
+                            "rule": {
+                                "selectorList": {
+                                    "selectors": selector_texts,
+                                    "text": style_rule.selectors.to_css_string(),
+                                },
+                                "origin": origin,
+                                "style": devtools_style(style_rule.block.read_with(guard), None),
+                            },
+                            "matchingSelectors": matching_selectors,
+                        }));
+                    }
+                    if let Some(nested_rules) = &style_rule.rules {
+                        let nested_rules = &nested_rules.read_with(guard).0;
+                        self.collect_matched_rules(
+                            node,
+                            nested_rules,
+                            origin,
+                            guard,
+                            matched_rules,
+                        );
+                    }
+                }
+                CssRule::Media(media_rule) => {
+                    let media_queries = media_rule.media_queries.read_with(guard);
+                    if media_queries.evaluate(self.stylist.device(), quirks_mode) {
+                        let nested_rules = &media_rule.rules.read_with(guard).0;
+                        self.collect_matched_rules(
+                            node,
+                            nested_rules,
+                            origin,
+                            guard,
+                            matched_rules,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Serialize a declaration block as a `CSS.CSSStyle`
+fn devtools_style(block: &PropertyDeclarationBlock, style_sheet_id: Option<String>) -> Value {
+    let mut css_text = String::new();
+    let properties: Vec<Value> = block
+        .declaration_importance_iter()
+        .map(|(declaration, importance)| {
+            let name = declaration.id().name();
+            let mut value = String::new();
+            let _ = declaration.to_css(&mut value);
+            let important = importance.important();
+            let text = match important {
+                true => format!("{name}: {value} !important;"),
+                false => format!("{name}: {value};"),
+            };
+            if !css_text.is_empty() {
+                css_text.push(' ');
+            }
+            css_text.push_str(&text);
+            json!({
+                "name": name,
+                "value": value,
+                "important": important,
+                "text": text,
+                "implicit": false,
+                "disabled": false,
+            })
+        })
+        .collect();
+
+    let mut style = json!({
+        "cssProperties": properties,
+        "shorthandEntries": [],
+        "cssText": css_text,
+    });
+    if let Some(style_sheet_id) = style_sheet_id {
+        style["styleSheetId"] = json!(style_sheet_id);
+    }
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use crate::{BaseDocument, DocumentConfig, qual_name};
+
+    fn request(doc: &mut BaseDocument, method: &str, params: Value) -> (Value, Vec<Value>) {
+        let message = json!({ "id": 1, "method": method, "params": params }).to_string();
+        let mut events = Vec::new();
+        let reply = doc.handle_devtools_message(&message, &mut events).unwrap();
+        (reply, events)
+    }
+
+    fn document_with_body() -> (BaseDocument, usize) {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let mut mutr = doc.mutate();
+        let html_id = mutr.create_element(qual_name!("html", html), vec![]);
+        let body_id = mutr.create_element(qual_name!("body", html), vec![]);
+        mutr.append_children(mutr.doc.root_node().id, &[html_id]);
+        mutr.append_children(html_id, &[body_id]);
+        drop(mutr);
+        (doc, body_id)
+    }
+
+    #[test]
+    fn get_document_offsets_node_ids() {
+        let (mut doc, body_id) = document_with_body();
+        let (reply, _) = request(&mut doc, "DOM.getDocument", json!({ "depth": -1 }));
+        let root = &reply["result"]["root"];
+        assert_eq!(root["nodeId"], 1);
+        assert_eq!(root["nodeName"], "#document");
+        let body = &root["children"][0]["children"][0];
+        assert_eq!(body["nodeName"], "BODY");
+        assert_eq!(body["nodeId"], body_id + 1);
+    }
+
+    #[test]
+    fn set_attribute_value_reports_modification() {
+        let (mut doc, body_id) = document_with_body();
+        let params = json!({ "nodeId": body_id + 1, "name": "class", "value": "dark" });
+        let (reply, events) = request(&mut doc, "DOM.setAttributeValue", params);
+        assert_eq!(reply["result"], json!({}));
+        assert_eq!(events[0]["method"], "DOM.attributeModified");
+        assert_eq!(
+            doc.nodes[body_id].attr(markup5ever::local_name!("class")),
+            Some("dark")
+        );
+    }
+
+    #[test]
+    fn unknown_methods_and_nodes_are_errors() {
+        let (mut doc, _) = document_with_body();
+        let (reply, _) = request(&mut doc, "Page.navigate", json!({}));
+        assert_eq!(reply["error"]["code"], -32601);
+        let (reply, _) = request(&mut doc, "DOM.getBoxModel", json!({ "nodeId": 9999 }));
+        assert_eq!(reply["error"]["code"], -32000);
+    }
+}
@@ -2,11 +2,19 @@ use crate::events::{DragMode, ScrollAnimationState, handle_dom_event};
 use crate::font_metrics::BlitzFontMetricsProvider;
 use crate::layout::construct::ConstructionTask;
 use crate::layout::damage::ALL_DAMAGE;
+use crate::layout::verify::LayoutMismatch;
+use crate::memory::MemoryState;
 use crate::mutator::ViewportMut;
 use crate::net::{
-    Resource, ResourceHandler, ResourceLoadResponse, StylesheetHandler, StylesheetLoader,
+    ImageHandler, Resource, ResourceHandler, ResourceLoadResponse, StylesheetHandler,
+    StylesheetLoader,
 };
 use crate::node::{ImageData, NodeFlags, RasterImageData, SpecialElementData, Status, TextBrush};
+use crate::observer::{IntersectionObservation, ResizeObservation};
+use crate::paint_invalidation::PaintInvalidations;
+use crate::profiler::FrameProfiler;
+use crate::scroll_chain::OverscrollState;
+use crate::scrollbar::{ScrollbarOwner, ScrollbarState};
 use crate::selection::TextSelection;
 use crate::stylo_to_cursor_icon::stylo_to_cursor_icon;
 use crate::traversal::TreeTraverser;
@@ -17,13 +25,13 @@ use crate::{
     EventDriver, HtmlParserProvider, Node, NodeData, NoopEventHandler, TextNodeData,
 };
 use blitz_traits::devtools::DevtoolSettings;
-use blitz_traits::events::{BlitzScrollEvent, DomEvent, DomEventData, HitResult, UiEvent};
+use blitz_traits::events::{DomEvent, HitResult, UiEvent};
 use blitz_traits::navigation::{DummyNavigationProvider, NavigationProvider};
 use blitz_traits::net::{DummyNetProvider, NetProvider, Request};
 use blitz_traits::shell::{ColorScheme, DummyShellProvider, ShellProvider, Viewport};
 use cursor_icon::CursorIcon;
 use linebender_resource_handle::Blob;
-use markup5ever::local_name;
+use markup5ever::{LocalName, local_name};
 use parley::{FontContext, PlainEditorDriver};
 use selectors::{Element, matching::QuirksMode};
 use slab::Slab;
@@ -33,7 +41,7 @@ use std::collections::{BTreeMap, Bound, HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::sync::{Arc, Mutex, MutexGuard, RwLockReadGuard, RwLockWriteGuard};
 use std::task::Context as TaskContext;
@@ -46,10 +54,9 @@ use style::media_queries::MediaType;
 use style::properties::ComputedValues;
 use style::properties::style_structs::Font;
 use style::queries::values::PrefersColorScheme;
-use style::selector_parser::ServoElementSnapshot;
+use style::selector_parser::{RestyleDamage, ServoElementSnapshot};
 use style::servo_arc::Arc as ServoArc;
 use style::values::GenericAtomIdent;
-use style::values::computed::Overflow;
 use style::{
     device::Device,
     dom::{TDocument, TNode},
@@ -59,6 +66,7 @@ use style::{
     stylesheets::{AllowImportRules, DocumentStyleSheet, Origin, Stylesheet},
     stylist::Stylist,
 };
+use style_dom::ElementState;
 use url::Url;
 
 #[cfg(feature = "parallel-construct")]
@@ -129,6 +137,14 @@ pub trait Document: Any + 'static {
         driver.handle_ui_event(event);
     }
 
+    /// Dispatch events which were queued by the [`BaseDocument`] itself (such as the intersection
+    /// and resize observer notifications generated by [`BaseDocument::resolve`])
+    fn flush_pending_events(&mut self) {
+        let mut doc = self.inner_mut();
+        let mut driver = EventDriver::new(&mut *doc, NoopEventHandler);
+        driver.flush_pending_events();
+    }
+
     /// Poll any pending async operations, and flush changes to the underlying [`BaseDocument`]
     fn poll(&mut self, task_context: Option<TaskContext>) -> bool {
         // Default implementation does nothing
@@ -140,8 +156,19 @@ pub trait Document: Any + 'static {
     fn id(&self) -> usize {
         self.inner().id
     }
+
+    /// The underlying [`BaseDocument`], if it can be resolved and painted on another thread.
+    ///
+    /// Shells may use this to run `resolve` and painting off the main thread (see
+    /// [`SharedDocument`]).
+    fn shared_state(&self) -> Option<Arc<Mutex<BaseDocument>>> {
+        None
+    }
 }
 
+/// Creates a sub-document when it is first needed (see [`BaseDocument::set_sub_document_loader`])
+pub type SubDocumentLoader = Box<dyn FnOnce() -> Box<dyn Document> + Send>;
+
 pub struct PlainDocument(pub BaseDocument);
 impl Document for PlainDocument {
     fn inner(&self) -> DocGuard<'_> {
@@ -152,6 +179,42 @@ impl Document for PlainDocument {
     }
 }
 
+/// A [`BaseDocument`] which can be shared with other threads, so that it can be resolved and
+/// painted off the main thread while the main thread remains responsive to input.
+///
+/// The document is locked while it is being resolved and painted, so code on the main thread
+/// should avoid accessing it when a frame is in progress.
+#[derive(Clone)]
+pub struct SharedDocument {
+    id: usize,
+    doc: Arc<Mutex<BaseDocument>>,
+}
+
+impl SharedDocument {
+    pub fn new(doc: BaseDocument) -> Self {
+        Self {
+            id: doc.id,
+            doc: Arc::new(Mutex::new(doc)),
+        }
+    }
+}
+
+impl Document for SharedDocument {
+    fn inner(&self) -> DocGuard<'_> {
+        DocGuard::Mutex(self.doc.lock().unwrap())
+    }
+    fn inner_mut(&mut self) -> DocGuardMut<'_> {
+        DocGuardMut::Mutex(self.doc.lock().unwrap())
+    }
+    fn id(&self) -> usize {
+        // Avoid locking the document (which may be in use by another thread)
+        self.id
+    }
+    fn shared_state(&self) -> Option<Arc<Mutex<BaseDocument>>> {
+        Some(self.doc.clone())
+    }
+}
+
 impl Document for BaseDocument {
     fn inner(&self) -> DocGuard<'_> {
         DocGuard::Ref(self)
@@ -184,6 +247,16 @@ pub struct BaseDocument {
     pub(crate) url: DocumentUrl,
     // Devtool settings. Currently used to render debug overlays
     pub(crate) devtool_settings: DevtoolSettings,
+    /// Remote inspector server (if one has been started)
+    #[cfg(feature = "devtools_server")]
+    pub(crate) devtools_server: Option<crate::devtools_server::DevtoolsServer>,
+    /// Per-phase timings of recent frames (disabled by default)
+    pub(crate) profiler: FrameProfiler,
+    /// Mismatches between incremental and full layout found in the last frame (`None` when
+    /// verification is disabled)
+    pub(crate) layout_verification: Option<Vec<LayoutMismatch>>,
+    /// Nodes which need repainting, for painters which retain drawing commands between frames
+    pub(crate) paint_invalidations: PaintInvalidations,
     // Viewport details such as the dimensions, HiDPI scale, and zoom factor,
     pub(crate) viewport: Viewport,
     // Scroll within our viewport
@@ -226,6 +299,10 @@ pub struct BaseDocument {
     pub(crate) hover_node_is_text: bool,
     /// The node which is currently focussed (if any)
     pub(crate) focus_node_id: Option<usize>,
+    /// Nodes which sequential focus navigation is restricted to (the last one is active)
+    pub(crate) focus_traps: Vec<usize>,
+    /// Whether the most recent user input was from the keyboard (see `:focus-visible`)
+    pub(crate) keyboard_modality: bool,
     /// The node which is currently active (if any)
     pub(crate) active_node_id: Option<usize>,
     /// The node which recieved a mousedown event (if any)
@@ -240,13 +317,24 @@ pub struct BaseDocument {
     pub(crate) drag_mode: DragMode,
     /// Whether and what kind of scroll animation is currently in progress
     pub(crate) scroll_animation: ScrollAnimationState,
+    /// Rubber-band overscroll of scroll containers (from touch input)
+    pub(crate) overscroll: OverscrollState,
+    /// Scroll containers which have moved since `scrollend` was last dispatched to them
+    pub(crate) scrolled_containers: HashSet<ScrollbarOwner>,
+    /// How scrollbars are presented, and when they were last active
+    pub(crate) scrollbars: ScrollbarState,
 
     /// Text selection state (for non-input text)
     pub(crate) text_selection: TextSelection,
+    /// The text input which the open context menu's editing commands apply to (if any)
+    pub(crate) context_menu_editable: Option<usize>,
 
     // TODO: collapse animating state into a bitflags
     /// Whether there are active CSS animations/transitions (so we should re-render every frame)
     pub(crate) has_active_animations: bool,
+    /// Whether the content of `::before` and `::after` pseudo-elements may have changed since it
+    /// was last resolved (see `resolve_generated_content`)
+    pub(crate) generated_content_dirty: bool,
     /// Whether there is a `<canvas>` element in the DOM (so we should re-render every frame)
     pub(crate) has_canvas: bool,
     /// Whether there are subdocuments that are animating (so we should re-render every frame)
@@ -276,6 +364,35 @@ pub struct BaseDocument {
     /// requests for the same URL are queued here instead of starting new fetches.
     /// Value is a list of (node_id, image_type) pairs waiting for the image.
     pub(crate) pending_images: HashMap<String, Vec<(usize, ImageType)>>,
+    /// Whether each in-flight image request is decoded synchronously. Shared with the request's
+    /// `ImageHandler`, and set if any element waiting for the image has `decoding="sync"`.
+    pub(crate) pending_image_sync_decoding: HashMap<String, Arc<AtomicBool>>,
+    /// Whether all images are decoded synchronously (see [`DocumentConfig::sync_image_decoding`])
+    pub(crate) sync_image_decoding: bool,
+
+    /// Elements whose loading is deferred until they are near the viewport. Either because they
+    /// have `loading="lazy"`, or because their image was evicted from `image_cache`.
+    pub(crate) lazy_load_nodes: HashSet<usize>,
+    /// Loaders for the sub-documents of `loading="lazy"` elements, which are called once the
+    /// element is near the viewport
+    pub(crate) pending_sub_documents: HashMap<usize, SubDocumentLoader>,
+    /// `<img>` elements which have a selected image source
+    pub(crate) image_nodes: HashSet<usize>,
+    /// `<img>` elements whose source is selected based on the viewport (see
+    /// [`BaseDocument::reselect_image_sources`])
+    pub(crate) responsive_image_nodes: HashSet<usize>,
+    /// Elements which have at least one background image
+    pub(crate) background_image_nodes: HashSet<usize>,
+    /// Memory limits and the state used to enforce them
+    pub(crate) memory: MemoryState,
+
+    /// Nodes whose intersection with the viewport (or a scroll container) is being observed
+    pub(crate) intersection_observations: HashMap<usize, IntersectionObservation>,
+    /// Nodes whose size is being observed
+    pub(crate) resize_observations: HashMap<usize, ResizeObservation>,
+    /// Events generated by the document itself (e.g. observer notifications) which are waiting to
+    /// be dispatched by [`Document::flush_pending_events`]
+    pub(crate) pending_events: Vec<DomEvent>,
 
     // Tracks in-flight "critical" resources (e.g. stylesheets linked from the `<head>`)
     pub(crate) pending_critical_resources: HashSet<usize>,
@@ -292,6 +409,11 @@ pub struct BaseDocument {
     pub html_parser_provider: Arc<dyn HtmlParserProvider>,
 }
 
+// SAFETY: The only field which isn't `Send` is the stylist's `Device`, which boxes its font
+// metrics provider without a `Send` bound. Documents always use a `BlitzFontMetricsProvider`
+// (see `make_device`), which is `Send`.
+unsafe impl Send for BaseDocument {}
+
 pub(crate) fn make_device(
     viewport: &Viewport,
     media_type: MediaType,
@@ -395,6 +517,11 @@ impl BaseDocument {
             viewport,
             media_type,
             devtool_settings: DevtoolSettings::default(),
+            #[cfg(feature = "devtools_server")]
+            devtools_server: None,
+            profiler: FrameProfiler::default(),
+            layout_verification: None,
+            paint_invalidations: PaintInvalidations::default(),
             viewport_scroll: crate::Point::ZERO,
             url: base_url,
             ua_stylesheets: HashMap::new(),
@@ -407,9 +534,12 @@ impl BaseDocument {
             hover_node_id: None,
             hover_node_is_text: false,
             focus_node_id: None,
+            focus_traps: Vec::new(),
+            keyboard_modality: false,
             active_node_id: None,
             mousedown_node_id: None,
             has_active_animations: false,
+            generated_content_dirty: true,
             subdoc_is_animating: false,
             has_canvas: false,
             sub_document_nodes: HashSet::new(),
@@ -417,6 +547,20 @@ impl BaseDocument {
             deferred_construction_nodes: Vec::new(),
             image_cache: HashMap::new(),
             pending_images: HashMap::new(),
+            pending_image_sync_decoding: HashMap::new(),
+            sync_image_decoding: config.sync_image_decoding,
+            lazy_load_nodes: HashSet::new(),
+            pending_sub_documents: HashMap::new(),
+            image_nodes: HashSet::new(),
+            responsive_image_nodes: HashSet::new(),
+            background_image_nodes: HashSet::new(),
+            memory: MemoryState {
+                limits: config.memory_limits.unwrap_or_default(),
+                ..Default::default()
+            },
+            intersection_observations: HashMap::new(),
+            resize_observations: HashMap::new(),
+            pending_events: Vec::new(),
             pending_critical_resources: HashSet::new(),
             controls_to_form: HashMap::new(),
             net_provider,
@@ -428,7 +572,11 @@ impl BaseDocument {
             click_count: 0,
             drag_mode: DragMode::None,
             scroll_animation: ScrollAnimationState::None,
+            overscroll: OverscrollState::default(),
+            scrolled_containers: HashSet::new(),
+            scrollbars: ScrollbarState::default(),
             text_selection: TextSelection::default(),
+            context_menu_editable: None,
         };
 
         // Initialise document with root Document node
@@ -473,6 +621,10 @@ impl BaseDocument {
 
     /// Set the Document's shell provider
     pub fn set_shell_provider(&mut self, shell_provider: Arc<dyn ShellProvider>) {
+        #[cfg(feature = "devtools_server")]
+        if let Some(server) = &self.devtools_server {
+            server.set_shell_provider(shell_provider.clone());
+        }
         self.shell_provider = shell_provider;
     }
 
@@ -615,12 +767,50 @@ impl BaseDocument {
         }
     }
 
+    pub fn style_property_value(&self, node_id: usize, name: &str) -> Option<String> {
+        self.nodes[node_id]
+            .element_data()?
+            .style_property_value(name, &self.guard)
+    }
+
     pub fn set_sub_document(&mut self, node_id: usize, sub_document: Box<dyn Document>) {
         self.nodes[node_id]
             .element_data_mut()
             .unwrap()
             .set_sub_document(sub_document);
         self.sub_document_nodes.insert(node_id);
+        if self.is_lazy_loading(node_id) {
+            self.lazy_load_nodes.insert(node_id);
+        }
+    }
+
+    /// Set the sub-document of a node using a loader which creates it. If the node has
+    /// `loading="lazy"`, the loader is not called (so the sub-document's resources are not
+    /// fetched) until layout has placed the node near the viewport.
+    pub fn set_sub_document_loader(&mut self, node_id: usize, loader: SubDocumentLoader) {
+        self.remove_sub_document(node_id);
+        if self.is_lazy_loading(node_id) {
+            self.pending_sub_documents.insert(node_id, loader);
+            self.lazy_load_nodes.insert(node_id);
+        } else {
+            self.load_sub_document(node_id, loader);
+        }
+    }
+
+    /// Create a node's sub-document from its loader
+    pub(crate) fn load_sub_document(&mut self, node_id: usize, loader: SubDocumentLoader) {
+        let mut sub_document = loader();
+        sub_document
+            .inner_mut()
+            .set_shell_provider(self.shell_provider.clone());
+        let node = &mut self.nodes[node_id];
+        node.element_data_mut()
+            .unwrap()
+            .set_sub_document(sub_document);
+        node.insert_damage(ALL_DAMAGE);
+        self.sub_document_nodes.insert(node_id);
+        self.lazy_load_nodes.remove(&node_id);
+        self.shell_provider.request_redraw();
     }
 
     pub fn remove_sub_document(&mut self, node_id: usize) {
@@ -629,6 +819,21 @@ impl BaseDocument {
             .unwrap()
             .remove_sub_document();
         self.sub_document_nodes.remove(&node_id);
+        self.pending_sub_documents.remove(&node_id);
+        self.lazy_load_nodes.remove(&node_id);
+    }
+
+    /// Remove a node's sub-document without dropping it (so that it can be displayed again later)
+    pub fn take_sub_document(&mut self, node_id: usize) -> Option<Box<dyn Document>> {
+        let sub_document = self
+            .nodes
+            .get_mut(node_id)?
+            .element_data_mut()?
+            .take_sub_document();
+        self.sub_document_nodes.remove(&node_id);
+        self.pending_sub_documents.remove(&node_id);
+        self.lazy_load_nodes.remove(&node_id);
+        sub_document
     }
 
     pub fn root_node(&self) -> &Node {
@@ -666,6 +871,16 @@ impl BaseDocument {
 
     pub(crate) fn drop_node_ignoring_parent(&mut self, node_id: usize) -> Option<Node> {
         let mut node = self.nodes.try_remove(node_id);
+        self.lazy_load_nodes.remove(&node_id);
+        self.pending_sub_documents.remove(&node_id);
+        self.image_nodes.remove(&node_id);
+        self.responsive_image_nodes.remove(&node_id);
+        self.background_image_nodes.remove(&node_id);
+        self.remove_observations(node_id);
+        self.focus_traps.retain(|&id| id != node_id);
+        self.overscroll.remove(ScrollbarOwner::Node(node_id));
+        self.scrolled_containers
+            .remove(&ScrollbarOwner::Node(node_id));
         if let Some(node) = &mut node {
             if let Some(before) = node.before {
                 self.drop_node_ignoring_parent(before);
@@ -745,6 +960,72 @@ impl BaseDocument {
         })
     }
 
+    /// Load an image from the specified url into a node's `special_data`
+    pub(crate) fn load_image(&mut self, node_id: usize, src: Url) {
+        let src_string = src.as_str();
+
+        // Check cache first
+        if let Some(cached_image) = self.image_cache.get(src_string) {
+            #[cfg(feature = "tracing")]
+            tracing::info!("Loading image {src_string} from cache");
+            let node = &mut self.nodes[node_id];
+            node.element_data_mut().unwrap().special_data =
+                SpecialElementData::Image(Box::new(cached_image.clone()));
+            node.cache.clear();
+            node.insert_damage(ALL_DAMAGE);
+            return;
+        }
+
+        let sync_decoding = self.nodes[node_id]
+            .attr(LocalName::from("decoding"))
+            .is_some_and(|decoding| decoding.eq_ignore_ascii_case("sync"));
+
+        // Check if there's already a pending request for this URL
+        if let Some(waiting_list) = self.pending_images.get_mut(src_string) {
+            #[cfg(feature = "tracing")]
+            tracing::info!("Image {src_string} already pending, queueing node {node_id}");
+            waiting_list.push((node_id, ImageType::Image));
+            if sync_decoding {
+                self.image_sync_decoding(src_string)
+                    .store(true, Ordering::Relaxed);
+            }
+            return;
+        }
+
+        // Start fetch and track as pending
+        #[cfg(feature = "tracing")]
+        tracing::info!("Fetching image {src_string}");
+        self.pending_images
+            .insert(src_string.to_string(), vec![(node_id, ImageType::Image)]);
+
+        let image_sync_decoding = self.image_sync_decoding(src_string);
+        if sync_decoding {
+            image_sync_decoding.store(true, Ordering::Relaxed);
+        }
+
+        self.net_provider.fetch(
+            self.id(),
+            Request::get(src),
+            ResourceHandler::boxed(
+                self.tx.clone(),
+                self.id(),
+                None, // Don't pass node_id, we'll handle it via pending_images
+                self.shell_provider.clone(),
+                ImageHandler::new(ImageType::Image).with_sync_decoding(image_sync_decoding),
+            ),
+        );
+    }
+
+    /// The flag which decides whether the in-flight request for the image at `url` is decoded
+    /// synchronously
+    pub(crate) fn image_sync_decoding(&mut self, url: &str) -> Arc<AtomicBool> {
+        let sync_image_decoding = self.sync_image_decoding;
+        self.pending_image_sync_decoding
+            .entry(url.to_string())
+            .or_insert_with(|| Arc::new(AtomicBool::new(sync_image_decoding)))
+            .clone()
+    }
+
     pub fn print_tree(&self) {
         crate::util::walk_tree(0, self.root_node());
     }
@@ -920,6 +1201,7 @@ impl BaseDocument {
 
                 // Get all nodes waiting for this image
                 let waiting_nodes = self.pending_images.remove(url).unwrap_or_default();
+                self.pending_image_sync_decoding.remove(url);
 
                 #[cfg(feature = "tracing")]
                 tracing::info!(
@@ -953,6 +1235,7 @@ impl BaseDocument {
                                 bg_image.status = Status::Ok;
                                 bg_image.image = image.clone();
                             }
+                            node.insert_damage(RestyleDamage::REPAINT);
                         }
                     }
                 }
@@ -968,6 +1251,7 @@ impl BaseDocument {
 
                 // Get all nodes waiting for this image
                 let waiting_nodes = self.pending_images.remove(url).unwrap_or_default();
+                self.pending_image_sync_decoding.remove(url);
 
                 #[cfg(feature = "tracing")]
                 tracing::info!(
@@ -1001,11 +1285,13 @@ impl BaseDocument {
                                 bg_image.status = Status::Ok;
                                 bg_image.image = image.clone();
                             }
+                            node.insert_damage(RestyleDamage::REPAINT);
                         }
                     }
                 }
             }
             Resource::Font(bytes) => {
+                self.memory.web_font_bytes += bytes.len();
                 let font = Blob::new(Arc::new(bytes));
 
                 // TODO: Implement FontInfoOveride
@@ -1036,63 +1322,94 @@ impl BaseDocument {
         }
     }
 
+    /// Record the current state and attributes of an element, so that Stylo can work out which
+    /// elements need restyling when its state changes (by comparing against the snapshot).
+    ///
+    /// Attribute changes should use [`BaseDocument::snapshot_attribute`] instead, which also
+    /// records which attribute is changing.
     pub fn snapshot_node(&mut self, node_id: usize) {
+        self.snapshot_node_state(node_id);
+
+        // Attributes are recorded by the first snapshot which needs them. They can't have changed
+        // since an earlier state-only snapshot, as attribute changes are always snapshotted.
+        let node = &self.nodes[node_id];
+        let Some(snapshot) = self.snapshots.get_mut(&TNode::opaque(&node)) else {
+            return;
+        };
+        if snapshot.attrs.is_some() {
+            return;
+        }
+        snapshot.attrs = node.attrs().map(|attrs| {
+            attrs
+                .iter()
+                .map(|attr| {
+                    let ident = AttrIdentifier {
+                        local_name: GenericAtomIdent(attr.name.local.clone()),
+                        name: GenericAtomIdent(attr.name.local.clone()),
+                        namespace: GenericAtomIdent(attr.name.ns.clone()),
+                        prefix: None,
+                    };
+
+                    let value = if attr.name.local == local_name!("id") {
+                        AttrValue::Atom(Atom::from(&*attr.value))
+                    } else if attr.name.local == local_name!("class") {
+                        let classes = attr
+                            .value
+                            .split_ascii_whitespace()
+                            .map(Atom::from)
+                            .collect();
+                        AttrValue::TokenList(attr.value.clone(), classes)
+                    } else {
+                        AttrValue::String(attr.value.clone())
+                    };
+
+                    (ident, value)
+                })
+                .collect()
+        });
+    }
+
+    /// Record the current state of an element without its attributes. This is cheaper than
+    /// [`BaseDocument::snapshot_node`] for state changes which affect many elements at once (such
+    /// as `:focus-within` on each ancestor of the focussed element).
+    pub(crate) fn snapshot_node_state(&mut self, node_id: usize) {
         let node = &mut self.nodes[node_id];
         let opaque_node_id = TNode::opaque(&&*node);
         node.has_snapshot = true;
         node.snapshot_handled
             .store(false, std::sync::atomic::Ordering::SeqCst);
 
-        // TODO: handle invalidations other than hover
-        if let Some(_existing_snapshot) = self.snapshots.get_mut(&opaque_node_id) {
-            // Do nothing
-            // TODO: update snapshot
-        } else {
-            let attrs: Option<Vec<_>> = node.attrs().map(|attrs| {
-                attrs
-                    .iter()
-                    .map(|attr| {
-                        let ident = AttrIdentifier {
-                            local_name: GenericAtomIdent(attr.name.local.clone()),
-                            name: GenericAtomIdent(attr.name.local.clone()),
-                            namespace: GenericAtomIdent(attr.name.ns.clone()),
-                            prefix: None,
-                        };
-
-                        let value = if attr.name.local == local_name!("id") {
-                            AttrValue::Atom(Atom::from(&*attr.value))
-                        } else if attr.name.local == local_name!("class") {
-                            let classes = attr
-                                .value
-                                .split_ascii_whitespace()
-                                .map(Atom::from)
-                                .collect();
-                            AttrValue::TokenList(attr.value.clone(), classes)
-                        } else {
-                            AttrValue::String(attr.value.clone())
-                        };
-
-                        (ident, value)
-                    })
-                    .collect()
+        // An existing snapshot already holds the state from before the first change
+        self.snapshots
+            .entry(opaque_node_id)
+            .or_insert_with(|| ServoElementSnapshot {
+                state: Some(node.element_state),
+                attrs: None,
+                changed_attrs: Vec::new(),
+                class_changed: false,
+                id_changed: false,
+                other_attributes_changed: false,
             });
+    }
+
+    /// Snapshot an element before changing its `name` attribute (see [`BaseDocument::snapshot_node`])
+    pub fn snapshot_attribute(&mut self, node_id: usize, name: &LocalName) {
+        self.snapshot_node(node_id);
 
-            let changed_attrs = attrs
-                .as_ref()
-                .map(|attrs| attrs.iter().map(|attr| attr.0.name.clone()).collect())
-                .unwrap_or_default();
-
-            self.snapshots.insert(
-                opaque_node_id,
-                ServoElementSnapshot {
-                    state: Some(node.element_state),
-                    attrs,
-                    changed_attrs,
-                    class_changed: true,
-                    id_changed: true,
-                    other_attributes_changed: true,
-                },
-            );
+        let opaque_node_id = TNode::opaque(&&self.nodes[node_id]);
+        let Some(snapshot) = self.snapshots.get_mut(&opaque_node_id) else {
+            return;
+        };
+        if *name == local_name!("class") {
+            snapshot.class_changed = true;
+        } else if *name == local_name!("id") {
+            snapshot.id_changed = true;
+        } else {
+            snapshot.other_attributes_changed = true;
+        }
+        let name = GenericAtomIdent(name.clone());
+        if !snapshot.changed_attrs.contains(&name) {
+            snapshot.changed_attrs.push(name);
         }
     }
 
@@ -1103,23 +1420,13 @@ impl BaseDocument {
 
     // Takes (x, y) co-ordinates (relative to the )
     pub fn hit(&self, x: f32, y: f32) -> Option<HitResult> {
-        if TDocument::as_node(&&self.nodes[0])
-            .first_element_child()
-            .is_none()
-        {
+        let Some(root) = self.try_root_element() else {
             #[cfg(feature = "tracing")]
             tracing::warn!("No DOM - not resolving hit test");
             return None;
-        }
-
-        self.root_element().hit(x, y)
-    }
+        };
 
-    pub fn focus_next_node(&mut self) -> Option<usize> {
-        let focussed_node_id = self.get_focussed_node_id()?;
-        let id = self.next_node(&self.nodes[focussed_node_id], |node| node.is_focussable())?;
-        self.set_focus_to(id);
-        Some(id)
+        root.hit(x, y)
     }
 
     /// Clear the focussed node
@@ -1127,6 +1434,8 @@ impl BaseDocument {
         if let Some(id) = self.focus_node_id {
             let shell_provider = self.shell_provider.clone();
             self.snapshot_node_and(id, |node| node.blur(shell_provider));
+            self.invalidate_paint(id);
+            self.update_focus_within(Some(id), None);
             self.focus_node_id = None;
         }
     }
@@ -1147,10 +1456,19 @@ impl BaseDocument {
         // Remove focus from the old node
         if let Some(id) = self.focus_node_id {
             self.snapshot_node_and(id, |node| node.blur(shell_provider.clone()));
+            // Repaint to remove the caret from text inputs
+            self.invalidate_paint(id);
         }
 
         // Focus the new node
-        self.snapshot_node_and(focus_node_id, |node| node.focus(shell_provider));
+        let focus_visible = self.focus_should_be_visible(focus_node_id);
+        self.snapshot_node_and(focus_node_id, |node| {
+            node.focus(shell_provider);
+            node.element_state
+                .set(ElementState::FOCUSRING, focus_visible);
+        });
+        self.invalidate_paint(focus_node_id);
+        self.update_focus_within(self.focus_node_id, Some(focus_node_id));
 
         self.focus_node_id = Some(focus_node_id);
 
@@ -1260,6 +1578,7 @@ impl BaseDocument {
 
     pub fn set_viewport(&mut self, viewport: Viewport) {
         let scale_has_changed = viewport.scale_f64() != self.viewport.scale_f64();
+        let size_has_changed = viewport.window_size != self.viewport.window_size;
         self.viewport = viewport;
         self.set_stylist_device(make_device(
             &self.viewport,
@@ -1268,6 +1587,12 @@ impl BaseDocument {
         ));
         self.scroll_viewport_by(0.0, 0.0); // Clamp scroll offset
 
+        // The best image source depends on the device pixel ratio and (through `sizes`
+        // and `media`) on the viewport size
+        if scale_has_changed || size_has_changed {
+            self.reselect_image_sources();
+        }
+
         if scale_has_changed {
             self.invalidate_inline_contexts();
             self.shell_provider.request_redraw();
@@ -1328,6 +1653,9 @@ impl BaseDocument {
             | self.has_active_animations
             | self.subdoc_is_animating
             | (self.scroll_animation != ScrollAnimationState::None)
+            | self.overscroll_is_animating()
+            | !self.scrolled_containers.is_empty()
+            | self.scrollbars_are_animating()
     }
 
     /// Update the device and reset the stylist to process the new size
@@ -1389,119 +1717,17 @@ impl BaseDocument {
         Some(CursorIcon::Default)
     }
 
-    pub fn scroll_node_by<F: FnMut(DomEvent)>(
-        &mut self,
-        node_id: usize,
-        x: f64,
-        y: f64,
-        dispatch_event: F,
-    ) {
-        self.scroll_node_by_has_changed(node_id, x, y, dispatch_event);
+    pub fn scroll_node_by(&mut self, node_id: usize, x: f64, y: f64) {
+        self.scroll_node_by_has_changed(node_id, x, y);
     }
 
     /// Scroll a node by given x and y
-    /// Will bubble scrolling up to parent node once it can no longer scroll further
-    /// If we're already at the root node, bubbles scrolling up to the viewport
-    pub fn scroll_node_by_has_changed<F: FnMut(DomEvent)>(
-        &mut self,
-        node_id: usize,
-        x: f64,
-        y: f64,
-        mut dispatch_event: F,
-    ) -> bool {
-        let Some(node) = self.nodes.get_mut(node_id) else {
-            return false;
-        };
-
-        let is_html_or_body = node.data.downcast_element().is_some_and(|e| {
-            let tag = &e.name.local;
-            tag == "html" || tag == "body"
-        });
-
-        let (can_x_scroll, can_y_scroll) = node
-            .primary_styles()
-            .map(|styles| {
-                (
-                    matches!(styles.clone_overflow_x(), Overflow::Scroll | Overflow::Auto),
-                    matches!(styles.clone_overflow_y(), Overflow::Scroll | Overflow::Auto)
-                        || (styles.clone_overflow_y() == Overflow::Visible && is_html_or_body),
-                )
-            })
-            .unwrap_or((false, false));
-
-        let initial = node.scroll_offset;
-        let new_x = node.scroll_offset.x - x;
-        let new_y = node.scroll_offset.y - y;
-
-        let mut bubble_x = 0.0;
-        let mut bubble_y = 0.0;
-
-        let scroll_width = node.final_layout.scroll_width() as f64;
-        let scroll_height = node.final_layout.scroll_height() as f64;
-
-        // Handle sub document case
-        if let Some(mut sub_doc) = node.subdoc_mut().map(|doc| doc.inner_mut()) {
-            let has_changed = if let Some(hover_node_id) = sub_doc.get_hover_node_id() {
-                sub_doc.scroll_node_by_has_changed(hover_node_id, x, y, dispatch_event)
-            } else {
-                sub_doc.scroll_viewport_by_has_changed(x, y)
-            };
-
-            // TODO: propagate remaining scroll to parent
-            return has_changed;
-        }
-
-        // If we're past our scroll bounds, transfer remainder of scrolling to parent/viewport
-        if !can_x_scroll {
-            bubble_x = x
-        } else if new_x < 0.0 {
-            bubble_x = -new_x;
-            node.scroll_offset.x = 0.0;
-        } else if new_x > scroll_width {
-            bubble_x = scroll_width - new_x;
-            node.scroll_offset.x = scroll_width;
-        } else {
-            node.scroll_offset.x = new_x;
-        }
-
-        if !can_y_scroll {
-            bubble_y = y
-        } else if new_y < 0.0 {
-            bubble_y = -new_y;
-            node.scroll_offset.y = 0.0;
-        } else if new_y > scroll_height {
-            bubble_y = scroll_height - new_y;
-            node.scroll_offset.y = scroll_height;
-        } else {
-            node.scroll_offset.y = new_y;
-        }
-
-        let has_changed = node.scroll_offset != initial;
-
-        if has_changed {
-            let layout = node.final_layout;
-            let event = BlitzScrollEvent {
-                scroll_top: node.scroll_offset.y,
-                scroll_left: node.scroll_offset.x,
-                scroll_width: layout.scroll_width() as i32,
-                scroll_height: layout.scroll_height() as i32,
-                client_width: layout.size.width as i32,
-                client_height: layout.size.height as i32,
-            };
-
-            dispatch_event(DomEvent::new(node_id, DomEventData::Scroll(event)));
-        }
-
-        if bubble_x != 0.0 || bubble_y != 0.0 {
-            if let Some(parent) = node.parent {
-                return self.scroll_node_by_has_changed(parent, bubble_x, bubble_y, dispatch_event)
-                    | has_changed;
-            } else {
-                return self.scroll_viewport_by_has_changed(bubble_x, bubble_y) | has_changed;
-            }
-        }
-
-        has_changed
+    /// Will chain scrolling up to the parent node once it can no longer scroll further (unless
+    /// prevented by its `overscroll-behavior`). If we're already at the root node, chains scrolling
+    /// up to the viewport. `scroll` events are queued for dispatch with the document's other
+    /// pending events.
+    pub fn scroll_node_by_has_changed(&mut self, node_id: usize, x: f64, y: f64) -> bool {
+        self.scroll_node_chain(node_id, x, y).has_changed
     }
 
     pub fn scroll_viewport_by(&mut self, x: f64, y: f64) {
@@ -1510,7 +1736,10 @@ impl BaseDocument {
 
     /// Scroll the viewport by the given values
     pub fn scroll_viewport_by_has_changed(&mut self, x: f64, y: f64) -> bool {
-        let content_size = self.root_element().final_layout.size;
+        let Some(root) = self.try_root_element() else {
+            return false;
+        };
+        let content_size = root.final_layout.size;
         let new_scroll = (self.viewport_scroll.x - x, self.viewport_scroll.y - y);
         let window_width = self.viewport.window_size.0 as f64 / self.viewport.scale() as f64;
         let window_height = self.viewport.window_size.1 as f64 / self.viewport.scale() as f64;
@@ -1525,7 +1754,12 @@ impl BaseDocument {
             f64::min(new_scroll.1, content_size.height as f64 - window_height),
         );
 
-        self.viewport_scroll != initial
+        let has_changed = self.viewport_scroll != initial;
+        if has_changed {
+            self.record_scroll_activity();
+            self.queue_scroll_event(ScrollbarOwner::Viewport);
+        }
+        has_changed
     }
 
     pub fn scroll_by(
@@ -1533,10 +1767,9 @@ impl BaseDocument {
         anchor_node_id: Option<usize>,
         scroll_x: f64,
         scroll_y: f64,
-        dispatch_event: &mut dyn FnMut(DomEvent),
     ) -> bool {
         if let Some(anchor_node_id) = anchor_node_id {
-            self.scroll_node_by_has_changed(anchor_node_id, scroll_x, scroll_y, dispatch_event)
+            self.scroll_node_by_has_changed(anchor_node_id, scroll_x, scroll_y)
         } else {
             self.scroll_viewport_by_has_changed(scroll_x, scroll_y)
         }
@@ -1687,6 +1920,38 @@ impl BaseDocument {
         self.text_selection.clear();
     }
 
+    /// Select all of the text in the document (excluding text inputs)
+    pub fn select_all_text(&mut self) {
+        let (first, last) = {
+            let mut inline_roots = TreeTraverser::new(self).flat_map(|node_id| {
+                let node = &self.nodes[node_id];
+                let anonymous_children: Vec<usize> = node
+                    .layout_children
+                    .borrow()
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .filter(|child_id| self.nodes[*child_id].is_anonymous())
+                    .collect();
+                std::iter::once(node_id)
+                    .chain(anonymous_children)
+                    .filter(|id| self.nodes[*id].flags.is_inline_root())
+            });
+            let first = inline_roots.next();
+            (first, inline_roots.last().or(first))
+        };
+        let (Some(first), Some(last)) = (first, last) else {
+            self.clear_text_selection();
+            return;
+        };
+        let last_len = self.nodes[last]
+            .element_data()
+            .and_then(|el| el.inline_layout_data.as_ref())
+            .map(|layout| layout.text.len())
+            .unwrap_or(0);
+        self.set_text_selection(first, 0, last, last_len);
+    }
+
     /// Update the selection focus point (used during mouse drag to extend selection).
     pub fn update_selection_focus(&mut self, focus_node: usize, focus_offset: usize) {
         // For anonymous blocks, store parent+sibling_index; otherwise store node directly
@@ -0,0 +1,378 @@
+//! HTML drag-and-drop (see <https://html.spec.whatwg.org/multipage/dnd.html>)
+//!
+//! Drags either start within the document (from a `draggable` element, a link, an image or the
+//! selected text) or come from another application (files dropped onto the window). Either way,
+//! the pointer's movement is turned into `dragenter`/`dragover`/`dragleave` events on the element
+//! beneath it, and releasing it dispatches `drop` if the last `dragover` accepted the drop.
+
+use blitz_traits::events::{
+    BlitzDragEvent, BlitzInputEvent, DataTransfer, DomEvent, DomEventData, DropEffect,
+    MouseEventButtons, PointerCoords, UiEvent,
+};
+use keyboard_types::Modifiers;
+use markup5ever::local_name;
+
+use crate::BaseDocument;
+
+use super::focus::generate_focus_events;
+use super::pointer::DragMode;
+
+/// Something which can be dragged out of the document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DragSource {
+    /// The selected text, which was pressed within the given inline root
+    Selection(usize),
+    /// A draggable element (including links and images, which are draggable by default)
+    Element(usize),
+}
+
+impl DragSource {
+    fn node_id(&self) -> usize {
+        match self {
+            Self::Selection(node_id) | Self::Element(node_id) => *node_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DragAndDropState {
+    /// The node being dragged. `None` if the data was dragged from another application.
+    pub(crate) source: Option<usize>,
+    pub(crate) data_transfer: DataTransfer,
+    /// The node which the pointer is currently over (the target of `dragover` and `drop`)
+    pub(crate) target: Option<usize>,
+}
+
+/// An element which accepts a drop by default (without script cancelling `dragover`)
+enum DropTarget {
+    TextInput(usize),
+    #[cfg(feature = "file_input")]
+    FileInput(usize),
+}
+
+/// The thing that pressing the pointer at (`x`, `y`) would start dragging (if anything)
+pub(crate) fn drag_source_at(
+    doc: &BaseDocument,
+    node_id: usize,
+    x: f32,
+    y: f32,
+) -> Option<DragSource> {
+    if doc.has_text_selection() {
+        if let Some((inline_root_id, offset)) = doc.find_text_position(x, y) {
+            let is_selected = doc
+                .get_text_selection_ranges()
+                .iter()
+                .any(|&(id, start, end)| id == inline_root_id && (start..end).contains(&offset));
+            if is_selected {
+                return Some(DragSource::Selection(inline_root_id));
+            }
+        }
+    }
+
+    for id in doc.node_chain(node_id) {
+        let Some(el) = doc.nodes[id].element_data() else {
+            continue;
+        };
+        match el.attr(local_name!("draggable")) {
+            Some("true") => return Some(DragSource::Element(id)),
+            Some("false") => return None,
+            _ => {}
+        }
+        if el.text_input_data().is_some() {
+            return None;
+        }
+        let is_link = el.name.local == local_name!("a") && el.has_attr(local_name!("href"));
+        let is_image = el.name.local == local_name!("img");
+        if is_link || is_image {
+            return Some(DragSource::Element(id));
+        }
+    }
+
+    None
+}
+
+/// Begin dragging `source`, dispatching `dragstart`. The drag only proceeds if that event isn't
+/// cancelled (see [`handle_dragstart`]).
+pub(crate) fn start_drag(
+    doc: &mut BaseDocument,
+    source: DragSource,
+    coords: PointerCoords,
+    buttons: MouseEventButtons,
+    mods: Modifiers,
+    dispatch_event: &mut dyn FnMut(DomEvent),
+) {
+    let data_transfer = DataTransfer::new();
+    match source {
+        DragSource::Selection(_) => {
+            if let Some(text) = doc.get_selected_text() {
+                data_transfer.set_data("text/plain", &text);
+            }
+        }
+        DragSource::Element(node_id) => {
+            let el = doc.nodes[node_id].element_data().unwrap();
+            let url = match el.name.local {
+                local_name!("a") => el
+                    .attr(local_name!("href"))
+                    .and_then(|href| doc.url.resolve_relative(href)),
+                local_name!("img") => doc.select_image_source(node_id).map(|source| source.url),
+                _ => None,
+            };
+            if let Some(url) = url {
+                data_transfer.set_data("text/uri-list", url.as_str());
+                data_transfer.set_data("text/plain", url.as_str());
+            }
+        }
+    }
+
+    doc.drag_mode = DragMode::DragStarting;
+    dispatch_event(DomEvent::new(
+        source.node_id(),
+        DomEventData::DragStart(BlitzDragEvent {
+            coords,
+            buttons,
+            mods,
+            data_transfer,
+        }),
+    ));
+}
+
+/// Default action of `dragstart`: the drag was not cancelled, so start it
+pub(crate) fn handle_dragstart(doc: &mut BaseDocument, target: usize, event: &BlitzDragEvent) {
+    if doc.drag_mode == DragMode::DragStarting {
+        doc.drag_mode = DragMode::DragAndDrop(DragAndDropState {
+            source: Some(target),
+            data_transfer: event.data_transfer.clone(),
+            target: None,
+        });
+    }
+}
+
+/// Move the drag to (`coords`), dispatching `drag` to the source, `dragenter`/`dragleave` if the
+/// element under the pointer changed, and `dragover` to the element under the pointer.
+pub(crate) fn update_drag(
+    doc: &mut BaseDocument,
+    coords: PointerCoords,
+    buttons: MouseEventButtons,
+    mods: Modifiers,
+    dispatch_event: &mut dyn FnMut(DomEvent),
+) {
+    let new_target = doc
+        .hit(coords.page_x, coords.page_y)
+        .map(|hit| hit.node_id)
+        .unwrap_or_else(|| doc.root_element().id);
+    let DragMode::DragAndDrop(state) = &mut doc.drag_mode else {
+        return;
+    };
+    let old_target = state.target.replace(new_target);
+    let source = state.source;
+    let data_transfer = state.data_transfer.clone();
+    let event = |data_transfer: &DataTransfer| BlitzDragEvent {
+        coords,
+        buttons,
+        mods,
+        data_transfer: data_transfer.clone(),
+    };
+
+    if let Some(source) = source {
+        dispatch_event(DomEvent::new(
+            source,
+            DomEventData::Drag(event(&data_transfer)),
+        ));
+    }
+    if old_target != Some(new_target) {
+        dispatch_event(DomEvent::new(
+            new_target,
+            DomEventData::DragEnter(event(&data_transfer)),
+        ));
+        if let Some(old_target) = old_target {
+            dispatch_event(DomEvent::new(
+                old_target,
+                DomEventData::DragLeave(event(&data_transfer)),
+            ));
+        }
+    }
+
+    // Script accepts the drop by cancelling `dragover` (optionally choosing a different effect).
+    // If it doesn't then the default action decides (see `handle_dragover`).
+    data_transfer.set_drop_effect(DropEffect::Copy);
+    dispatch_event(DomEvent::new(
+        new_target,
+        DomEventData::DragOver(event(&data_transfer)),
+    ));
+}
+
+/// Default action of `dragover`: only accept the drop if the target accepts it by default
+pub(crate) fn handle_dragover(doc: &BaseDocument, target: usize, event: &BlitzDragEvent) {
+    let drop_effect = match drop_target(doc, target, &event.data_transfer) {
+        Some(_) => DropEffect::Copy,
+        None => DropEffect::None,
+    };
+    event.data_transfer.set_drop_effect(drop_effect);
+}
+
+/// End the drag at (`coords`), dispatching `drop` (if the drop was accepted) and `dragend`
+pub(crate) fn finish_drag(
+    state: DragAndDropState,
+    coords: PointerCoords,
+    buttons: MouseEventButtons,
+    mods: Modifiers,
+    dispatch_event: &mut dyn FnMut(DomEvent),
+) {
+    let event = || BlitzDragEvent {
+        coords,
+        buttons,
+        mods,
+        data_transfer: state.data_transfer.clone(),
+    };
+
+    if let Some(target) = state.target {
+        if state.data_transfer.drop_effect() == DropEffect::None {
+            dispatch_event(DomEvent::new(target, DomEventData::DragLeave(event())));
+        } else {
+            dispatch_event(DomEvent::new(target, DomEventData::Drop(event())));
+        }
+    }
+    if let Some(source) = state.source {
+        dispatch_event(DomEvent::new(source, DomEventData::DragEnd(event())));
+    }
+}
+
+/// Default action of `drop`: insert dropped text into text inputs, and dropped files into
+/// file inputs
+pub(crate) fn handle_drop(
+    doc: &mut BaseDocument,
+    target: usize,
+    event: &BlitzDragEvent,
+    dispatch_event: &mut dyn FnMut(DomEvent),
+) {
+    match drop_target(doc, target, &event.data_transfer) {
+        Some(DropTarget::TextInput(node_id)) => {
+            let Some(text) = event.data_transfer.get_data("text/plain") else {
+                return;
+            };
+            generate_focus_events(
+                doc,
+                &mut |doc| {
+                    doc.set_focus_to(node_id);
+                },
+                dispatch_event,
+            );
+
+            let input_data = doc.nodes[node_id]
+                .element_data_mut()
+                .and_then(|el| el.text_input_data_mut())
+                .unwrap();
+            input_data
+                .editor
+                .driver(&mut doc.font_ctx.lock().unwrap(), &mut doc.layout_ctx)
+                .insert_or_replace_selection(&text);
+            let value = input_data.editor.raw_text().to_string();
+            dispatch_event(DomEvent::new(
+                node_id,
+                DomEventData::Input(BlitzInputEvent { value }),
+            ));
+            doc.shell_provider.request_redraw();
+        }
+        #[cfg(feature = "file_input")]
+        Some(DropTarget::FileInput(node_id)) => {
+            let multiple = doc.nodes[node_id]
+                .element_data()
+                .is_some_and(|el| el.has_attr(local_name!("multiple")));
+            let mut files = event.data_transfer.files();
+            if !multiple {
+                files.truncate(1);
+            }
+            let value = files
+                .first()
+                .map(|file| file.to_string_lossy().to_string())
+                .unwrap_or_default();
+            super::pointer::set_file_input_files(doc, node_id, files);
+            dispatch_event(DomEvent::new(
+                node_id,
+                DomEventData::Input(BlitzInputEvent { value }),
+            ));
+            doc.shell_provider.request_redraw();
+        }
+        None => {}
+    }
+}
+
+/// Handle data (such as files) being dragged from another application
+pub(crate) fn handle_external_drag(
+    doc: &mut BaseDocument,
+    event: UiEvent,
+    dispatch_event: &mut dyn FnMut(DomEvent),
+) {
+    match event {
+        UiEvent::DragEnter(event) => {
+            doc.drag_mode = DragMode::DragAndDrop(DragAndDropState {
+                source: None,
+                data_transfer: event.data_transfer,
+                target: None,
+            });
+            update_drag(doc, event.coords, event.buttons, event.mods, dispatch_event);
+        }
+        UiEvent::DragOver(event) => {
+            if matches!(doc.drag_mode, DragMode::DragAndDrop(_)) {
+                update_drag(doc, event.coords, event.buttons, event.mods, dispatch_event);
+            }
+        }
+        UiEvent::DragLeave(event) => {
+            if let DragMode::DragAndDrop(state) = doc.drag_mode.take()
+                && let Some(target) = state.target
+            {
+                dispatch_event(DomEvent::new(
+                    target,
+                    DomEventData::DragLeave(BlitzDragEvent {
+                        data_transfer: state.data_transfer,
+                        ..event
+                    }),
+                ));
+            }
+        }
+        UiEvent::Drop(event) => {
+            // The drop is at the position of the last `dragover`, whose result decides whether
+            // the drop is accepted
+            if let DragMode::DragAndDrop(state) = doc.drag_mode.take() {
+                finish_drag(
+                    state,
+                    event.coords,
+                    event.buttons,
+                    event.mods,
+                    dispatch_event,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The element under `target` which accepts the dragged data by default (if any)
+fn drop_target(
+    doc: &BaseDocument,
+    target: usize,
+    data_transfer: &DataTransfer,
+) -> Option<DropTarget> {
+    let has_text = data_transfer.get_data("text/plain").is_some();
+    #[cfg(feature = "file_input")]
+    let has_files = !data_transfer.files().is_empty();
+
+    for id in doc.node_chain(target) {
+        let Some(el) = doc.nodes[id].element_data() else {
+            continue;
+        };
+        if el.has_attr(local_name!("disabled")) {
+            return None;
+        }
+        if el.text_input_data().is_some() {
+            let is_readonly = el.has_attr(local_name!("readonly"));
+            return (has_text && !is_readonly).then_some(DropTarget::TextInput(id));
+        }
+        #[cfg(feature = "file_input")]
+        if el.name.local == local_name!("input") && el.attr(local_name!("type")) == Some("file") {
+            return has_files.then_some(DropTarget::FileInput(id));
+        }
+    }
+
+    None
+}
@@ -4,6 +4,8 @@ use blitz_traits::events::{
 };
 use std::collections::VecDeque;
 
+use super::handle_external_drag;
+
 pub trait EventHandler {
     fn handle_event(
         &mut self,
@@ -183,6 +185,12 @@ impl<'doc, Handler: EventHandler> EventDriver<'doc, Handler> {
             UiEvent::KeyDown(_) => focussed_node_id,
             UiEvent::Ime(_) => focussed_node_id,
             UiEvent::AppleStandardKeybinding(_) => focussed_node_id,
+            UiEvent::ContextMenuCommand(_) => focussed_node_id,
+            // Drag events are targeted at the element under the pointer by `handle_external_drag`
+            UiEvent::DragEnter(_) => hover_node_id,
+            UiEvent::DragOver(_) => hover_node_id,
+            UiEvent::DragLeave(_) => hover_node_id,
+            UiEvent::Drop(_) => hover_node_id,
         };
         let target = target.unwrap_or_else(|| self.doc.inner().root_element().id);
 
@@ -228,6 +236,23 @@ impl<'doc, Handler: EventHandler> EventDriver<'doc, Handler> {
                     DomEvent::new(target, DomEventData::AppleStandardKeybinding(data));
                 self.run_default_action(&mut dom_event);
             }
+            UiEvent::ContextMenuCommand(command) => {
+                let mut doc = self.doc.inner_mut();
+                doc.execute_context_menu_command(command, |new_evt| self.queue.push_back(new_evt));
+                drop(doc);
+                self.process_queue();
+            }
+            event @ (UiEvent::DragEnter(_)
+            | UiEvent::DragOver(_)
+            | UiEvent::DragLeave(_)
+            | UiEvent::Drop(_)) => {
+                let mut doc = self.doc.inner_mut();
+                handle_external_drag(&mut doc, event, &mut |new_evt| {
+                    self.queue.push_back(new_evt)
+                });
+                drop(doc);
+                self.process_queue();
+            }
         };
 
         // Update document input state (hover, focus, active, etc)
@@ -236,6 +261,15 @@ impl<'doc, Handler: EventHandler> EventDriver<'doc, Handler> {
         }
     }
 
+    /// Dispatch the events queued by the document (see [`BaseDocument::take_pending_events`])
+    ///
+    /// [`BaseDocument::take_pending_events`]: crate::BaseDocument::take_pending_events
+    pub fn flush_pending_events(&mut self) {
+        let events = self.doc.inner_mut().take_pending_events();
+        self.queue.extend(events);
+        self.process_queue();
+    }
+
     pub fn handle_dom_event(&mut self, event: DomEvent) {
         self.queue.push_back(event);
         self.process_queue();
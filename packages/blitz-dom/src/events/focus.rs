@@ -1,6 +1,12 @@
+use std::cmp::Ordering;
+
 use blitz_traits::events::{BlitzFocusEvent, DomEvent, DomEventData};
+use markup5ever::local_name;
+use style::computed_values::visibility::T as Visibility;
+use style_dom::ElementState;
 
-use crate::BaseDocument;
+use crate::traversal::TreeTraverser;
+use crate::{BaseDocument, ScrollIntoViewOptions, ScrollLogicalPosition};
 
 pub(crate) fn generate_focus_events(
     doc: &mut BaseDocument,
@@ -38,3 +44,260 @@ pub(crate) fn generate_focus_events(
         ));
     }
 }
+
+impl BaseDocument {
+    /// The elements which can be reached by sequential (Tab) focus navigation, in navigation
+    /// order: elements with a positive `tabindex` first (in increasing order of `tabindex`), then
+    /// the remaining elements in tree order. Elements with a negative `tabindex`, elements which
+    /// are not rendered and elements outside the active focus trap are excluded.
+    pub fn sequential_focus_order(&self) -> Vec<usize> {
+        let scope = self
+            .focus_traps
+            .last()
+            .copied()
+            .unwrap_or(self.root_node().id);
+
+        let mut order: Vec<(i32, usize)> = TreeTraverser::new_with_root(self, scope)
+            .filter_map(|node_id| {
+                let node = &self.nodes[node_id];
+                if !node.is_focussable() || !self.is_rendered(node_id) {
+                    return None;
+                }
+                let is_visible = node
+                    .primary_styles()
+                    .is_some_and(|style| style.clone_visibility() == Visibility::Visible);
+                if !is_visible {
+                    return None;
+                }
+                let tab_index: i32 = node
+                    .element_data()
+                    .and_then(|element| element.attr_parsed(local_name!("tabindex")))
+                    .unwrap_or(0);
+                Some((tab_index, node_id))
+            })
+            .collect();
+
+        // Stable sort, so that elements with equal tabindex remain in tree order
+        order.sort_by_key(|(tab_index, _)| match *tab_index {
+            index if index > 0 => index,
+            _ => i32::MAX,
+        });
+        order.into_iter().map(|(_, node_id)| node_id).collect()
+    }
+
+    /// Move focus to the next element in sequential focus navigation order (wrapping around at
+    /// the end), and scroll it into view
+    pub fn focus_next_node(&mut self) -> Option<usize> {
+        self.focus_sequential(true)
+    }
+
+    /// Move focus to the previous element in sequential focus navigation order (wrapping around
+    /// at the start), and scroll it into view
+    pub fn focus_previous_node(&mut self) -> Option<usize> {
+        self.focus_sequential(false)
+    }
+
+    fn focus_sequential(&mut self, forward: bool) -> Option<usize> {
+        let order = self.sequential_focus_order();
+        if order.is_empty() {
+            return None;
+        }
+
+        let len = order.len();
+        let current = self
+            .focus_node_id
+            .and_then(|focus_id| order.iter().position(|&id| id == focus_id));
+        let index = match (current, self.focus_node_id) {
+            (Some(index), _) if forward => (index + 1) % len,
+            (Some(index), _) => (index + len - 1) % len,
+            // Continue from the position in the tree of a focussed element which is not in the
+            // navigation order (such as one with a negative tabindex)
+            (None, Some(focus_id)) if forward => order
+                .iter()
+                .position(|&id| self.compare_document_order(id, focus_id) == Ordering::Greater)
+                .unwrap_or(0),
+            (None, Some(focus_id)) => order
+                .iter()
+                .rposition(|&id| self.compare_document_order(id, focus_id) == Ordering::Less)
+                .unwrap_or(len - 1),
+            (None, None) if forward => 0,
+            (None, None) => len - 1,
+        };
+
+        let id = order[index];
+        self.set_focus_to(id);
+        self.scroll_node_into_view(
+            id,
+            ScrollIntoViewOptions {
+                block: ScrollLogicalPosition::Nearest,
+                inline: ScrollLogicalPosition::Nearest,
+                ..Default::default()
+            },
+        );
+        Some(id)
+    }
+
+    /// Restrict sequential focus navigation to the subtree of a node (such as a modal dialog).
+    /// Traps nest, with the most recently added one being active. If the focussed element is
+    /// outside of the trap then focus is moved to the first element within it.
+    pub fn add_focus_trap(&mut self, node_id: usize) {
+        self.focus_traps.retain(|&id| id != node_id);
+        self.focus_traps.push(node_id);
+
+        let focus_is_trapped = self
+            .focus_node_id
+            .is_some_and(|focus_id| self.node_chain(focus_id).contains(&node_id));
+        if !focus_is_trapped {
+            if let Some(&first) = self.sequential_focus_order().first() {
+                self.set_focus_to(first);
+            }
+        }
+    }
+
+    pub fn remove_focus_trap(&mut self, node_id: usize) {
+        self.focus_traps.retain(|&id| id != node_id);
+    }
+
+    /// Record whether the most recent user input was from the keyboard, which determines whether
+    /// newly focussed elements match `:focus-visible`. Switching to keyboard input makes the
+    /// current focus visible.
+    pub(crate) fn set_keyboard_modality(&mut self, keyboard: bool) {
+        self.keyboard_modality = keyboard;
+        if let Some(focus_id) = self.focus_node_id.filter(|_| keyboard) {
+            if !self.nodes[focus_id]
+                .element_state
+                .contains(ElementState::FOCUSRING)
+            {
+                self.snapshot_node_and(focus_id, |node| {
+                    node.element_state.insert(ElementState::FOCUSRING);
+                    node.mark_ancestors_dirty();
+                });
+            }
+        }
+    }
+
+    /// Whether focussing a node should make the focus visible: after keyboard input, or for
+    /// elements which accept text input
+    pub(crate) fn focus_should_be_visible(&self, node_id: usize) -> bool {
+        self.keyboard_modality
+            || self.nodes[node_id]
+                .element_data()
+                .is_some_and(|element| element.text_input_data().is_some())
+    }
+
+    /// Update the `:focus-within` state of the ancestors of the old and new focussed nodes
+    pub(crate) fn update_focus_within(
+        &mut self,
+        old_focus: Option<usize>,
+        new_focus: Option<usize>,
+    ) {
+        let old_chain = old_focus.map(|id| self.node_chain(id)).unwrap_or_default();
+        let new_chain = new_focus.map(|id| self.node_chain(id)).unwrap_or_default();
+
+        // Only the state is snapshotted, so Stylo restyles just the elements matched by selectors
+        // which depend on `:focus-within`
+        for &id in old_chain.iter().filter(|id| !new_chain.contains(id)) {
+            self.snapshot_node_state(id);
+            let node = &mut self.nodes[id];
+            node.element_state.remove(ElementState::FOCUS_WITHIN);
+            node.mark_ancestors_dirty();
+        }
+        for &id in new_chain.iter().filter(|id| !old_chain.contains(id)) {
+            self.snapshot_node_state(id);
+            let node = &mut self.nodes[id];
+            node.element_state.insert(ElementState::FOCUS_WITHIN);
+            node.mark_ancestors_dirty();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, DocumentConfig, QualName, qual_name};
+
+    fn attrs(attrs: &[(&str, &str)]) -> Vec<Attribute> {
+        attrs
+            .iter()
+            .map(|(name, value)| Attribute {
+                name: QualName::new(None, Default::default(), (*name).into()),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sequential_focus_order_and_traps() {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let mut mutator = doc.mutate();
+        let input = mutator.create_element(qual_name!("input", html), Vec::new());
+        let second =
+            mutator.create_element(qual_name!("button", html), attrs(&[("tabindex", "2")]));
+        let skipped =
+            mutator.create_element(qual_name!("input", html), attrs(&[("tabindex", "-1")]));
+        let first = mutator.create_element(qual_name!("div", html), attrs(&[("tabindex", "1")]));
+        let hidden = mutator.create_element(
+            qual_name!("button", html),
+            attrs(&[("style", "display: none")]),
+        );
+        let dialog_a = mutator.create_element(qual_name!("button", html), Vec::new());
+        let dialog_b = mutator.create_element(qual_name!("button", html), Vec::new());
+        let dialog = mutator.create_element(qual_name!("div", html), Vec::new());
+        mutator.append_children(dialog, &[dialog_a, dialog_b]);
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[input, second, skipped, first, hidden, dialog]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        // Positive tabindex first, then tree order. Negative tabindex and hidden elements are skipped.
+        assert_eq!(
+            doc.sequential_focus_order(),
+            vec![first, second, input, dialog_a, dialog_b]
+        );
+        assert_eq!(doc.focus_next_node(), Some(first));
+        assert_eq!(doc.focus_previous_node(), Some(dialog_b));
+
+        // Navigating from an element which isn't in the order moves to the first element in the
+        // order which follows it in the tree
+        doc.set_focus_to(skipped);
+        assert_eq!(doc.focus_next_node(), Some(first));
+
+        // Traps move focus inside them and restrict navigation to their subtree
+        doc.set_focus_to(input);
+        doc.add_focus_trap(dialog);
+        assert_eq!(doc.get_focussed_node_id(), Some(dialog_a));
+        assert_eq!(doc.sequential_focus_order(), vec![dialog_a, dialog_b]);
+        assert_eq!(doc.focus_next_node(), Some(dialog_b));
+        assert_eq!(doc.focus_next_node(), Some(dialog_a));
+        assert_eq!(doc.focus_previous_node(), Some(dialog_b));
+
+        doc.remove_focus_trap(dialog);
+        assert_eq!(doc.focus_next_node(), Some(first));
+    }
+
+    #[test]
+    fn test_focus_within() {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let mut mutator = doc.mutate();
+        let button = mutator.create_element(qual_name!("button", html), Vec::new());
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[button]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+
+        let focus_within = |doc: &BaseDocument, id: usize| {
+            doc.nodes[id]
+                .element_state
+                .contains(ElementState::FOCUS_WITHIN)
+        };
+        doc.set_focus_to(button);
+        assert!(focus_within(&doc, body) && focus_within(&doc, html));
+        doc.clear_focus();
+        assert!(!focus_within(&doc, body) && !focus_within(&doc, html));
+    }
+}
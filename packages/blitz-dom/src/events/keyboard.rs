@@ -1,7 +1,10 @@
 use crate::{
     BaseDocument,
     node::{TextBrush, TextInputData},
+    scroll::KeyboardScroll,
 };
+
+use super::focus::generate_focus_events;
 use blitz_traits::{
     SmolStr,
     events::{BlitzInputEvent, BlitzKeyEvent, DomEvent, DomEventData},
@@ -30,11 +33,37 @@ pub(crate) fn handle_key_or_input_event<F: FnMut(DomEvent)>(
     mut dispatch_event: F,
 ) {
     if let KeyboardOrTextInputEvent::KeyPress(event) = &event {
+        if event.state.is_pressed() {
+            doc.set_keyboard_modality(true);
+        }
+
         if event.key == Key::Tab {
-            doc.focus_next_node();
+            if event.state.is_pressed() {
+                let forward = !event.modifiers.contains(Modifiers::SHIFT);
+                generate_focus_events(
+                    doc,
+                    &mut |doc| {
+                        if forward {
+                            doc.focus_next_node();
+                        } else {
+                            doc.focus_previous_node();
+                        }
+                    },
+                    &mut dispatch_event,
+                );
+            }
             return;
         }
 
+        if event.state.is_pressed() && !focus_handles_keys(doc) {
+            if let Some(scroll) = keyboard_scroll_for(event) {
+                if doc.keyboard_scroll(scroll) {
+                    doc.shell_provider.request_redraw();
+                }
+                return;
+            }
+        }
+
         // Handle copy (Ctrl+C/Cmd+C) for text selection when no text input is focused
         if event.state.is_pressed() {
             let action_mod = event.modifiers.contains(ACTION_MOD);
@@ -113,6 +142,48 @@ pub(crate) fn handle_key_or_input_event<F: FnMut(DomEvent)>(
     }
 }
 
+/// Whether the focussed element handles navigation keys itself (form controls use the arrow keys,
+/// Space, Home and End), in which case they don't scroll
+fn focus_handles_keys(doc: &BaseDocument) -> bool {
+    let Some(element) = doc
+        .focus_node_id
+        .and_then(|id| doc.get_node(id))
+        .and_then(|node| node.element_data())
+    else {
+        return false;
+    };
+    element.text_input_data().is_some()
+        || [
+            local_name!("input"),
+            local_name!("textarea"),
+            local_name!("select"),
+            local_name!("button"),
+        ]
+        .contains(&element.name.local)
+}
+
+/// The scroll performed by a key press (if any)
+fn keyboard_scroll_for(event: &BlitzKeyEvent) -> Option<KeyboardScroll> {
+    let mods = event.modifiers;
+    if mods.intersects(Modifiers::CONTROL | Modifiers::ALT | Modifiers::META | Modifiers::SUPER) {
+        return None;
+    }
+    let shift = mods.contains(Modifiers::SHIFT);
+    match &event.key {
+        Key::ArrowUp => Some(KeyboardScroll::LineUp),
+        Key::ArrowDown => Some(KeyboardScroll::LineDown),
+        Key::ArrowLeft => Some(KeyboardScroll::LineLeft),
+        Key::ArrowRight => Some(KeyboardScroll::LineRight),
+        Key::PageUp => Some(KeyboardScroll::PageUp),
+        Key::PageDown => Some(KeyboardScroll::PageDown),
+        Key::Home => Some(KeyboardScroll::Top),
+        Key::End => Some(KeyboardScroll::Bottom),
+        Key::Character(c) if c == " " && shift => Some(KeyboardScroll::PageUp),
+        Key::Character(c) if c == " " => Some(KeyboardScroll::PageDown),
+        _ => None,
+    }
+}
+
 #[cfg(target_os = "macos")]
 const ACTION_MOD: Modifiers = Modifiers::SUPER;
 #[cfg(not(target_os = "macos"))]
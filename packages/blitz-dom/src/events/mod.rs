@@ -1,16 +1,19 @@
+mod drag;
 mod driver;
 mod focus;
 mod ime;
 mod keyboard;
 mod pointer;
 
+use crate::scrollbar::ScrollbarMode;
 use crate::util::Point;
 use blitz_traits::events::{DomEvent, DomEventData, PointerCoords, UiEvent};
+pub(crate) use drag::handle_external_drag;
 pub use driver::{EventDriver, EventHandler, NoopEventHandler};
 use focus::generate_focus_events;
 pub(crate) use ime::handle_ime_event;
 use keyboard::{KeyboardOrTextInputEvent, handle_key_or_input_event};
-pub(crate) use pointer::{DragMode, ScrollAnimationState};
+pub(crate) use pointer::{DragMode, ScrollAnimationState, SnapState};
 use pointer::{handle_click, handle_pointerdown, handle_pointermove, handle_pointerup};
 
 use crate::{BaseDocument, events::pointer::handle_wheel};
@@ -87,10 +90,22 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
             DomEventData::Input(_) => None,
             DomEventData::Wheel(data) => Some(UiEvent::Wheel(data)),
             DomEventData::Scroll(_) => None,
+            DomEventData::ScrollEnd(_) => None,
             DomEventData::Focus(_) => None,
             DomEventData::Blur(_) => None,
             DomEventData::FocusIn(_) => None,
             DomEventData::FocusOut(_) => None,
+            DomEventData::Visible(_) => None,
+            DomEventData::Resize(_) => None,
+
+            // Drags are not (yet) forwarded into sub-documents
+            DomEventData::DragStart(_) => None,
+            DomEventData::Drag(_) => None,
+            DomEventData::DragEnter(_) => None,
+            DomEventData::DragOver(_) => None,
+            DomEventData::DragLeave(_) => None,
+            DomEventData::Drop(_) => None,
+            DomEventData::DragEnd(_) => None,
         };
 
         if let Some(ui_event) = ui_event {
@@ -112,6 +127,7 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
 
     match &event.data {
         DomEventData::PointerMove(event) => {
+            doc.scrollbars.mode = ScrollbarMode::for_pointer(&event.id);
             let changed = handle_pointermove(doc, target_node_id, event, dispatch_event);
             if changed {
                 doc.shell_provider.request_redraw();
@@ -121,11 +137,13 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
             // Do nothing (handled in PointerMove)
         }
         DomEventData::PointerDown(event) => {
+            doc.scrollbars.mode = ScrollbarMode::for_pointer(&event.id);
             handle_pointerdown(
                 doc,
                 target_node_id,
                 event.page_x(),
                 event.page_y(),
+                event.button,
                 event.mods,
                 &mut dispatch_event,
             );
@@ -170,8 +188,8 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
         DomEventData::Input(_) => {
             // Do nothing (no default action)
         }
-        DomEventData::ContextMenu(_) => {
-            // TODO: Open context menu
+        DomEventData::ContextMenu(event) => {
+            doc.open_context_menu(target_node_id, event);
         }
         DomEventData::DoubleClick(_) => {
             // Do nothing (no default action)
@@ -203,8 +221,11 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
         DomEventData::Scroll(_) => {
             // Handled elsewhere
         }
+        DomEventData::ScrollEnd(_) => {
+            // Do nothing (no default action)
+        }
         DomEventData::Wheel(event) => {
-            handle_wheel(doc, target_node_id, event.clone(), dispatch_event);
+            handle_wheel(doc, target_node_id, event.clone());
         }
         DomEventData::Focus(_) => {
             // Do nothing (no default action)
@@ -218,5 +239,32 @@ pub(crate) fn handle_dom_event<F: FnMut(DomEvent)>(
         DomEventData::FocusOut(_) => {
             // Do nothing (no default action)
         }
+        DomEventData::Visible(_) => {
+            // Do nothing (no default action)
+        }
+        DomEventData::Resize(_) => {
+            // Do nothing (no default action)
+        }
+        DomEventData::DragStart(event) => {
+            drag::handle_dragstart(doc, target_node_id, event);
+        }
+        DomEventData::Drag(_) => {
+            // Do nothing (no default action)
+        }
+        DomEventData::DragEnter(_) => {
+            // Do nothing (no default action)
+        }
+        DomEventData::DragOver(event) => {
+            drag::handle_dragover(doc, target_node_id, event);
+        }
+        DomEventData::DragLeave(_) => {
+            // Do nothing (no default action)
+        }
+        DomEventData::Drop(event) => {
+            drag::handle_drop(doc, target_node_id, event, &mut dispatch_event);
+        }
+        DomEventData::DragEnd(_) => {
+            // Do nothing (no default action)
+        }
     }
 }
@@ -13,8 +13,16 @@ use blitz_traits::{
 use keyboard_types::Modifiers;
 use markup5ever::local_name;
 
-use crate::{BaseDocument, node::SpecialElementData};
+use crate::{
+    BaseDocument,
+    node::SpecialElementData,
+    scroll::SmoothScroll,
+    scrollbar::{ScrollbarAxis, ScrollbarOwner, ScrollbarPart},
+};
 
+use super::drag::{
+    DragAndDropState, DragSource, drag_source_at, finish_drag, start_drag, update_drag,
+};
 use super::focus::generate_focus_events;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,10 +33,20 @@ pub(crate) struct FlingState {
     pub(crate) y_velocity: f64,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SnapState {
+    /// The node that the scroll gesture was targeting (`None` for the viewport)
+    pub(crate) target: Option<usize>,
+    pub(crate) last_scroll_time: Instant,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum ScrollAnimationState {
     None,
     Fling(FlingState),
+    /// Waiting for a wheel gesture to end before snapping
+    AwaitingSnap(SnapState),
+    Smooth(Vec<SmoothScroll>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -54,6 +72,25 @@ pub(crate) enum DragMode {
     Selecting,
     /// We are currently panning the document with a drag (probably touch)
     Panning(PanState),
+    /// We are currently pressing a scrollbar
+    Scrollbar(ScrollbarDragState),
+    /// The pointer was pressed on something draggable, but hasn't yet moved far enough to
+    /// start dragging it
+    PendingDrag(DragSource),
+    /// `dragstart` has been dispatched, but its default action hasn't yet started the drag
+    /// (if the event was cancelled, then it never will)
+    DragStarting,
+    /// We are currently dragging-and-dropping
+    DragAndDrop(DragAndDropState),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScrollbarDragState {
+    pub(crate) owner: ScrollbarOwner,
+    pub(crate) axis: ScrollbarAxis,
+    /// The distance between the pointer and the start of the thumb when the thumb was grabbed.
+    /// `None` if the track (rather than the thumb) was pressed.
+    pub(crate) thumb_grab_offset: Option<f64>,
 }
 
 impl DragMode {
@@ -150,13 +187,48 @@ pub(crate) fn handle_pointermove<F: FnMut(DomEvent)>(
 
     let mut changed = doc.set_hover_to(x, y);
 
+    // Drag the scrollbar thumb
+    if let DragMode::Scrollbar(state) = &doc.drag_mode {
+        let Some(grab_offset) = state.thumb_grab_offset else {
+            return changed;
+        };
+        let Some(scrollbar) = doc.scrollbar(state.owner, state.axis) else {
+            return changed;
+        };
+        let point = doc.scrollbar_local_point(state.owner, x, y);
+        let thumb_start = scrollbar.along_axis(point) - grab_offset;
+        let offset = scrollbar.scroll_offset_for_thumb_start(thumb_start);
+        return doc.set_scrollbar_offset(&scrollbar, offset) | changed;
+    }
+
+    match doc.drag_mode {
+        DragMode::DragStarting => return changed,
+        DragMode::DragAndDrop(_) => {
+            update_drag(doc, event.coords, buttons, event.mods, &mut dispatch_event);
+            return true;
+        }
+        _ => {}
+    }
+
     // Check if we've moved enough to be considered a selection drag (2px threshold)
-    if buttons != MouseEventButtons::None && doc.drag_mode == DragMode::None {
+    let is_pending = matches!(doc.drag_mode, DragMode::None | DragMode::PendingDrag(_));
+    if buttons != MouseEventButtons::None && is_pending {
         let dx = x - doc.mousedown_position.x;
         let dy = y - doc.mousedown_position.y;
         if dx.abs() > 2.0 || dy.abs() > 2.0 {
             match event.id {
                 BlitzPointerId::Mouse | BlitzPointerId::Pen => {
+                    if let DragMode::PendingDrag(source) = doc.drag_mode {
+                        start_drag(
+                            doc,
+                            source,
+                            event.coords,
+                            buttons,
+                            event.mods,
+                            &mut dispatch_event,
+                        );
+                        return true;
+                    }
                     doc.drag_mode = DragMode::Selecting;
                 }
                 BlitzPointerId::Finger(_) => {
@@ -180,7 +252,7 @@ pub(crate) fn handle_pointermove<F: FnMut(DomEvent)>(
         let target = state.target;
         let (dx, dy) = state.update(time_ms, event.screen_x(), event.screen_y());
 
-        let has_changed = doc.scroll_by(Some(target), dx, dy, &mut dispatch_event);
+        let has_changed = doc.scroll_by_with_overscroll(Some(target), dx, dy);
         return has_changed;
     }
 
@@ -255,9 +327,12 @@ pub(crate) fn handle_pointerdown(
     _target: usize,
     x: f32,
     y: f32,
+    button: MouseEventButton,
     mods: Modifiers,
     dispatch_event: &mut dyn FnMut(DomEvent),
 ) {
+    doc.set_keyboard_modality(false);
+
     // Compute click count using the previous mousedown position (before updating)
     // This handles both double-click detection and text input word/line selection
     // TODO: For text inputs, only increment click count if click maps to the same/similar caret position
@@ -279,6 +354,28 @@ pub(crate) fn handle_pointerdown(
     doc.drag_mode = DragMode::None;
     doc.scroll_animation = ScrollAnimationState::None;
 
+    // Scrollbars take priority over the content beneath them
+    if let Some((scrollbar, part)) = doc.hit_scrollbar(x, y) {
+        let thumb_grab_offset = match part {
+            ScrollbarPart::Thumb => {
+                let point = doc.scrollbar_local_point(scrollbar.owner, x, y);
+                Some(scrollbar.along_axis(point) - scrollbar.thumb_start())
+            }
+            ScrollbarPart::TrackBefore | ScrollbarPart::TrackAfter => {
+                if doc.page_scrollbar(&scrollbar, part) {
+                    doc.shell_provider.request_redraw();
+                }
+                None
+            }
+        };
+        doc.drag_mode = DragMode::Scrollbar(ScrollbarDragState {
+            owner: scrollbar.owner,
+            axis: scrollbar.axis,
+            thumb_grab_offset,
+        });
+        return;
+    }
+
     let Some(hit) = doc.hit(x, y) else {
         // Clear text selection when clicking outside any element
         doc.clear_text_selection();
@@ -290,6 +387,18 @@ pub(crate) fn handle_pointerdown(
     // but not DOM children), so we use the hit result for text selection.
     let actual_target = hit.node_id;
 
+    // Secondary clicks open the context menu, which acts on the existing selection
+    let is_secondary = button == MouseEventButton::Secondary;
+
+    // Pressing the selected text, a link, an image or a draggable element may start a drag
+    let drag_source = (button == MouseEventButton::Main)
+        .then(|| drag_source_at(doc, actual_target, x, y))
+        .flatten();
+    let is_selection_drag = matches!(drag_source, Some(DragSource::Selection(_)));
+    if let Some(source) = drag_source {
+        doc.drag_mode = DragMode::PendingDrag(source);
+    }
+
     // Check what kind of element we're dealing with and extract needed info
     enum ClickTarget {
         TextInput {
@@ -327,6 +436,8 @@ pub(crate) fn handle_pointerdown(
 
     match click_target {
         ClickTarget::Disabled => (),
+        // The selection is only collapsed on release if it isn't dragged
+        ClickTarget::SelectableText if is_secondary || is_selection_drag => (),
         ClickTarget::SelectableText => {
             // Handle text selection for non-input elements
             if let Some((inline_root_id, byte_offset)) = doc.find_text_position(x, y) {
@@ -336,6 +447,15 @@ pub(crate) fn handle_pointerdown(
                 doc.clear_text_selection();
             }
         }
+        ClickTarget::TextInput { .. } if is_secondary => {
+            generate_focus_events(
+                doc,
+                &mut |doc| {
+                    doc.set_focus_to(hit.node_id);
+                },
+                dispatch_event,
+            );
+        }
         ClickTarget::TextInput { content_box_offset } => {
             // Clear general text selection when focusing a text input
             doc.clear_text_selection();
@@ -392,7 +512,10 @@ pub(crate) fn handle_pointerup<F: FnMut(DomEvent)>(
                 node = doc.get_node(parent_id).unwrap();
             }
         }
-        doc.debug_log_node(node.id);
+        let node_id = node.id;
+        doc.debug_log_node(node_id);
+        #[cfg(feature = "devtools_server")]
+        doc.devtools_inspect_node(node_id);
         doc.devtools_mut().highlight_hover = false;
         return;
     }
@@ -401,9 +524,9 @@ pub(crate) fn handle_pointerup<F: FnMut(DomEvent)>(
     // locally for use within this function
     let drag_mode = doc.drag_mode.take();
 
-    // Don't dispatch click if we were doing a text selection drag or panning
-    // the document with a touch
-    let do_click = drag_mode == DragMode::None;
+    // Don't dispatch click if we were doing a text selection drag, panning
+    // the document with a touch, pressing a scrollbar or dragging-and-dropping
+    let do_click = matches!(drag_mode, DragMode::None | DragMode::PendingDrag(_));
 
     let time_ms = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -411,12 +534,43 @@ pub(crate) fn handle_pointerup<F: FnMut(DomEvent)>(
         .as_millis() as u64;
 
     if let DragMode::Panning(state) = &drag_mode {
-        if let Some(fling) = state.generate_fling(time_ms) {
+        // Overscrolled containers bounce back rather than flinging
+        let fling = state
+            .generate_fling(time_ms)
+            .filter(|_| !doc.is_overscrolled());
+        if let Some(fling) = fling {
             doc.scroll_animation = ScrollAnimationState::Fling(fling);
             doc.shell_provider.request_redraw();
+        } else {
+            doc.scroll_snap(Some(state.target));
+            doc.shell_provider.request_redraw();
         }
     }
 
+    match drag_mode {
+        // The selected text was pressed but not dragged, so collapse the selection
+        DragMode::PendingDrag(DragSource::Selection(_)) => {
+            let (x, y) = (event.page_x(), event.page_y());
+            if let Some((inline_root_id, offset)) = doc.find_text_position(x, y) {
+                doc.set_text_selection(inline_root_id, offset, inline_root_id, offset);
+            } else {
+                doc.clear_text_selection();
+            }
+            doc.shell_provider.request_redraw();
+        }
+        DragMode::DragAndDrop(state) => {
+            finish_drag(
+                state,
+                event.coords,
+                event.buttons,
+                event.mods,
+                &mut dispatch_event,
+            );
+            doc.shell_provider.request_redraw();
+        }
+        _ => {}
+    }
+
     // Dispatch a click event
     if do_click && event.button == MouseEventButton::Main {
         dispatch_event(DomEvent::new(target, DomEventData::Click(event.clone())));
@@ -538,38 +692,10 @@ pub(crate) fn handle_click(
                 }
                 #[cfg(feature = "file_input")]
                 local_name!("input") if el.attr(local_name!("type")) == Some("file") => {
-                    use crate::qual_name;
                     //TODO: Handle accept attribute https://developer.mozilla.org/en-US/docs/Web/HTML/Reference/Attributes/accept by passing an appropriate filter
                     let multiple = el.attr(local_name!("multiple")).is_some();
                     let files = doc.shell_provider.open_file_dialog(multiple, None);
-
-                    if let Some(file) = files.first() {
-                        el.attrs
-                            .set(qual_name!("value", html), &file.to_string_lossy());
-                    }
-                    let text_content = match files.len() {
-                        0 => "No Files Selected".to_string(),
-                        1 => files
-                            .first()
-                            .unwrap()
-                            .file_name()
-                            .unwrap_or_default()
-                            .to_string_lossy()
-                            .to_string(),
-                        x => format!("{x} Files Selected"),
-                    };
-
-                    if files.is_empty() {
-                        el.special_data = SpecialElementData::None;
-                    } else {
-                        el.special_data = SpecialElementData::FileInput(files.into())
-                    }
-                    let child_label_id = doc.nodes[node_id].children[1];
-                    let child_text_id = doc.nodes[child_label_id].children[0];
-                    let text_data = doc.nodes[child_text_id]
-                        .text_data_mut()
-                        .expect("Text data not found");
-                    text_data.content = text_content;
+                    set_file_input_files(doc, node_id, files);
                 }
                 _ => {}
             }
@@ -597,24 +723,57 @@ pub(crate) fn handle_click(
     }
 }
 
-pub(crate) fn handle_wheel<F: FnMut(DomEvent)>(
+/// Set the files selected by an `<input type=file>` and update its label
+#[cfg(feature = "file_input")]
+pub(crate) fn set_file_input_files(
     doc: &mut BaseDocument,
-    _: usize,
-    event: BlitzWheelEvent,
-    mut dispatch_event: F,
+    node_id: usize,
+    files: Vec<std::path::PathBuf>,
 ) {
+    use crate::qual_name;
+
+    let Some(el) = doc.nodes[node_id].element_data_mut() else {
+        return;
+    };
+    if let Some(file) = files.first() {
+        el.attrs
+            .set(qual_name!("value", html), &file.to_string_lossy());
+    }
+    let text_content = match files.len() {
+        0 => "No Files Selected".to_string(),
+        1 => files
+            .first()
+            .unwrap()
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        x => format!("{x} Files Selected"),
+    };
+
+    if files.is_empty() {
+        el.special_data = SpecialElementData::None;
+    } else {
+        el.special_data = SpecialElementData::FileInput(files.into())
+    }
+    let child_label_id = doc.nodes[node_id].children[1];
+    let child_text_id = doc.nodes[child_label_id].children[0];
+    let text_data = doc.nodes[child_text_id]
+        .text_data_mut()
+        .expect("Text data not found");
+    text_data.content = text_content;
+}
+
+pub(crate) fn handle_wheel(doc: &mut BaseDocument, _: usize, event: BlitzWheelEvent) {
     let (scroll_x, scroll_y) = match event.delta {
         BlitzWheelDelta::Lines(x, y) => (x * 20.0, y * 20.0),
         BlitzWheelDelta::Pixels(x, y) => (x, y),
     };
 
-    let has_changed = doc.scroll_by(
-        doc.get_hover_node_id(),
-        scroll_x,
-        scroll_y,
-        &mut dispatch_event,
-    );
+    let hover_node_id = doc.get_hover_node_id();
+    let has_changed = doc.scroll_by(hover_node_id, scroll_x, scroll_y);
     if has_changed {
+        doc.defer_scroll_snap(hover_node_id);
         doc.shell_provider.request_redraw();
     }
 }
@@ -1,6 +1,6 @@
 use crate::DocumentMutator;
 
-pub trait HtmlParserProvider {
+pub trait HtmlParserProvider: Send + Sync {
     fn parse_inner_html<'m, 'doc>(
         &self,
         mutr: &'m mut DocumentMutator<'doc>,
@@ -0,0 +1,134 @@
+//! Properties which are read from an element's `style` attribute rather than computed by Stylo
+//!
+//! The servo build of Stylo doesn't parse properties which are only implemented for Gecko (such as
+//! `scroll-snap-type` and `overscroll-behavior`), so declarations of them are dropped from
+//! stylesheets. Blitz supports these properties when they are set in an element's `style`
+//! attribute, where their declarations can be read directly.
+
+use cssparser::{Parser, ParserInput, Token, match_ignore_ascii_case};
+use markup5ever::local_name;
+
+use crate::Node;
+
+/// The font size which `em` lengths are resolved against for nodes without styles
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+/// The `(name, value)` pairs of the declarations in a `style` attribute (ignoring `!important`)
+fn declarations(style: &str) -> impl Iterator<Item = (&str, &str)> {
+    style.split(';').filter_map(|declaration| {
+        let (name, value) = declaration.split_once(':')?;
+        let value = value.trim();
+        let value = value.strip_suffix("!important").unwrap_or(value).trim_end();
+        Some((name.trim(), value))
+    })
+}
+
+/// Resolve a `<length>` (or a `<length-percentage>` if `percentage_basis` is given) to CSS pixels
+fn resolve_length(value: &str, font_size: f64, percentage_basis: Option<f64>) -> Option<f64> {
+    let mut input = ParserInput::new(value);
+    let mut parser = Parser::new(&mut input);
+    let length = match *parser.next().ok()? {
+        Token::Dimension {
+            value, ref unit, ..
+        } => {
+            let value = value as f64;
+            match_ignore_ascii_case! { unit,
+                "px" => value,
+                "em" => value * font_size,
+                "in" => value * 96.0,
+                "cm" => value * 96.0 / 2.54,
+                "mm" => value * 96.0 / 25.4,
+                "pt" => value * 96.0 / 72.0,
+                "pc" => value * 16.0,
+                _ => return None,
+            }
+        }
+        Token::Percentage { unit_value, .. } => unit_value as f64 * percentage_basis?,
+        Token::Number { value: 0.0, .. } => 0.0,
+        _ => return None,
+    };
+    parser.expect_exhausted().ok()?;
+    Some(length)
+}
+
+impl Node {
+    /// The `(name, value)` pairs of the declarations in this node's `style` attribute
+    pub(crate) fn inline_style_declarations(&self) -> impl Iterator<Item = (&str, &str)> {
+        declarations(self.attr(local_name!("style")).unwrap_or_default())
+    }
+
+    /// The value of the last declaration of `property` in this node's `style` attribute
+    pub(crate) fn inline_style_value(&self, property: &str) -> Option<&str> {
+        self.inline_style_declarations()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(property))
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// The values of a property for each side of the box (top, right, bottom, left), taken from
+    /// the declarations of its shorthand (such as `scroll-padding`) and physical longhands (such
+    /// as `scroll-padding-top`) in this node's `style` attribute
+    pub(crate) fn inline_style_sides(&self, shorthand: &str) -> [Option<&str>; 4] {
+        let mut sides = [None; 4];
+        let longhands =
+            ["top", "right", "bottom", "left"].map(|side| format!("{shorthand}-{side}"));
+        for (name, value) in self.inline_style_declarations() {
+            if name.eq_ignore_ascii_case(shorthand) {
+                let values: Vec<&str> = value.split_whitespace().collect();
+                sides = match values[..] {
+                    [all] => [Some(all); 4],
+                    [vertical, horizontal] => {
+                        [vertical, horizontal, vertical, horizontal].map(Some)
+                    }
+                    [top, horizontal, bottom] => [top, horizontal, bottom, horizontal].map(Some),
+                    [top, right, bottom, left] => [top, right, bottom, left].map(Some),
+                    _ => continue,
+                };
+            } else if let Some(side) = longhands
+                .iter()
+                .position(|longhand| name.eq_ignore_ascii_case(longhand))
+            {
+                sides[side] = Some(value);
+            }
+        }
+        sides
+    }
+
+    /// Resolve a `<length>` (or a `<length-percentage>` if `percentage_basis` is given) from this
+    /// node's `style` attribute to CSS pixels. Returns `None` for other values (such as `auto`).
+    pub(crate) fn resolve_inline_length(
+        &self,
+        value: &str,
+        percentage_basis: Option<f64>,
+    ) -> Option<f64> {
+        let font_size = self.primary_styles().map_or(DEFAULT_FONT_SIZE, |style| {
+            style.clone_font_size().computed_size().px() as f64
+        });
+        resolve_length(value, font_size, percentage_basis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declarations() {
+        let style = "scroll-snap-type: y mandatory; color:red !important;;invalid";
+        assert_eq!(
+            declarations(style).collect::<Vec<_>>(),
+            [("scroll-snap-type", "y mandatory"), ("color", "red")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_length() {
+        assert_eq!(resolve_length("10px", 16.0, None), Some(10.0));
+        assert_eq!(resolve_length("2em", 16.0, None), Some(32.0));
+        assert_eq!(resolve_length("0", 16.0, None), Some(0.0));
+        assert_eq!(resolve_length("25%", 16.0, Some(200.0)), Some(50.0));
+        assert_eq!(resolve_length("25%", 16.0, None), None);
+        assert_eq!(resolve_length("auto", 16.0, Some(200.0)), None);
+        assert_eq!(resolve_length("10px 20px", 16.0, None), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Restyle invalidation for DOM mutations
+//!
+//! Changes to an element's attributes and state are handled by Stylo's snapshot-based
+//! invalidation (see [`BaseDocument::snapshot_attribute`]). Stylo doesn't handle changes to the
+//! children of an element, or `:has()` selectors, so this module restyles the elements affected
+//! by such changes based on the [`ElementSelectorFlags`] which selector matching leaves on each
+//! node (e.g. a parent whose children were matched against `:nth-child()` is flagged with
+//! `HAS_SLOW_SELECTOR_NTH`).
+//!
+//! Note that the servo build of Stylo doesn't parse `:has()` yet, so until it does, no element is
+//! flagged as the anchor of a `:has()` selector.
+
+use selectors::matching::ElementSelectorFlags;
+use style::invalidation::element::restyle_hints::RestyleHint;
+
+use crate::BaseDocument;
+
+/// The flags set on elements which are the anchor of a `:has()` selector
+pub(crate) const RELATIVE_SELECTOR_ANCHOR_FLAGS: ElementSelectorFlags =
+    ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR
+        .union(ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR_NON_SUBJECT);
+
+impl BaseDocument {
+    /// Invalidate styles after nodes were inserted into or removed from the children of
+    /// `parent_id` at `index` (the index of the first inserted node, or where the removed nodes
+    /// used to be)
+    pub(crate) fn invalidate_for_child_list_change(&mut self, parent_id: usize, index: usize) {
+        let parent = &self.nodes[parent_id];
+        if !parent.flags.is_in_document() {
+            return;
+        }
+
+        // Counters depend on the elements which precede each pseudo-element
+        self.generated_content_dirty = true;
+
+        // Inserted nodes are unstyled, and are styled as long as the traversal reaches them
+        parent.set_dirty_descendants();
+        parent.mark_ancestors_dirty();
+
+        let flags = parent.selector_flags.get();
+        let element_children: Vec<(usize, usize)> = parent
+            .children
+            .iter()
+            .enumerate()
+            .filter(|(_, child_id)| self.nodes[**child_id].is_element())
+            .map(|(child_index, child_id)| (child_index, *child_id))
+            .collect();
+
+        let mut to_restyle = Vec::new();
+        if flags.intersects(
+            ElementSelectorFlags::HAS_SLOW_SELECTOR | ElementSelectorFlags::HAS_EMPTY_SELECTOR,
+        ) {
+            to_restyle.push(parent_id);
+        } else {
+            if flags.intersects(
+                ElementSelectorFlags::HAS_SLOW_SELECTOR_NTH
+                    | ElementSelectorFlags::HAS_SLOW_SELECTOR_NTH_OF,
+            ) {
+                // Both earlier (`:nth-last-child`) and later (`:nth-child`) siblings may change
+                to_restyle.extend(element_children.iter().map(|(_, id)| *id));
+            } else if flags.contains(ElementSelectorFlags::HAS_SLOW_SELECTOR_LATER_SIBLINGS) {
+                // Sibling combinators (`+` and `~`) only match later siblings
+                to_restyle.extend(
+                    element_children
+                        .iter()
+                        .filter(|(child_index, _)| *child_index >= index)
+                        .map(|(_, id)| *id),
+                );
+            }
+
+            if flags.contains(ElementSelectorFlags::HAS_EDGE_CHILD_SELECTOR) {
+                // `:first-child` and `:last-child` can change for the elements either side of
+                // the change
+                let previous = element_children
+                    .iter()
+                    .rev()
+                    .find(|(child_index, _)| *child_index < index);
+                let next = element_children
+                    .iter()
+                    .find(|(child_index, _)| *child_index >= index);
+                to_restyle.extend(previous.into_iter().chain(next).map(|(_, id)| *id));
+            }
+        }
+
+        for node_id in to_restyle {
+            self.nodes[node_id].set_restyle_hint(RestyleHint::restyle_subtree());
+        }
+
+        self.invalidate_relative_selector_anchors(parent_id);
+    }
+
+    /// Restyle the anchors of any `:has()` selectors which may be affected by a change to
+    /// `node_id` (or its descendants). Anchors are ancestors of the node (for `:has(.a)`) or
+    /// earlier siblings of the node or its ancestors (for `:has(+ .a)` and `:has(~ .a)`).
+    pub(crate) fn invalidate_relative_selector_anchors(&mut self, node_id: usize) {
+        let anchor_flags = RELATIVE_SELECTOR_ANCHOR_FLAGS;
+
+        // The document node is flagged when any element becomes an anchor (see
+        // `apply_selector_flags`), so there is nothing to find if no `:has()` selector has
+        // been matched
+        if !self.nodes[0].selector_flags.get().intersects(anchor_flags) {
+            return;
+        }
+
+        let mut to_restyle = Vec::new();
+        let mut push_anchor = |doc: &BaseDocument, anchor_id: usize| {
+            let anchor = &doc.nodes[anchor_id];
+            let flags = anchor.selector_flags.get();
+            if flags.contains(ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR_NON_SUBJECT) {
+                // The anchor is matched as part of a larger selector (e.g. `.a:has(.b) + .c`),
+                // so its siblings and their descendants may be affected too
+                to_restyle.push(anchor.parent.unwrap_or(anchor_id));
+            } else if flags.contains(ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR) {
+                to_restyle.push(anchor_id);
+            }
+        };
+
+        let mut current = Some(node_id);
+        while let Some(id) = current {
+            let node = &self.nodes[id];
+            if node.selector_flags.get().intersects(anchor_flags) {
+                push_anchor(self, id);
+            }
+            if let Some(parent_id) = node.parent {
+                for &sibling_id in &self.nodes[parent_id].children {
+                    if sibling_id == id {
+                        break;
+                    }
+                    if self.nodes[sibling_id]
+                        .selector_flags
+                        .get()
+                        .intersects(anchor_flags)
+                    {
+                        push_anchor(self, sibling_id);
+                    }
+                }
+            }
+            current = node.parent;
+        }
+
+        for node_id in to_restyle {
+            if self.nodes[node_id].is_element() {
+                self.nodes[node_id].set_restyle_hint(RestyleHint::restyle_subtree());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DocumentConfig, qual_name};
+
+    fn restyle_hint(doc: &BaseDocument, node_id: usize) -> RestyleHint {
+        doc.nodes[node_id]
+            .stylo_element_data
+            .get()
+            .map_or(RestyleHint::empty(), |data| data.hint)
+    }
+
+    #[test]
+    fn test_relative_selector_anchors_are_restyled() {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let mut mutator = doc.mutate();
+        let item = mutator.create_element(qual_name!("li", html), Vec::new());
+        let list = mutator.create_element(qual_name!("ul", html), Vec::new());
+        mutator.append_children(list, &[item]);
+        let heading = mutator.create_element(qual_name!("h1", html), Vec::new());
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[heading, list]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        // The servo build of Stylo doesn't parse `:has()` yet, so flag the anchors as matching
+        // `ul:has(li.selected)` and `h1:has(+ ul li.selected) + *` would
+        doc.nodes[list]
+            .selector_flags
+            .set(ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR);
+        doc.nodes[heading]
+            .selector_flags
+            .set(ElementSelectorFlags::ANCHORS_RELATIVE_SELECTOR_NON_SUBJECT);
+        doc.nodes[0]
+            .selector_flags
+            .set(RELATIVE_SELECTOR_ANCHOR_FLAGS);
+
+        doc.mutate()
+            .set_attribute(item, qual_name!("class"), "selected");
+        assert!(restyle_hint(&doc, list).contains(RestyleHint::restyle_subtree()));
+        // The siblings of a non-subject anchor are restyled through their parent
+        assert!(restyle_hint(&doc, body).contains(RestyleHint::restyle_subtree()));
+        assert!(restyle_hint(&doc, html).is_empty());
+    }
+}
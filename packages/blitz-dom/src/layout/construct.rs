@@ -10,9 +10,12 @@ use slab::Slab;
 use style::{
     computed_values::position::T as PositionProperty,
     data::ElementData as StyloElementData,
+    properties::ComputedValues,
+    selector_parser::PseudoElement,
+    servo_arc::Arc as ServoArc,
     shared_lock::StylesheetGuards,
     values::{
-        computed::{Content, ContentItem, Display, Float, TextTransform},
+        computed::{Display, Float},
         specified::box_::{DisplayInside, DisplayOutside},
     },
 };
@@ -28,9 +31,16 @@ use crate::{
     traversal::{iter_children, iter_children_and_pseudos},
 };
 
-use super::{damage::ALL_DAMAGE, list::collect_list_item_children, table::build_table_context};
+use super::{
+    damage::ALL_DAMAGE,
+    generated_content::{ContentPart, GeneratedContent},
+    list::collect_list_item_children,
+    table::build_table_context,
+    text::{Hyphens, TextProcessing, WhiteSpaceState, push_text},
+};
 
 const DUMMY_NAME: QualName = qual_name!("div", html);
+const IMG_NAME: QualName = qual_name!("img", html);
 
 #[derive(Clone)]
 pub(crate) struct ConstructionTask {
@@ -410,25 +420,38 @@ fn flush_pseudo_elements(doc: &mut BaseDocument, node_id: usize) {
     };
 
     // Sync pseudo element
-    // TODO: Make incremental
     for (idx, pe_style, pe_node_id) in [
         (1, before_style, before_node_id),
         (0, after_style, after_node_id),
     ] {
-        // Delete psuedo element if it exists but shouldn't
-        if let (Some(pe_node_id), None) = (pe_node_id, &pe_style) {
-            doc.remove_and_drop_pe(pe_node_id);
-            let node = &mut doc.nodes[node_id];
-            node.set_pe_by_index(idx, None);
-            node.insert_damage(ALL_DAMAGE);
-        }
+        // Content is resolved ahead of box construction (see `resolve_generated_content`)
+        let content = doc.nodes[node_id]
+            .element_data()
+            .and_then(|element| element.generated_content[idx].clone());
+        let pe_style = pe_style.filter(|_| content.is_some());
+
+        // Delete psuedo element if it exists but shouldn't, or if it switches between
+        // being an image and containing text
+        let is_image = matches!(content, Some(GeneratedContent::Image(_)));
+        let pe_node_id = match (pe_node_id, &pe_style) {
+            (Some(pe_node_id), Some(_)) if is_image_pe(doc, pe_node_id) == is_image => {
+                Some(pe_node_id)
+            }
+            (Some(pe_node_id), _) => {
+                doc.remove_and_drop_pe(pe_node_id);
+                let node = &mut doc.nodes[node_id];
+                node.set_pe_by_index(idx, None);
+                node.insert_damage(ALL_DAMAGE);
+                None
+            }
+            (None, _) => None,
+        };
 
         // Create pseudo element if it should exist but doesn't
         if let (None, Some(pe_style)) = (pe_node_id, &pe_style) {
-            let new_node_id = doc.create_node(NodeData::AnonymousBlock(ElementData::new(
-                DUMMY_NAME,
-                Vec::new(),
-            )));
+            let name = if is_image { IMG_NAME } else { DUMMY_NAME };
+            let new_node_id =
+                doc.create_node(NodeData::AnonymousBlock(ElementData::new(name, Vec::new())));
             doc.nodes[new_node_id].parent = Some(node_id);
             doc.nodes[new_node_id].layout_parent.set(Some(node_id));
             if doc.nodes[node_id].flags.contains(NodeFlags::IS_IN_DOCUMENT) {
@@ -437,26 +460,18 @@ fn flush_pseudo_elements(doc: &mut BaseDocument, node_id: usize) {
                     .insert(NodeFlags::IS_IN_DOCUMENT);
             }
 
-            let content = &pe_style.as_ref().get_counters().content;
-            if let Content::Items(item_data) = content {
-                let items = &item_data.items[0..item_data.alt_start];
-                match &items[0] {
-                    ContentItem::String(owned_str) => {
-                        let text_node_id = doc.create_text_node(owned_str);
-                        doc.nodes[new_node_id].children.push(text_node_id);
-                    }
-                    _ => {
-                        // TODO: other types of content
-                    }
-                }
-            }
-
             let mut element_data = StyloElementData::default();
             element_data.styles.primary = Some(pe_style.clone());
             element_data.set_restyled();
             element_data.damage = ALL_DAMAGE;
             *doc.nodes[new_node_id].stylo_element_data.ensure_init_mut() = element_data;
 
+            match &content {
+                Some(GeneratedContent::Image(url)) => doc.load_image(new_node_id, url.clone()),
+                Some(content) => update_pe_children(doc, new_node_id, &content.parts(), pe_style),
+                None => {}
+            }
+
             let node = &mut doc.nodes[node_id];
             node.set_pe_by_index(idx, Some(new_node_id));
             node.insert_damage(ALL_DAMAGE);
@@ -464,7 +479,24 @@ fn flush_pseudo_elements(doc: &mut BaseDocument, node_id: usize) {
 
         // Else: Update psuedo element
         if let (Some(pe_node_id), Some(pe_style)) = (pe_node_id, pe_style) {
-            // TODO: Update content
+            match content {
+                Some(GeneratedContent::Image(url)) => {
+                    let is_loaded = matches!(
+                        doc.nodes[pe_node_id]
+                            .element_data()
+                            .map(|e| &e.special_data),
+                        Some(SpecialElementData::Image(_))
+                    );
+                    let src_changed =
+                        doc.nodes[pe_node_id].attr(local_name!("src")) != Some(url.as_str());
+                    if src_changed || !is_loaded {
+                        set_pe_src(doc, pe_node_id, &url);
+                        doc.load_image(pe_node_id, url);
+                    }
+                }
+                Some(content) => update_pe_children(doc, pe_node_id, &content.parts(), &pe_style),
+                None => {}
+            }
 
             let mut node_styles = doc.nodes[pe_node_id].stylo_element_data.get_mut();
             let node_styles = &mut node_styles.as_mut().unwrap();
@@ -479,6 +511,101 @@ fn flush_pseudo_elements(doc: &mut BaseDocument, node_id: usize) {
     }
 }
 
+fn is_image_pe(doc: &BaseDocument, pe_node_id: usize) -> bool {
+    doc.nodes[pe_node_id]
+        .element_data()
+        .is_some_and(|element| element.name.local == local_name!("img"))
+}
+
+/// Record the source of an image pseudo element, so that we can tell when it changes
+fn set_pe_src(doc: &mut BaseDocument, pe_node_id: usize, url: &url::Url) {
+    let element = doc.nodes[pe_node_id].element_data_mut().unwrap();
+    element.attrs.set(qual_name!("src"), url.as_str());
+}
+
+/// Update the children of a (non-image) pseudo element to match its content: a text node for each
+/// run of text and an inline replaced element for each image. Existing children are reused if the
+/// structure of the content hasn't changed.
+fn update_pe_children(
+    doc: &mut BaseDocument,
+    pe_node_id: usize,
+    parts: &[ContentPart],
+    pe_style: &ServoArc<ComputedValues>,
+) {
+    let children = doc.nodes[pe_node_id].children.clone();
+    let is_same_structure = children.len() == parts.len()
+        && children.iter().zip(parts).all(|(child_id, part)| {
+            let child = &doc.nodes[*child_id];
+            match part {
+                ContentPart::Text(_) => child.is_text_node(),
+                ContentPart::Image(url) => child.attr(local_name!("src")) == Some(url.as_str()),
+            }
+        });
+
+    if is_same_structure {
+        for (child_id, part) in children.iter().zip(parts) {
+            let ContentPart::Text(text) = part else {
+                continue;
+            };
+            let text_node = &mut doc.nodes[*child_id];
+            let text_data = text_node.text_data_mut().unwrap();
+            if text_data.content != *text {
+                text_data.content = text.clone();
+                text_node.insert_damage(ALL_DAMAGE);
+                doc.nodes[pe_node_id].insert_damage(ALL_DAMAGE);
+            }
+        }
+        return;
+    }
+
+    for child_id in children {
+        doc.remove_and_drop_pe(child_id);
+    }
+    let is_in_document = doc.nodes[pe_node_id]
+        .flags
+        .contains(NodeFlags::IS_IN_DOCUMENT);
+    for part in parts {
+        let child_id = match part {
+            ContentPart::Text(text) => doc.create_text_node(text),
+            ContentPart::Image(url) => {
+                let image_id = doc.create_node(NodeData::AnonymousBlock(ElementData::new(
+                    IMG_NAME,
+                    Vec::new(),
+                )));
+
+                // Images are anonymous inline boxes which inherit from the pseudo element
+                let style = {
+                    let read_guard = doc.guard.read();
+                    let guards = StylesheetGuards::same(&read_guard);
+                    doc.stylist.style_for_anonymous::<&Node>(
+                        &guards,
+                        &PseudoElement::ServoAnonymousBox,
+                        pe_style,
+                    )
+                };
+                let mut element_data = StyloElementData::default();
+                element_data.styles.primary = Some(style);
+                element_data.set_restyled();
+                element_data.damage = ALL_DAMAGE;
+                *doc.nodes[image_id].stylo_element_data.ensure_init_mut() = element_data;
+
+                set_pe_src(doc, image_id, url);
+                doc.load_image(image_id, url.clone());
+                image_id
+            }
+        };
+
+        let child = &mut doc.nodes[child_id];
+        child.parent = Some(pe_node_id);
+        child.layout_parent.set(Some(pe_node_id));
+        if is_in_document {
+            child.flags.insert(NodeFlags::IS_IN_DOCUMENT);
+        }
+        doc.nodes[pe_node_id].children.push(child_id);
+    }
+    doc.nodes[pe_node_id].insert_damage(ALL_DAMAGE);
+}
+
 /// Handles the cases where there are text nodes or inline nodes that need to be wrapped in an anonymous block node
 fn collect_complex_layout_children(
     doc: &mut BaseDocument,
@@ -531,8 +658,6 @@ fn collect_complex_layout_children(
         // Push nodes that need wrapping into the current "anonymous block container".
         // If there is not an open one then we create one.
         else if needs_wrap(child_node_kind, display_outside) {
-            use style::selector_parser::PseudoElement;
-
             if anonymous_block_id.is_none() {
                 const NAME: QualName = QualName {
                     prefix: None,
@@ -787,17 +912,13 @@ pub(crate) fn build_inline_layout_into(
     let mut builder = layout_ctx.tree_builder(font_ctx, scale, true, &parley_style);
 
     // Set whitespace collapsing mode
-    let collapse_mode = root_node_style
+    let hyphens = Hyphens::inherited(nodes, inline_context_root_node_id);
+    let text_processing = root_node_style
         .as_ref()
-        .map(|s| s.get_inherited_text().white_space_collapse)
-        .map(stylo_to_parley::white_space_collapse)
-        .unwrap_or(WhiteSpaceCollapse::Collapse);
-    builder.set_white_space_mode(collapse_mode);
-
-    let text_transform = root_node_style
-        .as_ref()
-        .map(|s| s.clone_text_transform() & TextTransform::CASE_TRANSFORMS)
-        .unwrap_or(TextTransform::NONE);
+        .map(|s| TextProcessing::from_style(s, hyphens))
+        .unwrap_or_default();
+    text_processing.apply_white_space_mode(&mut builder);
+    let mut white_space = WhiteSpaceState::default();
 
     // Render position-inside list items
     if let Some(ListItemLayout {
@@ -811,6 +932,7 @@ pub(crate) fn build_inline_layout_into(
             Marker::Char(char) => builder.push_text(&format!("{char} ")),
             Marker::String(str) => builder.push_text(str),
         }
+        white_space.push_atomic_inline(&mut builder);
     };
 
     if let Some(before_id) = root_node.before {
@@ -819,9 +941,9 @@ pub(crate) fn build_inline_layout_into(
             nodes,
             inline_context_root_node_id,
             before_id,
-            collapse_mode,
-            text_transform,
+            text_processing,
             root_line_height,
+            &mut white_space,
         );
     }
     for child_id in root_node.children.iter().copied() {
@@ -830,9 +952,9 @@ pub(crate) fn build_inline_layout_into(
             nodes,
             inline_context_root_node_id,
             child_id,
-            collapse_mode,
-            text_transform,
+            text_processing,
             root_line_height,
+            &mut white_space,
         );
     }
     if let Some(after_id) = root_node.after {
@@ -841,9 +963,9 @@ pub(crate) fn build_inline_layout_into(
             nodes,
             inline_context_root_node_id,
             after_id,
-            collapse_mode,
-            text_transform,
+            text_processing,
             root_line_height,
+            &mut white_space,
         );
     }
 
@@ -855,9 +977,9 @@ pub(crate) fn build_inline_layout_into(
         nodes: &Slab<Node>,
         parent_id: usize,
         node_id: usize,
-        collapse_mode: WhiteSpaceCollapse,
-        parent_text_transform: TextTransform,
+        parent_text_processing: TextProcessing,
         root_line_height: f32,
+        white_space: &mut WhiteSpaceState,
     ) {
         let node = &nodes[node_id];
 
@@ -868,15 +990,11 @@ pub(crate) fn build_inline_layout_into(
         let style = style.as_ref();
 
         // Set whitespace collapsing mode
-        let collapse_mode = style
-            .map(|s| s.clone_white_space_collapse())
-            .map(stylo_to_parley::white_space_collapse)
-            .unwrap_or(collapse_mode);
-        builder.set_white_space_mode(collapse_mode);
-
-        let text_transform = style
-            .map(|s| s.clone_text_transform() & TextTransform::CASE_TRANSFORMS)
-            .unwrap_or(TextTransform::NONE);
+        let hyphens = Hyphens::from_node(node).unwrap_or(parent_text_processing.hyphens);
+        let text_processing = style
+            .map(|s| TextProcessing::from_style(s, hyphens))
+            .unwrap_or(parent_text_processing);
+        text_processing.apply_white_space_mode(builder);
 
         match &node.data {
             NodeData::Element(element_data) | NodeData::AnonymousBlock(element_data) => {
@@ -912,9 +1030,9 @@ pub(crate) fn build_inline_layout_into(
                                 nodes,
                                 parent_id,
                                 child_id,
-                                collapse_mode,
-                                text_transform,
+                                text_processing,
                                 root_line_height,
+                                white_space,
                             );
                         }
                     }
@@ -927,6 +1045,7 @@ pub(crate) fn build_inline_layout_into(
                             || *tag_name == local_name!("textarea")
                             || *tag_name == local_name!("button")
                         {
+                            white_space.push_atomic_inline(builder);
                             builder.push_inline_box(InlineBox {
                                 id: node_id as u64,
                                 kind: box_kind,
@@ -943,7 +1062,8 @@ pub(crate) fn build_inline_layout_into(
                             builder.set_white_space_mode(WhiteSpaceCollapse::Preserve);
                             builder.push_text("\n");
                             builder.pop_style_span();
-                            builder.set_white_space_mode(collapse_mode);
+                            white_space.push_line_break();
+                            text_processing.apply_white_space_mode(builder);
                         } else {
                             // node.remove_damage(CONSTRUCT_DESCENDENT | CONSTRUCT_FC | CONSTRUCT_BOX);
                             let mut style = node
@@ -973,9 +1093,9 @@ pub(crate) fn build_inline_layout_into(
                                     nodes,
                                     node_id,
                                     before_id,
-                                    collapse_mode,
-                                    text_transform,
+                                    text_processing,
                                     root_line_height,
+                                    white_space,
                                 );
                             }
 
@@ -985,9 +1105,9 @@ pub(crate) fn build_inline_layout_into(
                                     nodes,
                                     node_id,
                                     child_id,
-                                    collapse_mode,
-                                    text_transform,
+                                    text_processing,
                                     root_line_height,
+                                    white_space,
                                 );
                             }
                             if let Some(after_id) = node.after {
@@ -996,9 +1116,9 @@ pub(crate) fn build_inline_layout_into(
                                     nodes,
                                     node_id,
                                     after_id,
-                                    collapse_mode,
-                                    text_transform,
+                                    text_processing,
                                     root_line_height,
+                                    white_space,
                                 );
                             }
 
@@ -1007,6 +1127,7 @@ pub(crate) fn build_inline_layout_into(
                     }
                     // Inline box
                     (_, _) => {
+                        white_space.push_atomic_inline(builder);
                         builder.push_inline_box(InlineBox {
                             id: node_id as u64,
                             kind: box_kind,
@@ -1023,18 +1144,14 @@ pub(crate) fn build_inline_layout_into(
                 // node.remove_damage(CONSTRUCT_DESCENDENT | CONSTRUCT_FC | CONSTRUCT_BOX);
                 // dbg!(&data.content);
 
-                // TODO: optimize case transforms to be non-allocating
-                match parent_text_transform {
-                    TextTransform::UPPERCASE => {
-                        builder.push_text(&data.content.to_uppercase());
-                    }
-                    TextTransform::LOWERCASE => {
-                        builder.push_text(&data.content.to_lowercase());
-                    }
-                    _ => {
-                        builder.push_text(&data.content);
-                    }
-                }
+                push_text(
+                    builder,
+                    nodes,
+                    node_id,
+                    &data.content,
+                    text_processing,
+                    white_space,
+                );
             }
             NodeData::Comment => {
                 // node.remove_damage(CONSTRUCT_DESCENDENT | CONSTRUCT_FC | CONSTRUCT_BOX);
@@ -1,16 +1,11 @@
 use std::ops::Range;
 
-use crate::net::ResourceHandler;
+use crate::BaseDocument;
 use crate::node::NodeFlags;
-use crate::{
-    BaseDocument, net::ImageHandler, node::BackgroundImageData, node::Status, util::ImageType,
-};
 use crate::{NON_INCREMENTAL, Node};
-use blitz_traits::net::Request;
 use style::properties::ComputedValues;
 use style::properties::generated::longhands::position::computed_value::T as Position;
 use style::selector_parser::RestyleDamage;
-use style::servo::url::ComputedUrl;
 use style::values::computed::Float;
 use style::values::generics::image::Image as StyloImage;
 use style::values::specified::align::AlignFlags;
@@ -43,6 +38,10 @@ impl BaseDocument {
         } else {
             return RestyleDamage::empty();
         };
+        if !damage.is_empty() {
+            // Damaged by a restyle or a DOM mutation since the last frame
+            self.invalidate_paint(node_id);
+        }
         damage |= damage_from_parent;
 
         let damage_for_children = RestyleDamage::empty();
@@ -377,8 +376,6 @@ impl BaseDocument {
         node_id: usize,
         parent_stacking_context: Option<&mut HoistedPaintChildren>,
     ) {
-        let doc_id = self.id();
-
         let mut new_stacking_context: HoistedPaintChildren = HoistedPaintChildren::new();
         let stacking_context = &mut new_stacking_context;
 
@@ -399,76 +396,6 @@ impl BaseDocument {
             node.display_constructed_as = style.clone_display();
             // }
 
-            // Flush background image from style to dedicated storage on the node
-            // TODO: handle multiple background images
-            if let Some(elem) = node.data.downcast_element_mut() {
-                let style_bgs = &style.get_background().background_image.0;
-                let elem_bgs = &mut elem.background_images;
-
-                let len = style_bgs.len();
-                elem_bgs.resize_with(len, || None);
-
-                for idx in 0..len {
-                    let background_image = &style_bgs[idx];
-                    let new_bg_image = match background_image {
-                        StyloImage::Url(ComputedUrl::Valid(new_url)) => {
-                            let old_bg_image = elem_bgs[idx].as_ref();
-                            let old_bg_image_url = old_bg_image.map(|data| &data.url);
-                            if old_bg_image_url.is_some_and(|old_url| **new_url == **old_url) {
-                                break;
-                            }
-
-                            // Check cache first
-                            let url_str = new_url.as_str();
-                            if let Some(cached_image) = self.image_cache.get(url_str) {
-                                #[cfg(feature = "tracing")]
-                                tracing::info!("Loading image {url_str} from cache");
-                                Some(BackgroundImageData {
-                                    url: new_url.clone(),
-                                    status: Status::Ok,
-                                    image: cached_image.clone(),
-                                })
-                            } else if let Some(waiting_list) = self.pending_images.get_mut(url_str)
-                            {
-                                // Image is already being fetched, queue this node
-                                #[cfg(feature = "tracing")]
-                                tracing::info!(
-                                    "Image {url_str} already pending, queueing node {node_id}"
-                                );
-                                waiting_list.push((node_id, ImageType::Background(idx)));
-                                Some(BackgroundImageData::new(new_url.clone()))
-                            } else {
-                                // Start fetch and track as pending
-                                #[cfg(feature = "tracing")]
-                                tracing::info!("Fetching image {url_str}");
-                                self.pending_images.insert(
-                                    url_str.to_string(),
-                                    vec![(node_id, ImageType::Background(idx))],
-                                );
-
-                                self.net_provider.fetch(
-                                    doc_id,
-                                    Request::get((**new_url).clone()),
-                                    ResourceHandler::boxed(
-                                        self.tx.clone(),
-                                        doc_id,
-                                        None, // Don't pass node_id, we'll handle via pending_images
-                                        self.shell_provider.clone(),
-                                        ImageHandler::new(ImageType::Background(idx)),
-                                    ),
-                                );
-
-                                Some(BackgroundImageData::new(new_url.clone()))
-                            }
-                        }
-                        _ => None,
-                    };
-
-                    // Element will always exist due to resize_with above
-                    elem_bgs[idx] = new_bg_image;
-                }
-            }
-
             // In non-incremental mode we unconditionally clear the Taffy cache.
             // In incremental mode this is handled as part of damage propagation.
             if NON_INCREMENTAL {
@@ -485,6 +412,9 @@ impl BaseDocument {
             node.style.display
         };
 
+        // Flush background images from style to dedicated storage on the node
+        self.load_background_images(node_id);
+
         // If the node has children, then take those children and...
         let children = self.nodes[node_id].layout_children.borrow_mut().take();
         if let Some(mut children) = children {
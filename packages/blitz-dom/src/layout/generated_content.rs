@@ -0,0 +1,552 @@
+//! Resolution of the `content` property of `::before` and `::after` pseudo-elements
+//!
+//! Counters (`counter-reset`, `counter-increment` and `counter-set`) and quote nesting depend on
+//! everything that precedes a pseudo-element in the document. So rather than resolving content
+//! during box construction (which is incremental), we resolve the content of every pseudo-element
+//! in a single tree-order pass after styling. Box construction then reads the resolved content.
+//!
+//! Stylo's servo build doesn't support `counter-set`, so it is read from elements' `style` attributes.
+//!
+//! The pass only runs in frames where content may have changed: when elements were restyled, or
+//! when the tree or an attribute (which `attr()` may read) was mutated.
+//!
+//! See <https://drafts.csswg.org/css-content/> and <https://drafts.csswg.org/css-lists/#auto-numbering>
+
+use std::borrow::Cow;
+
+use cssparser::{Parser, ParserInput, Token};
+use markup5ever::{LocalName, local_name};
+use style::Atom;
+use style::properties::ComputedValues;
+use style::servo::url::ComputedUrl;
+use style::servo_arc::Arc as ServoArc;
+use style::values::computed::{Content, ContentItem};
+use style::values::generics::image::Image as StyloImage;
+use style::values::specified::list::Quotes;
+use url::Url;
+
+use super::damage::ALL_DAMAGE;
+use super::list::counter_representation;
+use crate::BaseDocument;
+
+/// Quotes used for `quotes: auto`
+const DEFAULT_QUOTES: [(&str, &str); 2] = [("\u{201C}", "\u{201D}"), ("\u{2018}", "\u{2019}")];
+
+/// The resolved content of a pseudo-element
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratedContent {
+    /// Text from concatenating strings, counters, quotes and attribute values
+    Text(String),
+    /// A single `url()` image. The pseudo-element is rendered as a replaced element.
+    Image(Url),
+    /// Text mixed with images. Each image is rendered as an inline replaced element.
+    Mixed(Vec<ContentPart>),
+}
+
+/// A run of text or an image within [`GeneratedContent::Mixed`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentPart {
+    Text(String),
+    Image(Url),
+}
+
+impl GeneratedContent {
+    /// The children of the pseudo-element. Empty for [`GeneratedContent::Image`], whose content is
+    /// the pseudo-element itself.
+    pub(crate) fn parts(&self) -> Cow<'_, [ContentPart]> {
+        match self {
+            GeneratedContent::Text(text) => Cow::Owned(vec![ContentPart::Text(text.clone())]),
+            GeneratedContent::Image(_) => Cow::Borrowed(&[]),
+            GeneratedContent::Mixed(parts) => Cow::Borrowed(parts),
+        }
+    }
+}
+
+/// An instance of a counter
+struct CounterInstance {
+    name: Atom,
+    value: i32,
+    /// The parent of the element that created the counter. The counter is in scope for that element's
+    /// following siblings and their descendants.
+    scope: usize,
+    /// Whether the counter counts down (the `list-item` counter of an `<ol reversed>`)
+    reversed: bool,
+}
+
+#[derive(Default)]
+struct ContentState {
+    /// Counters that are in scope, from outermost to innermost
+    counters: Vec<CounterInstance>,
+    quote_depth: usize,
+}
+
+impl ContentState {
+    fn innermost_mut(&mut self, name: &Atom) -> Option<&mut CounterInstance> {
+        self.counters
+            .iter_mut()
+            .rev()
+            .find(|counter| counter.name == *name)
+    }
+
+    fn reset(&mut self, name: &Atom, value: i32, scope: usize) {
+        // Resetting a counter created by a previous sibling replaces it rather than nesting a new one
+        match self.innermost_mut(name) {
+            Some(counter) if counter.scope == scope => counter.value = value,
+            _ => self.counters.push(CounterInstance {
+                name: name.clone(),
+                value,
+                scope,
+                reversed: false,
+            }),
+        }
+    }
+
+    /// Get the innermost instance of a counter, instantiating it if it does not exist
+    fn get_or_instantiate(&mut self, name: &Atom, scope: usize) -> &mut CounterInstance {
+        if self.innermost_mut(name).is_none() {
+            self.reset(name, 0, scope);
+        }
+        self.innermost_mut(name).unwrap()
+    }
+
+    fn apply_counter_properties(
+        &mut self,
+        style: &ComputedValues,
+        scope: usize,
+        is_list_item: bool,
+        counter_set: &[(Atom, i32)],
+    ) {
+        let counters = style.get_counters();
+
+        for pair in counters.counter_reset.iter() {
+            self.reset(&pair.name.0, pair.value, scope);
+        }
+
+        let mut increments_list_item = false;
+        for pair in counters.counter_increment.iter() {
+            increments_list_item |= pair.name.0 == *"list-item";
+            let counter = self.get_or_instantiate(&pair.name.0, scope);
+            counter.value = counter.value.saturating_add(pair.value);
+        }
+        // List items implicitly increment the list-item counter (or decrement it in reversed lists)
+        if is_list_item && !increments_list_item {
+            let counter = self.get_or_instantiate(&Atom::from("list-item"), scope);
+            let step = if counter.reversed { -1 } else { 1 };
+            counter.value = counter.value.saturating_add(step);
+        }
+
+        for (name, value) in counter_set {
+            self.get_or_instantiate(name, scope).value = *value;
+        }
+    }
+
+    fn value(&self, name: &Atom) -> i32 {
+        self.counters
+            .iter()
+            .rev()
+            .find(|counter| counter.name == *name)
+            .map_or(0, |counter| counter.value)
+    }
+
+    fn values(&self, name: &Atom) -> impl Iterator<Item = i32> {
+        self.counters
+            .iter()
+            .filter(move |counter| counter.name == *name)
+            .map(|counter| counter.value)
+    }
+
+    /// Remove counters created by children of the node
+    fn leave_scope(&mut self, scope: usize) {
+        self.counters.retain(|counter| counter.scope != scope);
+    }
+}
+
+impl BaseDocument {
+    /// Resolve the content of all `::before` and `::after` pseudo-elements in the document.
+    ///
+    /// Elements whose resolved content has changed are marked as damaged so that their
+    /// pseudo-elements are updated during box construction.
+    pub(crate) fn resolve_generated_content(&mut self) {
+        if !std::mem::take(&mut self.generated_content_dirty) {
+            return;
+        }
+
+        let root_id = self.root_node().id;
+        let mut state = ContentState::default();
+        self.resolve_generated_content_recursive(root_id, &mut state);
+    }
+
+    fn resolve_generated_content_recursive(&mut self, node_id: usize, state: &mut ContentState) {
+        let node = &self.nodes[node_id];
+        if node.is_element() {
+            let Some(style) = node.primary_styles().map(|style| ServoArc::clone(&style)) else {
+                return;
+            };
+
+            // Elements which do not generate boxes do not affect counters
+            let display = style.get_box().display;
+            if display.is_none() {
+                return;
+            }
+
+            let scope = node.parent.unwrap_or(node_id);
+            let element = node.element_data().unwrap();
+            if matches!(
+                element.name.local,
+                local_name!("ol") | local_name!("ul") | local_name!("menu")
+            ) && !resets_counter(&style, "list-item")
+            {
+                let start = element
+                    .attr(local_name!("start"))
+                    .and_then(|start| start.parse::<i32>().ok());
+                // Reversed lists count down from the number of list items
+                let reversed = element.name.local == local_name!("ol")
+                    && element.attr(local_name!("reversed")).is_some();
+                let value = if reversed {
+                    let count = || self.list_item_child_count(node_id) as i32;
+                    start.unwrap_or_else(count).saturating_add(1)
+                } else {
+                    start.map_or(0, |start| start.saturating_sub(1))
+                };
+                let list_item = Atom::from("list-item");
+                state.reset(&list_item, value, scope);
+                state.innermost_mut(&list_item).unwrap().reversed = reversed;
+            }
+            let counter_set = node
+                .inline_style_value("counter-set")
+                .map(parse_counter_set)
+                .unwrap_or_default();
+            state.apply_counter_properties(&style, scope, display.is_list_item(), &counter_set);
+        }
+
+        // Note: yes these are kinda backwards
+        let (before_style, after_style) = {
+            let style_data = self.nodes[node_id].stylo_element_data.get();
+            let pseudos = style_data
+                .as_ref()
+                .map(|data| data.styles.pseudos.as_array());
+            (
+                pseudos.and_then(|pseudos| pseudos[1].clone()),
+                pseudos.and_then(|pseudos| pseudos[0].clone()),
+            )
+        };
+
+        let before = before_style.map(|style| {
+            state.apply_counter_properties(&style, node_id, false, &[]);
+            self.resolve_content(node_id, &style, state)
+        });
+
+        // Resolving content doesn't change the tree, so children can be visited by index
+        for idx in 0..self.nodes[node_id].children.len() {
+            let child_id = self.nodes[node_id].children[idx];
+            self.resolve_generated_content_recursive(child_id, state);
+        }
+
+        let after = after_style.map(|style| {
+            state.apply_counter_properties(&style, node_id, false, &[]);
+            self.resolve_content(node_id, &style, state)
+        });
+
+        state.leave_scope(node_id);
+
+        // Store resolved content, marking the element as damaged if it has changed
+        let node = &mut self.nodes[node_id];
+        if let Some(element) = node.element_data_mut() {
+            let content = [after.flatten(), before.flatten()];
+            if element.generated_content != content {
+                element.generated_content = content;
+                node.insert_damage(ALL_DAMAGE);
+            }
+        }
+    }
+
+    /// The number of children of a node which are list items
+    fn list_item_child_count(&self, node_id: usize) -> usize {
+        self.nodes[node_id]
+            .children
+            .iter()
+            .filter(|child_id| {
+                self.nodes[**child_id]
+                    .primary_styles()
+                    .is_some_and(|style| style.get_box().display.is_list_item())
+            })
+            .count()
+    }
+
+    /// Resolve the `content` property of a pseudo-element of the specified node
+    fn resolve_content(
+        &self,
+        node_id: usize,
+        style: &ComputedValues,
+        state: &mut ContentState,
+    ) -> Option<GeneratedContent> {
+        let Content::Items(item_data) = &style.get_counters().content else {
+            return None;
+        };
+        let items = &item_data.items[0..item_data.alt_start];
+
+        // A single image is rendered as a replaced element
+        if let [ContentItem::Image(StyloImage::Url(ComputedUrl::Valid(url)))] = items {
+            return Some(GeneratedContent::Image((**url).clone()));
+        }
+
+        let quotes = &style.get_list().quotes;
+        let quote = |depth: usize, open: bool| -> String {
+            let pair = match quotes {
+                Quotes::QuoteList(list) => {
+                    let pairs = &list.0;
+                    pairs
+                        .get(depth.min(pairs.len().saturating_sub(1)))
+                        .map(|pair| (pair.opening.to_string(), pair.closing.to_string()))
+                }
+                Quotes::Auto => {
+                    let (opening, closing) = DEFAULT_QUOTES[depth.min(DEFAULT_QUOTES.len() - 1)];
+                    Some((opening.to_string(), closing.to_string()))
+                }
+            };
+            pair.map(|(opening, closing)| if open { opening } else { closing })
+                .unwrap_or_default()
+        };
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        for item in items {
+            match item {
+                ContentItem::String(string) => text.push_str(string),
+                ContentItem::Counter(name, counter_style) => {
+                    let value = state.value(&name.0);
+                    text.push_str(&counter_representation(counter_style, value));
+                }
+                ContentItem::Counters(name, separator, counter_style) => {
+                    let mut values = state.values(&name.0).peekable();
+                    if values.peek().is_none() {
+                        text.push_str(&counter_representation(counter_style, 0));
+                    }
+                    let representations: Vec<String> = values
+                        .map(|value| counter_representation(counter_style, value))
+                        .collect();
+                    text.push_str(&representations.join(separator));
+                }
+                ContentItem::OpenQuote => {
+                    text.push_str(&quote(state.quote_depth, true));
+                    state.quote_depth += 1;
+                }
+                ContentItem::CloseQuote if state.quote_depth > 0 => {
+                    state.quote_depth -= 1;
+                    text.push_str(&quote(state.quote_depth, false));
+                }
+                ContentItem::NoOpenQuote => state.quote_depth += 1,
+                ContentItem::NoCloseQuote => {
+                    state.quote_depth = state.quote_depth.saturating_sub(1)
+                }
+                ContentItem::Attr(attr) => {
+                    let name = LocalName::from(&*attr.attribute);
+                    match self.nodes[node_id].attr(name) {
+                        Some(value) => text.push_str(value),
+                        None => text.push_str(attr.fallback.as_ref()),
+                    }
+                }
+                ContentItem::Image(StyloImage::Url(ComputedUrl::Valid(url))) => {
+                    if !text.is_empty() {
+                        parts.push(ContentPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(ContentPart::Image((**url).clone()));
+                }
+                // Unmatched close quotes, invalid URLs and other images (such as gradients) are not
+                // rendered
+                _ => {}
+            }
+        }
+
+        if parts.is_empty() {
+            return Some(GeneratedContent::Text(text));
+        }
+        if !text.is_empty() {
+            parts.push(ContentPart::Text(text));
+        }
+        Some(GeneratedContent::Mixed(parts))
+    }
+}
+
+fn resets_counter(style: &ComputedValues, name: &str) -> bool {
+    style
+        .get_counters()
+        .counter_reset
+        .iter()
+        .any(|pair| pair.name.0 == *name)
+}
+
+/// Parse the value of a `counter-set` declaration into `(name, value)` pairs. The value of a
+/// counter defaults to zero, and invalid values (like `none`) set no counters.
+fn parse_counter_set(value: &str) -> Vec<(Atom, i32)> {
+    let mut input = ParserInput::new(value);
+    let mut parser = Parser::new(&mut input);
+    let mut pairs = Vec::new();
+    while let Ok(token) = parser.next() {
+        let Token::Ident(name) = token else {
+            return Vec::new();
+        };
+        if name.eq_ignore_ascii_case("none") {
+            return Vec::new();
+        }
+        let name = Atom::from(&**name);
+        let value = parser
+            .try_parse(|parser| parser.expect_integer())
+            .unwrap_or(0);
+        pairs.push((name, value));
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+    use markup5ever::{QualName, ns};
+
+    /// Create a document whose body contains a `<p>` for each of `titles` (with that `title`
+    /// attribute, if any), and resolve the content of their `::before` pseudo-elements
+    fn resolve_before(css: &str, titles: &[Option<&str>]) -> Vec<Option<GeneratedContent>> {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            ua_stylesheets: Some(vec![css.to_string()]),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let paragraphs: Vec<usize> = titles
+            .iter()
+            .map(|title| {
+                let attrs = title
+                    .iter()
+                    .map(|title| Attribute {
+                        name: qual_name!("title"),
+                        value: title.to_string(),
+                    })
+                    .collect();
+                mutator.create_element(qual_name!("p", html), attrs)
+            })
+            .collect();
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &paragraphs);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+
+        doc.resolve_stylist(0.0);
+        doc.resolve_generated_content();
+        paragraphs
+            .iter()
+            .map(|id| doc.nodes[*id].element_data().unwrap().generated_content[1].clone())
+            .collect()
+    }
+
+    /// Create a document whose body contains an `<ol>` with the given attributes and number of list
+    /// items, and resolve the content of the list items' `::before` pseudo-elements
+    fn resolve_list_items(
+        css: &str,
+        attrs: &[(&str, &str)],
+        count: usize,
+    ) -> Vec<Option<GeneratedContent>> {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            ua_stylesheets: Some(vec![format!("li {{ display: list-item }} {css}")]),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let items: Vec<usize> = (0..count)
+            .map(|_| mutator.create_element(qual_name!("li", html), Vec::new()))
+            .collect();
+        let attrs = attrs
+            .iter()
+            .map(|(name, value)| Attribute {
+                name: QualName::new(None, ns!(), LocalName::from(*name)),
+                value: value.to_string(),
+            })
+            .collect();
+        let list = mutator.create_element(qual_name!("ol", html), attrs);
+        mutator.append_children(list, &items);
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[list]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+
+        doc.resolve_stylist(0.0);
+        doc.resolve_generated_content();
+        items
+            .iter()
+            .map(|id| doc.nodes[*id].element_data().unwrap().generated_content[1].clone())
+            .collect()
+    }
+
+    fn text(text: &str) -> Option<GeneratedContent> {
+        Some(GeneratedContent::Text(text.to_string()))
+    }
+
+    #[test]
+    fn resolves_counters() {
+        let content = resolve_before(
+            "body { counter-reset: n 5 }
+            p { counter-increment: n 2 }
+            p::before { content: counter(n) '. ' counter(n, upper-roman) }",
+            &[None, None, None],
+        );
+        assert_eq!(content, [text("7. VII"), text("9. IX"), text("11. XI")]);
+    }
+
+    #[test]
+    fn resolves_attr_with_fallback() {
+        let content = resolve_before(
+            "p::before { content: '[' attr(title, 'untitled') ']' }",
+            &[Some("first"), None],
+        );
+        assert_eq!(content, [text("[first]"), text("[untitled]")]);
+    }
+
+    #[test]
+    fn resolves_nested_quotes() {
+        let content = resolve_before(
+            "body { quotes: '«' '»' '‹' '›' } p::before { content: open-quote }",
+            &[None, None, None],
+        );
+        // The last pair of quotes is used beyond its nesting depth
+        assert_eq!(content, [text("«"), text("‹"), text("‹")]);
+    }
+
+    #[test]
+    fn keeps_images_mixed_with_text() {
+        let content = resolve_before(
+            "p::before { content: 'icon: ' url(https://example.com/icon.png) }",
+            &[None],
+        );
+        let url = Url::parse("https://example.com/icon.png").unwrap();
+        assert_eq!(
+            content,
+            [Some(GeneratedContent::Mixed(vec![
+                ContentPart::Text(String::from("icon: ")),
+                ContentPart::Image(url),
+            ]))]
+        );
+    }
+
+    #[test]
+    fn numbers_reversed_lists() {
+        let css = "li::before { content: counter(list-item) }";
+        let content = resolve_list_items(css, &[("reversed", "")], 3);
+        assert_eq!(content, [text("3"), text("2"), text("1")]);
+        let content = resolve_list_items(css, &[("reversed", ""), ("start", "10")], 3);
+        assert_eq!(content, [text("10"), text("9"), text("8")]);
+    }
+
+    #[test]
+    fn sets_counters_from_style_attribute() {
+        let css = "li::before { content: counter(list-item) }";
+        let content = resolve_list_items(css, &[("style", "counter-set: list-item 7")], 2);
+        assert_eq!(content, [text("8"), text("9")]);
+        assert_eq!(
+            parse_counter_set("a 5 b"),
+            [(Atom::from("a"), 5), (Atom::from("b"), 0)]
+        );
+        assert_eq!(parse_counter_set("none"), []);
+    }
+}
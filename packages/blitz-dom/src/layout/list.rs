@@ -154,6 +154,109 @@ fn marker_for_style(list_style_type: ListStyleType, index: usize) -> Option<Mark
     })
 }
 
+/// Represent a counter value using a counter style (for `counter()` and `counters()` in generated content).
+///
+/// Unlike list markers this does not include a suffix. Values which cannot be represented by
+/// a style (such as zero in alphabetic styles) fall back to decimal.
+pub(crate) fn counter_representation(counter_style: &CounterStyle, value: i32) -> String {
+    let representation = match counter_style {
+        CounterStyle::None => return String::new(),
+        CounterStyle::Name(name) => match &*name.0 {
+            "lower-alpha" | "lower-latin" => alphabetic(value, &ALPHABET),
+            "upper-alpha" | "upper-latin" => {
+                alphabetic(value, &ALPHABET).map(|repr| repr.to_ascii_uppercase())
+            }
+            "lower-greek" => alphabetic(value, &GREEK_ALPHABET),
+            "lower-roman" => roman(value).map(|repr| repr.to_ascii_lowercase()),
+            "upper-roman" => roman(value),
+            "decimal-leading-zero" if (0..10).contains(&value) => Some(format!("0{value}")),
+            "arabic-indic" => Some(numeric(value, &ARABIC_INDIC_DIGITS)),
+            "persian" => Some(numeric(value, &PERSIAN_DIGITS)),
+            "devanagari" => Some(numeric(value, &DEVANAGARI_DIGITS)),
+            "cjk-decimal" => Some(numeric(value, &CJK_DECIMAL_DIGITS)),
+            "disc" => Some(String::from('•')),
+            "circle" => Some(String::from('◦')),
+            "square" => Some(String::from('▪')),
+            "disclosure-open" => Some(String::from('▾')),
+            "disclosure-closed" => Some(String::from('▸')),
+            _ => None,
+        },
+        CounterStyle::String(atom_string) => Some(atom_string.as_ref().to_string()),
+        CounterStyle::Symbols { symbols, .. } => {
+            let syms = &symbols.0;
+            (!syms.is_empty()).then(|| {
+                let idx = (value - 1).rem_euclid(syms.len() as i32) as usize;
+                match &syms[idx] {
+                    style::counter_style::Symbol::String(s) => s.to_string(),
+                    style::counter_style::Symbol::Ident(id) => id.0.to_string(),
+                }
+            })
+        }
+    };
+    representation.unwrap_or_else(|| value.to_string())
+}
+
+/// Represent a positive value using an alphabetic counter system (a, b, ..., z, aa, ab, ...)
+fn alphabetic(value: i32, letters: &[char]) -> Option<String> {
+    if value <= 0 {
+        return None;
+    }
+    let mut value = value as usize;
+    let mut representation = Vec::new();
+    while value > 0 {
+        value -= 1;
+        representation.push(letters[value % letters.len()]);
+        value /= letters.len();
+    }
+    Some(representation.into_iter().rev().collect())
+}
+
+/// Represent a value using a numeric counter system with the given digits
+fn numeric(value: i32, digits: &[char; 10]) -> String {
+    let representation: String = value
+        .unsigned_abs()
+        .to_string()
+        .bytes()
+        .map(|digit| digits[(digit - b'0') as usize])
+        .collect();
+    if value < 0 {
+        format!("-{representation}")
+    } else {
+        representation
+    }
+}
+
+/// Represent a value from 1 to 3999 as upper-case roman numerals
+fn roman(value: i32) -> Option<String> {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if !(1..=3999).contains(&value) {
+        return None;
+    }
+    let mut value = value;
+    let mut representation = String::new();
+    for (numeral_value, numeral) in NUMERALS {
+        while value >= numeral_value {
+            representation.push_str(numeral);
+            value -= numeral_value;
+        }
+    }
+    Some(representation)
+}
+
 // Override the font to our specific bullet font when rendering bullets
 fn font_for_bullet_style(list_style_type: ListStyleType) -> Option<FontFamily<'static>> {
     if list_style_type.0.is_bullet() {
@@ -168,6 +271,15 @@ const ALPHABET: [char; 26] = [
     't', 'u', 'v', 'w', 'x', 'y', 'z',
 ];
 
+const GREEK_ALPHABET: [char; 24] = [
+    'α', 'β', 'γ', 'δ', 'ε', 'ζ', 'η', 'θ', 'ι', 'κ', 'λ', 'μ', 'ν', 'ξ', 'ο', 'π', 'ρ', 'σ', 'τ',
+    'υ', 'φ', 'χ', 'ψ', 'ω',
+];
+const ARABIC_INDIC_DIGITS: [char; 10] = ['٠', '١', '٢', '٣', '٤', '٥', '٦', '٧', '٨', '٩'];
+const PERSIAN_DIGITS: [char; 10] = ['۰', '۱', '۲', '۳', '۴', '۵', '۶', '۷', '۸', '۹'];
+const DEVANAGARI_DIGITS: [char; 10] = ['०', '१', '२', '३', '४', '५', '६', '७', '८', '९'];
+const CJK_DECIMAL_DIGITS: [char; 10] = ['〇', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
 // Construct alphanumeric marker from index, appending characters when index exceeds powers of 26
 fn build_alpha_marker(index: usize, str: &mut String) {
     let rem = index % 26;
@@ -188,7 +300,7 @@ mod tests {
         values::{CustomIdent, computed::ListStyleType},
     };
 
-    use super::marker_for_style;
+    use super::{counter_representation, marker_for_style};
 
     fn list_style(s: &str) -> ListStyleType {
         ListStyleType(CounterStyle::Name(CustomIdent(Atom::from(s))))
@@ -231,4 +343,29 @@ mod tests {
         assert_eq!(result_extended_1, Some(Marker::String("AA. ".to_string())));
         assert_eq!(result_extended_2, Some(Marker::String("AB. ".to_string())));
     }
+
+    #[test]
+    fn test_counter_representation() {
+        let decimal = list_style("decimal").0;
+        let lower_alpha = list_style("lower-alpha").0;
+        assert_eq!(counter_representation(&decimal, 3), "3");
+        assert_eq!(counter_representation(&decimal, -1), "-1");
+        assert_eq!(counter_representation(&lower_alpha, 28), "ab");
+        // Alphabetic styles can't represent zero so fall back to decimal
+        assert_eq!(counter_representation(&lower_alpha, 0), "0");
+    }
+
+    #[test]
+    fn test_counter_representation_styles() {
+        let repr = |style: &str, value| counter_representation(&list_style(style).0, value);
+        assert_eq!(repr("upper-roman", 1994), "MCMXCIV");
+        assert_eq!(repr("lower-roman", 4), "iv");
+        assert_eq!(repr("upper-roman", 4000), "4000");
+        assert_eq!(repr("lower-greek", 25), "αα");
+        assert_eq!(repr("decimal-leading-zero", 7), "07");
+        assert_eq!(repr("decimal-leading-zero", 12), "12");
+        assert_eq!(repr("cjk-decimal", 2024), "二〇二四");
+        assert_eq!(repr("arabic-indic", -12), "-١٢");
+        assert_eq!(repr("unknown-style", 5), "5");
+    }
 }
@@ -21,10 +21,13 @@ use taffy::{
 
 pub(crate) mod construct;
 pub(crate) mod damage;
+pub(crate) mod generated_content;
 pub(crate) mod inline;
 pub(crate) mod list;
 pub(crate) mod replaced;
 pub(crate) mod table;
+pub(crate) mod text;
+pub(crate) mod verify;
 
 use self::replaced::{ReplacedContext, replaced_measure_function};
 use self::table::TableTreeWrapper;
@@ -214,6 +217,27 @@ impl BaseDocument {
                         _ => unreachable!(),
                     };
 
+                    // Images selected from a `srcset` may have a pixel density other than 1x
+                    let density = element_data
+                        .image_source
+                        .as_ref()
+                        .map_or(1.0, |source| source.density as f32);
+                    let inherent_size = inherent_size.map(|dimension| dimension / density);
+
+                    // Until an image has loaded (or while its bitmap is evicted) reserve space for
+                    // it using its last known size or its `width` and `height` attributes
+                    let inherent_size = if inherent_size == taffy::Size::ZERO {
+                        element_data
+                            .evicted_image_size
+                            .or(match (attr_size.width, attr_size.height) {
+                                (Some(width), Some(height)) => Some(taffy::Size { width, height }),
+                                _ => None,
+                            })
+                            .unwrap_or(inherent_size)
+                    } else {
+                        inherent_size
+                    };
+
                     let replaced_context = ReplacedContext {
                         inherent_size,
                         attr_size,
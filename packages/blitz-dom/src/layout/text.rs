@@ -0,0 +1,434 @@
+//! Preprocessing of text content before it is pushed into a Parley builder
+//!
+//! Parley natively handles collapsing (or preserving) white space and breaking lines. Some CSS text
+//! properties have no direct equivalent in Parley and are instead implemented by rewriting the text
+//! that we push into the builder.
+
+use std::borrow::Cow;
+
+use cssparser::match_ignore_ascii_case;
+use parley::TreeBuilder;
+use slab::Slab;
+use style::values::computed::TextTransform;
+
+use crate::Node;
+use crate::node::TextBrush;
+use crate::stylo_to_parley::{self, stylo};
+
+/// U+00AD SOFT HYPHEN. A break opportunity which renders as a hyphen if a line is broken there.
+pub(crate) const SOFT_HYPHEN: char = '\u{00AD}';
+
+/// The `hyphens` property. The servo build of Stylo doesn't support `hyphens`, so it is read from
+/// `style` attributes (see [`crate::inline_style`]).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Hyphens {
+    /// Words are never hyphenated (soft hyphens are ignored)
+    None,
+    /// Words are only hyphenated at soft hyphens
+    #[default]
+    Manual,
+    /// Words are hyphenated at soft hyphens and at hyphenation points from a dictionary
+    Auto,
+}
+
+impl Hyphens {
+    fn parse(value: &str) -> Option<Self> {
+        match_ignore_ascii_case! { value,
+            "none" => Some(Self::None),
+            "manual" => Some(Self::Manual),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    /// The `hyphens` set by a node's `style` attribute (if any)
+    pub(crate) fn from_node(node: &Node) -> Option<Self> {
+        node.inline_style_value("hyphens").and_then(Self::parse)
+    }
+
+    /// The `hyphens` of a node, which is inherited from the closest ancestor which sets it
+    pub(crate) fn inherited(nodes: &Slab<Node>, node_id: usize) -> Self {
+        let mut current = Some(node_id);
+        while let Some(node) = current.map(|id| &nodes[id]) {
+            if let Some(hyphens) = Self::from_node(node) {
+                return hyphens;
+            }
+            current = node.parent;
+        }
+        Self::default()
+    }
+}
+
+/// Inherited text styles which are applied by rewriting text rather than by Parley
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TextProcessing {
+    pub(crate) white_space: stylo::WhiteSpaceCollapse,
+    pub(crate) text_transform: TextTransform,
+    pub(crate) hyphens: Hyphens,
+}
+
+impl Default for TextProcessing {
+    fn default() -> Self {
+        Self {
+            white_space: stylo::WhiteSpaceCollapse::Collapse,
+            text_transform: TextTransform::NONE,
+            hyphens: Hyphens::Manual,
+        }
+    }
+}
+
+impl TextProcessing {
+    pub(crate) fn from_style(style: &stylo::ComputedValues, hyphens: Hyphens) -> Self {
+        let itext_styles = style.get_inherited_text();
+        Self {
+            white_space: itext_styles.white_space_collapse,
+            text_transform: itext_styles.text_transform & TextTransform::CASE_TRANSFORMS,
+            hyphens,
+        }
+    }
+
+    /// Set the builder's white space mode to match these styles
+    pub(crate) fn apply_white_space_mode(&self, builder: &mut TreeBuilder<TextBrush>) {
+        builder.set_white_space_mode(stylo_to_parley::white_space_collapse(self.white_space));
+    }
+}
+
+/// White space state which is carried across all of the text of an inline formatting context, so
+/// that white space which we collapse ourselves (rather than Parley) is collapsed across element
+/// boundaries
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct WhiteSpaceState {
+    /// Whether nothing but collapsed white space has been pushed since the start of the line
+    at_line_start: bool,
+    /// Whether the last text pushed ends with a space
+    after_space: bool,
+    /// A collapsed space which is only pushed once it is followed by something other than a line
+    /// break (as spaces at the end of a line are removed)
+    pending_space: bool,
+}
+
+impl Default for WhiteSpaceState {
+    fn default() -> Self {
+        Self {
+            at_line_start: true,
+            after_space: false,
+            pending_space: false,
+        }
+    }
+}
+
+impl WhiteSpaceState {
+    fn flush_pending_space(&mut self, builder: &mut TreeBuilder<TextBrush>) {
+        if std::mem::take(&mut self.pending_space) {
+            builder.push_text(" ");
+        }
+    }
+
+    /// Record that an atomic inline (such as an inline box or a list marker) has been pushed
+    pub(crate) fn push_atomic_inline(&mut self, builder: &mut TreeBuilder<TextBrush>) {
+        self.flush_pending_space(builder);
+        self.at_line_start = false;
+        self.after_space = false;
+    }
+
+    /// Record that a forced line break has been pushed, removing any collapsed space before it
+    pub(crate) fn push_line_break(&mut self) {
+        self.pending_space = false;
+        self.at_line_start = true;
+        self.after_space = false;
+    }
+}
+
+/// Push the content of a text node into a Parley builder, applying text processing to it first
+pub(crate) fn push_text(
+    builder: &mut TreeBuilder<TextBrush>,
+    nodes: &Slab<Node>,
+    node_id: usize,
+    content: &str,
+    processing: TextProcessing,
+    state: &mut WhiteSpaceState,
+) {
+    // TODO: optimize case transforms to be non-allocating
+    let text: Cow<str> = match processing.text_transform {
+        TextTransform::UPPERCASE => Cow::Owned(content.to_uppercase()),
+        TextTransform::LOWERCASE => Cow::Owned(content.to_lowercase()),
+        _ => Cow::Borrowed(content),
+    };
+
+    let text = match processing.hyphens {
+        Hyphens::None if text.contains(SOFT_HYPHEN) => Cow::Owned(text.replace(SOFT_HYPHEN, "")),
+        Hyphens::None | Hyphens::Manual => text,
+        Hyphens::Auto => auto_hyphenate(nodes, node_id, text),
+    };
+
+    match processing.white_space {
+        stylo::WhiteSpaceCollapse::PreserveBreaks => {
+            let collapsed = collapse_preserving_breaks(&text);
+            push_preserving_breaks(builder, &collapsed, state);
+        }
+        stylo::WhiteSpaceCollapse::Collapse => {
+            // Parley collapses the text itself (including across spans), so a deferred space
+            // only needs pushing if it isn't replaced by this text's own white space
+            if text.is_empty() {
+                return;
+            }
+            if text.chars().all(char::is_whitespace) {
+                state.pending_space = false;
+                state.after_space = true;
+            } else {
+                state.flush_pending_space(builder);
+                state.at_line_start = false;
+                state.after_space = text.ends_with(char::is_whitespace);
+            }
+            builder.push_text(&text);
+        }
+        stylo::WhiteSpaceCollapse::Preserve | stylo::WhiteSpaceCollapse::BreakSpaces => {
+            if text.is_empty() {
+                return;
+            }
+            if text.starts_with('\n') {
+                state.pending_space = false;
+            } else {
+                state.flush_pending_space(builder);
+            }
+            builder.push_text(&text);
+            state.at_line_start = text.ends_with('\n');
+            state.after_space = text.ends_with([' ', '\t']);
+        }
+    }
+}
+
+/// Push text whose white space has already been collapsed by [`collapse_preserving_breaks`],
+/// removing spaces which collapse with the previous text or which are at the start or end of a line
+fn push_preserving_breaks(
+    builder: &mut TreeBuilder<TextBrush>,
+    text: &str,
+    state: &mut WhiteSpaceState,
+) {
+    let mut text = text;
+    if state.at_line_start || state.after_space || state.pending_space {
+        text = text.strip_prefix(' ').unwrap_or(text);
+    }
+    if text.starts_with('\n') {
+        state.pending_space = false;
+    }
+
+    let (text, has_trailing_space) = match text.strip_suffix(' ') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    if !text.is_empty() {
+        state.flush_pending_space(builder);
+        builder.push_text(text);
+        state.at_line_start = text.ends_with('\n');
+        state.after_space = false;
+    }
+    state.pending_space |= has_trailing_space;
+}
+
+/// Implements `white-space-collapse: preserve-breaks` by collapsing sequences of spaces and
+/// tabs into a single space, while preserving segment breaks. Spaces and tabs immediately before
+/// or after a segment break are removed.
+pub(crate) fn collapse_preserving_breaks(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if !matches!(c, ' ' | '\t' | '\n' | '\r') {
+            output.push(c);
+            continue;
+        }
+
+        // Consume the entire run of white space
+        let mut newlines = usize::from(c == '\n');
+        while let Some(&next) = chars.peek() {
+            if !matches!(next, ' ' | '\t' | '\n' | '\r') {
+                break;
+            }
+            newlines += usize::from(next == '\n');
+            chars.next();
+        }
+
+        if newlines == 0 {
+            output.push(' ');
+        } else {
+            output.extend(std::iter::repeat_n('\n', newlines));
+        }
+    }
+
+    output
+}
+
+#[cfg(feature = "hyphenation")]
+fn auto_hyphenate<'a>(nodes: &Slab<Node>, node_id: usize, text: Cow<'a, str>) -> Cow<'a, str> {
+    match content_language(nodes, node_id) {
+        Some(lang) => Cow::Owned(hyphenate(&text, lang)),
+        None => text,
+    }
+}
+
+/// Without a hyphenation dictionary `hyphens: auto` behaves like `hyphens: manual`
+#[cfg(not(feature = "hyphenation"))]
+fn auto_hyphenate<'a>(_nodes: &Slab<Node>, _node_id: usize, text: Cow<'a, str>) -> Cow<'a, str> {
+    text
+}
+
+/// Find the language of a node from the `lang` attribute of it or its closest ancestor
+#[cfg(feature = "hyphenation")]
+fn content_language(nodes: &Slab<Node>, node_id: usize) -> Option<hypher::Lang> {
+    let mut current = Some(node_id);
+    while let Some(id) = current {
+        let node = &nodes[id];
+        if let Some(lang) = node.attr(markup5ever::local_name!("lang")) {
+            let code = lang
+                .split('-')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            return match code.as_bytes() {
+                [a, b] => hypher::Lang::from_iso([*a, *b]),
+                _ => None,
+            };
+        }
+        current = node.parent;
+    }
+
+    // Default to English if the document does not specify a language
+    Some(hypher::Lang::English)
+}
+
+/// Insert soft hyphens at the hyphenation points of every word in the text
+#[cfg(feature = "hyphenation")]
+pub(crate) fn hyphenate(text: &str, lang: hypher::Lang) -> String {
+    let mut output = String::with_capacity(text.len() + text.len() / 4);
+    let mut word_start = None;
+
+    let flush_word = |output: &mut String, word: &str| {
+        let mut syllables = hypher::hyphenate(word, lang);
+        if let Some(first) = syllables.next() {
+            output.push_str(first);
+        }
+        for syllable in syllables {
+            output.push(SOFT_HYPHEN);
+            output.push_str(syllable);
+        }
+    };
+
+    for (idx, c) in text.char_indices() {
+        match (c.is_alphabetic(), word_start) {
+            (true, None) => word_start = Some(idx),
+            (true, Some(_)) => {}
+            (false, Some(start)) => {
+                flush_word(&mut output, &text[start..idx]);
+                word_start = None;
+                output.push(c);
+            }
+            (false, None) => output.push(c),
+        }
+    }
+    if let Some(start) = word_start {
+        flush_word(&mut output, &text[start..]);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::collapse_preserving_breaks;
+    use crate::node::TextLayout;
+    use crate::{Attribute, BaseDocument, DocumentConfig, qual_name};
+
+    /// Lay out a `<div>` with the given style containing `children`, which are either text or (if
+    /// prefixed with `<span>`) a span containing text
+    fn layout_div(style: &str, children: &[&str]) -> (BaseDocument, usize) {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let style = vec![Attribute {
+            name: qual_name!("style"),
+            value: style.to_string(),
+        }];
+        let div = mutator.create_element(qual_name!("div", html), style);
+        for child in children {
+            let child = match child.strip_prefix("<span>") {
+                Some(text) => {
+                    let span = mutator.create_element(qual_name!("span", html), Vec::new());
+                    let text = mutator.create_text_node(text);
+                    mutator.append_children(span, &[text]);
+                    span
+                }
+                None => mutator.create_text_node(child),
+            };
+            mutator.append_children(div, &[child]);
+        }
+        mutator.append_children(0, &[div]);
+        drop(mutator);
+        doc.resolve(0.0);
+        (doc, div)
+    }
+
+    fn inline_layout(doc: &BaseDocument, node_id: usize) -> &TextLayout {
+        doc.nodes[node_id]
+            .element_data()
+            .and_then(|element| element.inline_layout_data.as_deref())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_preserve_breaks_collapses_spaces() {
+        assert_eq!(collapse_preserving_breaks("a  \t b"), "a b");
+    }
+
+    #[test]
+    fn test_preserve_breaks_keeps_newlines() {
+        assert_eq!(collapse_preserving_breaks("a  \n  b"), "a\nb");
+        assert_eq!(collapse_preserving_breaks("a\n\nb"), "a\n\nb");
+    }
+
+    #[test]
+    fn test_preserve_breaks_collapses_across_elements() {
+        // Spaces collapse across element boundaries, and are removed at the start and end of lines
+        let (doc, div) = layout_div("white-space: pre-line", &[" a ", "<span> b \n", " c"]);
+        assert_eq!(inline_layout(&doc, div).text, "a b\nc");
+    }
+
+    #[test]
+    fn test_break_spaces_wraps_preserved_spaces() {
+        let text = format!("aa{}bb", " ".repeat(20));
+        let (doc, div) = layout_div("width: 50px; white-space: break-spaces", &[&text]);
+        let layout = inline_layout(&doc, div);
+        assert_eq!(layout.text, text);
+
+        // Runs of preserved spaces wrap onto the following lines, with at most one space hanging
+        // past the end of each line (Parley doesn't support breaking before the hanging space)
+        let space_width = 5.0;
+        assert!(layout.layout.lines().count() > 2);
+        for line in layout.layout.lines() {
+            assert!(line.metrics().advance <= 50.0 + space_width);
+        }
+    }
+
+    #[test]
+    fn test_hyphens_from_style_attribute() {
+        let (mut doc, div) = layout_div("hyphens: none", &["<span>ex\u{AD}ample"]);
+        assert_eq!(inline_layout(&doc, div).text, "example");
+
+        // Changing `hyphens` relays out the text
+        doc.mutate()
+            .set_attribute(div, qual_name!("style"), "hyphens: manual");
+        doc.resolve(0.0);
+        assert_eq!(inline_layout(&doc, div).text, "ex\u{AD}ample");
+    }
+
+    #[cfg(feature = "hyphenation")]
+    #[test]
+    fn test_hyphenate() {
+        let result = super::hyphenate("an extensive list", hypher::Lang::English);
+        assert_eq!(result, "an ex\u{AD}ten\u{AD}sive list");
+    }
+}
@@ -0,0 +1,394 @@
+//! A correctness oracle for incremental layout
+//!
+//! When enabled (see [`BaseDocument::set_verify_incremental_layout`]), every call to
+//! [`BaseDocument::resolve`] copies the document's DOM into a fresh document (keeping node ids),
+//! which styles, constructs and lays out the tree from scratch. Any node whose results differ from
+//! the incrementally computed ones is reported as a [`LayoutMismatch`]. The live document keeps its
+//! incremental state, so every frame is checked against the frames before it.
+//!
+//! Anonymous blocks and pseudo elements are created independently by each document, so they are
+//! compared structurally (as a position within their parent's layout children) rather than by
+//! node id. Nodes which are only styled in one of the documents (e.g. descendants of
+//! `display: none` elements which keep stale styles) are not compared. CSS transitions and
+//! animations are not copied, so elements which are animating may be reported.
+
+use std::collections::BTreeMap;
+
+use style::stylesheets::Origin;
+use taffy::{Layout, Point, Size};
+
+use super::damage::ALL_DAMAGE;
+use crate::node::NodeFlags;
+use crate::{BaseDocument, DocumentConfig, NodeData};
+
+/// A node whose incremental layout differs from its layout after a full rebuild
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutMismatch {
+    pub node_id: usize,
+    pub kind: LayoutMismatchKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutMismatchKind {
+    /// The node's layout children differ. Anonymous blocks are represented as `None`.
+    LayoutChildren {
+        incremental: Vec<Option<usize>>,
+        full: Vec<Option<usize>>,
+    },
+    /// The text collected into the node's inline layout differs
+    InlineText { incremental: String, full: String },
+    /// The node's position or size differs
+    Layout {
+        incremental: NodeGeometry,
+        full: NodeGeometry,
+    },
+}
+
+/// The parts of a node's final layout which are compared by the oracle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeGeometry {
+    pub location: Point<f32>,
+    pub size: Size<f32>,
+    pub content_size: Size<f32>,
+}
+
+impl From<&Layout> for NodeGeometry {
+    fn from(layout: &Layout) -> Self {
+        Self {
+            location: layout.location,
+            size: layout.size,
+            content_size: layout.content_size,
+        }
+    }
+}
+
+struct NodeSnapshot {
+    layout_children: Option<Vec<Option<usize>>>,
+    inline_text: Option<String>,
+    geometry: NodeGeometry,
+}
+
+impl BaseDocument {
+    /// Check the results of incremental layout against a full rebuild after every `resolve`.
+    ///
+    /// This is very slow (every frame is also laid out from scratch in a copy of the document)
+    /// and intended for debugging and testing only.
+    pub fn set_verify_incremental_layout(&mut self, enabled: bool) {
+        self.layout_verification = enabled.then(Vec::new);
+    }
+
+    pub fn verifies_incremental_layout(&self) -> bool {
+        self.layout_verification.is_some()
+    }
+
+    /// The mismatches found when verifying the most recent `resolve` (empty if verification is
+    /// disabled)
+    pub fn layout_mismatches(&self) -> &[LayoutMismatch] {
+        self.layout_verification.as_deref().unwrap_or_default()
+    }
+
+    /// Style and lay out a copy of the document from scratch and compare the results with the
+    /// current (incrementally computed) tree. Called at the end of the layout phase of `resolve`.
+    pub(crate) fn verify_incremental_layout(&mut self, current_time_for_animations: f64) {
+        if self.layout_verification.is_none() {
+            return;
+        }
+
+        let mut full_doc = self.clone_dom();
+        full_doc.resolve(current_time_for_animations);
+
+        let mismatches = compare_snapshots(self.snapshot_layout(), full_doc.snapshot_layout());
+
+        #[cfg(feature = "tracing")]
+        for mismatch in &mismatches {
+            tracing::warn!("Incremental layout mismatch: {mismatch:?}");
+        }
+
+        self.layout_verification = Some(mismatches);
+    }
+
+    /// A copy of the document's DOM tree (with the same node ids) and stylesheets, without any
+    /// style, box construction or layout state
+    fn clone_dom(&self) -> BaseDocument {
+        let mut clone = BaseDocument::new(DocumentConfig {
+            viewport: Some(self.viewport.clone()),
+            ua_stylesheets: Some(Vec::new()),
+            font_ctx: Some(self.font_ctx.lock().unwrap().clone()),
+            media_type: Some(self.media_type.clone()),
+            ..Default::default()
+        });
+        clone.url = self.url.clone();
+
+        // Share the stylesheets (which are locked by the document's guard) in cascade order
+        clone.guard = self.guard.clone();
+        clone.nodes[0].guard = self.guard.clone();
+        let guard = self.guard.read();
+        for origin in [Origin::UserAgent, Origin::User, Origin::Author] {
+            for index in 0..self.stylist.sheet_count(origin) {
+                if let Some(sheet) = self.stylist.sheet_at(origin, index) {
+                    clone.stylist.append_stylesheet(sheet.clone(), &guard);
+                }
+            }
+        }
+        drop(guard);
+
+        let mut in_tree = vec![false; self.nodes.capacity()];
+        let mut stack = vec![0];
+        while let Some(node_id) = stack.pop() {
+            in_tree[node_id] = true;
+            stack.extend_from_slice(&self.nodes[node_id].children);
+        }
+
+        // Nodes outside of the DOM tree (anonymous blocks, pseudo elements and removed nodes) are
+        // replaced by placeholders until every node has been created, so that node ids match
+        let len = self
+            .nodes
+            .iter()
+            .map(|(node_id, _)| node_id + 1)
+            .max()
+            .unwrap_or(1);
+        let mut placeholders = Vec::new();
+        for (node_id, &is_in_tree) in in_tree.iter().enumerate().take(len).skip(1) {
+            let data = match self.nodes.get(node_id) {
+                Some(node) if is_in_tree => {
+                    let mut data = node.data.clone();
+                    if let Some(element) = data.downcast_element_mut() {
+                        element.inline_layout_data = None;
+                        element.list_item_data = None;
+                        element.template_contents = None;
+                    }
+                    data
+                }
+                _ => {
+                    placeholders.push(node_id);
+                    NodeData::Comment
+                }
+            };
+            let clone_id = clone.create_node(data);
+            debug_assert_eq!(clone_id, node_id);
+        }
+        for (node_id, node) in self.nodes.iter() {
+            if node_id == 0 || !in_tree[node_id] {
+                continue;
+            }
+            let clone_node = &mut clone.nodes[node_id];
+            clone_node.parent = node.parent;
+            clone_node.children = node.children.clone();
+            clone_node.element_state = node.element_state;
+            if node.flags.is_in_document() {
+                clone_node.flags.insert(NodeFlags::IS_IN_DOCUMENT);
+            }
+        }
+        clone.nodes[0].children = self.nodes[0].children.clone();
+        // Construct the whole tree (the mutator damages the document when its root is appended)
+        clone.nodes[0].insert_damage(ALL_DAMAGE);
+        for node_id in placeholders {
+            clone.nodes.remove(node_id);
+            clone.changed_nodes.remove(&node_id);
+        }
+
+        clone
+    }
+
+    fn snapshot_layout(&self) -> BTreeMap<usize, NodeSnapshot> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| {
+                node.flags.contains(NodeFlags::IS_IN_DOCUMENT)
+                    && node.is_element()
+                    && node.primary_styles().is_some()
+            })
+            .map(|(node_id, node)| {
+                let layout_children = node.layout_children.borrow().as_ref().map(|children| {
+                    children
+                        .iter()
+                        .map(|&child_id| {
+                            (!self.nodes.get(child_id).is_some_and(|c| c.is_anonymous()))
+                                .then_some(child_id)
+                        })
+                        .collect()
+                });
+                let inline_text = node
+                    .element_data()
+                    .and_then(|el| el.inline_layout_data.as_ref())
+                    .map(|layout| layout.text.clone());
+                let snapshot = NodeSnapshot {
+                    layout_children,
+                    inline_text,
+                    geometry: NodeGeometry::from(&node.final_layout),
+                };
+                (node_id, snapshot)
+            })
+            .collect()
+    }
+}
+
+fn compare_snapshots(
+    incremental: BTreeMap<usize, NodeSnapshot>,
+    mut full: BTreeMap<usize, NodeSnapshot>,
+) -> Vec<LayoutMismatch> {
+    let mut mismatches = Vec::new();
+    for (node_id, incremental) in incremental {
+        let Some(full) = full.remove(&node_id) else {
+            continue;
+        };
+        if incremental.layout_children != full.layout_children {
+            mismatches.push(LayoutMismatch {
+                node_id,
+                kind: LayoutMismatchKind::LayoutChildren {
+                    incremental: incremental.layout_children.unwrap_or_default(),
+                    full: full.layout_children.unwrap_or_default(),
+                },
+            });
+        }
+        if incremental.inline_text != full.inline_text {
+            mismatches.push(LayoutMismatch {
+                node_id,
+                kind: LayoutMismatchKind::InlineText {
+                    incremental: incremental.inline_text.unwrap_or_default(),
+                    full: full.inline_text.unwrap_or_default(),
+                },
+            });
+        }
+        if incremental.geometry != full.geometry {
+            mismatches.push(LayoutMismatch {
+                node_id,
+                kind: LayoutMismatchKind::Layout {
+                    incremental: incremental.geometry,
+                    full: full.geometry,
+                },
+            });
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, qual_name};
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    fn snapshot(children: &[Option<usize>], width: f32) -> NodeSnapshot {
+        NodeSnapshot {
+            layout_children: Some(children.to_vec()),
+            inline_text: None,
+            geometry: NodeGeometry {
+                location: Point::ZERO,
+                size: Size {
+                    width,
+                    height: 10.0,
+                },
+                content_size: Size::ZERO,
+            },
+        }
+    }
+
+    #[test]
+    fn test_compare_snapshots() {
+        let incremental = BTreeMap::from([
+            (1, snapshot(&[Some(2), None], 100.0)),
+            (2, snapshot(&[], 50.0)),
+        ]);
+        let full = BTreeMap::from([
+            (1, snapshot(&[Some(2), None], 100.0)),
+            (2, snapshot(&[], 50.0)),
+        ]);
+        assert!(compare_snapshots(incremental, full).is_empty());
+
+        // Nodes are matched by id, and nodes missing from either side are skipped
+        let incremental = BTreeMap::from([
+            (1, snapshot(&[Some(2)], 100.0)),
+            (2, snapshot(&[], 50.0)),
+            (3, snapshot(&[], 50.0)),
+        ]);
+        let full = BTreeMap::from([
+            (0, snapshot(&[], 10.0)),
+            (1, snapshot(&[None, Some(2)], 100.0)),
+            (2, snapshot(&[], 60.0)),
+        ]);
+        let mismatches = compare_snapshots(incremental, full);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].node_id, 1);
+        assert!(matches!(
+            mismatches[0].kind,
+            LayoutMismatchKind::LayoutChildren { .. }
+        ));
+        assert_eq!(mismatches[1].node_id, 2);
+        assert!(matches!(
+            mismatches[1].kind,
+            LayoutMismatchKind::Layout { .. }
+        ));
+    }
+
+    #[test]
+    fn test_verify_after_mutations() {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        });
+        doc.set_verify_incremental_layout(true);
+
+        let mut mutator = doc.mutate();
+        let text = mutator.create_text_node("Hello");
+        let span = mutator.create_element(qual_name!("span", html), Vec::new());
+        mutator.append_children(span, &[text]);
+        let paragraph = mutator.create_element(qual_name!("p", html), Vec::new());
+        mutator.append_children(paragraph, &[span]);
+        let block = mutator.create_element(
+            qual_name!("div", html),
+            vec![Attribute {
+                name: qual_name!("style"),
+                value: "width: 100px; height: 10px".to_string(),
+            }],
+        );
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[paragraph, block]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+        assert!(
+            doc.layout_mismatches().is_empty(),
+            "{:?}",
+            doc.layout_mismatches()
+        );
+
+        // Edit text, mix blocks into inline content (creating anonymous blocks) and restyle
+        let mut mutator = doc.mutate();
+        mutator.set_node_text(text, "Hello world");
+        let inner_block = mutator.create_element(qual_name!("div", html), Vec::new());
+        let more_text = mutator.create_text_node("More text");
+        mutator.append_children(inner_block, &[more_text]);
+        mutator.insert_nodes_after(span, &[inner_block]);
+        mutator.set_attribute(block, qual_name!("style"), "width: 200px; height: 20px");
+        drop(mutator);
+        doc.resolve(0.0);
+        assert!(
+            doc.layout_mismatches().is_empty(),
+            "{:?}",
+            doc.layout_mismatches()
+        );
+
+        let mut mutator = doc.mutate();
+        mutator.remove_and_drop_node(inner_block);
+        drop(mutator);
+        doc.resolve(0.0);
+        assert!(
+            doc.layout_mismatches().is_empty(),
+            "{:?}",
+            doc.layout_mismatches()
+        );
+
+        // Changing the text without going through the mutator leaves the incremental layout stale
+        if let NodeData::Text(data) = &mut doc.nodes[text].data {
+            data.content = String::from("Goodbye");
+        }
+        doc.resolve(0.0);
+        assert!(doc.layout_mismatches().iter().any(|mismatch| {
+            mismatch.node_id == paragraph
+                && matches!(mismatch.kind, LayoutMismatchKind::InlineText { .. })
+        }));
+    }
+}
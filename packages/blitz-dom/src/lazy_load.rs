@@ -0,0 +1,506 @@
+//! Lazy loading of images and sub-documents (`loading="lazy"`), and eviction of offscreen images
+//!
+//! Lazy-loaded elements are deferred until layout has placed them within a margin of the viewport.
+//! Separately, the decoded bitmaps of images which are far offscreen are freed (and reloaded if
+//! they come back near the viewport) to limit the memory usage of long documents.
+//!
+//! See <https://html.spec.whatwg.org/multipage/urls-and-fetching.html#lazy-loading-attributes>
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use markup5ever::LocalName;
+
+use crate::layout::damage::ALL_DAMAGE;
+use crate::node::{ElementData, ImageData, SpecialElementData};
+use crate::{BaseDocument, MemoryLimits};
+
+/// How close (in CSS pixels) to the viewport a lazy-loaded element must be before it is loaded
+const LAZY_LOAD_MARGIN: f64 = 1250.0;
+
+/// How far (in CSS pixels) from the viewport an image must be before its bitmap is freed. This is
+/// much larger than the lazy load margin so that scrolling back and forth doesn't repeatedly free
+/// and reload the same images.
+const IMAGE_EVICTION_MARGIN: f64 = 5000.0;
+
+/// The state which the last image eviction pass depended on. Images can only have moved relative
+/// to the viewport (or need evicting for other reasons) if it changed, or if the document was
+/// damaged.
+#[derive(Clone, PartialEq)]
+pub(crate) struct ImageEvictionKey {
+    viewport_scroll: crate::Point<f64>,
+    window_size: (u32, u32),
+    scale: f32,
+    last_scroll_time: Option<Instant>,
+    cached_images: usize,
+    image_nodes: usize,
+    background_image_nodes: usize,
+    limits: MemoryLimits,
+}
+
+impl BaseDocument {
+    /// Whether an element has a `loading="lazy"` attribute
+    pub(crate) fn is_lazy_loading(&self, node_id: usize) -> bool {
+        self.nodes[node_id]
+            .attr(LocalName::from("loading"))
+            .is_some_and(|loading| loading.eq_ignore_ascii_case("lazy"))
+    }
+
+    /// Whether a node's border box is within `margin` CSS pixels of the viewport
+    fn is_near_viewport(&self, node_id: usize, margin: f64) -> bool {
+        self.distance_from_viewport(node_id)
+            .is_some_and(|distance| distance <= margin)
+    }
+
+    /// The distance (in CSS pixels) between a node's border box and the viewport, which is zero if
+    /// they overlap. Nodes which are not in the document or which do not generate a box have no
+    /// distance, as they are never near the viewport.
+    fn distance_from_viewport(&self, node_id: usize) -> Option<f64> {
+        let node = &self.nodes[node_id];
+        if !node.flags.is_in_document() {
+            return None;
+        }
+        let generates_box = node
+            .primary_styles()
+            .is_some_and(|style| !style.get_box().display.is_none());
+        if !generates_box {
+            return None;
+        }
+
+        let rect = self.get_client_bounding_rect(node_id)?;
+        let scale = self.viewport.scale_f64();
+        let viewport_width = self.viewport.window_size.0 as f64 / scale;
+        let viewport_height = self.viewport.window_size.1 as f64 / scale;
+
+        let distance = [
+            -(rect.x + rect.width),
+            -(rect.y + rect.height),
+            rect.x - viewport_width,
+            rect.y - viewport_height,
+        ]
+        .into_iter()
+        .fold(0.0, f64::max);
+        Some(distance)
+    }
+
+    /// Load lazy-loaded images (and allow lazy-loaded sub-documents to be resolved) once layout has
+    /// placed them near the viewport.
+    pub(crate) fn resolve_lazy_loading(&mut self) {
+        if self.lazy_load_nodes.is_empty() {
+            return;
+        }
+
+        let ready: Vec<usize> = self
+            .lazy_load_nodes
+            .iter()
+            .copied()
+            .filter(|&node_id| self.is_near_viewport(node_id, LAZY_LOAD_MARGIN))
+            .collect();
+        if ready.is_empty() {
+            return;
+        }
+
+        for node_id in ready {
+            self.lazy_load_nodes.remove(&node_id);
+            if let Some(loader) = self.pending_sub_documents.remove(&node_id) {
+                self.load_sub_document(node_id, loader);
+                continue;
+            }
+            let source = self.nodes[node_id]
+                .element_data()
+                .and_then(|element| element.image_source.clone());
+            if let Some(source) = source {
+                self.load_image(node_id, source.url);
+            }
+        }
+
+        // Images loaded from the cache are applied immediately, but need another layout pass
+        self.shell_provider.request_redraw();
+    }
+
+    /// Load a node's deferred sub-document once it no longer has `loading="lazy"`
+    pub(crate) fn update_lazy_sub_document(&mut self, node_id: usize) {
+        if self.is_lazy_loading(node_id) {
+            return;
+        }
+        if let Some(loader) = self.pending_sub_documents.remove(&node_id) {
+            self.load_sub_document(node_id, loader);
+        } else if self.sub_document_nodes.contains(&node_id) {
+            self.lazy_load_nodes.remove(&node_id);
+        }
+    }
+
+    /// Free the decoded bitmaps of raster images which are only used by `<img>` elements that are
+    /// far offscreen (or not in the document). Their natural size is retained so that freeing them
+    /// does not affect layout, and they are reloaded when they come back near the viewport.
+    ///
+    /// If the document has an image memory limit and is over it, the least recently visible images
+    /// which are outside of the lazy load margin are also freed.
+    ///
+    /// The pass is skipped if nothing has moved relative to the viewport since the last one.
+    pub(crate) fn evict_offscreen_images(&mut self) {
+        if self.image_cache.is_empty() {
+            return;
+        }
+
+        let key = ImageEvictionKey {
+            viewport_scroll: self.viewport_scroll,
+            window_size: self.viewport.window_size,
+            scale: self.viewport.scale(),
+            last_scroll_time: self.scrollbars.last_scroll_time,
+            cached_images: self.image_cache.len(),
+            image_nodes: self.image_nodes.len(),
+            background_image_nodes: self.background_image_nodes.len(),
+            limits: self.memory.limits,
+        };
+        // Relayout (or any other damage) may have moved images. This is unknown without
+        // incremental layout, in which case the pass always runs.
+        let has_damage = self
+            .paint_invalidations_since(self.paint_generation().saturating_sub(1))
+            .is_none_or(|nodes| !nodes.is_empty());
+        if !has_damage && self.memory.last_image_eviction.as_ref() == Some(&key) {
+            return;
+        }
+        self.memory.last_image_eviction = Some(key);
+
+        self.memory.image_clock += 1;
+        let clock = self.memory.image_clock;
+
+        // Images that are in use by a nearby <img> element or by any background image
+        let mut in_use: HashSet<&str> = HashSet::new();
+        // Images that would be reloaded immediately if they were freed
+        let mut pinned: HashSet<&str> = HashSet::new();
+
+        let background_images = self
+            .background_image_nodes
+            .iter()
+            .filter_map(|&node_id| self.nodes[node_id].element_data())
+            .flat_map(|element| element.background_images.iter().flatten());
+        for bg_image in background_images {
+            in_use.insert(bg_image.url.as_str());
+            pinned.insert(bg_image.url.as_str());
+        }
+        for &node_id in &self.image_nodes {
+            let Some(element) = self.nodes[node_id].element_data() else {
+                continue;
+            };
+            let (Some(source), Some(ImageData::Raster(_))) =
+                (&element.image_source, element.image_data())
+            else {
+                continue;
+            };
+            let Some(distance) = self.distance_from_viewport(node_id) else {
+                continue;
+            };
+            if distance <= IMAGE_EVICTION_MARGIN {
+                in_use.insert(source.url.as_str());
+            }
+            if distance <= LAZY_LOAD_MARGIN {
+                pinned.insert(source.url.as_str());
+            }
+        }
+
+        for url in &pinned {
+            match self.memory.image_last_used.get_mut(*url) {
+                Some(last_used) => *last_used = clock,
+                None => {
+                    self.memory.image_last_used.insert(url.to_string(), clock);
+                }
+            }
+        }
+
+        let mut evicted: HashSet<&str> = self
+            .image_cache
+            .iter()
+            .filter(|(url, image)| {
+                matches!(image, ImageData::Raster(_)) && !in_use.contains(url.as_str())
+            })
+            .map(|(url, _)| url.as_str())
+            .collect();
+
+        if let Some(max_image_bytes) = self.memory.limits.max_image_bytes {
+            let mut candidates: Vec<(u64, &str, usize)> = Vec::new();
+            let mut retained_bytes = 0;
+            for (url, image) in &self.image_cache {
+                let ImageData::Raster(raster) = image else {
+                    continue;
+                };
+                if evicted.contains(url.as_str()) {
+                    continue;
+                }
+                retained_bytes += raster.data.len();
+                if !pinned.contains(url.as_str()) {
+                    let last_used = self.memory.image_last_used.get(url).copied();
+                    candidates.push((last_used.unwrap_or(0), url.as_str(), raster.data.len()));
+                }
+            }
+
+            // Least recently used first
+            candidates.sort_by_key(|(last_used, _, _)| *last_used);
+            for (_, url, size) in candidates {
+                if retained_bytes <= max_image_bytes {
+                    break;
+                }
+                retained_bytes -= size;
+                evicted.insert(url);
+            }
+        }
+
+        if !evicted.is_empty() {
+            let evicted: HashSet<String> = evicted.into_iter().map(str::to_owned).collect();
+            self.evict_images(|url| evicted.contains(url));
+        }
+    }
+
+    /// Free the decoded bitmaps of the raster images for which `should_evict` returns `true`,
+    /// from the image cache and from the `<img>` elements displaying them. Images which are in use
+    /// by background images are never freed.
+    pub(crate) fn evict_images(&mut self, should_evict: impl Fn(&str) -> bool) {
+        let background_urls: HashSet<&str> = self
+            .background_image_elements()
+            .flat_map(|element| element.background_images.iter().flatten())
+            .map(|bg_image| bg_image.url.as_str())
+            .collect();
+
+        let evicted_urls: HashSet<String> = self
+            .image_cache
+            .iter()
+            .filter(|(url, image)| {
+                matches!(image, ImageData::Raster(_))
+                    && !background_urls.contains(url.as_str())
+                    && should_evict(url)
+            })
+            .map(|(url, _)| url.clone())
+            .collect();
+        if evicted_urls.is_empty() {
+            return;
+        }
+
+        // <img> elements displaying the evicted images
+        let evicted_nodes: Vec<usize> = self
+            .image_nodes
+            .iter()
+            .copied()
+            .filter(|&node_id| {
+                self.nodes[node_id].element_data().is_some_and(|element| {
+                    matches!(element.image_data(), Some(ImageData::Raster(_)))
+                        && element
+                            .image_source
+                            .as_ref()
+                            .is_some_and(|source| evicted_urls.contains(source.url.as_str()))
+                })
+            })
+            .collect();
+
+        for url in &evicted_urls {
+            #[cfg(feature = "tracing")]
+            tracing::info!("Evicting image {url}");
+            self.image_cache.remove(url);
+            self.memory.image_last_used.remove(url);
+        }
+
+        for node_id in evicted_nodes {
+            let node = &mut self.nodes[node_id];
+            let element = node.element_data_mut().unwrap();
+            let Some(ImageData::Raster(image)) = element.image_data() else {
+                continue;
+            };
+            let density = element
+                .image_source
+                .as_ref()
+                .map_or(1.0, |source| source.density as f32);
+            element.evicted_image_size = Some(taffy::Size {
+                width: image.width as f32 / density,
+                height: image.height as f32 / density,
+            });
+            element.special_data = SpecialElementData::None;
+            node.cache.clear();
+            node.insert_damage(ALL_DAMAGE);
+            self.lazy_load_nodes.insert(node_id);
+        }
+    }
+
+    /// The elements which have background images
+    fn background_image_elements(&self) -> impl Iterator<Item = &ElementData> {
+        self.background_image_nodes
+            .iter()
+            .filter_map(|&node_id| self.nodes[node_id].element_data())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::*;
+    use crate::node::RasterImageData;
+    use crate::{Attribute, DocumentConfig, PlainDocument, QualName, qual_name};
+
+    /// The size of each of the decoded images cached by [`document_with_offscreen_elements`]
+    pub(crate) const IMAGE_BYTES: usize = 100;
+
+    pub(crate) fn attr(name: &str, value: &str) -> Attribute {
+        Attribute {
+            name: QualName::new(None, Default::default(), LocalName::from(name)),
+            value: value.to_string(),
+        }
+    }
+
+    /// Create an 800x600 document whose body contains a spacer which pushes `elements` far below
+    /// the viewport, with 5x5 images at each of `cached_urls` already in the image cache. Returns
+    /// the document and the ids of `elements`.
+    pub(crate) fn document_with_offscreen_elements(
+        elements: Vec<(QualName, Vec<Attribute>)>,
+        cached_urls: &[String],
+    ) -> (BaseDocument, Vec<usize>) {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            base_url: Some("https://example.com/".to_string()),
+            ..Default::default()
+        });
+        for url in cached_urls {
+            doc.image_cache.insert(
+                url.clone(),
+                ImageData::Raster(RasterImageData::new(5, 5, Arc::new(vec![0; IMAGE_BYTES]))),
+            );
+        }
+
+        let mut mutator = doc.mutate();
+        let spacer = mutator.create_element(
+            qual_name!("div", html),
+            vec![attr("style", "height: 10000px")],
+        );
+        let element_ids: Vec<usize> = elements
+            .into_iter()
+            .map(|(name, attrs)| mutator.create_element(name, attrs))
+            .collect();
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[spacer]);
+        mutator.append_children(body, &element_ids);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+
+        (doc, element_ids)
+    }
+
+    /// Create a document containing a single element far below the viewport (see
+    /// [`document_with_offscreen_elements`]), returning the document and the id of the element
+    fn document_with_offscreen(name: QualName, attrs: Vec<Attribute>) -> (BaseDocument, usize) {
+        let cached_urls = ["https://example.com/a.png".to_string()];
+        let (doc, element_ids) =
+            document_with_offscreen_elements(vec![(name, attrs)], &cached_urls);
+        (doc, element_ids[0])
+    }
+
+    fn scroll_to_bottom(doc: &mut BaseDocument) {
+        doc.set_viewport_scroll(crate::Point { x: 0.0, y: 9800.0 });
+        doc.resolve(0.0);
+    }
+
+    #[test]
+    fn test_lazy_sub_documents_are_loaded_near_the_viewport() {
+        let (mut doc, node_id) = document_with_offscreen(
+            qual_name!("div", html),
+            vec![
+                attr("loading", "lazy"),
+                attr("style", "width: 100px; height: 100px"),
+            ],
+        );
+        let loaded = Arc::new(AtomicBool::new(false));
+        let loader_loaded = loaded.clone();
+        doc.set_sub_document_loader(
+            node_id,
+            Box::new(move || {
+                loader_loaded.store(true, Ordering::SeqCst);
+                Box::new(PlainDocument(BaseDocument::new(DocumentConfig::default())))
+            }),
+        );
+
+        doc.resolve(0.0);
+        assert!(!loaded.load(Ordering::SeqCst));
+        assert!(
+            doc.nodes[node_id]
+                .element_data()
+                .unwrap()
+                .sub_doc_data()
+                .is_none()
+        );
+
+        scroll_to_bottom(&mut doc);
+        assert!(loaded.load(Ordering::SeqCst));
+        assert!(
+            doc.nodes[node_id]
+                .element_data()
+                .unwrap()
+                .sub_doc_data()
+                .is_some()
+        );
+        assert!(!doc.lazy_load_nodes.contains(&node_id));
+    }
+
+    #[test]
+    fn test_removing_lazy_loading_loads_sub_document() {
+        let (mut doc, node_id) =
+            document_with_offscreen(qual_name!("div", html), vec![attr("loading", "lazy")]);
+        doc.set_sub_document_loader(
+            node_id,
+            Box::new(|| Box::new(PlainDocument(BaseDocument::new(DocumentConfig::default())))),
+        );
+        assert!(doc.pending_sub_documents.contains_key(&node_id));
+
+        doc.mutate()
+            .clear_attribute(node_id, attr("loading", "").name);
+        assert!(doc.pending_sub_documents.is_empty());
+        assert!(
+            doc.nodes[node_id]
+                .element_data()
+                .unwrap()
+                .sub_doc_data()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_offscreen_images_are_evicted_and_reloaded() {
+        let url = "https://example.com/a.png";
+        let (mut doc, img_id) = document_with_offscreen(
+            qual_name!("img", html),
+            vec![
+                attr("src", "a.png"),
+                attr("width", "5"),
+                attr("height", "5"),
+            ],
+        );
+        // Loaded from the image cache
+        assert!(
+            doc.nodes[img_id]
+                .element_data()
+                .unwrap()
+                .image_data()
+                .is_some()
+        );
+
+        doc.resolve(0.0);
+        assert!(!doc.image_cache.contains_key(url));
+        let element = doc.nodes[img_id].element_data().unwrap();
+        assert!(element.image_data().is_none());
+        assert_eq!(
+            element.evicted_image_size,
+            Some(taffy::Size {
+                width: 5.0,
+                height: 5.0
+            })
+        );
+        assert!(doc.lazy_load_nodes.contains(&img_id));
+
+        // Scrolling the image back into view fetches it again
+        scroll_to_bottom(&mut doc);
+        assert!(!doc.lazy_load_nodes.contains(&img_id));
+        assert!(doc.pending_images.contains_key(url));
+    }
+}
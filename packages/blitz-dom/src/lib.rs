@@ -27,6 +27,10 @@
 // ## Feature flags
 //  - `default`: Enables the features listed below.
 //  - `tracing`: Enables tracing support.
+//  - `incremental`: Enables incremental box construction and layout. Can be checked against a full
+//    rebuild with `BaseDocument::set_verify_incremental_layout`.
+//  - `devtools_server`: Enables `BaseDocument::start_devtools_server` (a remote inspector).
+//  - `serde`: Enables serialization of `DocumentState` snapshots.
 
 pub const DEFAULT_CSS: &str = include_str!("../assets/default.css");
 pub const BULLET_FONT: &[u8] = include_bytes!("../assets/moz-bullet-font.otf");
@@ -43,17 +47,32 @@ mod document;
 pub mod node;
 
 mod config;
+mod context_menu;
 mod debug;
+#[cfg(feature = "devtools_server")]
+mod devtools_server;
 mod events;
 mod font_metrics;
 mod form;
 mod html;
+mod inline_style;
+mod invalidation;
 /// Integration of taffy and the DOM.
 mod layout;
+mod lazy_load;
+mod memory;
 mod mutator;
+mod observer;
+mod paint_invalidation;
+pub mod profiler;
 mod query_selector;
 mod resolve;
+mod responsive_image;
+mod scroll;
+mod scroll_chain;
+pub mod scrollbar;
 mod selection;
+mod session;
 /// Implementations that interact with servo's style engine
 mod stylo;
 mod stylo_to_cursor_icon;
@@ -72,14 +91,24 @@ pub mod util;
 mod accessibility;
 
 pub use config::DocumentConfig;
-pub use document::{BaseDocument, DocGuard, DocGuardMut, Document, PlainDocument};
+pub use document::{
+    BaseDocument, DocGuard, DocGuardMut, Document, PlainDocument, SharedDocument, SubDocumentLoader,
+};
+pub use layout::verify::{LayoutMismatch, LayoutMismatchKind, NodeGeometry};
 pub use markup5ever::{
     LocalName, Namespace, NamespaceStaticSet, Prefix, PrefixStaticSet, QualName, local_name,
     namespace_prefix, namespace_url, ns,
 };
+pub use memory::{MemoryLimits, MemoryReport};
 pub use mutator::DocumentMutator;
 pub use node::{Attribute, ElementData, Node, NodeData, TextNodeData};
+pub use observer::IntersectionObserverOptions;
 pub use parley::FontContext;
+pub use scroll::{ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition};
+pub use session::{
+    DOCUMENT_STATE_VERSION, DocumentState, NodePath, RestoreStateError, SavedName, SavedNode,
+    SavedTextInput,
+};
 pub use style::Atom;
 pub use style::invalidation::element::restyle_hints::RestyleHint;
 pub use style::media_queries::MediaType;
@@ -0,0 +1,327 @@
+//! Memory accounting and limits
+//!
+//! [`BaseDocument::memory_report`] estimates the heap memory used by a document, broken down by
+//! category. The estimates are based on the sizes of the data structures involved (and on the
+//! length of their text), so they are intended for comparing documents and spotting growth rather
+//! than as exact measurements. Memory which is shared between documents (such as system fonts and
+//! the font collection) is not included.
+//!
+//! Documents can also be given [`MemoryLimits`], and documents which are not being displayed
+//! (such as background tabs) can be [discarded](BaseDocument::discard) to free their layout and
+//! decoded images until they are next resolved.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use style::properties::{ComputedValues, PropertyDeclaration};
+use style::shared_lock::SharedRwLockReadGuard;
+use style::stylesheets::{CssRule, DocumentStyleSheet, StyleRule};
+
+use crate::layout::damage::ALL_DAMAGE;
+use crate::lazy_load::ImageEvictionKey;
+use crate::node::{Attribute, ImageData, TextLayout};
+use crate::{BaseDocument, Node, NodeData};
+
+/// The estimated size of the shaped text (clusters, glyphs and line data) for each byte of text
+/// in a text layout
+const SHAPED_BYTES_PER_TEXT_BYTE: usize = 48;
+
+/// Estimated memory usage (in bytes) of a document, by category
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The node tree, including attributes and text content
+    pub nodes: usize,
+    /// Computed styles
+    pub styles: usize,
+    /// Shaped text layouts of inline formatting contexts
+    pub text_layouts: usize,
+    /// Decoded raster images
+    pub images: usize,
+    /// Web fonts loaded by the document
+    pub fonts: usize,
+    /// Parsed user agent and author stylesheets
+    pub stylesheets: usize,
+    /// The total of all of the document's sub-documents
+    pub sub_documents: usize,
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> usize {
+        self.nodes
+            + self.styles
+            + self.text_layouts
+            + self.images
+            + self.fonts
+            + self.stylesheets
+            + self.sub_documents
+    }
+}
+
+/// Caps on the memory used by a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryLimits {
+    /// The maximum number of bytes of decoded raster images to keep in memory. When exceeded, the
+    /// least recently visible images which are not near the viewport are freed (and reloaded if
+    /// they come back near the viewport).
+    pub max_image_bytes: Option<usize>,
+}
+
+#[derive(Default)]
+pub(crate) struct MemoryState {
+    pub(crate) limits: MemoryLimits,
+    /// The image eviction pass in which each cached image was last near the viewport
+    pub(crate) image_last_used: HashMap<String, u64>,
+    /// Incremented by every image eviction pass
+    pub(crate) image_clock: u64,
+    /// The state when the image eviction pass last ran
+    pub(crate) last_image_eviction: Option<ImageEvictionKey>,
+    /// The size of the web fonts loaded by the document
+    pub(crate) web_font_bytes: usize,
+    pub(crate) is_discarded: bool,
+}
+
+impl BaseDocument {
+    /// Estimate the memory used by the document. Sizes are estimates based on the data structures
+    /// involved, and exclude memory shared with other documents (such as system fonts).
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport {
+            nodes: self.nodes.capacity() * size_of::<Node>(),
+            fonts: self.memory.web_font_bytes,
+            ..Default::default()
+        };
+
+        for (_, node) in self.nodes.iter() {
+            report.nodes += node.children.capacity() * size_of::<usize>();
+            for children in [&node.layout_children, &node.paint_children] {
+                report.nodes += children
+                    .borrow()
+                    .as_ref()
+                    .map_or(0, |children| children.capacity() * size_of::<usize>());
+            }
+            if let NodeData::Text(text) = &node.data {
+                report.nodes += text.content.capacity();
+            }
+
+            if node.primary_styles().is_some() {
+                report.styles +=
+                    size_of::<style::data::ElementData>() + size_of::<ComputedValues>();
+            }
+
+            let Some(element) = node.element_data() else {
+                continue;
+            };
+            report.nodes += element
+                .attrs()
+                .iter()
+                .map(|attr| size_of::<Attribute>() + attr.value.capacity())
+                .sum::<usize>();
+            if let Some(layout) = &element.inline_layout_data {
+                report.text_layouts += size_of::<TextLayout>()
+                    + layout.text.capacity()
+                    + layout.text.len() * SHAPED_BYTES_PER_TEXT_BYTE;
+            }
+            if let Some(sub_doc) = element.sub_doc_data() {
+                report.sub_documents += sub_doc.inner().memory_report().total();
+            }
+        }
+
+        report.images = self.cached_image_bytes();
+
+        let guard = self.guard.read();
+        report.stylesheets = self
+            .ua_stylesheets
+            .values()
+            .chain(self.nodes_to_stylesheet.values())
+            .map(|sheet| stylesheet_bytes(sheet, &guard))
+            .sum();
+
+        report
+    }
+
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.memory.limits
+    }
+
+    /// Set caps on the memory used by the document. They are enforced by the next `resolve`.
+    pub fn set_memory_limits(&mut self, limits: MemoryLimits) {
+        self.memory.limits = limits;
+        self.shell_provider.request_redraw();
+    }
+
+    /// Free the document's text layouts, layout caches and decoded images. This is intended for
+    /// documents which are not being displayed (such as background tabs): everything is
+    /// recomputed (and images are reloaded) by the next `resolve`.
+    ///
+    /// The DOM, styles and scroll positions are retained.
+    pub fn discard(&mut self) {
+        for (_, node) in self.nodes.iter_mut() {
+            node.cache.clear();
+            node.insert_damage(ALL_DAMAGE);
+            if let Some(layout) = node
+                .data
+                .downcast_element_mut()
+                .and_then(|element| element.inline_layout_data.as_mut())
+            {
+                // Construction reuses the existing (now empty) layout
+                **layout = TextLayout::new();
+            }
+        }
+        self.evict_images(|_| true);
+        self.memory.is_discarded = true;
+    }
+
+    /// Whether the document has been discarded (and not resolved since)
+    pub fn is_discarded(&self) -> bool {
+        self.memory.is_discarded
+    }
+
+    /// The size of the decoded raster images in the image cache
+    pub(crate) fn cached_image_bytes(&self) -> usize {
+        self.image_cache
+            .values()
+            .map(|image| match image {
+                ImageData::Raster(raster) => raster.data.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// Estimate the size of a parsed stylesheet from its rules and declarations
+fn stylesheet_bytes(sheet: &DocumentStyleSheet, guard: &SharedRwLockReadGuard) -> usize {
+    sheet
+        .0
+        .contents
+        .read_with(guard)
+        .rules(guard)
+        .iter()
+        .map(|rule| match rule {
+            CssRule::Style(style_rule) => {
+                let declarations = style_rule.read_with(guard).block.read_with(guard).len();
+                size_of::<StyleRule>() + declarations * size_of::<PropertyDeclaration>()
+            }
+            _ => size_of::<CssRule>(),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lazy_load::tests::{IMAGE_BYTES, attr, document_with_offscreen_elements};
+    use crate::{DocumentConfig, qual_name};
+
+    fn image_url(name: &str) -> String {
+        format!("https://example.com/{name}.png")
+    }
+
+    /// Create an 800x600 document containing a paragraph of text, and an `<img>` (whose image is
+    /// already cached) at each of the given offsets from the top of the document. Returns the
+    /// document and the ids of the images.
+    fn image_document(images: &[(&str, f64)]) -> (BaseDocument, Vec<usize>) {
+        let cached_urls: Vec<String> = images.iter().map(|(name, _)| image_url(name)).collect();
+        let elements = images
+            .iter()
+            .map(|(name, top)| {
+                let style = format!("position: absolute; top: {top}px; width: 5px; height: 5px");
+                let attrs = vec![attr("src", &format!("{name}.png")), attr("style", &style)];
+                (qual_name!("img", html), attrs)
+            })
+            .collect();
+        let (mut doc, img_ids) = document_with_offscreen_elements(elements, &cached_urls);
+
+        let spacer = doc.nodes[doc.nodes[img_ids[0]].parent.unwrap()].children[0];
+        let mut mutator = doc.mutate();
+        let text = mutator.create_text_node(&"hello world ".repeat(100));
+        let paragraph = mutator.create_element(qual_name!("p", html), Vec::new());
+        mutator.append_children(paragraph, &[text]);
+        mutator.insert_nodes_before(spacer, &[paragraph]);
+        drop(mutator);
+
+        (doc, img_ids)
+    }
+
+    fn scroll_to(doc: &mut BaseDocument, y: f64) {
+        doc.set_viewport_scroll(crate::Point { x: 0.0, y });
+        doc.resolve(0.0);
+    }
+
+    #[test]
+    fn report_counts_nodes_and_stylesheets() {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let empty = doc.memory_report();
+        assert!(
+            empty.stylesheets > 0,
+            "the user agent stylesheet is counted"
+        );
+
+        let mut mutator = doc.mutate();
+        let text = mutator.create_text_node(&"hello world ".repeat(100));
+        mutator.append_children(0, &[text]);
+        drop(mutator);
+
+        let report = doc.memory_report();
+        assert!(report.nodes >= empty.nodes + 1200);
+        assert_eq!(
+            report.total(),
+            report.nodes + report.stylesheets + report.styles
+        );
+    }
+
+    #[test]
+    fn image_limit_evicts_least_recently_visible_images() {
+        let (mut doc, _) = image_document(&[("a", 0.0), ("b", 2000.0), ("c", 4000.0)]);
+        doc.resolve(0.0);
+
+        // Everything is within the eviction margin of the viewport at some point, so nothing is
+        // freed without a limit. Scrolling to "c" makes it more recently visible than "b".
+        scroll_to(&mut doc, 4000.0);
+        scroll_to(&mut doc, 0.0);
+        assert_eq!(doc.cached_image_bytes(), 3 * IMAGE_BYTES);
+
+        doc.set_memory_limits(MemoryLimits {
+            max_image_bytes: Some(2 * IMAGE_BYTES),
+        });
+        doc.resolve(0.0);
+        assert!(doc.image_cache.contains_key(&image_url("a")));
+        assert!(!doc.image_cache.contains_key(&image_url("b")));
+        assert!(doc.image_cache.contains_key(&image_url("c")));
+        assert_eq!(doc.memory_report().images, 2 * IMAGE_BYTES);
+    }
+
+    #[test]
+    fn discard_frees_text_layouts_and_images() {
+        let (mut doc, _) = image_document(&[("a", 0.0)]);
+        doc.resolve(0.0);
+        let before = doc.memory_report();
+        assert_eq!(before.images, IMAGE_BYTES);
+
+        doc.discard();
+        assert!(doc.is_discarded());
+        let after = doc.memory_report();
+        assert_eq!(after.images, 0);
+        assert!(doc.image_cache.is_empty());
+        assert!(
+            before.text_layouts - after.text_layouts >= 1200 * SHAPED_BYTES_PER_TEXT_BYTE,
+            "the shaped text is freed"
+        );
+    }
+
+    #[test]
+    fn resolve_recomputes_discarded_document() {
+        let (mut doc, img_ids) = image_document(&[("a", 0.0)]);
+        doc.resolve(0.0);
+        let before = doc.memory_report();
+        let body = doc.nodes[img_ids[0]].parent.unwrap();
+        let body_size = doc.nodes[body].final_layout.size;
+
+        doc.discard();
+        doc.resolve(0.0);
+        assert!(!doc.is_discarded());
+        assert_eq!(doc.memory_report().text_layouts, before.text_layouts);
+        assert_eq!(doc.nodes[body].final_layout.size, body_size);
+
+        // The (visible) image is fetched again
+        assert!(doc.pending_images.contains_key(&image_url("a")));
+    }
+}
@@ -4,14 +4,16 @@ use std::ops::{Deref, DerefMut};
 
 use crate::document::make_device;
 use crate::layout::damage::ALL_DAMAGE;
-use crate::net::{ImageHandler, ResourceHandler, StylesheetHandler};
+use crate::layout::text::Hyphens;
+use crate::net::{ResourceHandler, StylesheetHandler};
 use crate::node::{CanvasData, NodeFlags, SpecialElementData};
-use crate::util::ImageType;
 use crate::{
-    Attribute, BaseDocument, Document, ElementData, Node, NodeData, QualName, local_name, qual_name,
+    Attribute, BaseDocument, Document, ElementData, Node, NodeData, QualName, SubDocumentLoader,
+    local_name, qual_name,
 };
 use blitz_traits::net::Request;
 use blitz_traits::shell::Viewport;
+use markup5ever::LocalName;
 use style::Atom;
 use style::invalidation::element::restyle_hints::RestyleHint;
 use style::stylesheets::OriginSet;
@@ -54,8 +56,7 @@ pub struct DocumentMutator<'doc> {
     /// Whether an element/attribute that affect animation status has been seen
     recompute_is_animating: bool,
 
-    /// The (latest) node which has been mounted in and had autofocus=true, if any
-    #[cfg(feature = "autofocus")]
+    /// The (latest) node which has been mounted in with the `autofocus` attribute, if any
     node_to_autofocus: Option<usize>,
 }
 
@@ -74,7 +75,6 @@ impl DocumentMutator<'_> {
             style_nodes: HashSet::new(),
             form_nodes: HashSet::new(),
             recompute_is_animating: false,
-            #[cfg(feature = "autofocus")]
             node_to_autofocus: None,
         }
     }
@@ -184,13 +184,17 @@ impl DocumentMutator<'_> {
         let node = &mut self.doc.nodes[node_id];
         node.insert_damage(ALL_DAMAGE);
         node.mark_ancestors_dirty();
+        let parent_id = node.parent;
         match node.text_data_mut() {
-            Some(data) => {
-                data.content += text;
-                Ok(())
-            }
-            None => Err(AppendTextErr::NotTextNode),
+            Some(data) => data.content += text,
+            None => return Err(AppendTextErr::NotTextNode),
+        }
+
+        // As with `set_node_text`, the parent's inline layout needs to be rebuilt
+        if let Some(parent_id) = parent_id {
+            self.doc.nodes[parent_id].insert_damage(ALL_DAMAGE);
         }
+        Ok(())
     }
 
     pub fn add_attrs_if_missing(&mut self, node_id: usize, attrs: Vec<Attribute>) {
@@ -213,27 +217,8 @@ impl DocumentMutator<'_> {
     }
 
     pub fn set_attribute(&mut self, node_id: usize, name: QualName, value: &str) {
-        self.doc.snapshot_node(node_id);
-
-        let node = &mut self.doc.nodes[node_id];
-        if let Some(mut data) = node.stylo_element_data.get_mut() {
-            data.hint |= RestyleHint::restyle_subtree();
-            data.damage.insert(ALL_DAMAGE);
-        }
-
-        // TODO: make this fine grained / conditional based on ElementSelectorFlags
-        let parent = node.parent;
-        if let Some(parent_id) = parent {
-            let parent = &mut self.doc.nodes[parent_id];
-            if let Some(mut data) = parent.stylo_element_data.get_mut() {
-                data.hint |= RestyleHint::restyle_subtree();
-            }
-        }
-
-        // Mark ancestors dirty so the style traversal visits this subtree.
-        // Without this, the traversal may skip nodes with pending RestyleHint/damage
-        // because it uses dirty_descendants flags to determine which subtrees to visit.
-        self.doc.nodes[node_id].mark_ancestors_dirty();
+        self.invalidate_attribute(node_id, &name.local);
+        let old_hyphens = self.inline_hyphens(node_id, &name.local);
 
         let node = &mut self.doc.nodes[node_id];
 
@@ -265,6 +250,9 @@ impl DocumentMutator<'_> {
         if *attr == local_name!("style") {
             element.flush_style_attribute(&self.doc.guard, &self.doc.url.url_extra_data());
             node.mark_style_attr_updated();
+            if Hyphens::from_node(node) != old_hyphens {
+                node.insert_damage(ALL_DAMAGE);
+            }
             return;
         }
 
@@ -281,8 +269,26 @@ impl DocumentMutator<'_> {
 
         if (tag, attr) == tag_and_attr!("input", "checked") {
             set_input_checked_state(element, value.to_string());
-        } else if (tag, attr) == tag_and_attr!("img", "src") {
+        } else if (tag, attr) == tag_and_attr!("img", "src")
+            || (tag, attr) == tag_and_attr!("img", "srcset")
+            || (tag, attr) == tag_and_attr!("img", "sizes")
+            || (*tag == local_name!("img") && &**attr == "loading")
+        {
             self.load_image(node_id);
+        } else if &**attr == "loading" {
+            self.doc.update_lazy_sub_document(node_id);
+        } else if *tag == local_name!("source")
+            && matches!(
+                *attr,
+                local_name!("srcset")
+                    | local_name!("sizes")
+                    | local_name!("media")
+                    | local_name!("type")
+            )
+        {
+            if let Some(picture_id) = node.parent {
+                self.reload_picture_image(picture_id);
+            }
         } else if (tag, attr) == tag_and_attr!("canvas", "src") {
             self.load_custom_paint_src(node_id);
         } else if (tag, attr) == tag_and_attr!("link", "href") {
@@ -291,19 +297,10 @@ impl DocumentMutator<'_> {
     }
 
     pub fn clear_attribute(&mut self, node_id: usize, name: QualName) {
-        self.doc.snapshot_node(node_id);
+        self.invalidate_attribute(node_id, &name.local);
+        let old_hyphens = self.inline_hyphens(node_id, &name.local);
 
         let node = &mut self.doc.nodes[node_id];
-
-        if let Some(mut data) = node.stylo_element_data.get_mut() {
-            data.hint |= RestyleHint::restyle_subtree();
-            data.damage.insert(ALL_DAMAGE);
-        }
-
-        // Mark ancestors dirty so the style traversal visits this subtree.
-        // Without this, the traversal may skip nodes with pending RestyleHint/damage.
-        node.mark_ancestors_dirty();
-
         let Some(element) = node.element_data_mut() else {
             return;
         };
@@ -340,11 +337,56 @@ impl DocumentMutator<'_> {
         if *attr == local_name!("style") {
             element.flush_style_attribute(&self.doc.guard, &self.doc.url.url_extra_data());
             node.mark_style_attr_updated();
+            if old_hyphens.is_some() {
+                node.insert_damage(ALL_DAMAGE);
+            }
         } else if (tag, attr) == tag_and_attr!("canvas", "src") {
             self.recompute_is_animating = true;
         } else if (tag, attr) == tag_and_attr!("link", "href") {
             self.unload_stylesheet(node_id);
+        } else if &**attr == "loading" {
+            if *tag == local_name!("img") {
+                self.load_image(node_id);
+            } else {
+                self.doc.update_lazy_sub_document(node_id);
+            }
+        }
+    }
+
+    /// Prepare for a change to an attribute of an element. Selectors which depend on the
+    /// attribute are invalidated by Stylo using a snapshot of the element.
+    /// The `hyphens` set by a node's `style` attribute before the attribute `name` is changed.
+    /// `hyphens` isn't computed by Stylo, so changes to it aren't found by comparing styles.
+    fn inline_hyphens(&self, node_id: usize, name: &LocalName) -> Option<Hyphens> {
+        if *name != local_name!("style") {
+            return None;
+        }
+        Hyphens::from_node(&self.doc.nodes[node_id])
+    }
+
+    fn invalidate_attribute(&mut self, node_id: usize, name: &LocalName) {
+        self.doc.snapshot_attribute(node_id, name);
+        // The attribute may be read by `attr()` in the content of a pseudo-element
+        self.doc.generated_content_dirty = true;
+
+        // Attributes other than `class` and `id` may map to presentational hints (e.g. `width`
+        // or `bgcolor`) or affect box construction (e.g. `colspan` or `src`), neither of which
+        // are covered by selector invalidation
+        let is_selector_only = *name == local_name!("class")
+            || *name == local_name!("id")
+            || *name == local_name!("style")
+            || name.starts_with("data-")
+            || name.starts_with("aria-");
+        let node = &mut self.doc.nodes[node_id];
+        if !is_selector_only {
+            node.set_restyle_hint(RestyleHint::RESTYLE_SELF);
+            node.insert_damage(ALL_DAMAGE);
         }
+
+        // Mark ancestors dirty so the style traversal reaches the element (and processes
+        // its snapshot)
+        node.mark_ancestors_dirty();
+        self.doc.invalidate_relative_selector_anchors(node_id);
     }
 
     pub fn set_style_property(&mut self, node_id: usize, name: &str, value: &str) {
@@ -359,6 +401,10 @@ impl DocumentMutator<'_> {
         self.doc.set_sub_document(node_id, sub_document)
     }
 
+    pub fn set_sub_document_loader(&mut self, node_id: usize, loader: SubDocumentLoader) {
+        self.doc.set_sub_document_loader(node_id, loader)
+    }
+
     pub fn remove_sub_document(&mut self, node_id: usize) {
         self.doc.remove_sub_document(node_id)
     }
@@ -371,10 +417,13 @@ impl DocumentMutator<'_> {
         if let Some(parent_id) = node.parent.take() {
             let parent = &mut self.doc.nodes[parent_id];
             parent.insert_damage(ALL_DAMAGE);
-            // Mark ancestors dirty so the style traversal visits this subtree.
-            parent.mark_ancestors_dirty();
+            let index = parent.children.iter().position(|id| *id == node_id);
             parent.children.retain(|id| *id != node_id);
+            if let Some(index) = index {
+                self.doc.invalidate_for_child_list_change(parent_id, index);
+            }
             self.maybe_record_node(parent_id);
+            self.reload_picture_image(parent_id);
         }
 
         self.process_removed_subtree(node_id);
@@ -389,19 +438,13 @@ impl DocumentMutator<'_> {
         if let Some(parent_id) = node.as_ref().and_then(|node| node.parent) {
             let parent = &mut self.doc.nodes[parent_id];
             parent.insert_damage(ALL_DAMAGE);
-            let parent_is_in_doc = parent.flags.is_in_document();
-
-            // TODO: make this fine grained / conditional based on ElementSelectorFlags
-            if parent_is_in_doc {
-                if let Some(mut data) = parent.stylo_element_data.get_mut() {
-                    data.hint |= RestyleHint::restyle_subtree();
-                }
-                // Mark ancestors dirty so the style traversal visits this subtree.
-                parent.mark_ancestors_dirty();
-            }
-
+            let index = parent.children.iter().position(|id| *id == node_id);
             parent.children.retain(|id| *id != node_id);
+            if let Some(index) = index {
+                self.doc.invalidate_for_child_list_change(parent_id, index);
+            }
             self.maybe_record_node(parent_id);
+            self.reload_picture_image(parent_id);
         }
 
         node
@@ -409,23 +452,16 @@ impl DocumentMutator<'_> {
 
     pub fn remove_and_drop_all_children(&mut self, node_id: usize) {
         let parent = &mut self.doc.nodes[node_id];
-        let parent_is_in_doc = parent.flags.is_in_document();
-
-        // TODO: make this fine grained / conditional based on ElementSelectorFlags
-        if parent_is_in_doc {
-            if let Some(mut data) = parent.stylo_element_data.get_mut() {
-                data.hint |= RestyleHint::restyle_subtree();
-            }
-            // Mark ancestors dirty so the style traversal visits this subtree.
-            parent.mark_ancestors_dirty();
-        }
+        parent.insert_damage(ALL_DAMAGE);
 
         let children = mem::take(&mut parent.children);
         for child_id in children {
             self.process_removed_subtree(child_id);
             let _ = self.doc.drop_node_ignoring_parent(child_id);
         }
+        self.doc.invalidate_for_child_list_change(node_id, 0);
         self.maybe_record_node(node_id);
+        self.reload_picture_image(node_id);
     }
 
     // Tree mutation methods
@@ -464,15 +500,6 @@ impl DocumentMutator<'_> {
         new_parent.insert_damage(ALL_DAMAGE);
         let new_parent_is_in_doc = new_parent.flags.is_in_document();
 
-        // TODO: make this fine grained / conditional based on ElementSelectorFlags
-        if new_parent_is_in_doc {
-            if let Some(mut data) = new_parent.stylo_element_data.get_mut() {
-                data.hint |= RestyleHint::restyle_subtree();
-            }
-            // Mark ancestors dirty so the style traversal visits this subtree.
-            new_parent.mark_ancestors_dirty();
-        }
-
         insert_children_fn(new_parent, child_ids);
 
         for child_id in child_ids.iter().copied() {
@@ -487,22 +514,35 @@ impl DocumentMutator<'_> {
             if let Some(old_parent_id) = old_parent_id {
                 let old_parent = &mut self.doc.nodes[old_parent_id];
                 old_parent.insert_damage(ALL_DAMAGE);
-
-                // TODO: make this fine grained / conditional based on ElementSelectorFlags
-                if child_was_in_doc {
-                    if let Some(mut data) = old_parent.stylo_element_data.get_mut() {
-                        data.hint |= RestyleHint::restyle_subtree();
-                    }
-                    // Mark ancestors dirty so the style traversal visits this subtree.
-                    old_parent.mark_ancestors_dirty();
-                }
-
+                let old_index = old_parent.children.iter().position(|id| *id == child_id);
                 old_parent.children.retain(|id| *id != child_id);
+                if let Some(old_index) = old_index {
+                    self.doc
+                        .invalidate_for_child_list_change(old_parent_id, old_index);
+                }
                 self.maybe_record_node(old_parent_id);
+                self.reload_picture_image(old_parent_id);
+            }
+
+            // Any styles the child has were computed for its previous position in the tree
+            if new_parent_is_in_doc {
+                self.doc.nodes[child_id].set_restyle_hint(RestyleHint::restyle_subtree());
             }
         }
 
+        let index = child_ids.first().and_then(|first_child_id| {
+            self.doc.nodes[parent_id]
+                .children
+                .iter()
+                .position(|id| id == first_child_id)
+        });
+        if let Some(index) = index {
+            self.doc.invalidate_for_child_list_change(parent_id, index);
+        }
+
         self.maybe_record_node(parent_id);
+        // Inserting a <source> (or <img>) into a <picture> may change the image it selects
+        self.reload_picture_image(parent_id);
     }
 
     // Tree mutation methods (that defer to other methods)
@@ -548,9 +588,12 @@ impl<'doc> DocumentMutator<'doc> {
             self.doc.reset_form_owner(id);
         }
 
-        #[cfg(feature = "autofocus")]
         if let Some(node_id) = self.node_to_autofocus.take() {
-            if self.doc.get_node(node_id).is_some() {
+            let is_mounted = self
+                .doc
+                .get_node(node_id)
+                .is_some_and(|node| node.flags.is_in_document());
+            if is_mounted {
                 self.doc.set_focus_to(node_id);
             }
         }
@@ -616,11 +659,11 @@ impl<'doc> DocumentMutator<'doc> {
                 _ => {}
             }
 
-            #[cfg(feature = "autofocus")]
+            // `autofocus` is a boolean attribute, but frameworks may also set it to "false"
             if node.is_focussable() {
                 if let NodeData::Element(ref element) = node.data {
                     if let Some(value) = element.attr(local_name!("autofocus")) {
-                        if value == "true" {
+                        if !value.eq_ignore_ascii_case("false") {
                             self.node_to_autofocus = Some(node_id);
                         }
                     }
@@ -778,51 +821,27 @@ impl<'doc> DocumentMutator<'doc> {
     }
 
     fn load_image(&mut self, target_id: usize) {
-        let node = &self.doc.nodes[target_id];
-        if let Some(raw_src) = node.attr(local_name!("src")) {
-            if !raw_src.is_empty() {
-                let src = self.doc.resolve_url(raw_src);
-                let src_string = src.as_str();
-
-                // Check cache first
-                if let Some(cached_image) = self.doc.image_cache.get(src_string) {
-                    #[cfg(feature = "tracing")]
-                    tracing::info!("Loading image {src_string} from cache");
-                    let node = &mut self.doc.nodes[target_id];
-                    node.element_data_mut().unwrap().special_data =
-                        SpecialElementData::Image(Box::new(cached_image.clone()));
-                    node.cache.clear();
-                    node.insert_damage(ALL_DAMAGE);
-                    return;
-                }
-
-                // Check if there's already a pending request for this URL
-                if let Some(waiting_list) = self.doc.pending_images.get_mut(src_string) {
-                    #[cfg(feature = "tracing")]
-                    tracing::info!("Image {src_string} already pending, queueing node {target_id}");
-                    waiting_list.push((target_id, ImageType::Image));
-                    return;
-                }
+        self.doc.load_responsive_image(target_id);
+    }
 
-                // Start fetch and track as pending
-                #[cfg(feature = "tracing")]
-                tracing::info!("Fetching image {src_string}");
-                self.doc
-                    .pending_images
-                    .insert(src_string.to_string(), vec![(target_id, ImageType::Image)]);
-
-                self.doc.net_provider.fetch(
-                    self.doc.id(),
-                    Request::get(src),
-                    ResourceHandler::boxed(
-                        self.doc.tx.clone(),
-                        self.doc.id(),
-                        None, // Don't pass node_id, we'll handle it via pending_images
-                        self.doc.shell_provider.clone(),
-                        ImageHandler::new(ImageType::Image),
-                    ),
-                );
-            }
+    /// Re-select the source of the \<img\> element in a \<picture\> element (because its
+    /// \<source\> elements have changed). Does nothing if `picture_id` isn't a \<picture\>.
+    fn reload_picture_image(&mut self, picture_id: usize) {
+        let picture = &self.doc.nodes[picture_id];
+        if !picture.flags.is_in_document()
+            || !picture
+                .data
+                .is_element_with_tag_name(&local_name!("picture"))
+        {
+            return;
+        }
+        let img_id = picture.children.iter().copied().find(|id| {
+            self.doc.nodes[*id]
+                .data
+                .is_element_with_tag_name(&local_name!("img"))
+        });
+        if let Some(img_id) = img_id {
+            self.load_image(img_id);
         }
     }
 
@@ -857,6 +876,7 @@ impl<'doc> DocumentMutator<'doc> {
             let value = value.to_string();
             let id = self.create_text_node(&value);
             self.append_children(target_id, &[id]);
+            #[cfg_attr(not(feature = "file_input"), allow(clippy::needless_return))]
             return;
         }
         #[cfg(feature = "file_input")]
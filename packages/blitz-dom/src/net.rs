@@ -2,7 +2,12 @@ use selectors::context::QuirksMode;
 use std::sync::atomic::Ordering as Ao;
 use std::{
     io::Cursor,
-    sync::{Arc, atomic::AtomicUsize, mpsc::Sender},
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize},
+        mpsc::Sender,
+    },
 };
 use style::{
     font_face::{FontFaceSourceFormat, FontFaceSourceFormatKeyword, Source},
@@ -427,17 +432,40 @@ pub(crate) fn fetch_font_face(
 
 pub struct ImageHandler {
     kind: ImageType,
+    sync_decoding: Arc<AtomicBool>,
 }
 impl ImageHandler {
     pub fn new(kind: ImageType) -> Self {
-        Self { kind }
+        Self {
+            kind,
+            sync_decoding: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Decode the image on the thread that received it (`decoding="sync"`) rather than on the
+    /// thread pool. The flag is shared so that it can be set by elements which request the image
+    /// while it is being fetched.
+    pub fn with_sync_decoding(mut self, sync_decoding: Arc<AtomicBool>) -> Self {
+        self.sync_decoding = sync_decoding;
+        self
     }
 }
 
 impl NetHandler for ResourceHandler<ImageHandler> {
     fn bytes(self: Box<Self>, resolved_url: String, bytes: Bytes) {
-        let result = self.data.parse(bytes);
-        self.respond(resolved_url, result)
+        if self.data.sync_decoding.load(Ao::Relaxed) {
+            let result = self.data.parse(bytes);
+            return self.respond(resolved_url, result);
+        }
+
+        // Decoding large images is slow, so avoid blocking the network thread. Either way the
+        // image is only applied to the document once it has been fully decoded. A panic in the
+        // thread pool would abort the process, so a panicking decoder is reported as an error.
+        rayon::spawn(move || {
+            let result = catch_unwind(AssertUnwindSafe(|| self.data.parse(bytes)))
+                .unwrap_or_else(|_| Err(String::from("Image decoder panicked")));
+            self.respond(resolved_url, result)
+        });
     }
 }
 
@@ -20,7 +20,9 @@ use url::Url;
 
 use super::{Attribute, Attributes};
 use crate::Document;
+use crate::layout::generated_content::GeneratedContent;
 use crate::layout::table::TableContext;
+use crate::responsive_image::ImageSource;
 
 macro_rules! local_names {
     ($($name:tt),+) => {
@@ -63,6 +65,17 @@ pub struct ElementData {
 
     /// The element's template contents (\<template\> elements only)
     pub template_contents: Option<usize>,
+
+    /// The source selected from the element's `src`, `srcset` and `sizes` attributes and any
+    /// \<picture\> sources (\<img\> elements only)
+    pub(crate) image_source: Option<ImageSource>,
+
+    /// The natural size (in CSS pixels) of the element's image if its bitmap has been freed
+    /// because it was far offscreen
+    pub(crate) evicted_image_size: Option<taffy::Size<f32>>,
+
+    /// The resolved content of the element's ::after and ::before pseudo-elements (in that order)
+    pub(crate) generated_content: [Option<GeneratedContent>; 2],
     // /// Whether the node is a [HTML integration point] (https://html.spec.whatwg.org/multipage/#html-integration-point)
     // pub mathml_annotation_xml_integration_point: bool,
 }
@@ -148,6 +161,9 @@ impl ElementData {
             special_data: SpecialElementData::None,
             template_contents: None,
             background_images: Vec::new(),
+            image_source: None,
+            evicted_image_size: None,
+            generated_content: [None, None],
         };
         data.flush_is_focussable();
         data
@@ -405,6 +421,19 @@ impl ElementData {
         false
     }
 
+    /// Serialize the value of a property in the element's style attribute (equivalent to
+    /// `element.style.getPropertyValue()`). Returns `None` if the property is not set.
+    pub fn style_property_value(&self, name: &str, guard: &SharedRwLock) -> Option<String> {
+        let property_id = PropertyId::parse_enabled_for_all_content(name).ok()?;
+        let style = self.style_attribute.as_ref()?;
+        let mut value = String::new();
+        style
+            .read_with(&guard.read())
+            .property_value_to_css(&property_id, &mut value)
+            .ok()?;
+        (!value.is_empty()).then_some(value)
+    }
+
     pub fn set_sub_document(&mut self, sub_document: Box<dyn Document>) {
         self.special_data = SpecialElementData::SubDocument(sub_document);
     }
@@ -413,6 +442,16 @@ impl ElementData {
         self.special_data = SpecialElementData::None;
     }
 
+    pub fn take_sub_document(&mut self) -> Option<Box<dyn Document>> {
+        match std::mem::take(&mut self.special_data) {
+            SpecialElementData::SubDocument(sub_document) => Some(sub_document),
+            other => {
+                self.special_data = other;
+                None
+            }
+        }
+    }
+
     pub fn take_inline_layout(&mut self) -> Option<Box<TextLayout>> {
         std::mem::take(&mut self.inline_layout_data)
     }
@@ -474,6 +513,8 @@ pub enum Status {
 pub struct BackgroundImageData {
     /// The url of the background image
     pub url: ServoArc<Url>,
+    /// The pixel density of the background image (from `image-set()`)
+    pub density: f64,
     /// The loading status of the background image
     pub status: Status,
     /// The image data
@@ -484,6 +525,7 @@ impl BackgroundImageData {
     pub fn new(url: ServoArc<Url>) -> Self {
         Self {
             url,
+            density: 1.0,
             status: Status::Loading,
             image: ImageData::None,
         }
@@ -17,8 +17,8 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use style::Atom;
 use style::invalidation::element::restyle_hints::RestyleHint;
-use style::properties::ComputedValues;
 use style::properties::generated::longhands::position::computed_value::T as Position;
+use style::properties::{ComputedValues, PropertyDeclarationId, PropertyId};
 use style::selector_parser::{PseudoElement, RestyleDamage};
 use style::servo_arc::Arc as ServoArc;
 use style::shared_lock::SharedRwLock;
@@ -291,6 +291,8 @@ impl Node {
             data.hint |= RestyleHint::RESTYLE_STYLE_ATTRIBUTE;
         }
         self.set_dirty_descendants();
+        // Mark ancestors dirty so the style traversal reaches this element
+        self.mark_ancestors_dirty();
     }
 
     /// Marks all ancestors of this node as having dirty descendants.
@@ -343,14 +345,18 @@ impl Node {
         }
     }
 
+    // The state changing methods below rely on the node having been snapshotted (see
+    // `BaseDocument::snapshot_node_and`), which Stylo uses to restyle only the elements whose
+    // selectors depend on the changed state.
+
     pub fn hover(&mut self) {
         self.element_state.insert(ElementState::HOVER);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.mark_ancestors_dirty();
     }
 
     pub fn unhover(&mut self) {
         self.element_state.remove(ElementState::HOVER);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.mark_ancestors_dirty();
     }
 
     pub fn is_hovered(&self) -> bool {
@@ -358,9 +364,8 @@ impl Node {
     }
 
     pub fn focus(&mut self, shell_provider: Arc<dyn ShellProvider>) {
-        self.element_state
-            .insert(ElementState::FOCUS | ElementState::FOCUSRING);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.element_state.insert(ElementState::FOCUS);
+        self.mark_ancestors_dirty();
 
         // If focussing a text input, enable IME and set IME area
         if self
@@ -381,7 +386,7 @@ impl Node {
     pub fn blur(&mut self, shell_provider: Arc<dyn ShellProvider>) {
         self.element_state
             .remove(ElementState::FOCUS | ElementState::FOCUSRING);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.mark_ancestors_dirty();
 
         // If blurring a text input, disable IME
         if self
@@ -399,12 +404,12 @@ impl Node {
 
     pub fn active(&mut self) {
         self.element_state.insert(ElementState::ACTIVE);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.mark_ancestors_dirty();
     }
 
     pub fn unactive(&mut self) {
         self.element_state.remove(ElementState::ACTIVE);
-        self.set_restyle_hint(RestyleHint::restyle_subtree());
+        self.mark_ancestors_dirty();
     }
 
     pub fn is_active(&self) -> bool {
@@ -805,6 +810,15 @@ impl Node {
         self.stylo_element_data.primary_styles()
     }
 
+    /// The computed value of a longhand property serialized as a string (equivalent to reading a
+    /// property from `getComputedStyle()`). Returns `None` for unknown properties and shorthands.
+    pub fn computed_style_value(&self, name: &str) -> Option<String> {
+        let property_id = PropertyId::parse_enabled_for_all_content(name).ok()?;
+        let longhand_id = property_id.longhand_id()?;
+        let styles = self.primary_styles()?;
+        Some(styles.computed_value_to_string(PropertyDeclarationId::Longhand(longhand_id)))
+    }
+
     pub fn text_content(&self) -> String {
         let mut out = String::new();
         self.write_text_content(&mut out);
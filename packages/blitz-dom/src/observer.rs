@@ -0,0 +1,414 @@
+//! Intersection and resize observation of nodes
+//!
+//! Embedders register observations on nodes, which are evaluated after each layout pass. Changes
+//! are queued as `visible` and `resize` events, which are delivered as a batch through the
+//! document's event handler by [`Document::flush_pending_events`](crate::Document::flush_pending_events).
+//!
+//! See <https://w3c.github.io/IntersectionObserver/> and <https://drafts.csswg.org/resize-observer/>
+
+use std::time::SystemTime;
+
+use blitz_traits::events::{
+    BlitzIntersectionEvent, BlitzRect, BlitzResizeEvent, DomEvent, DomEventData,
+};
+use kurbo::Rect;
+use taffy::Overflow;
+
+use crate::BaseDocument;
+
+/// Options for observing the intersection of a node with the viewport or a scroll container
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntersectionObserverOptions {
+    /// The ancestor whose padding box the node is intersected with. `None` uses the viewport.
+    pub root: Option<usize>,
+    /// Amounts (in CSS pixels) by which to grow the root's bounds before computing the
+    /// intersection, in the order top, right, bottom, left. Negative values shrink the bounds.
+    pub root_margin: [f64; 4],
+    /// Intersection ratios (from 0.0 to 1.0). A notification is sent whenever the node's
+    /// intersection ratio crosses one of them.
+    pub thresholds: Vec<f64>,
+}
+
+impl Default for IntersectionObserverOptions {
+    fn default() -> Self {
+        Self {
+            root: None,
+            root_margin: [0.0; 4],
+            thresholds: vec![0.0],
+        }
+    }
+}
+
+pub(crate) struct IntersectionObservation {
+    options: IntersectionObserverOptions,
+    /// The number of thresholds which the intersection ratio had reached when the last
+    /// notification was sent. `None` if no notification has been sent.
+    previous_threshold_index: Option<usize>,
+    previous_is_intersecting: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct ResizeObservation {
+    /// The border box and content box sizes from the last notification
+    last_size: [(f64, f64); 2],
+}
+
+fn to_blitz_rect(rect: Rect) -> BlitzRect {
+    BlitzRect {
+        x: rect.x0,
+        y: rect.y0,
+        width: rect.width(),
+        height: rect.height(),
+    }
+}
+
+/// The edge-inclusive intersection of two rectangles. Rectangles which touch intersect.
+fn intersect(a: Rect, b: Rect) -> Option<Rect> {
+    let rect = Rect::new(
+        a.x0.max(b.x0),
+        a.y0.max(b.y0),
+        a.x1.min(b.x1),
+        a.y1.min(b.y1),
+    );
+    (rect.x0 <= rect.x1 && rect.y0 <= rect.y1).then_some(rect)
+}
+
+impl BaseDocument {
+    /// Observe the intersection of a node with the viewport (or an ancestor scroll container).
+    /// A `visible` event is dispatched to the node after the next layout pass, and thereafter
+    /// whenever its intersection ratio crosses one of the thresholds.
+    pub fn observe_intersection(&mut self, node_id: usize, options: IntersectionObserverOptions) {
+        self.intersection_observations.insert(
+            node_id,
+            IntersectionObservation {
+                options,
+                previous_threshold_index: None,
+                previous_is_intersecting: false,
+            },
+        );
+    }
+
+    pub fn unobserve_intersection(&mut self, node_id: usize) {
+        self.intersection_observations.remove(&node_id);
+    }
+
+    /// Observe the size of a node. A `resize` event is dispatched to the node after the next layout
+    /// pass (unless its size is zero), and thereafter whenever its border box or content box size
+    /// changes.
+    pub fn observe_resize(&mut self, node_id: usize) {
+        self.resize_observations
+            .insert(node_id, ResizeObservation::default());
+    }
+
+    pub fn unobserve_resize(&mut self, node_id: usize) {
+        self.resize_observations.remove(&node_id);
+    }
+
+    pub fn has_pending_events(&self) -> bool {
+        !self.pending_events.is_empty()
+    }
+
+    /// Take the events which have been queued by the document itself (such as observer
+    /// notifications) for dispatch
+    pub fn take_pending_events(&mut self) -> Vec<DomEvent> {
+        std::mem::take(&mut self.pending_events)
+    }
+
+    /// Remove any observations of a node (e.g. because it has been dropped)
+    pub(crate) fn remove_observations(&mut self, node_id: usize) {
+        self.intersection_observations.remove(&node_id);
+        self.resize_observations.remove(&node_id);
+    }
+
+    /// Whether a node is in the document and generates a box
+    pub(crate) fn is_rendered(&self, node_id: usize) -> bool {
+        let node = &self.nodes[node_id];
+        node.flags.is_in_document()
+            && node
+                .primary_styles()
+                .is_some_and(|style| !style.get_box().display.is_none())
+    }
+
+    /// The border box of a node relative to the viewport. The unrounded layout is used (for both
+    /// position and size) so that the intersection ratios of partly visible nodes are exact.
+    pub(crate) fn border_box_client_rect(&self, node_id: usize) -> Rect {
+        let node = &self.nodes[node_id];
+        let location = node.unrounded_layout.location;
+        let mut x = location.x as f64 - self.viewport_scroll.x;
+        let mut y = location.y as f64 - self.viewport_scroll.y;
+
+        // Offset by each layout ancestor, less its scroll offset
+        let mut ancestor = node.layout_parent.get();
+        while let Some(ancestor_id) = ancestor {
+            let ancestor_node = &self.nodes[ancestor_id];
+            let location = ancestor_node.unrounded_layout.location;
+            x += location.x as f64 - ancestor_node.scroll_offset.x;
+            y += location.y as f64 - ancestor_node.scroll_offset.y;
+            ancestor = ancestor_node.layout_parent.get();
+        }
+
+        let size = node.unrounded_layout.size;
+        Rect::new(x, y, x + size.width as f64, y + size.height as f64)
+    }
+
+    /// The padding box of a node relative to the viewport
+    pub(crate) fn padding_box_client_rect(&self, node_id: usize) -> Rect {
+        let border = self.nodes[node_id].unrounded_layout.border;
+        let rect = self.border_box_client_rect(node_id);
+        Rect::new(
+            rect.x0 + border.left as f64,
+            rect.y0 + border.top as f64,
+            rect.x1 - border.right as f64,
+            rect.y1 - border.bottom as f64,
+        )
+    }
+
+    /// Compute the portion of a node's border box which is visible within the intersection root
+    /// (clipped by any scroll containers in between). Returns the node's border box, the visible
+    /// portion (if any) and the root bounds.
+    fn compute_intersection(
+        &self,
+        node_id: usize,
+        options: &IntersectionObserverOptions,
+    ) -> (Rect, Option<Rect>, Rect) {
+        let [top, right, bottom, left] = options.root_margin;
+        let root_bounds = match options.root {
+            Some(root_id) if self.nodes.contains(root_id) => self.padding_box_client_rect(root_id),
+            Some(_) => Rect::ZERO,
+            None => {
+                let scale = self.viewport.scale_f64();
+                let (width, height) = self.viewport.window_size;
+                Rect::new(0.0, 0.0, width as f64 / scale, height as f64 / scale)
+            }
+        };
+        let root_bounds = Rect::new(
+            root_bounds.x0 - left,
+            root_bounds.y0 - top,
+            root_bounds.x1 + right,
+            root_bounds.y1 + bottom,
+        );
+
+        let target_rect = self.border_box_client_rect(node_id);
+        if !self.is_rendered(node_id) {
+            return (Rect::ZERO, None, root_bounds);
+        }
+
+        // Clip by each scroll container between the node and the root
+        let mut intersection = Some(target_rect);
+        let mut ancestor = self.nodes[node_id].layout_parent.get();
+        loop {
+            if ancestor == options.root {
+                break;
+            }
+            let Some(ancestor_id) = ancestor else {
+                // The root is not an ancestor of the node
+                return (target_rect, None, root_bounds);
+            };
+
+            let overflow = self.nodes[ancestor_id].style.overflow;
+            if overflow.x != Overflow::Visible || overflow.y != Overflow::Visible {
+                let clip = self.padding_box_client_rect(ancestor_id);
+                let clip = Rect::new(
+                    if overflow.x != Overflow::Visible {
+                        clip.x0
+                    } else {
+                        f64::NEG_INFINITY
+                    },
+                    if overflow.y != Overflow::Visible {
+                        clip.y0
+                    } else {
+                        f64::NEG_INFINITY
+                    },
+                    if overflow.x != Overflow::Visible {
+                        clip.x1
+                    } else {
+                        f64::INFINITY
+                    },
+                    if overflow.y != Overflow::Visible {
+                        clip.y1
+                    } else {
+                        f64::INFINITY
+                    },
+                );
+                intersection = intersection.and_then(|rect| intersect(rect, clip));
+            }
+            ancestor = self.nodes[ancestor_id].layout_parent.get();
+        }
+
+        let intersection = intersection.and_then(|rect| intersect(rect, root_bounds));
+        (target_rect, intersection, root_bounds)
+    }
+
+    /// Evaluate all observations and queue notifications for those which have changed.
+    /// Called after layout.
+    pub(crate) fn resolve_observations(&mut self) {
+        if self.resize_observations.is_empty() && self.intersection_observations.is_empty() {
+            return;
+        }
+
+        let mut resized_nodes: Vec<usize> = self.resize_observations.keys().copied().collect();
+        resized_nodes.sort_unstable();
+        for node_id in resized_nodes {
+            let (border_box, content_box) = if self.is_rendered(node_id) {
+                let layout = &self.nodes[node_id].unrounded_layout;
+                let width = layout.size.width;
+                let height = layout.size.height;
+                let content_width = width
+                    - layout.padding.left
+                    - layout.padding.right
+                    - layout.border.left
+                    - layout.border.right;
+                let content_height = height
+                    - layout.padding.top
+                    - layout.padding.bottom
+                    - layout.border.top
+                    - layout.border.bottom;
+                (
+                    (width as f64, height as f64),
+                    (
+                        content_width.max(0.0) as f64,
+                        content_height.max(0.0) as f64,
+                    ),
+                )
+            } else {
+                ((0.0, 0.0), (0.0, 0.0))
+            };
+
+            let observation = self.resize_observations.get_mut(&node_id).unwrap();
+            if observation.last_size == [border_box, content_box] {
+                continue;
+            }
+            observation.last_size = [border_box, content_box];
+
+            let event = BlitzResizeEvent {
+                border_box_width: border_box.0,
+                border_box_height: border_box.1,
+                content_box_width: content_box.0,
+                content_box_height: content_box.1,
+            };
+            self.pending_events
+                .push(DomEvent::new(node_id, DomEventData::Resize(event)));
+        }
+
+        let mut intersected_nodes: Vec<usize> =
+            self.intersection_observations.keys().copied().collect();
+        intersected_nodes.sort_unstable();
+        let time = SystemTime::now();
+        for node_id in intersected_nodes {
+            let observation = &self.intersection_observations[&node_id];
+            let (target_rect, intersection, root_bounds) =
+                self.compute_intersection(node_id, &observation.options);
+
+            let is_intersecting = intersection.is_some();
+            let intersection_rect = intersection.unwrap_or(Rect::ZERO);
+            let target_area = target_rect.area();
+            let intersection_ratio = if target_area > 0.0 {
+                (intersection_rect.area() / target_area).clamp(0.0, 1.0)
+            } else if is_intersecting {
+                1.0
+            } else {
+                0.0
+            };
+            let threshold_index = if is_intersecting {
+                observation
+                    .options
+                    .thresholds
+                    .iter()
+                    .filter(|threshold| **threshold <= intersection_ratio)
+                    .count()
+            } else {
+                0
+            };
+
+            if observation.previous_threshold_index == Some(threshold_index)
+                && observation.previous_is_intersecting == is_intersecting
+            {
+                continue;
+            }
+            let observation = self.intersection_observations.get_mut(&node_id).unwrap();
+            observation.previous_threshold_index = Some(threshold_index);
+            observation.previous_is_intersecting = is_intersecting;
+
+            let event = BlitzIntersectionEvent {
+                bounding_client_rect: to_blitz_rect(target_rect),
+                intersection_rect: to_blitz_rect(intersection_rect),
+                root_bounds: to_blitz_rect(root_bounds),
+                intersection_ratio,
+                is_intersecting,
+                time,
+            };
+            self.pending_events
+                .push(DomEvent::new(node_id, DomEventData::Visible(event)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+
+    #[test]
+    fn test_intersect_is_edge_inclusive() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        assert_eq!(
+            intersect(a, Rect::new(5.0, 5.0, 20.0, 20.0)),
+            Some(Rect::new(5.0, 5.0, 10.0, 10.0))
+        );
+        assert_eq!(
+            intersect(a, Rect::new(10.0, 0.0, 20.0, 10.0)),
+            Some(Rect::new(10.0, 0.0, 10.0, 10.0))
+        );
+        assert_eq!(intersect(a, Rect::new(11.0, 0.0, 20.0, 10.0)), None);
+    }
+
+    #[test]
+    fn test_intersection_ratio_of_partly_visible_node() {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        });
+        let style = |value: &str| {
+            vec![Attribute {
+                name: qual_name!("style"),
+                value: value.to_string(),
+            }]
+        };
+        let mut mutator = doc.mutate();
+        // The spacer's fractional height is rounded in the final layout
+        let spacer = mutator.create_element(qual_name!("div", html), style("height: 550.25px"));
+        let target = mutator.create_element(
+            qual_name!("div", html),
+            style("width: 100px; height: 100px"),
+        );
+        let body = mutator.create_element(qual_name!("body", html), style("margin: 0"));
+        mutator.append_children(body, &[spacer, target]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+
+        doc.observe_intersection(
+            target,
+            IntersectionObserverOptions {
+                thresholds: vec![0.0, 0.5, 1.0],
+                ..Default::default()
+            },
+        );
+        doc.resolve(0.0);
+
+        let events = doc.take_pending_events();
+        let [event] = events.as_slice() else {
+            panic!("expected a single event");
+        };
+        let DomEventData::Visible(event) = &event.data else {
+            panic!("expected a visible event");
+        };
+        assert!(event.is_intersecting);
+        assert_eq!(event.bounding_client_rect.y, 550.25);
+        assert_eq!(event.intersection_rect.height, 49.75);
+        assert!((event.intersection_ratio - 0.4975).abs() < 1e-9);
+    }
+}
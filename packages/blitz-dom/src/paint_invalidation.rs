@@ -0,0 +1,132 @@
+//! Tracking of the nodes which need to be repainted
+//!
+//! Painters which retain drawing commands between frames (such as `blitz_paint::PaintCache`)
+//! need to know which parts of the document changed since they last painted. Every call to
+//! [`BaseDocument::resolve`] starts a new "paint generation" and records the nodes which were
+//! damaged (by a restyle or a DOM mutation) during it. A painter remembers the generation it
+//! painted and asks for the nodes invalidated since then.
+//!
+//! Damaged nodes are recorded while damage is propagated through the tree, so there is no
+//! separate pass over the document. Changes which don't damage a node (such as moving focus)
+//! are recorded with [`BaseDocument::invalidate_paint`].
+//!
+//! Invalidations are recorded for the damaged nodes only. A change to a node may affect the
+//! painting of its ancestors (and, if it was relaid out, the position of its siblings), which
+//! painters are expected to account for.
+
+use std::collections::VecDeque;
+
+use crate::BaseDocument;
+
+/// The number of generations for which invalidations are retained. Painters which fall further
+/// behind than this have to repaint everything.
+const PAINT_INVALIDATION_HISTORY: usize = 16;
+
+#[derive(Default)]
+pub(crate) struct PaintInvalidations {
+    generation: u64,
+    /// Nodes invalidated by each of the most recent generations (oldest first). `None` if the
+    /// whole document was invalidated.
+    history: VecDeque<(u64, Option<Vec<usize>>)>,
+    /// Nodes invalidated since the current generation was recorded
+    pending: Vec<usize>,
+}
+
+impl BaseDocument {
+    /// The current paint generation. Incremented by every call to `resolve`.
+    pub fn paint_generation(&self) -> u64 {
+        self.paint_invalidations.generation
+    }
+
+    /// The nodes which need repainting because they changed after `generation` was painted.
+    ///
+    /// Returns `None` if everything needs repainting (because `generation` is too old, or
+    /// because changes are not tracked with incremental layout disabled).
+    pub fn paint_invalidations_since(&self, generation: u64) -> Option<Vec<usize>> {
+        let invalidations = &self.paint_invalidations;
+        if generation > invalidations.generation {
+            return None;
+        }
+
+        // Check that the history reaches back to the generation after `generation`
+        if generation < invalidations.generation {
+            let oldest = invalidations
+                .history
+                .front()
+                .map(|(recorded, _)| *recorded)?;
+            if generation + 1 < oldest {
+                return None;
+            }
+        }
+
+        let mut node_ids = Vec::new();
+        for (_, nodes) in invalidations
+            .history
+            .iter()
+            .filter(|(recorded, _)| *recorded > generation)
+        {
+            node_ids.extend(nodes.as_ref()?);
+        }
+        node_ids.extend(&invalidations.pending);
+        node_ids.sort_unstable();
+        node_ids.dedup();
+        Some(node_ids)
+    }
+
+    /// Mark a node as needing to be repainted, for changes which don't otherwise damage the node
+    pub fn invalidate_paint(&mut self, node_id: usize) {
+        self.paint_invalidations.pending.push(node_id);
+    }
+
+    /// Start a new paint generation, recording the nodes which have been invalidated since the
+    /// last one. Damaged nodes are invalidated as damage is propagated, so this is called by
+    /// `resolve` after damage propagation.
+    pub(crate) fn record_paint_invalidations(&mut self) {
+        let pending = std::mem::take(&mut self.paint_invalidations.pending);
+        let nodes = if crate::INCREMENTAL {
+            Some(pending)
+        } else {
+            // Damage isn't cleared between frames, so we can't tell what changed
+            None
+        };
+
+        let invalidations = &mut self.paint_invalidations;
+        invalidations.generation += 1;
+        invalidations
+            .history
+            .push_back((invalidations.generation, nodes));
+        if invalidations.history.len() > PAINT_INVALIDATION_HISTORY {
+            invalidations.history.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DocumentConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_paint_invalidations_since() {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        let root = doc.root_node().id;
+        assert_eq!(doc.paint_invalidations_since(0), Some(Vec::new()));
+
+        doc.invalidate_paint(root);
+        assert_eq!(doc.paint_invalidations_since(0), Some(vec![root]));
+        doc.record_paint_invalidations();
+        let generation = doc.paint_generation();
+        assert_eq!(generation, 1);
+        assert_eq!(doc.paint_invalidations_since(generation), Some(Vec::new()));
+
+        if crate::INCREMENTAL {
+            assert_eq!(doc.paint_invalidations_since(0), Some(vec![root]));
+        }
+
+        for _ in 0..PAINT_INVALIDATION_HISTORY {
+            doc.record_paint_invalidations();
+        }
+        assert_eq!(doc.paint_invalidations_since(0), None);
+    }
+}
@@ -0,0 +1,434 @@
+//! Per-frame profiling of the rendering pipeline
+//!
+//! When enabled, each call to [`BaseDocument::resolve`] starts a new [`FrameProfile`] recording
+//! how long each phase took along with node, restyle and relayout counts. Painting (and shells
+//! which build an accessibility tree) add their own phases to the latest frame. The most recent
+//! frames are kept in a ring buffer which can be queried or exported as a trace for viewing in
+//! `chrome://tracing` or [Perfetto](https://ui.perfetto.dev).
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::BaseDocument;
+
+/// The default number of frames kept by a [`FrameProfiler`]
+pub const DEFAULT_PROFILE_CAPACITY: usize = 300;
+
+/// A phase of rendering a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FramePhase {
+    /// Applying pending messages, scroll animations, etc before styling
+    Prepare,
+    /// Stylo's style traversal
+    Style,
+    /// Counters, quotes and other generated content
+    GeneratedContent,
+    /// Propagating restyle damage through the tree
+    Damage,
+    /// Box tree construction (anonymous blocks, inline layouts, etc)
+    Construct,
+    /// Converting computed styles into layout styles
+    Flush,
+    /// Taffy layout
+    Layout,
+    /// Lazy loading and eviction of images
+    Images,
+    /// Intersection and resize observation
+    Observe,
+    /// Resolving sub-documents (e.g. iframes)
+    SubDocuments,
+    /// Building the scene to be rendered
+    Paint,
+    /// Updating the accessibility tree
+    Accessibility,
+}
+
+impl FramePhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Prepare => "prepare",
+            Self::Style => "style",
+            Self::GeneratedContent => "generated-content",
+            Self::Damage => "damage",
+            Self::Construct => "construct",
+            Self::Flush => "flush",
+            Self::Layout => "layout",
+            Self::Images => "images",
+            Self::Observe => "observe",
+            Self::SubDocuments => "subdocuments",
+            Self::Paint => "paint",
+            Self::Accessibility => "accessibility",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseTiming {
+    pub phase: FramePhase,
+    /// When the phase started, relative to when the profiler was created
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameProfile {
+    /// Sequential frame number (starting at 0 when the profiler is created)
+    pub frame: u64,
+    /// When the frame started, relative to when the profiler was created
+    pub start: Duration,
+    pub phases: Vec<PhaseTiming>,
+    /// The number of nodes in the document
+    pub node_count: usize,
+    /// The number of elements whose styles were recomputed
+    pub restyle_count: usize,
+    /// The number of nodes whose layout was recomputed (i.e. whose layout cache was cleared)
+    pub relayout_count: usize,
+}
+
+impl FrameProfile {
+    /// The total time spent in all phases of the frame
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+
+    /// The time spent in `phase` (zero if it didn't run)
+    pub fn phase_duration(&self, phase: FramePhase) -> Duration {
+        self.phases
+            .iter()
+            .filter(|timing| timing.phase == phase)
+            .map(|timing| timing.duration)
+            .sum()
+    }
+}
+
+struct ProfilerState {
+    frames: VecDeque<FrameProfile>,
+    capacity: usize,
+    next_frame: u64,
+}
+
+/// Records [`FrameProfile`]s into a ring buffer. Disabled by default.
+///
+/// Recording takes `&self` so that phases can be recorded while the document is borrowed
+/// immutably (e.g. while painting).
+pub struct FrameProfiler {
+    enabled: AtomicBool,
+    epoch: Instant,
+    state: Mutex<ProfilerState>,
+}
+
+impl Default for FrameProfiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROFILE_CAPACITY)
+    }
+}
+
+impl FrameProfiler {
+    /// Create a (disabled) profiler which keeps the last `capacity` frames
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            epoch: Instant::now(),
+            state: Mutex::new(ProfilerState {
+                frames: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                next_frame: 0,
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Change the number of frames kept, dropping the oldest frames if necessary
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.capacity = capacity.max(1);
+        while state.frames.len() > state.capacity {
+            state.frames.pop_front();
+        }
+    }
+
+    /// Discard all recorded frames
+    pub fn clear(&self) {
+        self.state.lock().unwrap().frames.clear();
+    }
+
+    /// The recorded frames, oldest first
+    pub fn frames(&self) -> Vec<FrameProfile> {
+        self.state.lock().unwrap().frames.iter().cloned().collect()
+    }
+
+    /// The most recently started frame
+    pub fn last_frame(&self) -> Option<FrameProfile> {
+        self.state.lock().unwrap().frames.back().cloned()
+    }
+
+    /// Start recording a new frame (if enabled)
+    pub fn begin_frame(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let start = self.epoch.elapsed();
+        let mut state = self.state.lock().unwrap();
+        if state.frames.len() >= state.capacity {
+            state.frames.pop_front();
+        }
+        let frame = state.next_frame;
+        state.next_frame += 1;
+        state.frames.push_back(FrameProfile {
+            frame,
+            start,
+            ..Default::default()
+        });
+    }
+
+    /// Record that `phase` of the current frame ran from `start` until now
+    pub fn record_phase(&self, phase: FramePhase, start: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        let duration = start.elapsed();
+        let start = start.saturating_duration_since(self.epoch);
+        self.with_current_frame(|frame| {
+            frame.phases.push(PhaseTiming {
+                phase,
+                start,
+                duration,
+            })
+        });
+    }
+
+    /// Record that `phase` ran from `start` until now, and reset `start` for the next phase
+    pub(crate) fn end_phase(&self, phase: FramePhase, start: &mut Instant) {
+        self.record_phase(phase, *start);
+        *start = Instant::now();
+    }
+
+    /// Record the node, restyle and relayout counts of the current frame
+    pub(crate) fn record_counts(
+        &self,
+        node_count: usize,
+        restyle_count: usize,
+        relayout_count: usize,
+    ) {
+        self.with_current_frame(|frame| {
+            frame.node_count = node_count;
+            frame.restyle_count = restyle_count;
+            frame.relayout_count = relayout_count;
+        });
+    }
+
+    fn with_current_frame(&self, f: impl FnOnce(&mut FrameProfile)) {
+        let mut state = self.state.lock().unwrap();
+        if state.frames.is_empty() {
+            // Phases recorded before the first `resolve` (e.g. painting a document that was
+            // resolved before profiling was enabled) start a frame of their own
+            drop(state);
+            self.begin_frame();
+            state = self.state.lock().unwrap();
+        }
+        if let Some(frame) = state.frames.back_mut() {
+            f(frame);
+        }
+    }
+
+    /// Export the recorded frames in the Chrome trace event JSON format (which can also be opened
+    /// by Perfetto). See <https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU>.
+    pub fn to_chrome_trace_json(&self) -> String {
+        let frames = self.frames();
+        let mut events = Vec::new();
+        for frame in &frames {
+            let args = format!(
+                r#"{{"frame":{},"nodes":{},"restyled":{},"relayout":{}}}"#,
+                frame.frame, frame.node_count, frame.restyle_count, frame.relayout_count
+            );
+            events.push(trace_event("frame", frame.start, frame.total(), &args));
+            for phase in &frame.phases {
+                events.push(trace_event(
+                    phase.phase.name(),
+                    phase.start,
+                    phase.duration,
+                    "{}",
+                ));
+            }
+        }
+        format!(
+            r#"{{"displayTimeUnit":"ms","traceEvents":[{}]}}"#,
+            events.join(",")
+        )
+    }
+
+    /// Export the recorded frames as a [Perfetto](https://perfetto.dev) protobuf trace, with each
+    /// frame and phase as a slice on a single track
+    pub fn to_perfetto_trace(&self) -> Vec<u8> {
+        const TRACK_UUID: u64 = 1;
+        const SEQUENCE_ID: u64 = 1;
+        const SLICE_BEGIN: u64 = 1;
+        const SLICE_END: u64 = 2;
+
+        let mut trace = Vec::new();
+
+        // TracePacket { track_descriptor: TrackDescriptor { uuid, name } }
+        let mut descriptor = Vec::new();
+        proto::write_varint_field(&mut descriptor, 1, TRACK_UUID);
+        proto::write_bytes_field(&mut descriptor, 2, b"Blitz frames");
+        let mut packet = Vec::new();
+        proto::write_varint_field(&mut packet, 10, SEQUENCE_ID);
+        proto::write_bytes_field(&mut packet, 60, &descriptor);
+        proto::write_bytes_field(&mut trace, 1, &packet);
+
+        // TracePacket { timestamp, trusted_packet_sequence_id, track_event: TrackEvent { .. } }
+        let mut write_event = |timestamp: Duration, event_type: u64, name: Option<&str>| {
+            let mut event = Vec::new();
+            proto::write_varint_field(&mut event, 9, event_type);
+            proto::write_varint_field(&mut event, 11, TRACK_UUID);
+            if let Some(name) = name {
+                proto::write_bytes_field(&mut event, 23, name.as_bytes());
+            }
+            let mut packet = Vec::new();
+            proto::write_varint_field(&mut packet, 8, timestamp.as_nanos() as u64);
+            proto::write_varint_field(&mut packet, 10, SEQUENCE_ID);
+            proto::write_bytes_field(&mut packet, 11, &event);
+            proto::write_bytes_field(&mut trace, 1, &packet);
+        };
+
+        for frame in self.frames() {
+            let frame_end = frame
+                .phases
+                .iter()
+                .map(|phase| phase.start + phase.duration)
+                .max();
+            let frame_end = frame_end.unwrap_or(frame.start).max(frame.start);
+            let name = format!(
+                "frame {} ({} nodes, {} restyled, {} relayout)",
+                frame.frame, frame.node_count, frame.restyle_count, frame.relayout_count
+            );
+            write_event(frame.start, SLICE_BEGIN, Some(&name));
+            for phase in &frame.phases {
+                write_event(phase.start, SLICE_BEGIN, Some(phase.phase.name()));
+                write_event(phase.start + phase.duration, SLICE_END, None);
+            }
+            write_event(frame_end, SLICE_END, None);
+        }
+
+        trace
+    }
+}
+
+fn trace_event(name: &str, start: Duration, duration: Duration, args: &str) -> String {
+    let mut event = String::new();
+    write!(
+        event,
+        r#"{{"name":"{name}","cat":"blitz","ph":"X","pid":1,"tid":1,"ts":{:.3},"dur":{:.3},"args":{args}}}"#,
+        start.as_secs_f64() * 1_000_000.0,
+        duration.as_secs_f64() * 1_000_000.0,
+    )
+    .unwrap();
+    event
+}
+
+/// Minimal protobuf encoding (enough to write Perfetto traces)
+mod proto {
+    fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    pub(super) fn write_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+        write_varint(out, field << 3);
+        write_varint(out, value);
+    }
+
+    pub(super) fn write_bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        write_varint(out, (field << 3) | 2);
+        write_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+}
+
+impl BaseDocument {
+    pub fn profiler(&self) -> &FrameProfiler {
+        &self.profiler
+    }
+
+    /// The number of elements with restyle damage (i.e. which were just restyled). Only counted
+    /// when profiling, as it requires visiting every node.
+    pub(crate) fn count_restyled_elements(&self) -> usize {
+        if !self.profiler.is_enabled() {
+            return 0;
+        }
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.is_element() && node.damage().is_some_and(|d| !d.is_empty()))
+            .count()
+    }
+
+    /// Record the node, restyle and relayout counts of the current frame. Must be called before
+    /// layout, as nodes needing relayout are those whose layout cache has been cleared.
+    pub(crate) fn record_profile_counts(&self, restyle_count: usize) {
+        if !self.profiler.is_enabled() {
+            return;
+        }
+        let relayout_count = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.cache.is_empty())
+            .count();
+        self.profiler
+            .record_counts(self.nodes.len(), restyle_count, relayout_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_keeps_latest_frames() {
+        let profiler = FrameProfiler::new(2);
+        profiler.set_enabled(true);
+        for _ in 0..3 {
+            profiler.begin_frame();
+            profiler.record_phase(FramePhase::Style, Instant::now());
+        }
+        let frames = profiler.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].frame, 1);
+        assert_eq!(frames[1].phases[0].phase, FramePhase::Style);
+    }
+
+    #[test]
+    fn disabled_profiler_records_nothing() {
+        let profiler = FrameProfiler::default();
+        profiler.begin_frame();
+        profiler.record_phase(FramePhase::Layout, Instant::now());
+        assert!(profiler.frames().is_empty());
+        assert_eq!(
+            profiler.to_chrome_trace_json(),
+            r#"{"displayTimeUnit":"ms","traceEvents":[]}"#
+        );
+    }
+
+    #[test]
+    fn varints_are_little_endian_base_128() {
+        let mut out = Vec::new();
+        proto::write_varint_field(&mut out, 1, 300);
+        assert_eq!(out, [0x08, 0xac, 0x02]);
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::{
     cell::RefCell,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use debug_timer::debug_timer;
@@ -34,6 +34,7 @@ use crate::{
         damage::{ALL_DAMAGE, CONSTRUCT_BOX, CONSTRUCT_DESCENDENT, CONSTRUCT_FC},
     },
     node::TextBrush,
+    profiler::FramePhase,
 };
 
 impl BaseDocument {
@@ -48,10 +49,21 @@ impl BaseDocument {
             return;
         }
 
+        self.profiler.begin_frame();
+        let mut phase_start = Instant::now();
+
         // Process messages that have been sent to our message channel (e.g. loaded resource)
         self.handle_messages();
 
+        // Apply requests from remote inspectors (which may edit the document)
+        #[cfg(feature = "devtools_server")]
+        self.handle_devtools_requests();
+
         self.resolve_scroll_animation();
+        self.resolve_overscroll();
+        self.resolve_scroll_end();
+        self.profiler
+            .end_phase(FramePhase::Prepare, &mut phase_start);
 
         let root_node_id = self.root_element().id;
         debug_timer!(timer, feature = "log_phase_times");
@@ -59,12 +71,27 @@ impl BaseDocument {
         // we need to resolve stylist first since it will need to drive our layout bits
         self.resolve_stylist(current_time_for_animations);
         timer.record_time("style");
+        self.profiler.end_phase(FramePhase::Style, &mut phase_start);
+        let restyle_count = self.count_restyled_elements();
+
+        // Resolve counters, quotes and other ::before/::after content in document order
+        self.resolve_generated_content();
+        timer.record_time("content");
+        self.profiler
+            .end_phase(FramePhase::GeneratedContent, &mut phase_start);
 
         // Propagate damage flags (from mutation and restyles) up and down the tree
         #[cfg(feature = "incremental")]
         self.propagate_damage_flags(root_node_id, RestyleDamage::empty());
         #[cfg(feature = "incremental")]
-        timer.record_time("damage");
+        {
+            timer.record_time("damage");
+            self.profiler
+                .end_phase(FramePhase::Damage, &mut phase_start);
+        }
+
+        // Record the damaged nodes for painters which retain drawing commands between frames
+        self.record_paint_invalidations();
 
         // Fix up tree for layout (insert anonymous blocks as necessary, etc)
         self.resolve_layout_children();
@@ -72,10 +99,15 @@ impl BaseDocument {
 
         self.resolve_deferred_tasks();
         timer.record_time("pconstruct");
+        self.profiler
+            .end_phase(FramePhase::Construct, &mut phase_start);
 
         // Merge stylo into taffy
         self.flush_styles_to_layout(root_node_id);
         timer.record_time("flush");
+        self.profiler.end_phase(FramePhase::Flush, &mut phase_start);
+        self.record_profile_counts(restyle_count);
+        phase_start = Instant::now();
 
         // Next we resolve layout with the data resolved by stlist
         self.resolve_layout();
@@ -90,9 +122,43 @@ impl BaseDocument {
             }
             timer.record_time("c_damage");
         }
+        self.profiler
+            .end_phase(FramePhase::Layout, &mut phase_start);
+        self.memory.is_discarded = false;
+
+        // Check the incremental results against a full rebuild (if enabled)
+        self.verify_incremental_layout(current_time_for_animations);
+        phase_start = Instant::now();
+
+        // Load lazy images that are now near the viewport, and free images that are far from it
+        self.resolve_lazy_loading();
+        self.evict_offscreen_images();
+        timer.record_time("images");
+        self.profiler
+            .end_phase(FramePhase::Images, &mut phase_start);
+
+        // Queue notifications for observed nodes whose size or visibility has changed
+        self.resolve_observations();
+        timer.record_time("observe");
+        self.profiler
+            .end_phase(FramePhase::Observe, &mut phase_start);
 
         let mut subdoc_is_animating = false;
         for &node_id in &self.sub_document_nodes {
+            // Lazy sub-documents are not resolved until they are near the viewport
+            if self.lazy_load_nodes.contains(&node_id) {
+                continue;
+            }
+
+            // Sub-documents which aren't rendered (e.g. background tabs) are not resolved, so
+            // that they stay discarded (see `BaseDocument::discard`)
+            let is_rendered = self.nodes[node_id]
+                .primary_styles()
+                .is_some_and(|style| !style.get_box().display.is_none());
+            if !is_rendered {
+                continue;
+            }
+
             let node = &mut self.nodes[node_id];
             let size = node.final_layout.size;
             if let Some(mut sub_doc) = node.subdoc_mut().map(|doc| doc.inner_mut()) {
@@ -117,6 +183,8 @@ impl BaseDocument {
         }
         self.subdoc_is_animating = subdoc_is_animating;
         timer.record_time("subdocs");
+        self.profiler
+            .end_phase(FramePhase::SubDocuments, &mut phase_start);
 
         timer.print_times(&format!("Resolve({}): ", self.id()));
     }
@@ -142,11 +210,18 @@ impl BaseDocument {
                 let dx = fling_state.x_velocity * time_diff_ms;
                 let dy = fling_state.y_velocity * time_diff_ms;
 
-                self.scroll_by(Some(fling_state.target), dx, dy, &mut |_| {});
-                if fling_state.x_velocity.abs() < 0.1 && fling_state.y_velocity.abs() < 0.1 {
+                self.scroll_by_with_overscroll(Some(fling_state.target), dx, dy);
+
+                // Flings which run into the end of the scroll chain stop (and bounce back)
+                let has_stopped =
+                    fling_state.x_velocity.abs() < 0.1 && fling_state.y_velocity.abs() < 0.1;
+                if has_stopped || self.is_overscrolled() {
                     self.scroll_animation = ScrollAnimationState::None;
+                    self.scroll_snap(Some(fling_state.target));
                 }
             }
+            ScrollAnimationState::AwaitingSnap(_) => self.resolve_pending_scroll_snap(),
+            ScrollAnimationState::Smooth(_) => self.resolve_smooth_scroll(),
             ScrollAnimationState::None => {
                 // Do nothing
             }
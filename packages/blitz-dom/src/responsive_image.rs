@@ -0,0 +1,651 @@
+//! Selection of image sources for `<img srcset sizes>`, `<picture>` and `image-set()`
+//!
+//! See <https://html.spec.whatwg.org/multipage/images.html#selecting-an-image-source>
+
+use blitz_traits::net::Request;
+use cssparser::{Parser, ParserInput};
+use markup5ever::local_name;
+use selectors::matching::QuirksMode;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use style::media_queries::MediaList;
+use style::parser::ParserContext;
+use style::servo::url::ComputedUrl;
+use style::servo_arc::Arc as ServoArc;
+use style::stylesheets::{CssRuleType, CustomMediaEvaluator, Origin};
+use style::values::computed::{Image, Resolution};
+use style::values::generics::image::{GenericImageSetItem, Image as StyloImage};
+use style::values::specified::source_size_list::SourceSizeList;
+use style_traits::ParsingMode;
+use url::Url;
+
+use crate::BaseDocument;
+use crate::layout::damage::ALL_DAMAGE;
+use crate::net::{ImageHandler, ResourceHandler};
+use crate::node::{BackgroundImageData, SpecialElementData, Status};
+use crate::util::ImageType;
+
+type ImageSetItem = GenericImageSetItem<Image, Resolution>;
+
+/// The image source selected for an `<img>` element
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageSource {
+    pub url: Url,
+    /// The pixel density of the image. The image's natural size is its size in image pixels
+    /// divided by its density.
+    pub density: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Descriptor {
+    Density(f64),
+    Width(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SrcsetCandidate {
+    url: String,
+    descriptor: Descriptor,
+}
+
+impl SrcsetCandidate {
+    fn density(&self, source_size: f64) -> f64 {
+        match self.descriptor {
+            Descriptor::Density(density) => density,
+            Descriptor::Width(width) => width as f64 / source_size,
+        }
+    }
+}
+
+/// Parse a `srcset` attribute into image candidates. Invalid candidates are dropped.
+fn parse_srcset(input: &str) -> Vec<SrcsetCandidate> {
+    let mut candidates = Vec::new();
+    let mut rest = input;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+
+        let url_end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let (url, after_url) = rest.split_at(url_end);
+        rest = after_url;
+
+        // A URL ending in a comma has no descriptors. Otherwise descriptors continue until the
+        // next comma which is not within parentheses.
+        let (url, descriptors) = if url.ends_with(',') {
+            (url.trim_end_matches(','), "")
+        } else {
+            let mut depth = 0usize;
+            let end = rest
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth = depth.saturating_sub(1),
+                        ',' if depth == 0 => return true,
+                        _ => {}
+                    }
+                    false
+                })
+                .map_or(rest.len(), |(idx, _)| idx);
+            let descriptors = &rest[..end];
+            rest = &rest[end..];
+            (url, descriptors)
+        };
+
+        if let Some(descriptor) = parse_descriptors(descriptors) {
+            candidates.push(SrcsetCandidate {
+                url: url.to_string(),
+                descriptor,
+            });
+        }
+    }
+
+    candidates
+}
+
+fn parse_descriptors(input: &str) -> Option<Descriptor> {
+    let mut descriptor = None;
+    let mut has_height = false;
+
+    for token in input.split_ascii_whitespace() {
+        let unit = token.chars().last().unwrap();
+        let value = &token[..token.len() - unit.len_utf8()];
+        match unit {
+            'x' if descriptor.is_none() => {
+                let density = value.parse::<f64>().ok()?;
+                if !density.is_finite() || density <= 0.0 {
+                    return None;
+                }
+                descriptor = Some(Descriptor::Density(density));
+            }
+            'w' if descriptor.is_none() => {
+                let width = value.parse::<u32>().ok().filter(|width| *width > 0)?;
+                descriptor = Some(Descriptor::Width(width));
+            }
+            // Height descriptors are allowed (alongside a width descriptor) but ignored
+            'h' if !has_height && value.parse::<u32>().is_ok() => has_height = true,
+            _ => return None,
+        }
+    }
+
+    if has_height && !matches!(descriptor, Some(Descriptor::Width(_))) {
+        return None;
+    }
+
+    Some(descriptor.unwrap_or(Descriptor::Density(1.0)))
+}
+
+/// Select the candidate with the lowest density that is at least the device pixel ratio, or the
+/// highest density candidate if there are none.
+fn select_by_density<T>(
+    candidates: impl IntoIterator<Item = (T, f64)>,
+    device_pixel_ratio: f64,
+) -> Option<(T, f64)> {
+    let mut best: Option<(T, f64)> = None;
+    for (candidate, density) in candidates {
+        let is_better = match &best {
+            None => true,
+            Some((_, best_density)) => {
+                let best_is_enough = *best_density >= device_pixel_ratio;
+                let is_enough = density >= device_pixel_ratio;
+                match (best_is_enough, is_enough) {
+                    (true, true) => density < *best_density,
+                    (false, false) => density > *best_density,
+                    (false, true) => true,
+                    (true, false) => false,
+                }
+            }
+        };
+        if is_better {
+            best = Some((candidate, density));
+        }
+    }
+    best
+}
+
+/// Whether we are able to decode images of the specified MIME type
+fn is_supported_image_type(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    let essence = essence.to_ascii_lowercase();
+    let is_raster = matches!(
+        essence.as_str(),
+        "image/png"
+            | "image/apng"
+            | "image/jpeg"
+            | "image/gif"
+            | "image/webp"
+            | "image/bmp"
+            | "image/x-icon"
+            | "image/vnd.microsoft.icon"
+    );
+    is_raster || (cfg!(feature = "svg") && essence == "image/svg+xml")
+}
+
+/// Select an image from an `image-set()`. Returns the image's url and density.
+pub(crate) fn select_image_set_item(
+    items: &[ImageSetItem],
+    device_pixel_ratio: f64,
+) -> Option<(ServoArc<Url>, f64)> {
+    let candidates = items.iter().filter_map(|item| {
+        if item.has_mime_type && !is_supported_image_type(&item.mime_type) {
+            return None;
+        }
+        let StyloImage::Url(ComputedUrl::Valid(url)) = &item.image else {
+            return None;
+        };
+        Some((url.clone(), item.resolution.dppx() as f64))
+    });
+    select_by_density(candidates, device_pixel_ratio)
+}
+
+impl BaseDocument {
+    fn parse_with_context<T>(
+        &self,
+        input: &str,
+        rule_type: CssRuleType,
+        parse: impl FnOnce(&ParserContext, &mut Parser) -> T,
+    ) -> T {
+        let url_data = self.url.url_extra_data();
+        let context = ParserContext::new(
+            Origin::Author,
+            &url_data,
+            Some(rule_type),
+            ParsingMode::DEFAULT,
+            QuirksMode::NoQuirks,
+            /* namespaces = */ Default::default(),
+            None,
+            None,
+        );
+        let mut input = ParserInput::new(input);
+        let mut parser = Parser::new(&mut input);
+        parse(&context, &mut parser)
+    }
+
+    /// Evaluate a `sizes` attribute to get the size of the image in CSS pixels
+    fn evaluate_sizes(&self, sizes: Option<&str>) -> f64 {
+        let sizes = sizes.unwrap_or("");
+        let size_list = self.parse_with_context(sizes, CssRuleType::Style, SourceSizeList::parse);
+        size_list
+            .evaluate(self.stylist.device(), QuirksMode::NoQuirks)
+            .to_f64_px()
+    }
+
+    fn evaluate_media(&self, media: &str) -> bool {
+        let media_list = self.parse_with_context(media, CssRuleType::Media, MediaList::parse);
+        media_list.evaluate(
+            self.stylist.device(),
+            QuirksMode::NoQuirks,
+            &mut CustomMediaEvaluator::none(),
+        )
+    }
+
+    /// Select a source from a `srcset` and `sizes` attribute (plus an optional `src` attribute)
+    fn select_from_srcset(
+        &self,
+        srcset: &str,
+        sizes: Option<&str>,
+        src: Option<&str>,
+    ) -> Option<(String, f64)> {
+        let mut candidates = parse_srcset(srcset);
+
+        // The src attribute is a 1x candidate unless the srcset has a 1x candidate or any
+        // width-based candidates
+        if let Some(src) = src.filter(|src| !src.is_empty()) {
+            let has_1x_or_width = candidates
+                .iter()
+                .any(|candidate| match candidate.descriptor {
+                    Descriptor::Density(density) => density == 1.0,
+                    Descriptor::Width(_) => true,
+                });
+            if !has_1x_or_width {
+                candidates.push(SrcsetCandidate {
+                    url: src.to_string(),
+                    descriptor: Descriptor::Density(1.0),
+                });
+            }
+        }
+
+        let source_size = self.evaluate_sizes(sizes);
+        let device_pixel_ratio = self.viewport.scale_f64();
+        let candidates = candidates.iter().map(|candidate| {
+            let density = candidate.density(source_size);
+            (candidate.url.as_str(), density)
+        });
+        select_by_density(candidates, device_pixel_ratio)
+            .map(|(url, density)| (url.to_string(), density))
+    }
+
+    /// Select the source of an `<img>` element from its `src`, `srcset` and `sizes` attributes,
+    /// and from any `<source>` elements if it is within a `<picture>` element.
+    pub(crate) fn select_image_source(&self, img_id: usize) -> Option<ImageSource> {
+        let img = &self.nodes[img_id];
+        let element = img.element_data()?;
+        let src = element.attr(local_name!("src"));
+
+        // Sources in a <picture> element take precedence over the <img> element's own attributes
+        let picture = img
+            .parent
+            .map(|parent_id| &self.nodes[parent_id])
+            .filter(|parent| {
+                parent
+                    .data
+                    .is_element_with_tag_name(&local_name!("picture"))
+            });
+        let from_picture = picture.and_then(|picture| {
+            picture
+                .children
+                .iter()
+                .take_while(|child_id| **child_id != img_id)
+                .filter_map(|child_id| self.nodes[*child_id].element_data())
+                .filter(|source| source.name.local == local_name!("source"))
+                .find_map(|source| {
+                    let srcset = source.attr(local_name!("srcset"))?;
+                    if let Some(media) = source.attr(local_name!("media")) {
+                        if !self.evaluate_media(media) {
+                            return None;
+                        }
+                    }
+                    if let Some(mime_type) = source.attr(local_name!("type")) {
+                        if !is_supported_image_type(mime_type) {
+                            return None;
+                        }
+                    }
+                    self.select_from_srcset(srcset, source.attr(local_name!("sizes")), None)
+                })
+        });
+
+        let (url, density) = match from_picture {
+            Some(selected) => selected,
+            None => match element.attr(local_name!("srcset")) {
+                Some(srcset) => {
+                    self.select_from_srcset(srcset, element.attr(local_name!("sizes")), src)?
+                }
+                None => (src.filter(|src| !src.is_empty())?.to_string(), 1.0),
+            },
+        };
+
+        Some(ImageSource {
+            url: self.url.resolve_relative(&url)?,
+            density,
+        })
+    }
+
+    /// Whether the source selected for an `<img>` element depends on the viewport (because it
+    /// has a `srcset` or is in a `<picture>` element)
+    fn is_responsive_image(&self, img_id: usize) -> bool {
+        let img = &self.nodes[img_id];
+        let in_picture = img.parent.is_some_and(|parent_id| {
+            self.nodes[parent_id]
+                .data
+                .is_element_with_tag_name(&local_name!("picture"))
+        });
+        in_picture || img.attr(local_name!("srcset")).is_some()
+    }
+
+    /// Select the source of an `<img>` element and load it if it has changed
+    pub(crate) fn load_responsive_image(&mut self, img_id: usize) {
+        if self.nodes[img_id].flags.is_in_document() && self.is_responsive_image(img_id) {
+            self.responsive_image_nodes.insert(img_id);
+        } else {
+            self.responsive_image_nodes.remove(&img_id);
+        }
+
+        let source = self.select_image_source(img_id);
+        let is_lazy = self.is_lazy_loading(img_id);
+        let Some(element) = self.nodes[img_id].element_data_mut() else {
+            return;
+        };
+        if element.image_source == source {
+            // The load may have been deferred by a `loading="lazy"` attribute which has since been removed
+            if !is_lazy && self.lazy_load_nodes.remove(&img_id) {
+                if let Some(source) = source {
+                    self.load_image(img_id, source.url);
+                }
+            }
+            return;
+        }
+        element.image_source = source.clone();
+        element.evicted_image_size = None;
+        if source.is_some() {
+            self.image_nodes.insert(img_id);
+        } else {
+            self.image_nodes.remove(&img_id);
+        }
+
+        match source {
+            // Lazy images are loaded once layout has placed them near the viewport
+            Some(_) if is_lazy => {
+                self.lazy_load_nodes.insert(img_id);
+            }
+            Some(source) => {
+                self.lazy_load_nodes.remove(&img_id);
+                self.load_image(img_id, source.url);
+            }
+            None => {
+                self.lazy_load_nodes.remove(&img_id);
+                let node = &mut self.nodes[img_id];
+                node.element_data_mut().unwrap().special_data = SpecialElementData::None;
+                node.cache.clear();
+                node.insert_damage(ALL_DAMAGE);
+            }
+        }
+    }
+
+    /// Re-select the sources of `<img>` elements and background images which depend on the
+    /// viewport (because it has been resized or its scale has changed)
+    pub(crate) fn reselect_image_sources(&mut self) {
+        let img_ids: Vec<usize> = self.responsive_image_nodes.iter().copied().collect();
+        for img_id in img_ids {
+            self.load_responsive_image(img_id);
+        }
+
+        let background_ids: Vec<usize> = self.background_image_nodes.iter().copied().collect();
+        for node_id in background_ids {
+            self.load_background_images(node_id);
+        }
+    }
+
+    /// Select the background images of a node (choosing from any `image-set()`s based on the
+    /// device pixel ratio), and load those which have changed
+    pub(crate) fn load_background_images(&mut self, node_id: usize) {
+        let doc_id = self.id();
+        let device_pixel_ratio = self.viewport.scale_f64();
+        let node = &mut self.nodes[node_id];
+        let Some(style) = node.primary_styles().map(|style| ServoArc::clone(&style)) else {
+            return;
+        };
+        // TODO: handle multiple background images
+        let Some(elem) = node.data.downcast_element_mut() else {
+            return;
+        };
+
+        let style_bgs = &style.get_background().background_image.0;
+        let elem_bgs = &mut elem.background_images;
+
+        let len = style_bgs.len();
+        // Padding with `None` (e.g. for `background-image: none`) doesn't change what is painted
+        let mut has_changed = elem_bgs.iter().skip(len).any(Option::is_some);
+        elem_bgs.resize_with(len, || None);
+
+        for idx in 0..len {
+            let background_image = &style_bgs[idx];
+            let selected_image = match background_image {
+                StyloImage::Url(ComputedUrl::Valid(url)) => Some((url.clone(), 1.0)),
+                StyloImage::ImageSet(image_set) => {
+                    select_image_set_item(&image_set.items, device_pixel_ratio)
+                }
+                _ => None,
+            };
+            let new_bg_image = match selected_image {
+                Some((new_url, density)) => {
+                    let old_bg_image = elem_bgs[idx].as_ref();
+                    let old_bg_image_url = old_bg_image.map(|data| &data.url);
+                    if old_bg_image_url.is_some_and(|old_url| *new_url == **old_url) {
+                        continue;
+                    }
+
+                    // Check cache first
+                    let url_str = new_url.as_str();
+                    if let Some(cached_image) = self.image_cache.get(url_str) {
+                        #[cfg(feature = "tracing")]
+                        tracing::info!("Loading image {url_str} from cache");
+                        Some(BackgroundImageData {
+                            url: new_url.clone(),
+                            density,
+                            status: Status::Ok,
+                            image: cached_image.clone(),
+                        })
+                    } else if let Some(waiting_list) = self.pending_images.get_mut(url_str) {
+                        // Image is already being fetched, queue this node
+                        #[cfg(feature = "tracing")]
+                        tracing::info!("Image {url_str} already pending, queueing node {node_id}");
+                        waiting_list.push((node_id, ImageType::Background(idx)));
+                        Some(BackgroundImageData {
+                            density,
+                            ..BackgroundImageData::new(new_url.clone())
+                        })
+                    } else {
+                        // Start fetch and track as pending
+                        #[cfg(feature = "tracing")]
+                        tracing::info!("Fetching image {url_str}");
+                        self.pending_images.insert(
+                            url_str.to_string(),
+                            vec![(node_id, ImageType::Background(idx))],
+                        );
+                        let sync_decoding = Arc::new(AtomicBool::new(self.sync_image_decoding));
+                        self.pending_image_sync_decoding
+                            .insert(url_str.to_string(), sync_decoding.clone());
+
+                        self.net_provider.fetch(
+                            doc_id,
+                            Request::get((*new_url).clone()),
+                            ResourceHandler::boxed(
+                                self.tx.clone(),
+                                doc_id,
+                                None, // Don't pass node_id, we'll handle via pending_images
+                                self.shell_provider.clone(),
+                                ImageHandler::new(ImageType::Background(idx))
+                                    .with_sync_decoding(sync_decoding),
+                            ),
+                        );
+
+                        Some(BackgroundImageData {
+                            density,
+                            ..BackgroundImageData::new(new_url)
+                        })
+                    }
+                }
+                None => None,
+            };
+
+            // Element will always exist due to resize_with above
+            has_changed |= new_bg_image.is_some() || elem_bgs[idx].is_some();
+            elem_bgs[idx] = new_bg_image;
+        }
+
+        if elem_bgs.iter().any(Option::is_some) {
+            self.background_image_nodes.insert(node_id);
+        } else {
+            self.background_image_nodes.remove(&node_id);
+        }
+        if has_changed {
+            self.invalidate_paint(node_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use markup5ever::{LocalName, QualName, ns};
+
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+
+    fn attrs(attrs: &[(&str, &str)]) -> Vec<Attribute> {
+        attrs
+            .iter()
+            .map(|(name, value)| Attribute {
+                name: QualName::new(None, ns!(), LocalName::from(*name)),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    fn selected_url(doc: &BaseDocument, img_id: usize) -> Option<&str> {
+        let element = doc.nodes[img_id].element_data()?;
+        let source = element.image_source.as_ref()?;
+        source.url.path_segments()?.next_back()
+    }
+
+    #[test]
+    fn test_parse_srcset() {
+        let candidates =
+            parse_srcset("a.png, b.png 2x,c.png 3.5x , d(1).png 100w 50h, e.png,, f.png 0x");
+        let parsed: Vec<(&str, Descriptor)> = candidates
+            .iter()
+            .map(|candidate| (candidate.url.as_str(), candidate.descriptor))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("a.png", Descriptor::Density(1.0)),
+                ("b.png", Descriptor::Density(2.0)),
+                ("c.png", Descriptor::Density(3.5)),
+                ("d(1).png", Descriptor::Width(100)),
+                ("e.png", Descriptor::Density(1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_by_density() {
+        let candidates = [("1x", 1.0), ("2x", 2.0), ("3x", 3.0)];
+        assert_eq!(select_by_density(candidates, 1.0), Some(("1x", 1.0)));
+        assert_eq!(select_by_density(candidates, 1.5), Some(("2x", 2.0)));
+        assert_eq!(select_by_density(candidates, 4.0), Some(("3x", 3.0)));
+        assert_eq!(select_by_density(Vec::<(&str, f64)>::new(), 1.0), None);
+    }
+
+    #[test]
+    fn test_select_image_set_item() {
+        let item = |url: &str, dppx: f32, mime_type: Option<&str>| ImageSetItem {
+            image: StyloImage::Url(ComputedUrl::Valid(ServoArc::new(
+                Url::parse(&format!("https://example.com/{url}")).unwrap(),
+            ))),
+            resolution: Resolution::from_dppx(dppx),
+            mime_type: mime_type.unwrap_or_default().to_string().into(),
+            has_mime_type: mime_type.is_some(),
+        };
+        let items = [
+            item("1x.png", 1.0, None),
+            item("2x.avif", 2.0, Some("image/avif")),
+            item("2x.png", 2.0, Some("image/png")),
+            item("3x.png", 3.0, None),
+        ];
+        let select = |device_pixel_ratio| {
+            select_image_set_item(&items, device_pixel_ratio)
+                .map(|(url, density)| (url.path().to_string(), density))
+        };
+
+        assert_eq!(select(1.0), Some(("/1x.png".to_string(), 1.0)));
+        // Items with unsupported types are skipped
+        assert_eq!(select(1.5), Some(("/2x.png".to_string(), 2.0)));
+        assert_eq!(select(4.0), Some(("/3x.png".to_string(), 3.0)));
+    }
+
+    #[test]
+    fn test_picture_source_selection() {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            base_url: Some("https://example.com/".to_string()),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let picture = mutator.create_element(qual_name!("picture", html), Vec::new());
+        let wide = mutator.create_element(
+            qual_name!("source", html),
+            attrs(&[("media", "(min-width: 1000px)"), ("srcset", "wide.png")]),
+        );
+        let avif = mutator.create_element(
+            qual_name!("source", html),
+            attrs(&[("type", "image/avif"), ("srcset", "image.avif")]),
+        );
+        let sized = mutator.create_element(
+            qual_name!("source", html),
+            attrs(&[
+                ("srcset", "small.png 100w, large.png 400w"),
+                ("sizes", "200px"),
+            ]),
+        );
+        let img =
+            mutator.create_element(qual_name!("img", html), attrs(&[("src", "fallback.png")]));
+        mutator.append_children(picture, &[wide, avif, sized, img]);
+        mutator.append_children(0, &[picture]);
+        drop(mutator);
+
+        // The media query doesn't match and AVIF isn't supported, so the image is selected from
+        // the `srcset` of the third source: at 200px, `large.png` has a density of 2x
+        assert_eq!(selected_url(&doc, img), Some("large.png"));
+        let density = doc.nodes[img].element_data().unwrap().image_source.as_ref();
+        assert_eq!(density.map(|source| source.density), Some(2.0));
+
+        // Resizing the viewport re-selects the source
+        doc.set_viewport(Viewport::new(1200, 600, 1.0, ColorScheme::Light));
+        assert_eq!(selected_url(&doc, img), Some("wide.png"));
+
+        // As does removing or inserting a <source>
+        doc.mutate().remove_node(wide);
+        assert_eq!(selected_url(&doc, img), Some("large.png"));
+        doc.mutate().remove_node(sized);
+        assert_eq!(selected_url(&doc, img), Some("fallback.png"));
+        doc.mutate().insert_nodes_before(avif, &[wide]);
+        assert_eq!(selected_url(&doc, img), Some("wide.png"));
+    }
+}
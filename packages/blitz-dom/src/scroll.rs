@@ -0,0 +1,926 @@
+//! Smooth scrolling, scroll snapping and scrolling nodes into view
+//!
+//! Scroll containers (and the viewport) with a `scroll-snap-type` are snapped to the nearest
+//! snap position of their descendants' `scroll-snap-align` once a wheel, pan or fling gesture
+//! ends. Programmatic scrolls honour `scroll-behavior: smooth`, and smooth scrolls are advanced
+//! each frame by [`BaseDocument::resolve_scroll_animation`].
+//!
+//! The servo build of Stylo doesn't support the scroll snapping properties, `scroll-behavior`,
+//! `scroll-padding` or `scroll-margin`, so they are read from `style` attributes (see
+//! [`crate::inline_style`]).
+//!
+//! Logical axes are mapped to physical axes assuming a horizontal writing mode (the block axis is
+//! vertical and the inline axis is horizontal).
+//!
+//! See <https://drafts.csswg.org/css-scroll-snap/> and <https://drafts.csswg.org/cssom-view/#scrolling>
+
+use std::time::{Duration, Instant};
+
+use blitz_traits::events::{BlitzScrollEvent, DomEvent, DomEventData};
+use cssparser::match_ignore_ascii_case;
+use kurbo::{Rect, Vec2};
+use markup5ever::local_name;
+use style::properties::ComputedValues;
+use style::servo_arc::Arc as ServoArc;
+use style::values::computed::Overflow;
+
+use crate::events::{ScrollAnimationState, SnapState};
+use crate::scrollbar::{PAGE_SCROLL_FRACTION, ScrollbarOwner};
+use crate::traversal::TreeTraverser;
+use crate::{BaseDocument, Node, Point};
+
+/// How long a smooth scroll takes to reach its destination
+const SMOOTH_SCROLL_DURATION: Duration = Duration::from_millis(300);
+/// How long after the last wheel event a wheel gesture is considered to have ended
+pub(crate) const WHEEL_GESTURE_END_DELAY: Duration = Duration::from_millis(150);
+/// How far (in CSS pixels) the arrow keys scroll
+const LINE_SCROLL_DISTANCE: f64 = 40.0;
+/// With `proximity` snapping, the fraction of the snapport's size within which a snap position
+/// must be for the container to snap to it
+const PROXIMITY_THRESHOLD: f64 = 0.3;
+
+/// Whether a programmatic scroll is animated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollBehavior {
+    /// Use the scroll container's computed `scroll-behavior`
+    #[default]
+    Auto,
+    Instant,
+    Smooth,
+}
+
+/// How a node is aligned within a scroll container along one axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollLogicalPosition {
+    Start,
+    Center,
+    End,
+    /// Scroll as little as possible to bring the node into view (or not at all if it is
+    /// already fully visible)
+    Nearest,
+}
+
+/// Options for [`BaseDocument::scroll_node_into_view`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollIntoViewOptions {
+    pub behavior: ScrollBehavior,
+    /// Alignment along the block (vertical) axis
+    pub block: ScrollLogicalPosition,
+    /// Alignment along the inline (horizontal) axis
+    pub inline: ScrollLogicalPosition,
+}
+
+impl Default for ScrollIntoViewOptions {
+    fn default() -> Self {
+        Self {
+            behavior: ScrollBehavior::Auto,
+            block: ScrollLogicalPosition::Start,
+            inline: ScrollLogicalPosition::Nearest,
+        }
+    }
+}
+
+/// How strictly a scroll container snaps to its snap positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScrollSnapStrictness {
+    Mandatory,
+    Proximity,
+}
+
+/// The computed `scroll-snap-type` of a scroll container which snaps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScrollSnapType {
+    x: bool,
+    y: bool,
+    strictness: ScrollSnapStrictness,
+}
+
+/// A scroll requested using the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyboardScroll {
+    LineUp,
+    LineDown,
+    LineLeft,
+    LineRight,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+}
+
+/// An in-progress smooth scroll of a single scroll container
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SmoothScroll {
+    container: ScrollbarOwner,
+    from: Point<f64>,
+    to: Point<f64>,
+    start_time: Instant,
+}
+
+fn ease_in_out(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The scroll offset along one axis which aligns an `area` with a `port` (both given as
+/// `(start, end)` at the `current` scroll offset).
+fn aligned_offset(
+    current: f64,
+    area: (f64, f64),
+    port: (f64, f64),
+    position: ScrollLogicalPosition,
+) -> f64 {
+    let (area_start, area_end) = area;
+    let (port_start, port_end) = port;
+    let align_start = current + area_start - port_start;
+    let align_end = current + area_end - port_end;
+    match position {
+        ScrollLogicalPosition::Start => align_start,
+        ScrollLogicalPosition::End => align_end,
+        ScrollLogicalPosition::Center => {
+            current + (area_start + area_end - port_start - port_end) / 2.0
+        }
+        ScrollLogicalPosition::Nearest => {
+            let fully_visible = area_start >= port_start && area_end <= port_end;
+            let covers_port = area_start < port_start && area_end > port_end;
+            if fully_visible || covers_port {
+                current
+            } else {
+                // Reveal the start edge of areas which are overflowing the start of the port,
+                // unless they are too large to fit (and the other way around for the end edge)
+                let is_larger = area_end - area_start > port_end - port_start;
+                if (area_start < port_start) != is_larger {
+                    align_start
+                } else {
+                    align_end
+                }
+            }
+        }
+    }
+}
+
+/// Parse a `scroll-snap-type`. Returns `None` for `none` (and invalid values).
+fn parse_scroll_snap_type(value: &str) -> Option<ScrollSnapType> {
+    let mut keywords = value.split_whitespace();
+    let (x, y) = match_ignore_ascii_case! { keywords.next()?,
+        "x" | "inline" => (true, false),
+        "y" | "block" => (false, true),
+        "both" => (true, true),
+        _ => return None,
+    };
+    let strictness = match keywords.next() {
+        None => ScrollSnapStrictness::Proximity,
+        Some(keyword) => match_ignore_ascii_case! { keyword,
+            "proximity" => ScrollSnapStrictness::Proximity,
+            "mandatory" => ScrollSnapStrictness::Mandatory,
+            _ => return None,
+        },
+    };
+    if keywords.next().is_some() {
+        return None;
+    }
+    Some(ScrollSnapType { x, y, strictness })
+}
+
+/// Parse a `scroll-snap-align` into its (block, inline) alignments
+fn parse_scroll_snap_align(
+    value: &str,
+) -> (Option<ScrollLogicalPosition>, Option<ScrollLogicalPosition>) {
+    let position = |keyword: &str| {
+        match_ignore_ascii_case! { keyword,
+            "start" => Some(ScrollLogicalPosition::Start),
+            "end" => Some(ScrollLogicalPosition::End),
+            "center" => Some(ScrollLogicalPosition::Center),
+            _ => None,
+        }
+    };
+    let keywords: Vec<&str> = value.split_whitespace().collect();
+    match keywords[..] {
+        [both] => (position(both), position(both)),
+        [block, inline] => (position(block), position(inline)),
+        _ => (None, None),
+    }
+}
+
+/// Whether a node clips its overflow to a scrollport (including `overflow: hidden`, which can be
+/// scrolled programmatically)
+fn is_scroll_container(node: &Node) -> bool {
+    node.primary_styles().is_some_and(|style| {
+        !matches!(style.clone_overflow_x(), Overflow::Visible | Overflow::Clip)
+            || !matches!(style.clone_overflow_y(), Overflow::Visible | Overflow::Clip)
+    })
+}
+
+impl BaseDocument {
+    /// Scroll each of a node's ancestor scroll containers (and the viewport) as needed to bring it
+    /// into view, aligned as requested. The node's `scroll-margin` and the containers'
+    /// `scroll-padding` are taken into account.
+    pub fn scroll_node_into_view(&mut self, node_id: usize, options: ScrollIntoViewOptions) {
+        if !self.nodes.contains(node_id) || !self.is_rendered(node_id) {
+            return;
+        }
+
+        let mut area = self.snap_area_rect(node_id);
+
+        let mut targets = Vec::new();
+        for container in self.scroll_containers(self.nodes[node_id].layout_parent.get()) {
+            let snapport = self.snapport(container);
+            let current = self.scroll_position(container);
+            let max = self.max_scroll_position(container);
+            let x = aligned_offset(
+                current.x,
+                (area.x0, area.x1),
+                (snapport.x0, snapport.x1),
+                options.inline,
+            )
+            .clamp(0.0, max.x);
+            let y = aligned_offset(
+                current.y,
+                (area.y0, area.y1),
+                (snapport.y0, snapport.y1),
+                options.block,
+            )
+            .clamp(0.0, max.y);
+
+            // Outer containers see the node where scrolling this container will move it to
+            area = area + Vec2::new(current.x - x, current.y - y);
+            targets.push((container, Point { x, y }));
+        }
+
+        self.scroll_containers_to(targets, options.behavior);
+    }
+
+    /// Scroll a scroll container (or the viewport if `node_id` is `None`) to a position
+    pub fn scroll_to(
+        &mut self,
+        node_id: Option<usize>,
+        position: Point<f64>,
+        behavior: ScrollBehavior,
+    ) {
+        let container = node_id.map_or(ScrollbarOwner::Viewport, ScrollbarOwner::Node);
+        self.scroll_containers_to(vec![(container, position)], behavior);
+    }
+
+    /// Scroll to the target of a URL fragment (the element with a matching `id`, or else the first
+    /// `<a>` with a matching `name`), as when following a link to `#fragment`. An empty fragment
+    /// or `top` scrolls to the top of the document. Returns `false` if there is no target.
+    pub fn scroll_to_fragment(&mut self, fragment: &str) -> bool {
+        let target = self.get_element_by_id(fragment).or_else(|| {
+            TreeTraverser::new(self).find(|node_id| {
+                let node = &self.nodes[*node_id];
+                node.data.is_element_with_tag_name(&local_name!("a"))
+                    && node.attr(local_name!("name")) == Some(fragment)
+            })
+        });
+        match target {
+            Some(node_id) => {
+                self.scroll_node_into_view(node_id, ScrollIntoViewOptions::default());
+                true
+            }
+            None if fragment.is_empty() || fragment.eq_ignore_ascii_case("top") => {
+                self.scroll_to(None, Point { x: 0.0, y: 0.0 }, ScrollBehavior::Auto);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn scroll_containers_to(
+        &mut self,
+        targets: Vec<(ScrollbarOwner, Point<f64>)>,
+        behavior: ScrollBehavior,
+    ) {
+        let start_time = Instant::now();
+        let mut scrolls = Vec::new();
+        for (container, to) in targets {
+            let from = self.scroll_position(container);
+            if from == to {
+                continue;
+            }
+            if self.is_smooth(container, behavior) {
+                scrolls.push(SmoothScroll {
+                    container,
+                    from,
+                    to,
+                    start_time,
+                });
+            } else {
+                self.set_scroll_position(container, to);
+            }
+        }
+
+        // An explicit scroll interrupts any ongoing fling or smooth scroll
+        self.scroll_animation = if scrolls.is_empty() {
+            ScrollAnimationState::None
+        } else {
+            ScrollAnimationState::Smooth(scrolls)
+        };
+        self.shell_provider.request_redraw();
+    }
+
+    /// Advance any in-progress smooth scrolls. Called once per frame.
+    pub(crate) fn resolve_smooth_scroll(&mut self) {
+        let ScrollAnimationState::Smooth(scrolls) = &self.scroll_animation else {
+            return;
+        };
+        let scrolls = scrolls.clone();
+
+        let mut finished = true;
+        for scroll in scrolls {
+            let t = (scroll.start_time.elapsed().as_secs_f64()
+                / SMOOTH_SCROLL_DURATION.as_secs_f64())
+            .min(1.0);
+            finished &= t >= 1.0;
+            let progress = ease_in_out(t);
+            let position = Point {
+                x: scroll.from.x + (scroll.to.x - scroll.from.x) * progress,
+                y: scroll.from.y + (scroll.to.y - scroll.from.y) * progress,
+            };
+            self.set_scroll_position(scroll.container, position);
+        }
+
+        if finished {
+            self.scroll_animation = ScrollAnimationState::None;
+        }
+    }
+
+    /// Snap the scroll containers containing a node (and the viewport) once a scroll gesture
+    /// which has no explicit end (such as wheel scrolling) has been idle for a short time
+    pub(crate) fn defer_scroll_snap(&mut self, node_id: Option<usize>) {
+        let has_snap_container = self
+            .scroll_containers(node_id)
+            .into_iter()
+            .any(|container| self.scroll_snap_type(container).is_some());
+        if has_snap_container {
+            self.scroll_animation = ScrollAnimationState::AwaitingSnap(SnapState {
+                target: node_id,
+                last_scroll_time: Instant::now(),
+            });
+        }
+    }
+
+    /// Snap once the gesture awaiting a snap has ended. Called once per frame.
+    pub(crate) fn resolve_pending_scroll_snap(&mut self) {
+        let ScrollAnimationState::AwaitingSnap(state) = &self.scroll_animation else {
+            return;
+        };
+        if state.last_scroll_time.elapsed() < WHEEL_GESTURE_END_DELAY {
+            return;
+        }
+        let target = state.target;
+        self.scroll_animation = ScrollAnimationState::None;
+        self.scroll_snap(target);
+    }
+
+    /// Smoothly scroll the scroll containers containing a node (and the viewport) to their
+    /// nearest snap positions
+    pub(crate) fn scroll_snap(&mut self, node_id: Option<usize>) {
+        let targets: Vec<_> = self
+            .scroll_containers(node_id)
+            .into_iter()
+            .filter_map(|container| Some((container, self.snap_position(container)?)))
+            .collect();
+        if targets
+            .iter()
+            .all(|(container, position)| self.scroll_position(*container) == *position)
+        {
+            return;
+        }
+        self.scroll_containers_to(targets, ScrollBehavior::Smooth);
+    }
+
+    /// Scroll the innermost scroll container containing the focussed node (or the viewport) which
+    /// can scroll in the requested direction. Returns whether anything was scrolled.
+    pub(crate) fn keyboard_scroll(&mut self, scroll: KeyboardScroll) -> bool {
+        for container in self.scroll_containers(self.focus_node_id) {
+            let Some(style) = self.container_style(container) else {
+                continue;
+            };
+
+            // Containers with `overflow: hidden` (including the viewport, when set on the root
+            // element) can only be scrolled programmatically
+            let horizontal = matches!(scroll, KeyboardScroll::LineLeft | KeyboardScroll::LineRight);
+            let overflow = if horizontal {
+                style.clone_overflow_x()
+            } else {
+                style.clone_overflow_y()
+            };
+            let is_user_scrollable = match container {
+                ScrollbarOwner::Viewport => !matches!(overflow, Overflow::Hidden | Overflow::Clip),
+                ScrollbarOwner::Node(_) => matches!(overflow, Overflow::Scroll | Overflow::Auto),
+            };
+            if !is_user_scrollable {
+                continue;
+            }
+
+            let current = self.scroll_position(container);
+            let max = self.max_scroll_position(container);
+            let page = self.snapport(container).height() * PAGE_SCROLL_FRACTION;
+            let target = match scroll {
+                KeyboardScroll::LineUp => Point {
+                    x: current.x,
+                    y: current.y - LINE_SCROLL_DISTANCE,
+                },
+                KeyboardScroll::LineDown => Point {
+                    x: current.x,
+                    y: current.y + LINE_SCROLL_DISTANCE,
+                },
+                KeyboardScroll::LineLeft => Point {
+                    x: current.x - LINE_SCROLL_DISTANCE,
+                    y: current.y,
+                },
+                KeyboardScroll::LineRight => Point {
+                    x: current.x + LINE_SCROLL_DISTANCE,
+                    y: current.y,
+                },
+                KeyboardScroll::PageUp => Point {
+                    x: current.x,
+                    y: current.y - page,
+                },
+                KeyboardScroll::PageDown => Point {
+                    x: current.x,
+                    y: current.y + page,
+                },
+                KeyboardScroll::Top => Point {
+                    x: current.x,
+                    y: 0.0,
+                },
+                KeyboardScroll::Bottom => Point {
+                    x: current.x,
+                    y: max.y,
+                },
+            };
+            let target = Point {
+                x: target.x.clamp(0.0, max.x),
+                y: target.y.clamp(0.0, max.y),
+            };
+            if target != current {
+                self.scroll_containers_to(vec![(container, target)], ScrollBehavior::Auto);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The scroll containers containing a node (starting with the node itself), innermost first
+    /// and ending with the viewport
+    fn scroll_containers(&self, node_id: Option<usize>) -> Vec<ScrollbarOwner> {
+        let mut containers = Vec::new();
+        let mut current = node_id;
+        while let Some(node) = current.and_then(|id| self.nodes.get(id)) {
+            if is_scroll_container(node) {
+                containers.push(ScrollbarOwner::Node(node.id));
+            }
+            current = node.layout_parent.get();
+        }
+        containers.push(ScrollbarOwner::Viewport);
+        containers
+    }
+
+    /// The node which controls a scroll container (the root element for the viewport)
+    pub(crate) fn container_node(&self, container: ScrollbarOwner) -> Option<&Node> {
+        match container {
+            ScrollbarOwner::Viewport => self.try_root_element(),
+            ScrollbarOwner::Node(node_id) => self.nodes.get(node_id),
+        }
+    }
+
+    /// The style which controls a scroll container (the root element's style for the viewport)
+    pub(crate) fn container_style(
+        &self,
+        container: ScrollbarOwner,
+    ) -> Option<ServoArc<ComputedValues>> {
+        let node = self.container_node(container)?;
+        node.primary_styles().map(|style| ServoArc::clone(&style))
+    }
+
+    fn scroll_snap_type(&self, container: ScrollbarOwner) -> Option<ScrollSnapType> {
+        let node = self.container_node(container)?;
+        parse_scroll_snap_type(node.inline_style_value("scroll-snap-type")?)
+    }
+
+    fn is_smooth(&self, container: ScrollbarOwner, behavior: ScrollBehavior) -> bool {
+        match behavior {
+            ScrollBehavior::Instant => false,
+            ScrollBehavior::Smooth => true,
+            ScrollBehavior::Auto => self
+                .container_node(container)
+                .and_then(|node| node.inline_style_value("scroll-behavior"))
+                .is_some_and(|value| value.eq_ignore_ascii_case("smooth")),
+        }
+    }
+
+    /// The furthest the viewport can be scrolled in each axis
+    pub fn max_viewport_scroll(&self) -> Point<f64> {
+        self.max_scroll_position(ScrollbarOwner::Viewport)
+    }
+
+    /// The client rects (in CSS pixels) of the nodes which can currently be scrolled by the user.
+    ///
+    /// Used by shells which scroll the last painted frame while the document is busy, as wheel
+    /// gestures over these nodes scroll the node rather than the viewport.
+    pub fn scrollable_node_rects(&self) -> Vec<Rect> {
+        self.nodes
+            .iter()
+            .filter(|(node_id, node)| {
+                node.flags.is_in_document() && is_scroll_container(node) && {
+                    let max = self.max_scroll_position(ScrollbarOwner::Node(*node_id));
+                    max.x > 0.0 || max.y > 0.0
+                }
+            })
+            .filter_map(|(node_id, _)| self.get_client_bounding_rect(node_id))
+            .map(|rect| Rect::new(rect.x, rect.y, rect.x + rect.width, rect.y + rect.height))
+            .collect()
+    }
+
+    pub(crate) fn scroll_position(&self, container: ScrollbarOwner) -> Point<f64> {
+        match container {
+            ScrollbarOwner::Viewport => self.viewport_scroll,
+            ScrollbarOwner::Node(node_id) => self.nodes[node_id].scroll_offset,
+        }
+    }
+
+    pub(crate) fn max_scroll_position(&self, container: ScrollbarOwner) -> Point<f64> {
+        match container {
+            ScrollbarOwner::Viewport => {
+                let Some(root) = self.try_root_element() else {
+                    return Point::ZERO;
+                };
+                let scale = self.viewport.scale_f64();
+                let content_size = root.final_layout.size;
+                Point {
+                    x: (content_size.width as f64 - self.viewport.window_size.0 as f64 / scale)
+                        .max(0.0),
+                    y: (content_size.height as f64 - self.viewport.window_size.1 as f64 / scale)
+                        .max(0.0),
+                }
+            }
+            ScrollbarOwner::Node(node_id) => {
+                let layout = &self.nodes[node_id].final_layout;
+                Point {
+                    x: layout.scroll_width() as f64,
+                    y: layout.scroll_height() as f64,
+                }
+            }
+        }
+    }
+
+    /// Set the scroll position of a container (clamped to its scroll range). Scroll events are
+    /// queued for dispatch with the document's other pending events.
+    fn set_scroll_position(&mut self, container: ScrollbarOwner, position: Point<f64>) -> bool {
+        let max = self.max_scroll_position(container);
+        let position = Point {
+            x: position.x.clamp(0.0, max.x),
+            y: position.y.clamp(0.0, max.y),
+        };
+        if self.scroll_position(container) == position {
+            return false;
+        }
+
+        match container {
+            ScrollbarOwner::Viewport => self.viewport_scroll = position,
+            ScrollbarOwner::Node(node_id) => self.nodes[node_id].scroll_offset = position,
+        }
+        self.record_scroll_activity();
+        self.queue_scroll_event(container);
+        true
+    }
+
+    /// The node which scroll events for a container are dispatched to (the document node for the
+    /// viewport)
+    pub(crate) fn scroll_event_target(&self, container: ScrollbarOwner) -> usize {
+        match container {
+            ScrollbarOwner::Viewport => self.root_node().id,
+            ScrollbarOwner::Node(node_id) => node_id,
+        }
+    }
+
+    /// A scroll event describing a container's current scroll position
+    pub(crate) fn scroll_event(&self, container: ScrollbarOwner) -> BlitzScrollEvent {
+        let position = self.scroll_position(container);
+        let max = self.max_scroll_position(container);
+        let (client_width, client_height) = match container {
+            ScrollbarOwner::Viewport => {
+                let scale = self.viewport.scale_f64();
+                let (width, height) = self.viewport.window_size;
+                (width as f64 / scale, height as f64 / scale)
+            }
+            ScrollbarOwner::Node(node_id) => {
+                let size = self.nodes[node_id].final_layout.size;
+                (size.width as f64, size.height as f64)
+            }
+        };
+        BlitzScrollEvent {
+            scroll_top: position.y,
+            scroll_left: position.x,
+            scroll_width: max.x as i32,
+            scroll_height: max.y as i32,
+            client_width: client_width as i32,
+            client_height: client_height as i32,
+        }
+    }
+
+    /// Queue a `scroll` event for a container which has moved (for dispatch with the document's
+    /// other pending events), and a `scrollend` event for once scrolling stops
+    pub(crate) fn queue_scroll_event(&mut self, container: ScrollbarOwner) {
+        let event = DomEvent::new(
+            self.scroll_event_target(container),
+            DomEventData::Scroll(self.scroll_event(container)),
+        );
+        self.pending_events.push(event);
+        self.scrolled_containers.insert(container);
+    }
+
+    /// The area of a scroll container (relative to the viewport) which nodes are aligned to when
+    /// snapping or scrolling them into view: its padding box reduced by `scroll-padding`
+    fn snapport(&self, container: ScrollbarOwner) -> Rect {
+        let port = match container {
+            ScrollbarOwner::Viewport => {
+                let scale = self.viewport.scale_f64();
+                let (width, height) = self.viewport.window_size;
+                Rect::new(0.0, 0.0, width as f64 / scale, height as f64 / scale)
+            }
+            ScrollbarOwner::Node(node_id) => self.padding_box_client_rect(node_id),
+        };
+        let Some(node) = self.container_node(container) else {
+            return port;
+        };
+        let [top, right, bottom, left] = node.inline_style_sides("scroll-padding");
+        let resolve = |padding: Option<&str>, basis: f64| {
+            padding
+                .and_then(|padding| node.resolve_inline_length(padding, Some(basis)))
+                .unwrap_or(0.0)
+        };
+        let (width, height) = (port.width(), port.height());
+        Rect::new(
+            port.x0 + resolve(left, width),
+            port.y0 + resolve(top, height),
+            port.x1 - resolve(right, width),
+            port.y1 - resolve(bottom, height),
+        )
+    }
+
+    /// A node's border box (relative to the viewport) grown by its `scroll-margin`
+    fn snap_area_rect(&self, node_id: usize) -> Rect {
+        let rect = self.border_box_client_rect(node_id);
+        let node = &self.nodes[node_id];
+        let [top, right, bottom, left] = node.inline_style_sides("scroll-margin").map(|margin| {
+            margin
+                .and_then(|margin| node.resolve_inline_length(margin, None))
+                .unwrap_or(0.0)
+        });
+        Rect::new(
+            rect.x0 - left,
+            rect.y0 - top,
+            rect.x1 + right,
+            rect.y1 + bottom,
+        )
+    }
+
+    /// The nodes with a `scroll-snap-align` whose nearest scroll container is `container`
+    fn snap_areas(&self, container: ScrollbarOwner) -> Vec<usize> {
+        let root_id = match container {
+            ScrollbarOwner::Viewport => self.try_root_element().map(|root| root.id),
+            ScrollbarOwner::Node(node_id) => Some(node_id),
+        };
+
+        let mut areas = Vec::new();
+        let mut stack: Vec<usize> = root_id.into_iter().collect();
+        while let Some(node_id) = stack.pop() {
+            let layout_children = self.nodes[node_id].layout_children.borrow();
+            for &child_id in layout_children.iter().flatten() {
+                let Some(child) = self.nodes.get(child_id) else {
+                    continue;
+                };
+                let has_snap_align = child
+                    .inline_style_value("scroll-snap-align")
+                    .is_some_and(|align| parse_scroll_snap_align(align) != (None, None));
+                if has_snap_align {
+                    areas.push(child_id);
+                }
+                // Descendants of nested scroll containers snap within those containers instead
+                if !is_scroll_container(child) {
+                    stack.push(child_id);
+                }
+            }
+        }
+        areas
+    }
+
+    /// The snap position of a scroll container which is closest to its current scroll position
+    /// (if it has a `scroll-snap-type` and one is close enough)
+    fn snap_position(&self, container: ScrollbarOwner) -> Option<Point<f64>> {
+        let snap_type = self.scroll_snap_type(container)?;
+
+        let current = self.scroll_position(container);
+        let max = self.max_scroll_position(container);
+        let snapport = self.snapport(container);
+
+        let closest = |best: Option<f64>, candidate: f64, current: f64| match best {
+            Some(best) if (best - current).abs() <= (candidate - current).abs() => Some(best),
+            _ => Some(candidate),
+        };
+        let mut best_x = None;
+        let mut best_y = None;
+        for area_id in self.snap_areas(container) {
+            let Some(align) = self.nodes[area_id].inline_style_value("scroll-snap-align") else {
+                continue;
+            };
+            let (block, inline) = parse_scroll_snap_align(align);
+            let area = self.snap_area_rect(area_id);
+
+            if let Some(position) = inline.filter(|_| snap_type.x) {
+                let x = aligned_offset(
+                    current.x,
+                    (area.x0, area.x1),
+                    (snapport.x0, snapport.x1),
+                    position,
+                )
+                .clamp(0.0, max.x);
+                best_x = closest(best_x, x, current.x);
+            }
+            if let Some(position) = block.filter(|_| snap_type.y) {
+                let y = aligned_offset(
+                    current.y,
+                    (area.y0, area.y1),
+                    (snapport.y0, snapport.y1),
+                    position,
+                )
+                .clamp(0.0, max.y);
+                best_y = closest(best_y, y, current.y);
+            }
+        }
+
+        if snap_type.strictness == ScrollSnapStrictness::Proximity {
+            best_x =
+                best_x.filter(|x| (x - current.x).abs() <= snapport.width() * PROXIMITY_THRESHOLD);
+            best_y =
+                best_y.filter(|y| (y - current.y).abs() <= snapport.height() * PROXIMITY_THRESHOLD);
+        }
+
+        if best_x.is_none() && best_y.is_none() {
+            return None;
+        }
+        Some(Point {
+            x: best_x.unwrap_or(current.x),
+            y: best_y.unwrap_or(current.y),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+
+    #[test]
+    fn test_aligned_offset_nearest() {
+        let port = (0.0, 100.0);
+        let nearest = ScrollLogicalPosition::Nearest;
+        // Fully visible areas don't scroll
+        assert_eq!(aligned_offset(50.0, (10.0, 90.0), port, nearest), 50.0);
+        // Areas overflowing the start are aligned to the start
+        assert_eq!(aligned_offset(50.0, (-20.0, 30.0), port, nearest), 30.0);
+        // Areas overflowing the end are aligned to the end
+        assert_eq!(aligned_offset(50.0, (80.0, 130.0), port, nearest), 80.0);
+        // Areas which are larger than the port are aligned to the start when overflowing the end
+        assert_eq!(aligned_offset(50.0, (40.0, 200.0), port, nearest), 90.0);
+        // Areas which cover the port don't scroll
+        assert_eq!(aligned_offset(50.0, (-10.0, 200.0), port, nearest), 50.0);
+    }
+
+    #[test]
+    fn test_parse_scroll_snap() {
+        assert_eq!(parse_scroll_snap_type("none"), None);
+        assert_eq!(
+            parse_scroll_snap_type("Y mandatory"),
+            Some(ScrollSnapType {
+                x: false,
+                y: true,
+                strictness: ScrollSnapStrictness::Mandatory,
+            })
+        );
+        assert_eq!(
+            parse_scroll_snap_type("both"),
+            Some(ScrollSnapType {
+                x: true,
+                y: true,
+                strictness: ScrollSnapStrictness::Proximity,
+            })
+        );
+        assert_eq!(parse_scroll_snap_type("y sometimes"), None);
+
+        let start = Some(ScrollLogicalPosition::Start);
+        let center = Some(ScrollLogicalPosition::Center);
+        assert_eq!(parse_scroll_snap_align("start"), (start, start));
+        assert_eq!(parse_scroll_snap_align("none center"), (None, center));
+        assert_eq!(parse_scroll_snap_align("start end center"), (None, None));
+    }
+
+    fn style(value: &str) -> Vec<Attribute> {
+        vec![Attribute {
+            name: qual_name!("style"),
+            value: value.to_string(),
+        }]
+    }
+
+    fn document() -> BaseDocument {
+        BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_snap_position() {
+        let mut doc = document();
+        let mut mutator = doc.mutate();
+        let container = mutator.create_element(
+            qual_name!("div", html),
+            style("overflow: scroll; height: 100px; scroll-snap-type: y mandatory"),
+        );
+        for _ in 0..4 {
+            let area = mutator.create_element(
+                qual_name!("div", html),
+                style("height: 100px; scroll-snap-align: start"),
+            );
+            mutator.append_children(container, &[area]);
+        }
+        mutator.append_children(0, &[container]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        let container = ScrollbarOwner::Node(container);
+        doc.set_scroll_position(container, Point { x: 0.0, y: 130.0 });
+        assert_eq!(
+            doc.snap_position(container),
+            Some(Point { x: 0.0, y: 100.0 })
+        );
+        doc.set_scroll_position(container, Point { x: 0.0, y: 160.0 });
+        assert_eq!(
+            doc.snap_position(container),
+            Some(Point { x: 0.0, y: 200.0 })
+        );
+    }
+
+    #[test]
+    fn test_scroll_into_view_uses_scroll_padding_and_margin() {
+        let mut doc = document();
+        let mut mutator = doc.mutate();
+        let spacer = mutator.create_element(qual_name!("div", html), style("height: 1000px"));
+        let target = mutator.create_element(
+            qual_name!("div", html),
+            style("height: 50px; scroll-margin: 10px 0"),
+        );
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[spacer, target]);
+        let html = mutator.create_element(
+            qual_name!("html", html),
+            style("height: 3000px; scroll-padding-top: 50px; scroll-behavior: smooth"),
+        );
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        // `scroll-behavior: smooth` animates scrolls which don't request a behavior
+        doc.scroll_node_into_view(target, ScrollIntoViewOptions::default());
+        assert!(matches!(
+            doc.scroll_animation,
+            ScrollAnimationState::Smooth(_)
+        ));
+
+        let target_top = doc.border_box_client_rect(target).y0;
+        doc.scroll_node_into_view(
+            target,
+            ScrollIntoViewOptions {
+                behavior: ScrollBehavior::Instant,
+                ..Default::default()
+            },
+        );
+        assert_eq!(doc.viewport_scroll().y, target_top - 60.0);
+    }
+
+    #[test]
+    fn test_keyboard_scroll_respects_root_overflow() {
+        let mut doc = document();
+        let mut mutator = doc.mutate();
+        let spacer = mutator.create_element(qual_name!("div", html), style("height: 2000px"));
+        let body = mutator.create_element(qual_name!("body", html), Vec::new());
+        mutator.append_children(body, &[spacer]);
+        let html = mutator.create_element(qual_name!("html", html), style("overflow: hidden"));
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+
+        assert!(!doc.keyboard_scroll(KeyboardScroll::PageDown));
+        assert_eq!(doc.viewport_scroll().y, 0.0);
+
+        doc.mutate().set_attribute(html, qual_name!("style"), "");
+        doc.resolve(0.0);
+        assert!(doc.keyboard_scroll(KeyboardScroll::LineDown));
+        assert_eq!(doc.viewport_scroll().y, LINE_SCROLL_DISTANCE);
+    }
+}
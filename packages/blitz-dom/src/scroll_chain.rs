@@ -0,0 +1,500 @@
+//! Scroll chaining, overscroll and `scrollend`
+//!
+//! Scrolling a node scrolls its nearest scroll container, and any scroll which that container can't
+//! consume (because it has reached its scroll limit) is chained to the next scroll container up
+//! the ancestor chain and finally to the viewport. `overscroll-behavior: contain | none` stops the
+//! chain at a scroll container. For touch input, scroll which reaches the end of the chain is
+//! displayed as a rubber-band overscroll which springs back once the gesture ends.
+//!
+//! The servo build of Stylo doesn't support `overscroll-behavior`, so it is read from `style`
+//! attributes (see [`crate::inline_style`]).
+//!
+//! See <https://drafts.csswg.org/css-overscroll/> and <https://drafts.csswg.org/cssom-view/#scrolling-events>
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use blitz_traits::events::{DomEvent, DomEventData};
+use cssparser::match_ignore_ascii_case;
+use style::values::computed::{Overflow, OverscrollBehavior};
+
+use crate::events::{DragMode, ScrollAnimationState};
+use crate::scroll::WHEEL_GESTURE_END_DELAY;
+use crate::scrollbar::ScrollbarOwner;
+use crate::{BaseDocument, Node, Point};
+
+/// The maximum distance (in CSS pixels) that a scroll container can be overscrolled by
+const MAX_OVERSCROLL: f64 = 120.0;
+/// The fraction of the unconsumed scroll delta which is applied as overscroll (before the
+/// additional resistance which increases as the overscroll approaches its maximum)
+const OVERSCROLL_RESISTANCE: f64 = 0.5;
+/// The time constant of the exponential decay with which overscroll springs back
+const OVERSCROLL_RELAX_TIME: Duration = Duration::from_millis(80);
+/// Overscroll smaller than this (in CSS pixels) is snapped back to zero
+const MIN_OVERSCROLL: f64 = 0.5;
+
+/// The result of scrolling a chain of scroll containers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct ScrollChain {
+    pub(crate) has_changed: bool,
+    /// The scroll delta along each axis which could not be consumed by the chain, and the
+    /// container at the end of the chain which may display it as overscroll. `None` if overscroll
+    /// effects are disabled with `overscroll-behavior: none`.
+    pub(crate) overscroll_x: Option<(ScrollbarOwner, f64)>,
+    pub(crate) overscroll_y: Option<(ScrollbarOwner, f64)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OverscrollState {
+    /// The overscroll offsets of the containers which are overscrolled (in the same direction as
+    /// their scroll positions, so negative when overscrolled past the start)
+    offsets: HashMap<ScrollbarOwner, Point<f64>>,
+    /// When the offsets last sprang back towards zero
+    last_relax_time: Option<Instant>,
+}
+
+impl OverscrollState {
+    pub(crate) fn remove(&mut self, container: ScrollbarOwner) {
+        self.offsets.remove(&container);
+    }
+}
+
+/// Apply a scroll delta to an overscroll offset if it moves it back towards zero. Returns the
+/// part of the delta which was not consumed.
+fn relax_overscroll(offset: &mut f64, delta: f64) -> f64 {
+    // Scroll deltas are subtracted from scroll positions
+    let new_offset = *offset - delta;
+    if *offset == 0.0 || new_offset.abs() > offset.abs() {
+        delta
+    } else if new_offset * *offset <= 0.0 {
+        *offset = 0.0;
+        -new_offset
+    } else {
+        *offset = new_offset;
+        0.0
+    }
+}
+
+/// Apply an unconsumed scroll delta to an overscroll offset, with resistance
+fn rubber_band(offset: &mut f64, delta: f64) {
+    let resistance = OVERSCROLL_RESISTANCE * (1.0 - offset.abs() / MAX_OVERSCROLL).max(0.0);
+    *offset = (*offset - delta * resistance).clamp(-MAX_OVERSCROLL, MAX_OVERSCROLL);
+}
+
+fn parse_overscroll_behavior(keyword: &str) -> Option<OverscrollBehavior> {
+    match_ignore_ascii_case! { keyword,
+        "auto" => Some(OverscrollBehavior::Auto),
+        "contain" => Some(OverscrollBehavior::Contain),
+        "none" => Some(OverscrollBehavior::None),
+        _ => None,
+    }
+}
+
+/// A node's (horizontal, vertical) `overscroll-behavior`
+fn overscroll_behavior(node: &Node) -> (OverscrollBehavior, OverscrollBehavior) {
+    let mut behavior = (OverscrollBehavior::Auto, OverscrollBehavior::Auto);
+    for (name, value) in node.inline_style_declarations() {
+        match_ignore_ascii_case! { name,
+            "overscroll-behavior" => {
+                let keywords: Vec<_> = value.split_whitespace().map(parse_overscroll_behavior).collect();
+                match keywords[..] {
+                    [Some(both)] => behavior = (both, both),
+                    [Some(x), Some(y)] => behavior = (x, y),
+                    _ => {}
+                }
+            },
+            "overscroll-behavior-x" | "overscroll-behavior-inline" => {
+                if let Some(x) = parse_overscroll_behavior(value) {
+                    behavior.0 = x;
+                }
+            },
+            "overscroll-behavior-y" | "overscroll-behavior-block" => {
+                if let Some(y) = parse_overscroll_behavior(value) {
+                    behavior.1 = y;
+                }
+            },
+            _ => {}
+        }
+    }
+    behavior
+}
+
+impl BaseDocument {
+    /// Scroll a node, chaining any scroll that it can't consume to its ancestors and the viewport.
+    /// `scroll` events are queued for the containers which move (see
+    /// [`BaseDocument::queue_scroll_event`]).
+    pub(crate) fn scroll_node_chain(&mut self, node_id: usize, x: f64, y: f64) -> ScrollChain {
+        let Some(node) = self.nodes.get_mut(node_id) else {
+            return ScrollChain::default();
+        };
+
+        let is_html_or_body = node.data.downcast_element().is_some_and(|e| {
+            let tag = &e.name.local;
+            tag == "html" || tag == "body"
+        });
+
+        let (can_x_scroll, can_y_scroll) = node
+            .primary_styles()
+            .map(|styles| {
+                (
+                    matches!(styles.clone_overflow_x(), Overflow::Scroll | Overflow::Auto),
+                    matches!(styles.clone_overflow_y(), Overflow::Scroll | Overflow::Auto)
+                        || (styles.clone_overflow_y() == Overflow::Visible && is_html_or_body),
+                )
+            })
+            .unwrap_or((false, false));
+        let (overscroll_behavior_x, overscroll_behavior_y) = overscroll_behavior(node);
+
+        let parent = node.parent;
+        let initial = node.scroll_offset;
+        let new_x = node.scroll_offset.x - x;
+        let new_y = node.scroll_offset.y - y;
+
+        let mut bubble_x = 0.0;
+        let mut bubble_y = 0.0;
+
+        let scroll_width = node.final_layout.scroll_width() as f64;
+        let scroll_height = node.final_layout.scroll_height() as f64;
+
+        // Handle sub document case
+        let sub_doc_chain = node.subdoc_mut().map(|doc| {
+            let mut sub_doc = doc.inner_mut();
+            if let Some(hover_node_id) = sub_doc.get_hover_node_id() {
+                sub_doc.scroll_node_chain(hover_node_id, x, y)
+            } else {
+                sub_doc.scroll_viewport_chain(x, y)
+            }
+        });
+        if let Some(chain) = sub_doc_chain {
+            // Chain scroll which reached the sub document's viewport to the parent document
+            let remaining = |overscroll: Option<(ScrollbarOwner, f64)>| match overscroll {
+                Some((ScrollbarOwner::Viewport, remaining)) => remaining,
+                _ => 0.0,
+            };
+            let (bubble_x, bubble_y) =
+                (remaining(chain.overscroll_x), remaining(chain.overscroll_y));
+            let chain = ScrollChain {
+                has_changed: chain.has_changed,
+                ..ScrollChain::default()
+            };
+            return self.chain_to_parent(parent, chain, bubble_x, bubble_y);
+        }
+
+        // If we're past our scroll bounds, transfer remainder of scrolling to parent/viewport
+        if !can_x_scroll {
+            bubble_x = x
+        } else if new_x < 0.0 {
+            bubble_x = -new_x;
+            node.scroll_offset.x = 0.0;
+        } else if new_x > scroll_width {
+            bubble_x = scroll_width - new_x;
+            node.scroll_offset.x = scroll_width;
+        } else {
+            node.scroll_offset.x = new_x;
+        }
+
+        if !can_y_scroll {
+            bubble_y = y
+        } else if new_y < 0.0 {
+            bubble_y = -new_y;
+            node.scroll_offset.y = 0.0;
+        } else if new_y > scroll_height {
+            bubble_y = scroll_height - new_y;
+            node.scroll_offset.y = scroll_height;
+        } else {
+            node.scroll_offset.y = new_y;
+        }
+
+        let has_changed = node.scroll_offset != initial;
+
+        if has_changed {
+            self.record_scroll_activity();
+            self.queue_scroll_event(ScrollbarOwner::Node(node_id));
+        }
+
+        // `overscroll-behavior` stops scroll chaining at scroll containers (the root element's
+        // value applies to the viewport instead)
+        let mut chain = ScrollChain {
+            has_changed,
+            ..ScrollChain::default()
+        };
+        if can_x_scroll && !is_html_or_body && overscroll_behavior_x != OverscrollBehavior::Auto {
+            chain.overscroll_x = (overscroll_behavior_x == OverscrollBehavior::Contain)
+                .then_some((ScrollbarOwner::Node(node_id), bubble_x));
+            bubble_x = 0.0;
+        }
+        if can_y_scroll && !is_html_or_body && overscroll_behavior_y != OverscrollBehavior::Auto {
+            chain.overscroll_y = (overscroll_behavior_y == OverscrollBehavior::Contain)
+                .then_some((ScrollbarOwner::Node(node_id), bubble_y));
+            bubble_y = 0.0;
+        }
+
+        self.chain_to_parent(parent, chain, bubble_x, bubble_y)
+    }
+
+    /// Chain the unconsumed part of a scroll to a node's parent (or the viewport)
+    fn chain_to_parent(
+        &mut self,
+        parent: Option<usize>,
+        mut chain: ScrollChain,
+        bubble_x: f64,
+        bubble_y: f64,
+    ) -> ScrollChain {
+        if bubble_x == 0.0 && bubble_y == 0.0 {
+            return chain;
+        }
+
+        let parent_chain = match parent {
+            Some(parent) => self.scroll_node_chain(parent, bubble_x, bubble_y),
+            None => self.scroll_viewport_chain(bubble_x, bubble_y),
+        };
+        chain.has_changed |= parent_chain.has_changed;
+        if bubble_x != 0.0 {
+            chain.overscroll_x = parent_chain.overscroll_x;
+        }
+        if bubble_y != 0.0 {
+            chain.overscroll_y = parent_chain.overscroll_y;
+        }
+        chain
+    }
+
+    /// Scroll the viewport (the end of every scroll chain)
+    pub(crate) fn scroll_viewport_chain(&mut self, x: f64, y: f64) -> ScrollChain {
+        let initial = self.viewport_scroll;
+        let has_changed = self.scroll_viewport_by_has_changed(x, y);
+
+        // Scroll deltas are subtracted from scroll positions
+        let remaining_x = x - (initial.x - self.viewport_scroll.x);
+        let remaining_y = y - (initial.y - self.viewport_scroll.y);
+
+        let (overscroll_behavior_x, overscroll_behavior_y) = self
+            .container_node(ScrollbarOwner::Viewport)
+            .map(overscroll_behavior)
+            .unwrap_or((OverscrollBehavior::Auto, OverscrollBehavior::Auto));
+
+        ScrollChain {
+            has_changed,
+            overscroll_x: (overscroll_behavior_x != OverscrollBehavior::None)
+                .then_some((ScrollbarOwner::Viewport, remaining_x)),
+            overscroll_y: (overscroll_behavior_y != OverscrollBehavior::None)
+                .then_some((ScrollbarOwner::Viewport, remaining_y)),
+        }
+    }
+
+    /// Scroll in response to touch input: scroll which reverses an existing overscroll is applied
+    /// to it first, and scroll which reaches the end of the scroll chain is applied as overscroll.
+    pub(crate) fn scroll_by_with_overscroll(
+        &mut self,
+        anchor_node_id: Option<usize>,
+        scroll_x: f64,
+        scroll_y: f64,
+    ) -> bool {
+        let initial_offsets = self.overscroll.offsets.clone();
+
+        let (mut scroll_x, mut scroll_y) = (scroll_x, scroll_y);
+        for offset in self.overscroll.offsets.values_mut() {
+            scroll_x = relax_overscroll(&mut offset.x, scroll_x);
+            scroll_y = relax_overscroll(&mut offset.y, scroll_y);
+        }
+        self.overscroll
+            .offsets
+            .retain(|_, offset| offset.x != 0.0 || offset.y != 0.0);
+
+        let chain = match anchor_node_id {
+            Some(node_id) => self.scroll_node_chain(node_id, scroll_x, scroll_y),
+            None => self.scroll_viewport_chain(scroll_x, scroll_y),
+        };
+
+        if let Some((container, remaining)) = chain.overscroll_x.filter(|(_, r)| *r != 0.0) {
+            let offset = self
+                .overscroll
+                .offsets
+                .entry(container)
+                .or_insert(Point::ZERO);
+            rubber_band(&mut offset.x, remaining);
+        }
+        if let Some((container, remaining)) = chain.overscroll_y.filter(|(_, r)| *r != 0.0) {
+            let offset = self
+                .overscroll
+                .offsets
+                .entry(container)
+                .or_insert(Point::ZERO);
+            rubber_band(&mut offset.y, remaining);
+        }
+
+        chain.has_changed || self.overscroll.offsets != initial_offsets
+    }
+
+    pub(crate) fn is_overscrolled(&self) -> bool {
+        !self.overscroll.offsets.is_empty()
+    }
+
+    /// Whether overscroll is springing back (and needs to be repainted)
+    pub(crate) fn overscroll_is_animating(&self) -> bool {
+        self.is_overscrolled() && !matches!(self.drag_mode, DragMode::Panning(_))
+    }
+
+    /// The scroll offset at which a container's contents should be painted, including any
+    /// rubber-band overscroll
+    pub fn visual_scroll_offset(&self, container: ScrollbarOwner) -> Point<f64> {
+        let position = self.scroll_position(container);
+        match self.overscroll.offsets.get(&container) {
+            Some(overscroll) => Point {
+                x: position.x + overscroll.x,
+                y: position.y + overscroll.y,
+            },
+            None => position,
+        }
+    }
+
+    /// Spring overscroll back towards zero once the touch gesture which caused it has ended.
+    /// Called once per frame.
+    pub(crate) fn resolve_overscroll(&mut self) {
+        if !self.overscroll_is_animating() {
+            self.overscroll.last_relax_time = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let elapsed = self
+            .overscroll
+            .last_relax_time
+            .map_or(Duration::from_millis(16), |time| now - time);
+        self.overscroll.last_relax_time = Some(now);
+
+        let decay = (-elapsed.as_secs_f64() / OVERSCROLL_RELAX_TIME.as_secs_f64()).exp();
+        for offset in self.overscroll.offsets.values_mut() {
+            offset.x *= decay;
+            offset.y *= decay;
+            if offset.x.abs() < MIN_OVERSCROLL {
+                offset.x = 0.0;
+            }
+            if offset.y.abs() < MIN_OVERSCROLL {
+                offset.y = 0.0;
+            }
+        }
+        self.overscroll
+            .offsets
+            .retain(|_, offset| offset.x != 0.0 || offset.y != 0.0);
+    }
+
+    /// Queue `scrollend` events for the containers which have moved once scrolling has stopped
+    /// (no gesture, animation or overscroll is in progress and no scroll has happened recently).
+    /// Called once per frame.
+    pub(crate) fn resolve_scroll_end(&mut self) {
+        if self.scrolled_containers.is_empty() {
+            return;
+        }
+
+        let is_scrolling = self.scroll_animation != ScrollAnimationState::None
+            || matches!(
+                self.drag_mode,
+                DragMode::Panning(_) | DragMode::Scrollbar(_)
+            )
+            || self.is_overscrolled()
+            || self
+                .scrollbars
+                .last_scroll_time
+                .is_some_and(|time| time.elapsed() < WHEEL_GESTURE_END_DELAY);
+        if is_scrolling {
+            return;
+        }
+
+        let mut containers: Vec<ScrollbarOwner> = self.scrolled_containers.drain().collect();
+        containers.sort_by_key(|container| match container {
+            ScrollbarOwner::Viewport => None,
+            ScrollbarOwner::Node(node_id) => Some(*node_id),
+        });
+        for container in containers {
+            if let ScrollbarOwner::Node(node_id) = container {
+                if !self.nodes.contains(node_id) {
+                    continue;
+                }
+            }
+            let event = DomEvent::new(
+                self.scroll_event_target(container),
+                DomEventData::ScrollEnd(self.scroll_event(container)),
+            );
+            self.pending_events.push(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::*;
+    use crate::{Attribute, DocumentConfig, qual_name};
+
+    #[test]
+    fn test_relax_overscroll() {
+        // Scrolling further into the overscroll is not consumed
+        let mut offset = -20.0;
+        assert_eq!(relax_overscroll(&mut offset, 5.0), 5.0);
+        assert_eq!(offset, -20.0);
+
+        // Scrolling back reduces the overscroll
+        assert_eq!(relax_overscroll(&mut offset, -5.0), 0.0);
+        assert_eq!(offset, -15.0);
+
+        // Scrolling back past zero consumes the overscroll and returns the rest
+        assert_eq!(relax_overscroll(&mut offset, -25.0), -10.0);
+        assert_eq!(offset, 0.0);
+    }
+
+    #[test]
+    fn test_scroll_events_are_queued_along_the_chain() {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(800, 600, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        });
+        let style = |value: &str| {
+            vec![Attribute {
+                name: qual_name!("style"),
+                value: value.to_string(),
+            }]
+        };
+        let mut mutator = doc.mutate();
+        let content = mutator.create_element(qual_name!("div", html), style("height: 300px"));
+        let container = mutator.create_element(
+            qual_name!("div", html),
+            style("height: 100px; overflow: auto"),
+        );
+        mutator.append_children(container, &[content]);
+        let spacer = mutator.create_element(qual_name!("div", html), style("height: 2000px"));
+        let body = mutator.create_element(qual_name!("body", html), style("margin: 0"));
+        mutator.append_children(body, &[container, spacer]);
+        let html = mutator.create_element(qual_name!("html", html), Vec::new());
+        mutator.append_children(html, &[body]);
+        mutator.append_children(0, &[html]);
+        drop(mutator);
+        doc.resolve(0.0);
+        doc.take_pending_events();
+
+        // The container consumes 200px and chains the rest to the viewport
+        assert!(doc.scroll_node_by_has_changed(content, 0.0, -500.0));
+        assert_eq!(doc.nodes[container].scroll_offset.y, 200.0);
+        assert_eq!(doc.viewport_scroll().y, 300.0);
+
+        let events = doc.take_pending_events();
+        let targets: Vec<usize> = events
+            .iter()
+            .filter(|event| matches!(event.data, DomEventData::Scroll(_)))
+            .map(|event| event.target)
+            .collect();
+        assert_eq!(targets, vec![container, doc.root_node().id]);
+
+        // `overscroll-behavior: contain` stops the chain at the container
+        let style = "height: 100px; overflow: auto; overscroll-behavior: auto contain";
+        doc.mutate()
+            .set_attribute(container, qual_name!("style"), style);
+        doc.resolve(0.0);
+        let chain = doc.scroll_node_chain(content, 0.0, -500.0);
+        assert_eq!(doc.viewport_scroll().y, 300.0);
+        assert_eq!(
+            chain.overscroll_y,
+            Some((ScrollbarOwner::Node(container), -500.0))
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! Scrollbar geometry, hit-testing and visibility
+//!
+//! Scrollbars are painted by `blitz-paint` and hit-tested by the pointer event handlers, so their
+//! geometry is computed here to ensure that both agree. Scrollbars are overlaid on top of the
+//! padding box of their scroll container (or on top of the window for the viewport) and don't take
+//! up any layout space.
+
+use std::time::{Duration, Instant};
+
+use blitz_traits::events::BlitzPointerId;
+use kurbo::{Point, Rect};
+use style::values::computed::Overflow;
+
+use crate::{BaseDocument, Node};
+
+/// The minimum length of a scrollbar thumb (so that it remains possible to grab it)
+const MIN_THUMB_LENGTH: f64 = 24.0;
+/// The fraction of the scroll container's size that is scrolled when clicking the scrollbar track
+pub(crate) const PAGE_SCROLL_FRACTION: f64 = 0.875;
+/// How long overlay scrollbars remain visible after scrolling
+const OVERLAY_VISIBLE_DURATION: Duration = Duration::from_millis(1000);
+/// How long overlay scrollbars take to fade out once they are no longer visible
+const OVERLAY_FADE_DURATION: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollbarAxis {
+    Horizontal,
+    Vertical,
+}
+
+/// The scroll container that a scrollbar belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScrollbarOwner {
+    Viewport,
+    Node(usize),
+}
+
+/// How scrollbars are presented. This follows the most recently used pointer type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollbarMode {
+    /// Scrollbars are always visible (mouse and pen input)
+    #[default]
+    Classic,
+    /// Scrollbars are only visible while scrolling, without a track (touch input)
+    Overlay,
+}
+
+impl ScrollbarMode {
+    pub(crate) fn for_pointer(pointer_id: &BlitzPointerId) -> Self {
+        match pointer_id {
+            BlitzPointerId::Mouse | BlitzPointerId::Pen => ScrollbarMode::Classic,
+            BlitzPointerId::Finger(_) => ScrollbarMode::Overlay,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct ScrollbarState {
+    pub(crate) mode: ScrollbarMode,
+    /// The last time that any scroll container was scrolled
+    pub(crate) last_scroll_time: Option<Instant>,
+}
+
+/// The part of a scrollbar that was hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollbarPart {
+    Thumb,
+    /// The track before (above or to the left of) the thumb
+    TrackBefore,
+    /// The track after (below or to the right of) the thumb
+    TrackAfter,
+}
+
+/// The geometry of a single scrollbar. Rects are in CSS pixels, relative to the border box of
+/// the owning node (or to the window for the viewport).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scrollbar {
+    pub owner: ScrollbarOwner,
+    pub axis: ScrollbarAxis,
+    pub track: Rect,
+    pub thumb: Rect,
+    /// The length of the scroll container's visible area along the scrollbar's axis
+    pub client_length: f64,
+    /// The maximum scroll offset along the scrollbar's axis
+    pub max_scroll: f64,
+}
+
+impl Scrollbar {
+    pub fn hit(&self, point: Point) -> Option<ScrollbarPart> {
+        if !self.track.contains(point) {
+            return None;
+        }
+        if self.thumb.contains(point) {
+            return Some(ScrollbarPart::Thumb);
+        }
+        if self.along_axis(point) < self.thumb_start() {
+            Some(ScrollbarPart::TrackBefore)
+        } else {
+            Some(ScrollbarPart::TrackAfter)
+        }
+    }
+
+    /// The position of a point along the scrollbar's axis
+    pub fn along_axis(&self, point: Point) -> f64 {
+        match self.axis {
+            ScrollbarAxis::Horizontal => point.x,
+            ScrollbarAxis::Vertical => point.y,
+        }
+    }
+
+    pub fn thumb_start(&self) -> f64 {
+        match self.axis {
+            ScrollbarAxis::Horizontal => self.thumb.x0,
+            ScrollbarAxis::Vertical => self.thumb.y0,
+        }
+    }
+
+    /// The scroll offset that corresponds to the thumb starting at the specified position
+    pub fn scroll_offset_for_thumb_start(&self, thumb_start: f64) -> f64 {
+        let (track_start, track_length, thumb_length) = match self.axis {
+            ScrollbarAxis::Horizontal => (self.track.x0, self.track.width(), self.thumb.width()),
+            ScrollbarAxis::Vertical => (self.track.y0, self.track.height(), self.thumb.height()),
+        };
+        let travel = track_length - thumb_length;
+        if travel <= 0.0 {
+            return 0.0;
+        }
+        ((thumb_start - track_start) / travel * self.max_scroll).clamp(0.0, self.max_scroll)
+    }
+}
+
+/// The thickness of scrollbars (in CSS pixels)
+pub const SCROLLBAR_THICKNESS: f64 = 12.0;
+
+/// Compute the scrollbars of a scroll container with the specified client area (padding box).
+///
+/// `show` is whether each of the (horizontal, vertical) scrollbars should be shown.
+fn compute_scrollbars(
+    owner: ScrollbarOwner,
+    client: Rect,
+    scroll_offset: Point,
+    max_scroll: (f64, f64),
+    show: (bool, bool),
+    thickness: f64,
+) -> [Option<Scrollbar>; 2] {
+    if thickness <= 0.0 {
+        return [None, None];
+    }
+
+    // Leave a gap in the corner when both scrollbars are shown
+    let corner = if show.0 && show.1 { thickness } else { 0.0 };
+
+    let thumb =
+        |track_start: f64, track_length: f64, client_length: f64, max_scroll: f64, offset: f64| {
+            let content_length = client_length + max_scroll;
+            let thumb_length = (track_length * client_length / content_length)
+                .max(MIN_THUMB_LENGTH)
+                .min(track_length);
+            let progress = if max_scroll > 0.0 {
+                (offset / max_scroll).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let thumb_start = track_start + (track_length - thumb_length) * progress;
+            (thumb_start, thumb_length)
+        };
+
+    let horizontal = show.0.then(|| {
+        let track = Rect::new(
+            client.x0,
+            client.y1 - thickness,
+            client.x1 - corner,
+            client.y1,
+        );
+        let (start, length) = thumb(
+            track.x0,
+            track.width(),
+            client.width(),
+            max_scroll.0,
+            scroll_offset.x,
+        );
+        Scrollbar {
+            owner,
+            axis: ScrollbarAxis::Horizontal,
+            track,
+            thumb: Rect::new(start, track.y0, start + length, track.y1),
+            client_length: client.width(),
+            max_scroll: max_scroll.0,
+        }
+    });
+
+    let vertical = show.1.then(|| {
+        let track = Rect::new(
+            client.x1 - thickness,
+            client.y0,
+            client.x1,
+            client.y1 - corner,
+        );
+        let (start, length) = thumb(
+            track.y0,
+            track.height(),
+            client.height(),
+            max_scroll.1,
+            scroll_offset.y,
+        );
+        Scrollbar {
+            owner,
+            axis: ScrollbarAxis::Vertical,
+            track,
+            thumb: Rect::new(track.x0, start, track.x1, start + length),
+            client_length: client.height(),
+            max_scroll: max_scroll.1,
+        }
+    });
+
+    [horizontal, vertical]
+}
+
+impl Node {
+    /// The scrollbars of this node (if it is a scroll container)
+    pub fn scrollbars(&self) -> [Option<Scrollbar>; 2] {
+        let Some(style) = self.primary_styles() else {
+            return [None, None];
+        };
+
+        let layout = &self.final_layout;
+        let max_scroll = (layout.scroll_width() as f64, layout.scroll_height() as f64);
+        let show = |overflow: Overflow, max_scroll: f64| match overflow {
+            Overflow::Scroll => true,
+            Overflow::Auto => max_scroll > 0.0,
+            Overflow::Visible | Overflow::Hidden | Overflow::Clip => false,
+        };
+        let show = (
+            show(style.clone_overflow_x(), max_scroll.0),
+            show(style.clone_overflow_y(), max_scroll.1),
+        );
+
+        let client = Rect::new(
+            layout.border.left as f64,
+            layout.border.top as f64,
+            (layout.size.width - layout.border.right) as f64,
+            (layout.size.height - layout.border.bottom) as f64,
+        );
+        let scroll_offset = Point::new(self.scroll_offset.x, self.scroll_offset.y);
+
+        compute_scrollbars(
+            ScrollbarOwner::Node(self.id),
+            client,
+            scroll_offset,
+            max_scroll,
+            show,
+            SCROLLBAR_THICKNESS,
+        )
+    }
+}
+
+impl BaseDocument {
+    /// The scrollbars of the viewport
+    pub fn viewport_scrollbars(&self) -> [Option<Scrollbar>; 2] {
+        let Some(root) = self.try_root_element() else {
+            return [None, None];
+        };
+
+        let scale = self.viewport.scale_f64();
+        let window_width = self.viewport.window_size.0 as f64 / scale;
+        let window_height = self.viewport.window_size.1 as f64 / scale;
+        let content_size = root.final_layout.size;
+        let max_scroll = (
+            (content_size.width as f64 - window_width).max(0.0),
+            (content_size.height as f64 - window_height).max(0.0),
+        );
+        let show = (max_scroll.0 > 0.0, max_scroll.1 > 0.0);
+
+        compute_scrollbars(
+            ScrollbarOwner::Viewport,
+            Rect::new(0.0, 0.0, window_width, window_height),
+            Point::new(self.viewport_scroll.x, self.viewport_scroll.y),
+            max_scroll,
+            show,
+            SCROLLBAR_THICKNESS,
+        )
+    }
+
+    pub fn scrollbar_mode(&self) -> ScrollbarMode {
+        self.scrollbars.mode
+    }
+
+    /// The opacity with which scrollbars should be painted. Overlay scrollbars fade out
+    /// once scrolling has stopped.
+    pub fn scrollbar_opacity(&self) -> f32 {
+        match self.scrollbars.mode {
+            ScrollbarMode::Classic => 1.0,
+            ScrollbarMode::Overlay => {
+                let Some(last_scroll_time) = self.scrollbars.last_scroll_time else {
+                    return 0.0;
+                };
+                let elapsed = last_scroll_time.elapsed();
+                if elapsed < OVERLAY_VISIBLE_DURATION {
+                    1.0
+                } else {
+                    let fade = (elapsed - OVERLAY_VISIBLE_DURATION).as_secs_f32()
+                        / OVERLAY_FADE_DURATION.as_secs_f32();
+                    (1.0 - fade).max(0.0)
+                }
+            }
+        }
+    }
+
+    /// Whether overlay scrollbars are currently visible (and need to be repainted as they fade out)
+    pub(crate) fn scrollbars_are_animating(&self) -> bool {
+        self.scrollbars.mode == ScrollbarMode::Overlay
+            && self.scrollbars.last_scroll_time.is_some_and(|time| {
+                time.elapsed() < OVERLAY_VISIBLE_DURATION + OVERLAY_FADE_DURATION
+            })
+    }
+
+    pub(crate) fn record_scroll_activity(&mut self) {
+        self.scrollbars.last_scroll_time = Some(Instant::now());
+    }
+
+    /// Get the current geometry of a scrollbar
+    pub fn scrollbar(&self, owner: ScrollbarOwner, axis: ScrollbarAxis) -> Option<Scrollbar> {
+        let scrollbars = match owner {
+            ScrollbarOwner::Viewport => self.viewport_scrollbars(),
+            ScrollbarOwner::Node(node_id) => self.get_node(node_id)?.scrollbars(),
+        };
+        scrollbars
+            .into_iter()
+            .flatten()
+            .find(|scrollbar| scrollbar.axis == axis)
+    }
+
+    /// Convert a point in page coordinates into the coordinate space of a scrollbar owner
+    pub(crate) fn scrollbar_local_point(&self, owner: ScrollbarOwner, x: f32, y: f32) -> Point {
+        match owner {
+            ScrollbarOwner::Viewport => Point::new(
+                x as f64 - self.viewport_scroll.x,
+                y as f64 - self.viewport_scroll.y,
+            ),
+            ScrollbarOwner::Node(node_id) => {
+                let node = &self.nodes[node_id];
+                let pos = node.absolute_position(0.0, 0.0);
+                Point::new(
+                    x as f64 - pos.x as f64 - node.scroll_offset.x,
+                    y as f64 - pos.y as f64 - node.scroll_offset.y,
+                )
+            }
+        }
+    }
+
+    /// Find the scrollbar (if any) at a point in page coordinates
+    pub fn hit_scrollbar(&self, x: f32, y: f32) -> Option<(Scrollbar, ScrollbarPart)> {
+        // Hidden overlay scrollbars are not interactive
+        if self.scrollbar_opacity() == 0.0 {
+            return None;
+        }
+
+        // The viewport's scrollbars are painted on top of everything else
+        let point = self.scrollbar_local_point(ScrollbarOwner::Viewport, x, y);
+        for scrollbar in self.viewport_scrollbars().into_iter().flatten() {
+            if let Some(part) = scrollbar.hit(point) {
+                return Some((scrollbar, part));
+            }
+        }
+
+        // Otherwise check each scroll container containing the hit node (innermost first)
+        let hit = self.hit(x, y)?;
+        let mut maybe_node = self.get_node(hit.node_id);
+        while let Some(node) = maybe_node {
+            let point = self.scrollbar_local_point(ScrollbarOwner::Node(node.id), x, y);
+            for scrollbar in node.scrollbars().into_iter().flatten() {
+                if let Some(part) = scrollbar.hit(point) {
+                    return Some((scrollbar, part));
+                }
+            }
+            maybe_node = node.layout_parent.get().and_then(|id| self.get_node(id));
+        }
+
+        None
+    }
+
+    /// Scroll a scrollbar's owner so that the scroll offset along the scrollbar's axis is `offset`
+    pub(crate) fn set_scrollbar_offset(&mut self, scrollbar: &Scrollbar, offset: f64) -> bool {
+        let offset = offset.clamp(0.0, scrollbar.max_scroll);
+        let current = match scrollbar.owner {
+            ScrollbarOwner::Viewport => self.viewport_scroll,
+            ScrollbarOwner::Node(node_id) => self.nodes[node_id].scroll_offset,
+        };
+        let (dx, dy) = match scrollbar.axis {
+            ScrollbarAxis::Horizontal => (current.x - offset, 0.0),
+            ScrollbarAxis::Vertical => (0.0, current.y - offset),
+        };
+
+        match scrollbar.owner {
+            ScrollbarOwner::Viewport => self.scroll_viewport_by_has_changed(dx, dy),
+            ScrollbarOwner::Node(node_id) => self.scroll_node_by_has_changed(node_id, dx, dy),
+        }
+    }
+
+    /// Scroll by one page towards the clicked part of the scrollbar's track
+    pub(crate) fn page_scrollbar(&mut self, scrollbar: &Scrollbar, part: ScrollbarPart) -> bool {
+        let current = match (scrollbar.owner, scrollbar.axis) {
+            (ScrollbarOwner::Viewport, ScrollbarAxis::Horizontal) => self.viewport_scroll.x,
+            (ScrollbarOwner::Viewport, ScrollbarAxis::Vertical) => self.viewport_scroll.y,
+            (ScrollbarOwner::Node(id), ScrollbarAxis::Horizontal) => self.nodes[id].scroll_offset.x,
+            (ScrollbarOwner::Node(id), ScrollbarAxis::Vertical) => self.nodes[id].scroll_offset.y,
+        };
+        let page = scrollbar.client_length * PAGE_SCROLL_FRACTION;
+        let offset = match part {
+            ScrollbarPart::TrackBefore => current - page,
+            ScrollbarPart::TrackAfter => current + page,
+            ScrollbarPart::Thumb => current,
+        };
+        self.set_scrollbar_offset(scrollbar, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertical_scrollbar(client: Rect, max_scroll: f64, offset: f64) -> Scrollbar {
+        let [_, vertical] = compute_scrollbars(
+            ScrollbarOwner::Viewport,
+            client,
+            Point::new(0.0, offset),
+            (0.0, max_scroll),
+            (false, true),
+            10.0,
+        );
+        vertical.unwrap()
+    }
+
+    #[test]
+    fn test_thumb_reflects_scroll_position() {
+        let client = Rect::new(0.0, 0.0, 100.0, 200.0);
+        let scrollbar = vertical_scrollbar(client, 200.0, 100.0);
+        assert_eq!(scrollbar.track, Rect::new(90.0, 0.0, 100.0, 200.0));
+        // Half of the content is visible, and it is scrolled half way
+        assert_eq!(scrollbar.thumb, Rect::new(90.0, 50.0, 100.0, 150.0));
+
+        // Very long content still has a thumb that can be grabbed
+        let scrollbar = vertical_scrollbar(client, 100_000.0, 0.0);
+        assert_eq!(scrollbar.thumb.height(), MIN_THUMB_LENGTH);
+    }
+
+    #[test]
+    fn test_scrollbars_leave_a_corner_gap() {
+        let client = Rect::new(0.0, 0.0, 100.0, 200.0);
+        let [horizontal, vertical] = compute_scrollbars(
+            ScrollbarOwner::Node(1),
+            client,
+            Point::ZERO,
+            (50.0, 50.0),
+            (true, true),
+            10.0,
+        );
+        assert_eq!(
+            horizontal.unwrap().track,
+            Rect::new(0.0, 190.0, 90.0, 200.0)
+        );
+        assert_eq!(vertical.unwrap().track, Rect::new(90.0, 0.0, 100.0, 190.0));
+
+        let hidden = compute_scrollbars(
+            ScrollbarOwner::Node(1),
+            client,
+            Point::ZERO,
+            (50.0, 50.0),
+            (true, true),
+            0.0,
+        );
+        assert_eq!(hidden, [None, None]);
+    }
+
+    #[test]
+    fn test_scroll_offset_for_thumb_start() {
+        let scrollbar = vertical_scrollbar(Rect::new(0.0, 0.0, 100.0, 200.0), 200.0, 0.0);
+        // The inverse of the thumb position
+        assert_eq!(scrollbar.scroll_offset_for_thumb_start(50.0), 100.0);
+        // Dragging beyond the track is clamped
+        assert_eq!(scrollbar.scroll_offset_for_thumb_start(-20.0), 0.0);
+        assert_eq!(scrollbar.scroll_offset_for_thumb_start(500.0), 200.0);
+
+        // A thumb which fills the track can't be dragged
+        let scrollbar = vertical_scrollbar(Rect::new(0.0, 0.0, 100.0, 20.0), 10.0, 0.0);
+        assert_eq!(scrollbar.thumb.height(), scrollbar.track.height());
+        assert_eq!(scrollbar.scroll_offset_for_thumb_start(5.0), 0.0);
+    }
+
+    #[test]
+    fn test_hit() {
+        let scrollbar = vertical_scrollbar(Rect::new(0.0, 0.0, 100.0, 200.0), 200.0, 100.0);
+        assert_eq!(
+            scrollbar.hit(Point::new(95.0, 20.0)),
+            Some(ScrollbarPart::TrackBefore)
+        );
+        assert_eq!(
+            scrollbar.hit(Point::new(95.0, 100.0)),
+            Some(ScrollbarPart::Thumb)
+        );
+        assert_eq!(
+            scrollbar.hit(Point::new(95.0, 180.0)),
+            Some(ScrollbarPart::TrackAfter)
+        );
+        assert_eq!(scrollbar.hit(Point::new(50.0, 100.0)), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Saving and restoring document state (session snapshots)
+//!
+//! [`BaseDocument::save_state`] captures the parts of a document's state which are not recreated
+//! by loading its URL again: the DOM tree (which scripts or user interaction may have changed), the
+//! values, checkedness and selections of form controls, the scroll offsets of the viewport and of
+//! every scroll container, and the focused node. [`BaseDocument::restore_state`] applies a
+//! snapshot to a fresh document which was loaded from the same URL, which can be used to implement
+//! session restore or a back/forward cache.
+//!
+//! Nodes are identified by their path of child indices from the root node, so snapshots remain
+//! valid across documents. With the `serde` feature, [`DocumentState`] can be serialized in a
+//! stable format (whose [version](DOCUMENT_STATE_VERSION) is stored in the snapshot).
+//!
+//! Sub-documents (such as iframes) are not included in their parent's snapshot.
+
+use markup5ever::{LocalName, Namespace, Prefix, QualName};
+
+use crate::node::{SpecialElementData, TextInputData};
+use crate::{Attribute, BaseDocument, NodeData};
+
+/// The version of the [`DocumentState`] format. Incremented whenever the format changes.
+pub const DOCUMENT_STATE_VERSION: u32 = 1;
+
+/// A path of child indices from the root node to a node
+pub type NodePath = Vec<usize>;
+
+/// A snapshot of the state of a document
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentState {
+    /// The [`DOCUMENT_STATE_VERSION`] of the snapshot
+    pub version: u32,
+    /// The URL of the document
+    pub url: String,
+    /// The children of the root node
+    pub tree: Vec<SavedNode>,
+    /// The scroll position of the viewport
+    pub viewport_scroll: (f64, f64),
+    /// The scroll offsets of scroll containers which are scrolled
+    pub scroll_offsets: Vec<(NodePath, f64, f64)>,
+    pub text_inputs: Vec<SavedTextInput>,
+    /// Whether each checkbox and radio button is checked
+    pub checkboxes: Vec<(NodePath, bool)>,
+    pub focus: Option<NodePath>,
+}
+
+/// A node in a [`DocumentState`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SavedNode {
+    Element {
+        name: SavedName,
+        attrs: Vec<(SavedName, String)>,
+        children: Vec<SavedNode>,
+    },
+    Text {
+        text: String,
+    },
+    Comment,
+}
+
+/// A qualified element or attribute name
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedName {
+    pub prefix: Option<String>,
+    pub ns: String,
+    pub local: String,
+}
+
+/// The contents and selection of a text input or textarea
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedTextInput {
+    pub path: NodePath,
+    pub text: String,
+    pub is_multiline: bool,
+    /// The byte index of the selection anchor
+    pub anchor: usize,
+    /// The byte index of the selection focus (the cursor)
+    pub focus: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestoreStateError {
+    /// The snapshot was saved in a different version of the format
+    UnsupportedVersion(u32),
+    /// The snapshot was saved from a document with a different URL
+    UrlMismatch,
+}
+
+impl From<&QualName> for SavedName {
+    fn from(name: &QualName) -> Self {
+        Self {
+            prefix: name.prefix.as_ref().map(|prefix| prefix.to_string()),
+            ns: name.ns.to_string(),
+            local: name.local.to_string(),
+        }
+    }
+}
+
+impl From<&SavedName> for QualName {
+    fn from(name: &SavedName) -> Self {
+        QualName::new(
+            name.prefix.as_deref().map(Prefix::from),
+            Namespace::from(name.ns.as_str()),
+            LocalName::from(name.local.as_str()),
+        )
+    }
+}
+
+impl BaseDocument {
+    /// Capture the state of the document (see the [module documentation](self))
+    pub fn save_state(&self) -> DocumentState {
+        let mut state = DocumentState {
+            version: DOCUMENT_STATE_VERSION,
+            url: self.url.as_str().to_string(),
+            tree: Vec::new(),
+            viewport_scroll: (self.viewport_scroll.x, self.viewport_scroll.y),
+            scroll_offsets: Vec::new(),
+            text_inputs: Vec::new(),
+            checkboxes: Vec::new(),
+            focus: None,
+        };
+        state.tree = self.save_children(0, &mut Vec::new(), &mut state);
+        state
+    }
+
+    /// Apply a snapshot saved by [`save_state`](Self::save_state) to the document, which should
+    /// have been loaded from the same URL. If the document's tree differs from the snapshot's, it
+    /// is replaced by the snapshot's. Entries whose node no longer exists are ignored.
+    pub fn restore_state(&mut self, state: &DocumentState) -> Result<(), RestoreStateError> {
+        if state.version != DOCUMENT_STATE_VERSION {
+            return Err(RestoreStateError::UnsupportedVersion(state.version));
+        }
+        if !urls_match(&state.url, self.url.as_str()) {
+            return Err(RestoreStateError::UrlMismatch);
+        }
+
+        if self.save_state().tree != state.tree {
+            let mut mutator = self.mutate();
+            mutator.remove_and_drop_all_children(0);
+            let children: Vec<usize> = state
+                .tree
+                .iter()
+                .map(|node| create_saved_node(&mut mutator, node))
+                .collect();
+            mutator.append_children(0, &children);
+        }
+
+        // Form control data is created when boxes are constructed (and existing data is kept),
+        // so it is created here if the document hasn't been resolved yet
+        for saved in &state.text_inputs {
+            let Some(node_id) = self.node_at_path(&saved.path) else {
+                continue;
+            };
+            let Some(element) = self.nodes[node_id].element_data_mut() else {
+                continue;
+            };
+            if element.text_input_data().is_none() {
+                element.special_data =
+                    SpecialElementData::TextInput(TextInputData::new(saved.is_multiline));
+            }
+            let input = element.text_input_data_mut().unwrap();
+            input.set_text(
+                &mut self.font_ctx.lock().unwrap(),
+                &mut self.layout_ctx,
+                &saved.text,
+            );
+            self.with_text_input(node_id, |mut driver| {
+                driver.select_byte_range(saved.anchor, saved.focus)
+            });
+        }
+
+        for (path, checked) in &state.checkboxes {
+            let Some(node_id) = self.node_at_path(path) else {
+                continue;
+            };
+            if let Some(element) = self.nodes[node_id].element_data_mut() {
+                element.special_data = SpecialElementData::CheckboxInput(*checked);
+            }
+        }
+
+        for (path, x, y) in &state.scroll_offsets {
+            if let Some(node_id) = self.node_at_path(path) {
+                self.nodes[node_id].scroll_offset = crate::Point { x: *x, y: *y };
+            }
+        }
+        let (x, y) = state.viewport_scroll;
+        self.set_viewport_scroll(crate::Point { x, y });
+
+        if let Some(node_id) = state
+            .focus
+            .as_ref()
+            .and_then(|path| self.node_at_path(path))
+        {
+            self.set_focus_to(node_id);
+        }
+
+        self.shell_provider.request_redraw();
+        Ok(())
+    }
+
+    fn node_at_path(&self, path: &[usize]) -> Option<usize> {
+        path.iter().try_fold(0, |node_id, index| {
+            self.nodes[node_id].children.get(*index).copied()
+        })
+    }
+
+    /// Save the children of the node at `path`, recording the state of each of them in `state`
+    fn save_children(
+        &self,
+        node_id: usize,
+        path: &mut NodePath,
+        state: &mut DocumentState,
+    ) -> Vec<SavedNode> {
+        let mut saved = Vec::new();
+        for (index, &child_id) in self.nodes[node_id].children.iter().enumerate() {
+            path.push(index);
+            self.save_node_state(child_id, path, state);
+            match &self.nodes[child_id].data {
+                NodeData::Element(element) => saved.push(SavedNode::Element {
+                    name: (&element.name).into(),
+                    attrs: element
+                        .attrs()
+                        .iter()
+                        .map(|attr| ((&attr.name).into(), attr.value.clone()))
+                        .collect(),
+                    children: self.save_children(child_id, path, state),
+                }),
+                NodeData::Text(text) => saved.push(SavedNode::Text {
+                    text: text.content.clone(),
+                }),
+                NodeData::Comment => saved.push(SavedNode::Comment),
+                NodeData::Document | NodeData::AnonymousBlock(_) => {}
+            }
+            path.pop();
+        }
+        saved
+    }
+
+    /// Record the focus, scroll offset and form control state of the node at `path`
+    fn save_node_state(&self, node_id: usize, path: &NodePath, state: &mut DocumentState) {
+        let node = &self.nodes[node_id];
+        if self.focus_node_id == Some(node_id) {
+            state.focus = Some(path.clone());
+        }
+        if node.scroll_offset.x != 0.0 || node.scroll_offset.y != 0.0 {
+            state
+                .scroll_offsets
+                .push((path.clone(), node.scroll_offset.x, node.scroll_offset.y));
+        }
+
+        let Some(element) = node.element_data() else {
+            return;
+        };
+        if let Some(input) = element.text_input_data() {
+            let selection = input.editor.raw_selection();
+            state.text_inputs.push(SavedTextInput {
+                path: path.clone(),
+                text: input.editor.raw_text().to_string(),
+                is_multiline: input.is_multiline,
+                anchor: selection.anchor().index(),
+                focus: selection.focus().index(),
+            });
+        } else if let Some(checked) = element.checkbox_input_checked() {
+            state.checkboxes.push((path.clone(), checked));
+        }
+    }
+}
+
+fn create_saved_node(mutator: &mut crate::DocumentMutator<'_>, node: &SavedNode) -> usize {
+    match node {
+        SavedNode::Element {
+            name,
+            attrs,
+            children,
+        } => {
+            let attrs = attrs
+                .iter()
+                .map(|(name, value)| Attribute {
+                    name: name.into(),
+                    value: value.clone(),
+                })
+                .collect();
+            let element_id = mutator.create_element(name.into(), attrs);
+            let children: Vec<usize> = children
+                .iter()
+                .map(|child| create_saved_node(mutator, child))
+                .collect();
+            mutator.append_children(element_id, &children);
+            element_id
+        }
+        SavedNode::Text { text } => mutator.create_text_node(text),
+        SavedNode::Comment => mutator.create_comment_node(),
+    }
+}
+
+/// Whether two document URLs are the same (ignoring their fragments)
+fn urls_match(a: &str, b: &str) -> bool {
+    let without_fragment = |url: &str| url.split('#').next().unwrap_or_default().to_string();
+    without_fragment(a) == without_fragment(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DocumentConfig, local_name, qual_name};
+
+    fn make_doc() -> BaseDocument {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            base_url: Some("https://example.com/form".to_string()),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let input = mutator.create_element(qual_name!("input", html), Vec::new());
+        let text = mutator.create_text_node("label");
+        mutator.append_children(0, &[input, text]);
+        drop(mutator);
+        doc
+    }
+
+    #[test]
+    fn restores_into_fresh_document() {
+        let mut doc = make_doc();
+        let input_id = doc.nodes[0].children[0];
+        let element = doc.nodes[input_id].element_data_mut().unwrap();
+        element.special_data = SpecialElementData::TextInput(TextInputData::new(false));
+        doc.with_text_input(input_id, |mut driver| {
+            driver.insert_or_replace_selection("hello");
+            driver.select_byte_range(1, 3);
+        });
+        doc.set_focus_to(input_id);
+        let state = doc.save_state();
+
+        let mut fresh = make_doc();
+        fresh.restore_state(&state).unwrap();
+        assert_eq!(fresh.save_state(), state);
+        assert_eq!(fresh.focus_node_id, Some(input_id));
+        assert!(
+            fresh.nodes[input_id]
+                .data
+                .is_element_with_tag_name(&local_name!("input"))
+        );
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        let mut state = make_doc().save_state();
+        state.url = "https://example.com/other".to_string();
+        assert_eq!(
+            make_doc().restore_state(&state),
+            Err(RestoreStateError::UrlMismatch)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_through_serde() {
+        let mut doc = make_doc();
+        let input_id = doc.nodes[0].children[0];
+        let element = doc.nodes[input_id].element_data_mut().unwrap();
+        element.special_data = SpecialElementData::TextInput(TextInputData::new(false));
+        doc.with_text_input(input_id, |mut driver| {
+            driver.insert_or_replace_selection("hello");
+        });
+        doc.set_focus_to(input_id);
+        doc.set_viewport_scroll(crate::Point { x: 0.0, y: 40.0 });
+        let state = doc.save_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let deserialized: DocumentState = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, state);
+    }
+}
@@ -4,6 +4,7 @@
 use std::ptr::NonNull;
 use std::sync::atomic::Ordering;
 
+use crate::invalidation::RELATIVE_SELECTOR_ANCHOR_FLAGS;
 use crate::layout::damage::compute_layout_damage;
 use crate::node::Node;
 use crate::node::NodeData;
@@ -122,12 +123,15 @@ impl crate::document::BaseDocument {
         // dbg!(root);
         let token = RecalcStyle::pre_traverse(root, &context);
 
-        if token.should_traverse() {
+        let should_traverse = token.should_traverse();
+        if should_traverse {
             // Style the elements, resolving their data
             let traverser = RecalcStyle::new(context);
             let rayon_pool = STYLE_THREAD_POOL.pool();
             style::driver::traverse_dom(&traverser, token, rayon_pool.as_ref());
         }
+        // Restyled elements may have changed counters or pseudo-element content
+        self.generated_content_dirty |= should_traverse;
 
         for opaque in self.snapshots.keys() {
             let id = opaque.id();
@@ -405,8 +409,10 @@ impl selectors::Element for BlitzNode<'_> {
             NonTSPseudoClass::Disabled => self.element_state.contains(ElementState::DISABLED),
             NonTSPseudoClass::Enabled => self.element_state.contains(ElementState::ENABLED),
             NonTSPseudoClass::Focus => self.element_state.contains(ElementState::FOCUS),
-            NonTSPseudoClass::FocusWithin => false,
-            NonTSPseudoClass::FocusVisible => false,
+            NonTSPseudoClass::FocusWithin => {
+                self.element_state.contains(ElementState::FOCUS_WITHIN)
+            }
+            NonTSPseudoClass::FocusVisible => self.element_state.contains(ElementState::FOCUSRING),
             NonTSPseudoClass::Fullscreen => false,
             NonTSPseudoClass::Hover => self.element_state.contains(ElementState::HOVER),
             NonTSPseudoClass::Indeterminate => false,
@@ -461,6 +467,16 @@ impl selectors::Element for BlitzNode<'_> {
         if !self_flags.is_empty() {
             self.selector_flags
                 .set(self.selector_flags.get() | self_flags);
+
+            // Also flag the document node, so that DOM mutations only look for the anchors of
+            // `:has()` selectors in documents which have some
+            let anchor_flags = self_flags & RELATIVE_SELECTOR_ANCHOR_FLAGS;
+            if !anchor_flags.is_empty() {
+                let document = self.with(0);
+                document
+                    .selector_flags
+                    .set(document.selector_flags.get() | anchor_flags);
+            }
         }
 
         // Handle flags that apply to the parent.
@@ -97,7 +97,11 @@ pub(crate) fn white_space_collapse(input: stylo::WhiteSpaceCollapse) -> parley::
         stylo::WhiteSpaceCollapse::Collapse => parley::WhiteSpaceCollapse::Collapse,
         stylo::WhiteSpaceCollapse::Preserve => parley::WhiteSpaceCollapse::Preserve,
 
-        // TODO: Implement PreserveBreaks and BreakSpaces modes
+        // Parley only natively supports collapsing or preserving all white space. `preserve-breaks`
+        // is implemented when the text is pushed into the Parley builder (see
+        // `layout::text::push_text`), which collapses spaces and tabs itself. Parley wraps runs of
+        // preserved spaces onto the following lines, so `break-spaces` only differs from `pre-wrap`
+        // in that the first space past the end of each line still hangs.
         stylo::WhiteSpaceCollapse::PreserveBreaks => parley::WhiteSpaceCollapse::Preserve,
         stylo::WhiteSpaceCollapse::BreakSpaces => parley::WhiteSpaceCollapse::Preserve,
     }
@@ -0,0 +1,67 @@
+//! Measures how much of a large list is restyled when one item changes
+//!
+//! Run with `cargo bench -p blitz-html --bench restyle`
+
+use std::time::{Duration, Instant};
+
+use blitz_dom::{DocumentConfig, QualName, local_name, ns};
+use blitz_html::HtmlDocument;
+
+const ITEM_COUNT: usize = 10_000;
+const ITERATIONS: u32 = 50;
+
+const CSS: &str = "
+    li { color: blue }
+    li.selected { color: red }
+    li.selected + li { font-weight: bold }
+    body.dark li { color: white }
+";
+
+fn list_document() -> HtmlDocument {
+    let items: String = (0..ITEM_COUNT)
+        .map(|i| format!(r#"<li id="item-{i}"><span>Item {i}</span></li>"#))
+        .collect();
+    let html =
+        format!("<html><head><style>{CSS}</style></head><body><ul>{items}</ul></body></html>");
+    let mut doc = HtmlDocument::from_html(&html, DocumentConfig::default());
+    doc.resolve(0.0);
+    doc.profiler().set_enabled(true);
+    doc
+}
+
+/// Toggle the `class` of `node_id` and resolve, `ITERATIONS` times. Returns the mean time per
+/// frame and the number of elements restyled by the last frame.
+fn bench_toggle(doc: &mut HtmlDocument, node_id: usize, class: &str) -> (Duration, usize) {
+    let name = QualName::new(None, ns!(), local_name!("class"));
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        if i % 2 == 0 {
+            doc.mutate().set_attribute(node_id, name.clone(), class);
+        } else {
+            doc.mutate().clear_attribute(node_id, name.clone());
+        }
+        doc.resolve(0.0);
+    }
+    let restyle_count = doc
+        .profiler()
+        .last_frame()
+        .map(|frame| frame.restyle_count)
+        .unwrap_or_default();
+    (start.elapsed() / ITERATIONS, restyle_count)
+}
+
+fn main() {
+    let mut doc = list_document();
+    let item = doc
+        .get_element_by_id(&format!("item-{}", ITEM_COUNT / 2))
+        .unwrap();
+    let body = doc.query_selector("body").unwrap().unwrap();
+
+    for (label, node_id, class) in [
+        ("toggle class on one item", item, "selected"),
+        ("toggle class on body", body, "dark"),
+    ] {
+        let (mean, restyle_count) = bench_toggle(&mut doc, node_id, class);
+        println!("{label:<26} {mean:>10.2?}/frame  {restyle_count:>6} elements restyled");
+    }
+}
@@ -2,7 +2,9 @@ use std::ops::{Deref, DerefMut};
 
 use crate::DocumentHtmlParser;
 
-use blitz_dom::{BaseDocument, DEFAULT_CSS, DocGuard, DocGuardMut, Document, DocumentConfig};
+use blitz_dom::{
+    BaseDocument, DEFAULT_CSS, DocGuard, DocGuardMut, Document, DocumentConfig, SharedDocument,
+};
 
 pub struct HtmlDocument {
     inner: BaseDocument,
@@ -53,4 +55,109 @@ impl HtmlDocument {
     pub fn into_inner(self) -> BaseDocument {
         self.into()
     }
+
+    /// Convert the [`HtmlDocument`] into a [`SharedDocument`], which shells can resolve and paint
+    /// off the main thread
+    pub fn into_shared(self) -> SharedDocument {
+        SharedDocument::new(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::{QualName, local_name, ns};
+
+    const ITEM_COUNT: usize = 500;
+
+    fn list_document(css: &str) -> HtmlDocument {
+        let items: String = (0..ITEM_COUNT)
+            .map(|i| format!(r#"<li id="item-{i}"><span>Item {i}</span></li>"#))
+            .collect();
+        let html =
+            format!("<html><head><style>{css}</style></head><body><ul>{items}</ul></body></html>");
+        let mut doc = HtmlDocument::from_html(&html, DocumentConfig::default());
+        doc.resolve(0.0);
+        doc
+    }
+
+    /// The elements damaged by `mutate`: those restyled with different styles, and those whose
+    /// boxes must be rebuilt (e.g. new elements and the parents of inserted ones). Damage is
+    /// cleared at the end of each `resolve`, so it is read straight after the style traversal.
+    fn damaged_elements(
+        doc: &mut HtmlDocument,
+        mutate: impl FnOnce(&mut BaseDocument),
+    ) -> Vec<usize> {
+        mutate(&mut doc.inner);
+        doc.inner.resolve_stylist(0.0);
+        let damaged = doc
+            .tree()
+            .iter()
+            .filter(|(_, node)| node.is_element() && node.damage().is_some_and(|d| !d.is_empty()))
+            .map(|(node_id, _)| node_id)
+            .collect();
+        doc.resolve(0.0);
+        damaged
+    }
+
+    /// `node_id` followed by its first child
+    fn with_first_child(doc: &HtmlDocument, node_id: usize) -> Vec<usize> {
+        vec![node_id, doc.tree()[node_id].children[0]]
+    }
+
+    fn class_name() -> QualName {
+        QualName::new(None, ns!(), local_name!("class"))
+    }
+
+    #[test]
+    fn toggling_a_class_restyles_only_the_item() {
+        let mut doc = list_document("li { color: blue } li.selected { color: red }");
+        let item = doc.get_element_by_id("item-250").unwrap();
+
+        let damaged = damaged_elements(&mut doc, |doc| {
+            doc.mutate().set_attribute(item, class_name(), "selected")
+        });
+        // The item and its child span (which inherits the new color)
+        assert_eq!(damaged, with_first_child(&doc, item));
+
+        let damaged = damaged_elements(&mut doc, |doc| {
+            doc.mutate().clear_attribute(item, class_name())
+        });
+        assert_eq!(damaged, with_first_child(&doc, item));
+    }
+
+    #[test]
+    fn toggling_a_class_restyles_dependent_siblings() {
+        let mut doc = list_document("li.selected + li { color: red }");
+        let item = doc.get_element_by_id("item-250").unwrap();
+        let next_item = doc.get_element_by_id("item-251").unwrap();
+
+        let damaged = damaged_elements(&mut doc, |doc| {
+            doc.mutate().set_attribute(item, class_name(), "selected")
+        });
+        // The next item and its span
+        assert_eq!(damaged, with_first_child(&doc, next_item));
+    }
+
+    #[test]
+    fn appending_an_item_restyles_only_edge_children() {
+        let mut doc = list_document("li:last-child { color: red }");
+        let list = doc.query_selector("ul").unwrap().unwrap();
+        let last_item = doc.get_element_by_id("item-499").unwrap();
+
+        let mut new_item = None;
+        let damaged = damaged_elements(&mut doc, |doc| {
+            let mut mutator = doc.mutate();
+            let item =
+                mutator.create_element(QualName::new(None, ns!(), local_name!("li")), vec![]);
+            mutator.append_children(list, &[item]);
+            new_item = Some(item);
+        });
+        // The list (whose children changed), the new item, and the previous last item and its
+        // span (which are no longer `:last-child`)
+        let mut expected = vec![list, new_item.unwrap()];
+        expected.extend(with_first_child(&doc, last_item));
+        expected.sort();
+        assert_eq!(damaged, expected);
+    }
 }
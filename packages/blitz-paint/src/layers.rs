@@ -64,6 +64,15 @@ impl LayerManager {
         true
     }
 
+    pub(crate) fn layers_used(&self) -> u32 {
+        self.layers_used.get()
+    }
+
+    /// Account for the layers pushed by a scene fragment which was recorded in a previous frame
+    pub(crate) fn add_replayed_layers(&self, count: u32) {
+        self.layers_used.update(|x| x + count);
+    }
+
     pub(crate) fn maybe_pop_layer(&self, scene: &mut impl PaintScene, condition: bool) {
         if condition {
             scene.pop_layer();
@@ -8,14 +8,17 @@ mod debug_overlay;
 mod gradient;
 mod kurbo_css;
 mod layers;
+mod paint_cache;
 mod render;
 mod sizing;
 mod text;
 
 use anyrender::PaintScene;
-use blitz_dom::{BaseDocument, util::Color};
+use blitz_dom::{BaseDocument, profiler::FramePhase, util::Color};
 use render::BlitzDomPainter;
 
+pub use paint_cache::{DirtyRegion, PREPAINT_MARGIN, PaintCache, paint_scene_cached};
+
 /// Paint a [`blitz_dom::BaseDocument`] by pushing drawing commands into
 /// an impl [`anyrender::PaintScene`].
 ///
@@ -34,9 +37,11 @@ pub fn paint_scene(
     x_offset: u32,
     y_offset: u32,
 ) {
+    let start = std::time::Instant::now();
     let generator =
         BlitzDomPainter::new(dom, scale, width, height, x_offset as f64, y_offset as f64);
     generator.paint_scene(scene);
+    dom.profiler().record_phase(FramePhase::Paint, start);
 
     // println!(
     //     "Rendered using {} clips (depth: {}) (wanted: {})",
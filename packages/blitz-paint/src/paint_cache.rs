@@ -0,0 +1,476 @@
+//! Retained painting
+//!
+//! [`paint_scene_cached`] records the drawing commands of each layer (an element which is a
+//! stacking context, clips its contents, or has opacity) into a separate [`anyrender::Scene`],
+//! and replays it in later frames for as long as nothing inside the layer has changed. It also
+//! reports the area of the viewport which may have changed since the previous frame, so that
+//! renderers can restrict rasterization to that area.
+//!
+//! A layer is repainted if any node within it was damaged (see
+//! [`BaseDocument::paint_invalidations_since`]), changed size, scrolled, contains focused or
+//! selected text, or contains content which changes outside of the document (canvases and
+//! sub-documents). Layers which moved without changing are replayed with a translation.
+//!
+//! The contents of scroll containers (and of the viewport) are additionally retained as scroll
+//! layers, which are painted with a margin of [`PREPAINT_MARGIN`] beyond the viewport. A change to
+//! the scroll position alone only replays the scroll layer with a new translation, for as long as
+//! the visible area stays within the prepainted area.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use anyrender::PaintScene;
+use blitz_dom::BaseDocument;
+use blitz_dom::profiler::FramePhase;
+use blitz_dom::scrollbar::ScrollbarOwner;
+use kurbo::{Point, Rect};
+
+use crate::render::BlitzDomPainter;
+
+/// The distance (as a fraction of the viewport's size) on each side of the viewport which is
+/// painted into retained layers, so that they can be scrolled without repainting
+pub const PREPAINT_MARGIN: f64 = 0.5;
+
+/// The area of the viewport (in device pixels) which changed since the previous frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirtyRegion {
+    /// Nothing changed, so the previously rendered frame can be presented as-is
+    None,
+    /// Only pixels within this rectangle changed
+    Partial(Rect),
+    /// Everything needs to be rasterized
+    Full,
+}
+
+impl DirtyRegion {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, DirtyRegion::None)
+    }
+
+    /// The dirty rectangle, clamped to a `width` by `height` viewport
+    pub fn bounds(&self, width: u32, height: u32) -> Option<Rect> {
+        let viewport = Rect::new(0.0, 0.0, width as f64, height as f64);
+        match self {
+            DirtyRegion::None => None,
+            DirtyRegion::Partial(rect) => {
+                let rect = rect.intersect(viewport);
+                (rect.area() > 0.0).then_some(rect)
+            }
+            DirtyRegion::Full => Some(viewport),
+        }
+    }
+}
+
+/// Drawing commands and other paint state retained between frames by [`paint_scene_cached`].
+///
+/// A cache should only be used with a single document.
+#[derive(Default)]
+pub struct PaintCache {
+    /// The document's paint generation when the cache was last updated
+    generation: Option<u64>,
+    key: Option<CacheKey>,
+    fragments: HashMap<usize, Fragment>,
+    scroll_layers: HashMap<ScrollbarOwner, ScrollLayer>,
+    /// The area (in device pixels) painted by each node the last time it was painted
+    bounds: HashMap<usize, Rect>,
+    /// The scroll offset of each node the last time it was painted
+    scroll_offsets: HashMap<usize, Point>,
+    selection_ranges: HashMap<usize, (usize, usize)>,
+    /// Per-frame state
+    pub(crate) frame: FrameState,
+}
+
+/// Painting parameters which invalidate every fragment when changed
+#[derive(Clone, PartialEq)]
+struct CacheKey {
+    scale: f64,
+    width: u32,
+    height: u32,
+    x_offset: u32,
+    y_offset: u32,
+    show_layout: bool,
+    scrollbar_opacity: f32,
+}
+
+/// The recorded drawing commands of a layer
+pub(crate) struct Fragment {
+    pub(crate) scene: anyrender::Scene,
+    /// The position of the layer's border box (in CSS pixels) when it was recorded
+    pub(crate) origin: Point,
+    pub(crate) size: taffy::Size<f32>,
+    pub(crate) content_size: taffy::Size<f32>,
+    /// The number of clip and opacity layers pushed by the fragment
+    pub(crate) layers: u32,
+    /// Scroll offsets of the scroll containers painted within the fragment
+    pub(crate) scroll_offsets: Vec<(usize, Point)>,
+}
+
+/// The recorded contents of a scroll container (or the viewport)
+pub(crate) struct ScrollLayer {
+    /// The contents, whose origin is the scrolled position of the content box
+    pub(crate) fragment: Fragment,
+    /// The area of the contents (in CSS pixels, relative to the origin) which was painted
+    pub(crate) painted: Rect,
+}
+
+#[derive(Default)]
+pub(crate) struct FrameState {
+    /// Nodes which can't be replayed from the cache this frame (changed nodes and their ancestors)
+    pub(crate) dirty: HashSet<usize>,
+    /// Nodes whose painting changed this frame (their old and new bounds are dirty)
+    pub(crate) changed: HashSet<usize>,
+    /// The fragments currently being recorded (innermost last)
+    pub(crate) recording: Vec<Recording>,
+    pub(crate) region: Option<Rect>,
+}
+
+pub(crate) struct Recording {
+    pub(crate) node_id: usize,
+    pub(crate) scroll_offsets: Vec<(usize, Point)>,
+    /// Whether the fragment contains content which changes outside of the document
+    pub(crate) volatile: bool,
+    /// Whether this is the recording of a scroll layer (rather than of the node's fragment)
+    pub(crate) is_scroll_layer: bool,
+}
+
+impl PaintCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Discard all retained drawing commands, so that the next frame is fully repainted
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The number of layers with retained drawing commands
+    pub fn fragment_count(&self) -> usize {
+        self.fragments.len() + self.scroll_layers.len()
+    }
+
+    pub(crate) fn fragment(&self, node_id: usize) -> Option<&Fragment> {
+        self.fragments.get(&node_id)
+    }
+
+    pub(crate) fn insert_fragment(&mut self, node_id: usize, fragment: Fragment) {
+        self.fragments.insert(node_id, fragment);
+    }
+
+    pub(crate) fn remove_fragment(&mut self, node_id: usize) {
+        self.fragments.remove(&node_id);
+    }
+
+    pub(crate) fn scroll_layer(&self, owner: ScrollbarOwner) -> Option<&ScrollLayer> {
+        self.scroll_layers.get(&owner)
+    }
+
+    pub(crate) fn insert_scroll_layer(&mut self, owner: ScrollbarOwner, layer: ScrollLayer) {
+        self.scroll_layers.insert(owner, layer);
+    }
+
+    pub(crate) fn remove_scroll_layer(&mut self, owner: ScrollbarOwner) {
+        self.scroll_layers.remove(&owner);
+    }
+
+    /// Finish the innermost recording, merging its state into the recording which contains it
+    pub(crate) fn finish_recording(&mut self) -> Recording {
+        let recording = self.frame.recording.pop().unwrap();
+        if let Some(parent) = self.frame.recording.last_mut() {
+            parent.volatile |= recording.volatile;
+            parent.scroll_offsets.extend(&recording.scroll_offsets);
+        }
+        recording
+    }
+
+    /// Record the area painted by a node this frame. If the node changed or moved, both its
+    /// previous and current areas are added to the dirty region.
+    pub(crate) fn update_bounds(&mut self, node_id: usize, bounds: Rect) {
+        let previous = self.bounds.insert(node_id, bounds);
+        if previous != Some(bounds) || self.frame.changed.contains(&node_id) {
+            self.add_dirty_rect(bounds);
+            if let Some(previous) = previous {
+                self.add_dirty_rect(previous);
+            }
+        }
+    }
+
+    /// Record the scroll offset of a node this frame, returning whether it changed. The scroll
+    /// offset is also recorded in any fragments which are being recorded.
+    pub(crate) fn update_scroll_offset(&mut self, node_id: usize, offset: Point) -> bool {
+        for recording in &mut self.frame.recording {
+            recording.scroll_offsets.push((node_id, offset));
+        }
+        let previous = self.scroll_offsets.insert(node_id, offset);
+        previous.is_some_and(|previous| previous != offset)
+    }
+
+    pub(crate) fn mark_changed(&mut self, node_id: usize) {
+        self.frame.changed.insert(node_id);
+        if let Some(bounds) = self.bounds.get(&node_id).copied() {
+            self.add_dirty_rect(bounds);
+        }
+    }
+
+    pub(crate) fn add_dirty_rect(&mut self, rect: Rect) {
+        if rect.area() <= 0.0 {
+            return;
+        }
+        self.frame.region = Some(match self.frame.region {
+            Some(region) => region.union(rect),
+            None => rect,
+        });
+    }
+
+    /// Prepare for painting a new frame, returning `false` if everything needs repainting
+    fn begin_frame(&mut self, dom: &BaseDocument, key: CacheKey) -> bool {
+        self.frame = FrameState::default();
+
+        let invalidations = self
+            .generation
+            .filter(|_| self.key.as_ref() == Some(&key))
+            .and_then(|generation| dom.paint_invalidations_since(generation));
+        self.generation = Some(dom.paint_generation());
+        self.key = Some(key);
+
+        let selection_ranges: HashMap<usize, (usize, usize)> = dom
+            .get_text_selection_ranges()
+            .into_iter()
+            .map(|(node_id, start, end)| (node_id, (start, end)))
+            .collect();
+        let previous_selection = std::mem::replace(&mut self.selection_ranges, selection_ranges);
+
+        let Some(mut changed) = invalidations else {
+            self.fragments.clear();
+            self.scroll_layers.clear();
+            self.bounds.clear();
+            self.scroll_offsets.clear();
+            return false;
+        };
+
+        // Text inputs aren't damaged when edited, and carets are painted within the input
+        changed.extend(dom.get_focussed_node_id().filter(|node_id| {
+            dom.get_node(*node_id)
+                .and_then(|node| node.element_data())
+                .is_some_and(|element| element.text_input_data().is_some())
+        }));
+
+        // Text whose selection changed
+        changed.extend(
+            previous_selection
+                .iter()
+                .filter(|(node_id, range)| self.selection_ranges.get(node_id) != Some(range))
+                .map(|(node_id, _)| *node_id),
+        );
+        changed.extend(
+            self.selection_ranges
+                .keys()
+                .filter(|node_id| !previous_selection.contains_key(node_id)),
+        );
+
+        for node_id in changed {
+            self.mark_changed(node_id);
+
+            // A change to a node changes the painting of every layer it is painted within
+            let mut current = Some(node_id);
+            while let Some(id) = current {
+                if !self.frame.dirty.insert(id) {
+                    break;
+                }
+                current = dom
+                    .get_node(id)
+                    .and_then(|node| node.layout_parent.get().or(node.parent));
+            }
+        }
+
+        true
+    }
+}
+
+/// Paint a [`BaseDocument`] like [`paint_scene`](crate::paint_scene), replaying the drawing
+/// commands of unchanged layers from `cache`.
+///
+/// Returns the area of the viewport which changed since the previous call with the same cache.
+/// A [`DirtyRegion::Full`] is returned for the first frame, and whenever the scale, viewport or
+/// offsets change.
+#[allow(clippy::too_many_arguments)]
+pub fn paint_scene_cached(
+    scene: &mut impl PaintScene,
+    dom: &BaseDocument,
+    cache: &mut PaintCache,
+    scale: f64,
+    width: u32,
+    height: u32,
+    x_offset: u32,
+    y_offset: u32,
+) -> DirtyRegion {
+    if dom.has_pending_critical_resources() {
+        cache.clear();
+        return DirtyRegion::Full;
+    }
+
+    let start = std::time::Instant::now();
+    let key = CacheKey {
+        scale,
+        width,
+        height,
+        x_offset,
+        y_offset,
+        show_layout: dom.devtools().show_layout,
+        scrollbar_opacity: dom.scrollbar_opacity(),
+    };
+    let is_incremental = cache.begin_frame(dom, key);
+
+    let cell = RefCell::new(std::mem::take(cache));
+    let generator =
+        BlitzDomPainter::new(dom, scale, width, height, x_offset as f64, y_offset as f64)
+            .with_cache(&cell);
+    generator.paint_scene(scene);
+    *cache = cell.into_inner();
+    dom.profiler().record_phase(FramePhase::Paint, start);
+
+    // The debug overlay is painted over the document without any change tracking
+    let devtools = dom.devtools();
+    let has_overlay = devtools.highlighted_node.is_some()
+        || (devtools.highlight_hover && dom.get_hover_node_id().is_some());
+    if !is_incremental || has_overlay {
+        return DirtyRegion::Full;
+    }
+    match cache.frame.region {
+        // Round out to whole pixels, as renderers rasterize pixels which are partially covered
+        Some(region) => DirtyRegion::Partial(region.expand()),
+        None => DirtyRegion::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyrender::render_to_buffer;
+    use anyrender_vello_cpu::VelloCpuImageRenderer;
+    use blitz_dom::{DocumentConfig, qual_name};
+    use blitz_html::HtmlDocument;
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use kurbo::Vec2;
+
+    use super::*;
+    use crate::paint_scene;
+
+    const WIDTH: u32 = 400;
+    const HEIGHT: u32 = 300;
+
+    const HTML: &str = r#"
+        <html><head><style>
+            .faded { opacity: 0.5 }
+            .selected { background: red }
+            #scroller { height: 100px; overflow: auto }
+            #scroller div { height: 40px; background: blue; margin-bottom: 10px }
+            #badge { position: relative; z-index: 1; width: 50px; height: 50px; background: green }
+        </style></head><body>
+            <p id="text">Some text</p>
+            <div id="scroller" class="faded">
+                <div></div><div></div><div></div><div></div><div></div>
+            </div>
+            <div id="badge"></div>
+        </body></html>
+    "#;
+
+    fn document() -> HtmlDocument {
+        let viewport = Viewport::new(WIDTH, HEIGHT, 1.0, ColorScheme::Light);
+        let config = DocumentConfig {
+            viewport: Some(viewport),
+            ..Default::default()
+        };
+        let mut doc = HtmlDocument::from_html(HTML, config);
+        doc.resolve(0.0);
+        doc
+    }
+
+    fn render(dom: &BaseDocument, cache: Option<&mut PaintCache>) -> Vec<u8> {
+        render_to_buffer::<VelloCpuImageRenderer, _>(
+            |scene| match cache {
+                Some(cache) => {
+                    paint_scene_cached(scene, dom, cache, 1.0, WIDTH, HEIGHT, 0, 0);
+                }
+                None => paint_scene(scene, dom, 1.0, WIDTH, HEIGHT, 0, 0),
+            },
+            WIDTH,
+            HEIGHT,
+        )
+    }
+
+    /// Paint with the cache, checking the output against painting from scratch
+    fn assert_cached_output_matches(dom: &BaseDocument, cache: &mut PaintCache, step: &str) {
+        let cached = render(dom, Some(cache));
+        let uncached = render(dom, None);
+        assert!(cached == uncached, "cached output differs after {step}");
+    }
+
+    #[test]
+    fn test_cached_output_matches_uncached() {
+        let mut doc = document();
+        let mut cache = PaintCache::new();
+        assert_cached_output_matches(&doc, &mut cache, "the first frame");
+        assert!(cache.fragment_count() > 0);
+
+        // An unchanged frame is replayed entirely from the cache
+        assert_cached_output_matches(&doc, &mut cache, "an unchanged frame");
+
+        let text = doc.get_element_by_id("text").unwrap();
+        doc.mutate()
+            .set_attribute(text, qual_name!("class"), "selected");
+        doc.resolve(0.0);
+        assert_cached_output_matches(&doc, &mut cache, "restyling an element");
+
+        let scroller = doc.get_element_by_id("scroller").unwrap();
+        doc.scroll_node_by(scroller, 0.0, -30.0);
+        doc.resolve(0.0);
+        assert_cached_output_matches(&doc, &mut cache, "scrolling");
+
+        // Removing the paragraph moves the layers which follow it
+        doc.mutate().remove_node(text);
+        doc.resolve(0.0);
+        assert_cached_output_matches(&doc, &mut cache, "removing an element");
+    }
+
+    #[test]
+    fn test_scroll_layer_reused_within_painted_area() {
+        let html = r#"
+            <html><body style="margin: 0">
+                <div id="scroller" style="height: 100px; overflow: auto">
+                    <div style="width: 3000px; height: 80px;
+                        background: linear-gradient(to right, red, blue)"></div>
+                </div>
+            </body></html>
+        "#;
+        let viewport = Viewport::new(WIDTH, HEIGHT, 1.0, ColorScheme::Light);
+        let config = DocumentConfig {
+            viewport: Some(viewport),
+            ..Default::default()
+        };
+        let mut doc = HtmlDocument::from_html(html, config);
+        doc.resolve(0.0);
+        let scroller = doc.get_element_by_id("scroller").unwrap();
+        let owner = ScrollbarOwner::Node(scroller);
+
+        let mut cache = PaintCache::new();
+        assert_cached_output_matches(&doc, &mut cache, "the first frame");
+        let painted = cache.scroll_layer(owner).unwrap().painted;
+        assert_eq!(
+            painted.width(),
+            WIDTH as f64 * (1.0 + 2.0 * PREPAINT_MARGIN)
+        );
+
+        // Scrolling within the prepainted area replays the recorded layer
+        doc.scroll_node_by(scroller, -100.0, 0.0);
+        doc.resolve(0.0);
+        assert_cached_output_matches(&doc, &mut cache, "scrolling within the painted area");
+        assert_eq!(cache.scroll_layer(owner).unwrap().painted, painted);
+
+        // Scrolling beyond it records the layer again
+        doc.scroll_node_by(scroller, -200.0, 0.0);
+        doc.resolve(0.0);
+        assert_cached_output_matches(&doc, &mut cache, "scrolling beyond the painted area");
+        let repainted = cache.scroll_layer(owner).unwrap().painted;
+        assert_eq!(repainted, painted + Vec2::new(300.0, 0.0));
+    }
+}
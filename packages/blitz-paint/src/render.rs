@@ -1,8 +1,11 @@
 mod background;
 mod box_shadow;
 mod form_controls;
+mod retained;
+mod scrollbar;
 
 use std::any::Any;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 use super::kurbo_css::{CssBox, Edge};
@@ -11,14 +14,18 @@ use crate::color::{Color, ToColorColor};
 use crate::debug_overlay::render_debug_overlay;
 use crate::kurbo_css::NonUniformRoundedRectRadii;
 use crate::layers::LayerManager;
+use crate::paint_cache::PaintCache;
 use crate::sizing::compute_object_fit;
+use crate::text::TextOverflowMarker;
 use anyrender::{CustomPaint, Paint, PaintScene};
 use blitz_dom::node::{
     ListItemLayout, ListItemLayoutPosition, Marker, NodeData, RasterImageData, SpecialElementData,
     TextInputData, TextNodeData,
 };
+use blitz_dom::scrollbar::ScrollbarOwner;
 use blitz_dom::{BaseDocument, ElementData, Node, local_name};
 use blitz_traits::devtools::DevtoolSettings;
+use retained::ScrollLayerPaint;
 
 use style::values::computed::BorderCornerRadius;
 use style::{
@@ -30,7 +37,7 @@ use style::{
     },
     values::{
         computed::{CSSPixelLength, Overflow},
-        specified::{BorderStyle, OutlineStyle, image::ImageRendering},
+        specified::{BorderStyle, OutlineStyle, image::ImageRendering, text::TextOverflowSide},
     },
 };
 
@@ -52,6 +59,8 @@ pub struct BlitzDomPainter<'dom> {
     pub(crate) layer_manager: LayerManager,
     /// Cached selection ranges for O(1) lookup: node_id -> (start_offset, end_offset)
     pub(crate) selection_ranges: HashMap<usize, (usize, usize)>,
+    /// Drawing commands retained between frames (see [`crate::paint_scene_cached`])
+    pub(crate) cache: Option<&'dom RefCell<PaintCache>>,
 }
 
 impl<'dom> BlitzDomPainter<'dom> {
@@ -81,9 +90,16 @@ impl<'dom> BlitzDomPainter<'dom> {
             initial_y,
             layer_manager,
             selection_ranges,
+            cache: None,
         }
     }
 
+    /// Replay unchanged layers from (and record changed layers into) a paint cache
+    pub(crate) fn with_cache(mut self, cache: &'dom RefCell<PaintCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
     fn node_position(&self, node: usize, location: Point) -> (Layout, Point) {
         let layout = self.layout(node);
         let pos = location + Vec2::new(layout.location.x as f64, layout.location.y as f64);
@@ -107,7 +123,10 @@ impl<'dom> BlitzDomPainter<'dom> {
 
         // Simply render the document (the root element (note that this is not the same as the root node)))
         // scene.reset();
-        let viewport_scroll = self.dom.as_ref().viewport_scroll();
+        let viewport_scroll = self
+            .dom
+            .as_ref()
+            .visual_scroll_offset(ScrollbarOwner::Viewport);
 
         let root_element = self.dom.as_ref().root_element();
         let root_id = root_element.id;
@@ -151,18 +170,48 @@ impl<'dom> BlitzDomPainter<'dom> {
             scene.fill(Fill::NonZero, Affine::IDENTITY, bg_color, None, &rect);
         }
 
-        self.render_element(
+        let location = Point {
+            x: self.initial_x - viewport_scroll.x,
+            y: self.initial_y - viewport_scroll.y,
+        };
+        let clip = Rect::new(
+            self.initial_x,
+            self.initial_y,
+            self.initial_x + self.width as f64 / self.scale,
+            self.initial_y + self.height as f64 / self.scale,
+        );
+        match self.begin_scroll_layer(scene, ScrollbarOwner::Viewport, location, clip) {
+            ScrollLayerPaint::Replayed => {}
+            ScrollLayerPaint::Direct => self.render_element(scene, root_id, location),
+            ScrollLayerPaint::Record(layers_before) => {
+                let mut recorded = anyrender::Scene::new();
+                self.render_element(&mut recorded, root_id, location);
+                self.finish_scroll_layer(
+                    scene,
+                    ScrollbarOwner::Viewport,
+                    location,
+                    recorded,
+                    layers_before,
+                );
+            }
+        }
+
+        self.draw_scrollbars(
             scene,
-            root_id,
-            Point {
-                x: self.initial_x - viewport_scroll.x,
-                y: self.initial_y - viewport_scroll.y,
-            },
+            self.dom.viewport_scrollbars(),
+            Affine::translate((self.initial_x * self.scale, self.initial_y * self.scale)),
         );
 
         // Render debug overlay
-        if self.dom.devtools().highlight_hover {
-            if let Some(node_id) = self.dom.as_ref().get_hover_node_id() {
+        let devtools = self.dom.devtools();
+        let highlighted_node = devtools.highlighted_node.or_else(|| {
+            devtools
+                .highlight_hover
+                .then(|| self.dom.as_ref().get_hover_node_id())
+                .flatten()
+        });
+        if let Some(node_id) = highlighted_node {
+            if self.dom.as_ref().get_node(node_id).is_some() {
                 render_debug_overlay(
                     scene,
                     self.dom,
@@ -255,10 +304,18 @@ impl<'dom> BlitzDomPainter<'dom> {
             height: (size.height as f64 - scaled_pb.top - scaled_pb.bottom) * self.scale,
         };
 
-        // Don't render things that are out of view
+        // Don't render things that are out of view (allowing for content which is prepainted
+        // so that it can be scrolled into view without repainting)
+        let scaled_x = (box_position.x - self.initial_x) * self.scale;
         let scaled_y = (box_position.y - self.initial_y) * self.scale;
+        let scaled_content_width = content_size.width.max(size.width) as f64 * self.scale;
         let scaled_content_height = content_size.height.max(size.height) as f64 * self.scale;
-        if scaled_y > self.height as f64 || scaled_y + scaled_content_height < 0.0 {
+        let margin = self.prepaint_margin();
+        if scaled_x > self.width as f64 + margin.x
+            || scaled_x + scaled_content_width < -margin.x
+            || scaled_y > self.height as f64 + margin.y
+            || scaled_y + scaled_content_height < -margin.y
+        {
             return;
         }
 
@@ -270,6 +327,15 @@ impl<'dom> BlitzDomPainter<'dom> {
 
         let mut cx = self.element_cx(node, layout, box_position);
 
+        // Replay or record the element's drawing commands if it is a layer
+        let is_scroll_container =
+            !matches!(overflow_x, Overflow::Visible) || !matches!(overflow_y, Overflow::Visible);
+        let is_layer = should_clip || has_opacity || node.stacking_context.is_some();
+        let is_scroll_layer = is_scroll_container && !is_text_input && !is_sub_doc;
+        if self.paint_retained(scene, &cx, is_layer, is_scroll_container, location) {
+            return;
+        }
+
         cx.draw_outline(scene);
         cx.draw_outset_box_shadow(scene);
 
@@ -289,12 +355,26 @@ impl<'dom> BlitzDomPainter<'dom> {
                 cx.draw_border(scene);
                 cx.stroke_devtools(scene);
 
+                // Scrollbars are positioned relative to the border box, so save the transform
+                // before it is offset by the scroll position
+                let border_box_transform = cx.transform;
+                let scroll_offset = self
+                    .dom
+                    .as_ref()
+                    .visual_scroll_offset(ScrollbarOwner::Node(node_id));
+
                 // TODO: allow layers with opacity to be unclipped (overflow: visible)
                 let clip = if is_text_input {
                     &cx.frame.content_box_path()
                 } else {
                     &cx.frame.padding_box_path()
                 };
+                let clip_rect = Rect::new(
+                    cx.pos.x + border.left as f64,
+                    cx.pos.y + border.top as f64,
+                    cx.pos.x + (size.width - border.right) as f64,
+                    cx.pos.y + (size.height - border.bottom) as f64,
+                );
 
                 // Clip layer if box requires clipping. Opacity set to 1.0
                 self.layer_manager.maybe_with_layer(
@@ -306,29 +386,42 @@ impl<'dom> BlitzDomPainter<'dom> {
                     |scene| {
                         // Now that background has been drawn, offset pos and cx in order to draw our contents scrolled
                         let content_position = Point {
-                            x: content_position.x - node.scroll_offset.x,
-                            y: content_position.y - node.scroll_offset.y,
+                            x: content_position.x - scroll_offset.x,
+                            y: content_position.y - scroll_offset.y,
                         };
                         cx.pos = Point {
-                            x: cx.pos.x - node.scroll_offset.x,
-                            y: cx.pos.y - node.scroll_offset.y,
+                            x: cx.pos.x - scroll_offset.x,
+                            y: cx.pos.y - scroll_offset.y,
                         };
                         cx.transform = cx.transform.then_translate(Vec2 {
-                            x: -node.scroll_offset.x,
-                            y: -node.scroll_offset.y,
+                            x: -scroll_offset.x,
+                            y: -scroll_offset.y,
                         });
-                        cx.draw_image(scene);
-                        #[cfg(feature = "svg")]
-                        cx.draw_svg(scene);
-                        cx.draw_canvas(scene);
-                        cx.draw_sub_document(scene);
-                        cx.draw_input(scene);
-                        cx.draw_text_input_text(scene, content_position);
-                        cx.draw_inline_layout(scene, content_position);
-                        cx.draw_marker(scene, content_position);
-                        cx.draw_children(scene);
+                        if !is_scroll_layer {
+                            cx.draw_contents(scene, content_position);
+                            return;
+                        }
+
+                        let owner = ScrollbarOwner::Node(node_id);
+                        match self.begin_scroll_layer(scene, owner, cx.pos, clip_rect) {
+                            ScrollLayerPaint::Replayed => {}
+                            ScrollLayerPaint::Direct => cx.draw_contents(scene, content_position),
+                            ScrollLayerPaint::Record(layers_before) => {
+                                let mut recorded = anyrender::Scene::new();
+                                cx.draw_contents(&mut recorded, content_position);
+                                self.finish_scroll_layer(
+                                    scene,
+                                    owner,
+                                    cx.pos,
+                                    recorded,
+                                    layers_before,
+                                );
+                            }
+                        }
                     },
                 );
+
+                self.draw_scrollbars(scene, node.scrollbars(), border_box_transform);
             },
         );
     }
@@ -490,15 +583,35 @@ impl ElementCx<'_> {
             }
 
             // Render text
-            crate::text::stroke_text(
+            crate::text::stroke_inline_layout(
                 scene,
-                text_layout.layout.lines(),
+                text_layout,
                 self.context.dom,
                 transform,
+                self.text_overflow_marker(),
             );
         }
     }
 
+    /// Resolve the `text-overflow` marker which replaces content that overflows the end of this
+    /// element's lines. This only applies when the element's overflow is clipped.
+    fn text_overflow_marker(&self) -> Option<TextOverflowMarker<'_>> {
+        if matches!(self.style.get_box().overflow_x, Overflow::Visible) {
+            return None;
+        }
+
+        let marker = match &self.style.get_text().text_overflow.second {
+            TextOverflowSide::Clip => return None,
+            TextOverflowSide::Ellipsis => "\u{2026}",
+            TextOverflowSide::String(marker) => &**marker,
+        };
+
+        Some(TextOverflowMarker {
+            marker,
+            max_advance: self.frame.content_box.width() as f32,
+        })
+    }
+
     fn draw_text_input_text(&self, scene: &mut impl PaintScene, pos: Point) {
         // Render the text in text inputs
         if let Some(input_data) = self.text_input {
@@ -590,6 +703,20 @@ impl ElementCx<'_> {
         }
     }
 
+    /// Draw the element's replaced content, text and children (offset by its scroll position)
+    fn draw_contents(&self, scene: &mut impl PaintScene, content_position: Point) {
+        self.draw_image(scene);
+        #[cfg(feature = "svg")]
+        self.draw_svg(scene);
+        self.draw_canvas(scene);
+        self.draw_sub_document(scene);
+        self.draw_input(scene);
+        self.draw_text_input_text(scene, content_position);
+        self.draw_inline_layout(scene, content_position);
+        self.draw_marker(scene, content_position);
+        self.draw_children(scene);
+    }
+
     fn draw_children(&self, scene: &mut impl PaintScene) {
         // Negative z_index hoisted nodes
         if let Some(hoisted) = &self.node.stacking_context {
@@ -83,8 +83,10 @@ impl ElementCx<'_> {
                             warn!("Implement background drawing for Image::CrossFade")
                         }
                         ImageSet(_) => {
-                            #[cfg(feature = "tracing")]
-                            warn!("Implement background drawing for Image::ImageSet")
+                            // The candidate is selected when styles are flushed to the node
+                            self.draw_raster_bg_image(scene, idx);
+                            #[cfg(feature = "svg")]
+                            self.draw_svg_bg_image(scene, idx);
                         }
                     }
                 },
@@ -170,7 +172,10 @@ impl ElementCx<'_> {
             frame_w,
             frame_h,
             idx,
-            BackgroundSizeComputeMode::Size(svg_size.width(), svg_size.height()),
+            BackgroundSizeComputeMode::Size(
+                svg_size.width() / bg_image.density as f32,
+                svg_size.height() / bg_image.density as f32,
+            ),
         );
 
         let x_ratio = (bg_size.width / svg_size.width() as f64) * self.scale;
@@ -224,7 +229,11 @@ impl ElementCx<'_> {
             origin_rect.width() / self.scale,
             origin_rect.height() / self.scale,
             idx,
-            BackgroundSizeComputeMode::Size(image_width as f32, image_height as f32),
+            // The natural size of the image is its size in image pixels divided by its density
+            BackgroundSizeComputeMode::Size(
+                (image_width / bg_image.density) as f32,
+                (image_height / bg_image.density) as f32,
+            ),
         );
 
         let bg_pos_x = bg_pos.x * self.scale;
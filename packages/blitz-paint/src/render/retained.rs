@@ -0,0 +1,330 @@
+use super::{BlitzDomPainter, ElementCx};
+use crate::paint_cache::{Fragment, PREPAINT_MARGIN, Recording, ScrollLayer};
+use anyrender::PaintScene;
+use blitz_dom::scrollbar::ScrollbarOwner;
+use kurbo::{Affine, Point, Rect, Vec2};
+
+/// How the contents of a scroll container (or of the viewport) should be painted
+pub(super) enum ScrollLayerPaint {
+    /// The contents were replayed from the paint cache
+    Replayed,
+    /// The contents should be painted into a new scene, which is then passed to
+    /// [`BlitzDomPainter::finish_scroll_layer`] along with the number of layers used beforehand
+    Record(u32),
+    /// The contents should be painted directly (as there is no paint cache)
+    Direct,
+}
+
+impl BlitzDomPainter<'_> {
+    /// Paint an element using the paint cache (if there is one). Returns `true` if the element
+    /// was painted (by replaying or recording a fragment), or `false` if it should be painted
+    /// directly.
+    pub(super) fn paint_retained(
+        &self,
+        scene: &mut impl PaintScene,
+        cx: &ElementCx,
+        is_layer: bool,
+        is_scroll_container: bool,
+        location: Point,
+    ) -> bool {
+        let Some(cache) = self.cache else {
+            return false;
+        };
+        let node_id = cx.node.id;
+
+        // We are being called to record this element's fragment
+        if cache
+            .borrow()
+            .frame
+            .recording
+            .last()
+            .is_some_and(|recording| recording.node_id == node_id && !recording.is_scroll_layer)
+        {
+            return false;
+        }
+
+        let mut cache_mut = cache.borrow_mut();
+
+        // Content which changes outside of the document must be repainted every frame
+        let is_volatile = cx.element.canvas_data().is_some() || cx.element.sub_doc_data().is_some();
+        if is_volatile {
+            cache_mut.mark_changed(node_id);
+            for recording in &mut cache_mut.frame.recording {
+                recording.volatile = true;
+            }
+        }
+
+        let mut scrolled = false;
+        if is_scroll_container {
+            let offset = self.dom.visual_scroll_offset(ScrollbarOwner::Node(node_id));
+            scrolled = cache_mut.update_scroll_offset(node_id, Point::new(offset.x, offset.y));
+            if scrolled {
+                cache_mut.mark_changed(node_id);
+            }
+        }
+
+        let bounds = cx.paint_bounds();
+        cache_mut.update_bounds(node_id, bounds);
+
+        if !is_layer {
+            return false;
+        }
+
+        // Replay the fragment recorded in a previous frame
+        let layout = &cx.node.final_layout;
+        let reusable = !scrolled
+            && !cache_mut.frame.dirty.contains(&node_id)
+            && cache_mut.fragment(node_id).is_some_and(|fragment| {
+                fragment.size == layout.size
+                    && fragment.content_size == layout.content_size
+                    && fragment.scroll_offsets.iter().all(|(id, offset)| {
+                        let current = self.dom.visual_scroll_offset(ScrollbarOwner::Node(*id));
+                        *offset == Point::new(current.x, current.y)
+                    })
+            });
+        if reusable {
+            let fragment = cache_mut.fragment(node_id).unwrap();
+            let translation = (cx.pos - fragment.origin) * self.scale;
+            scene.append_scene(fragment.scene.clone(), Affine::translate(translation));
+            self.layer_manager.add_replayed_layers(fragment.layers);
+
+            let scroll_offsets = fragment.scroll_offsets.clone();
+            for recording in &mut cache_mut.frame.recording {
+                recording.scroll_offsets.extend(&scroll_offsets);
+            }
+            return true;
+        }
+
+        // Only record fragments which are entirely within the viewport (as content outside of
+        // the viewport isn't painted), and which don't change outside of the document
+        let margin = self.prepaint_margin();
+        let painted = Rect::new(
+            self.initial_x * self.scale - margin.x,
+            self.initial_y * self.scale - margin.y,
+            self.initial_x * self.scale + self.width as f64 + margin.x,
+            self.initial_y * self.scale + self.height as f64 + margin.y,
+        );
+        let is_in_view = bounds.x0 >= painted.x0
+            && bounds.y0 >= painted.y0
+            && bounds.x1 <= painted.x1
+            && bounds.y1 <= painted.y1;
+        if !is_in_view || is_volatile {
+            cache_mut.remove_fragment(node_id);
+            return false;
+        }
+
+        cache_mut.frame.recording.push(Recording {
+            node_id,
+            scroll_offsets: Vec::new(),
+            volatile: false,
+            is_scroll_layer: false,
+        });
+        drop(cache_mut);
+
+        let layers_before = self.layer_manager.layers_used();
+        let mut recorded = anyrender::Scene::new();
+        self.render_element(&mut recorded, node_id, location);
+        let layers = self.layer_manager.layers_used() - layers_before;
+
+        let mut cache_mut = cache.borrow_mut();
+        let recording = cache_mut.finish_recording();
+
+        scene.append_scene(recorded.clone(), Affine::IDENTITY);
+        if recording.volatile {
+            cache_mut.remove_fragment(node_id);
+        } else {
+            cache_mut.insert_fragment(
+                node_id,
+                Fragment {
+                    scene: recorded,
+                    origin: cx.pos,
+                    size: layout.size,
+                    content_size: layout.content_size,
+                    layers,
+                    scroll_offsets: recording.scroll_offsets,
+                },
+            );
+        }
+
+        true
+    }
+
+    /// The distance (in device pixels) beyond each side of the viewport which is painted
+    pub(super) fn prepaint_margin(&self) -> Vec2 {
+        match self.cache {
+            Some(_) => Vec2::new(self.width as f64, self.height as f64) * PREPAINT_MARGIN,
+            None => Vec2::ZERO,
+        }
+    }
+
+    /// Start painting the contents of a scroll container (or of the viewport), whose scrolled
+    /// content box is positioned at `origin`. `clip` is the area of the document (in CSS pixels)
+    /// within which the contents are visible.
+    ///
+    /// The contents are replayed from the paint cache if nothing within them changed, and the
+    /// visible area is within the area which was painted when they were recorded.
+    pub(super) fn begin_scroll_layer(
+        &self,
+        scene: &mut impl PaintScene,
+        owner: ScrollbarOwner,
+        origin: Point,
+        clip: Rect,
+    ) -> ScrollLayerPaint {
+        let Some(cache) = self.cache else {
+            return ScrollLayerPaint::Direct;
+        };
+        let mut cache_mut = cache.borrow_mut();
+
+        let node = match owner {
+            ScrollbarOwner::Viewport => self.dom.root_element(),
+            ScrollbarOwner::Node(node_id) => &self.dom.as_ref().tree()[node_id],
+        };
+        let viewport = Rect::new(
+            self.initial_x,
+            self.initial_y,
+            self.initial_x + self.width as f64 / self.scale,
+            self.initial_y + self.height as f64 / self.scale,
+        );
+        let visible = clip.intersect(viewport);
+
+        let layout = &node.final_layout;
+        let reusable = !cache_mut.frame.dirty.contains(&node.id)
+            && cache_mut.scroll_layer(owner).is_some_and(|layer| {
+                let fragment = &layer.fragment;
+                let painted = layer.painted;
+                let visible = visible - origin.to_vec2();
+                painted.x0 <= visible.x0
+                    && painted.y0 <= visible.y0
+                    && painted.x1 >= visible.x1
+                    && painted.y1 >= visible.y1
+                    && fragment.size == layout.size
+                    && fragment.content_size == layout.content_size
+                    && fragment.scroll_offsets.iter().all(|(id, offset)| {
+                        let current = self.dom.visual_scroll_offset(ScrollbarOwner::Node(*id));
+                        *offset == Point::new(current.x, current.y)
+                    })
+            });
+        if reusable {
+            let layer = cache_mut.scroll_layer(owner).unwrap();
+            let translation = (origin - layer.fragment.origin) * self.scale;
+            scene.append_scene(layer.fragment.scene.clone(), Affine::translate(translation));
+            self.layer_manager
+                .add_replayed_layers(layer.fragment.layers);
+
+            let scroll_offsets = layer.fragment.scroll_offsets.clone();
+            for recording in &mut cache_mut.frame.recording {
+                recording.scroll_offsets.extend(&scroll_offsets);
+            }
+            if translation != Vec2::ZERO {
+                cache_mut.add_dirty_rect(Rect::new(
+                    visible.x0 * self.scale,
+                    visible.y0 * self.scale,
+                    visible.x1 * self.scale,
+                    visible.y1 * self.scale,
+                ));
+            }
+            return ScrollLayerPaint::Replayed;
+        }
+
+        cache_mut.frame.recording.push(Recording {
+            node_id: node.id,
+            scroll_offsets: Vec::new(),
+            volatile: false,
+            is_scroll_layer: true,
+        });
+        ScrollLayerPaint::Record(self.layer_manager.layers_used())
+    }
+
+    /// Finish painting the contents of a scroll container (or of the viewport) which were recorded
+    /// into `recorded`, retaining them in the paint cache
+    pub(super) fn finish_scroll_layer(
+        &self,
+        scene: &mut impl PaintScene,
+        owner: ScrollbarOwner,
+        origin: Point,
+        recorded: anyrender::Scene,
+        layers_before: u32,
+    ) {
+        let layers = self.layer_manager.layers_used() - layers_before;
+        scene.append_scene(recorded.clone(), Affine::IDENTITY);
+
+        let Some(cache) = self.cache else {
+            return;
+        };
+        let mut cache_mut = cache.borrow_mut();
+        let recording = cache_mut.finish_recording();
+        if recording.volatile {
+            cache_mut.remove_scroll_layer(owner);
+            return;
+        }
+
+        // Elements are painted if they are within the prepaint margin of the viewport
+        let margin = self.prepaint_margin() / self.scale;
+        let layout = &self.dom.as_ref().tree()[recording.node_id].final_layout;
+        cache_mut.insert_scroll_layer(
+            owner,
+            ScrollLayer {
+                fragment: Fragment {
+                    scene: recorded,
+                    origin,
+                    size: layout.size,
+                    content_size: layout.content_size,
+                    layers,
+                    scroll_offsets: recording.scroll_offsets,
+                },
+                painted: Rect::new(
+                    self.initial_x - margin.x,
+                    self.initial_y - margin.y,
+                    self.initial_x + self.width as f64 / self.scale + margin.x,
+                    self.initial_y + self.height as f64 / self.scale + margin.y,
+                ) - origin.to_vec2(),
+            },
+        );
+    }
+}
+
+impl ElementCx<'_> {
+    /// The area (in device pixels) which may be painted by the element and its descendants
+    fn paint_bounds(&self) -> Rect {
+        let layout = &self.node.final_layout;
+        let scale = self.scale;
+
+        let content = Rect::new(
+            0.0,
+            0.0,
+            layout.content_size.width as f64 * scale,
+            layout.content_size.height as f64 * scale,
+        );
+        let mut bounds = self.frame.outline_box.union(content);
+
+        if let Some(hoisted) = &self.node.stacking_context {
+            let area = hoisted.content_area;
+            bounds = bounds.union(Rect::new(
+                area.left as f64 * scale,
+                area.top as f64 * scale,
+                area.right as f64 * scale,
+                area.bottom as f64 * scale,
+            ));
+        }
+
+        let shadow_extent = self
+            .style
+            .get_effects()
+            .box_shadow
+            .0
+            .iter()
+            .filter(|shadow| !shadow.inset)
+            .map(|shadow| {
+                let x = shadow.base.horizontal.px().abs() as f64;
+                let y = shadow.base.vertical.px().abs() as f64;
+                let blur = shadow.base.blur.px() as f64;
+                let spread = shadow.spread.px().max(0.0) as f64;
+                (x.max(y) + spread + blur * 2.5) * scale
+            })
+            .fold(0.0, f64::max);
+
+        // Glyphs may extend slightly outside of their line boxes
+        let bounds = bounds.inflate(shadow_extent + scale, shadow_extent + scale);
+        self.transform.transform_rect_bbox(bounds)
+    }
+}
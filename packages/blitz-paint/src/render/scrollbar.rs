@@ -0,0 +1,50 @@
+use super::BlitzDomPainter;
+use crate::color::Color;
+use anyrender::PaintScene;
+use blitz_dom::scrollbar::{Scrollbar, ScrollbarMode};
+use kurbo::{Affine, RoundedRect};
+use peniko::Fill;
+
+const DEFAULT_THUMB_COLOR: Color = Color::from_rgba8(0, 0, 0, 115);
+const DEFAULT_TRACK_COLOR: Color = Color::from_rgba8(0, 0, 0, 13);
+/// The gap between the thumb and the edges of the track (in CSS pixels)
+const THUMB_INSET: f64 = 2.0;
+
+impl BlitzDomPainter<'_> {
+    /// Draw the scrollbars of a scroll container. `transform` maps the scrollbars' coordinate
+    /// space (the owner's border box, scaled to device pixels) into the scene.
+    pub(super) fn draw_scrollbars(
+        &self,
+        scene: &mut impl PaintScene,
+        scrollbars: [Option<Scrollbar>; 2],
+        transform: Affine,
+    ) {
+        if scrollbars.iter().all(Option::is_none) {
+            return;
+        }
+
+        let opacity = self.dom.scrollbar_opacity();
+        if opacity <= 0.0 {
+            return;
+        }
+
+        let thumb_color = DEFAULT_THUMB_COLOR.multiply_alpha(opacity);
+        let track_color = DEFAULT_TRACK_COLOR.multiply_alpha(opacity);
+
+        for scrollbar in scrollbars.into_iter().flatten() {
+            // Overlay scrollbars don't have a visible track
+            if self.dom.scrollbar_mode() == ScrollbarMode::Classic {
+                let track = scrollbar.track.scale_from_origin(self.scale);
+                scene.fill(Fill::NonZero, transform, track_color, None, &track);
+            }
+
+            let thumb = scrollbar
+                .thumb
+                .inset(-THUMB_INSET)
+                .scale_from_origin(self.scale);
+            let radius = thumb.width().min(thumb.height()) / 2.0;
+            let thumb = RoundedRect::from_rect(thumb, radius);
+            scene.fill(Fill::NonZero, transform, thumb_color, None, &thumb);
+        }
+    }
+}
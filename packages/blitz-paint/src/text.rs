@@ -1,12 +1,18 @@
 use anyrender::PaintScene;
-use blitz_dom::{BaseDocument, node::TextBrush, util::ToColorColor};
+use blitz_dom::node::{TextBrush, TextLayout};
+use blitz_dom::{BaseDocument, util::ToColorColor};
 use kurbo::{Affine, Stroke};
-use parley::{Affinity, Cursor, Layout, Line, PositionedLayoutItem, Selection};
+use parley::{Affinity, Cursor, Glyph, GlyphRun, Layout, Line, PositionedLayoutItem, Selection};
 use peniko::Fill;
+use skrifa::instance::{LocationRef, NormalizedCoord, Size};
+use skrifa::{FontRef, GlyphId, MetadataProvider as _};
 use style::values::computed::TextDecorationLine;
 
 use crate::SELECTION_COLOR;
 
+/// U+00AD SOFT HYPHEN
+const SOFT_HYPHEN: char = '\u{00AD}';
+
 pub(crate) fn stroke_text<'a>(
     scene: &mut impl PaintScene,
     lines: impl Iterator<Item = Line<'a, TextBrush>>,
@@ -16,80 +22,279 @@ pub(crate) fn stroke_text<'a>(
     for line in lines {
         for item in line.items() {
             if let PositionedLayoutItem::GlyphRun(glyph_run) = item {
-                let run = glyph_run.run();
-                let font = run.font();
-                let font_size = run.font_size();
-                let metrics = run.metrics();
-                let style = glyph_run.style();
-                let synthesis = run.synthesis();
-                let glyph_xform = synthesis
-                    .skew()
-                    .map(|angle| Affine::skew(angle.to_radians().tan() as f64, 0.0));
-
-                // Styles
-                let styles = doc
-                    .get_node(style.brush.id)
-                    .unwrap()
-                    .primary_styles()
-                    .unwrap();
-                let itext_styles = styles.get_inherited_text();
-                let text_styles = styles.get_text();
-                let text_color = itext_styles.color.as_color_color();
-                let text_decoration_color = text_styles
-                    .text_decoration_color
-                    .as_absolute()
-                    .map(ToColorColor::as_color_color)
-                    .unwrap_or(text_color);
-                let text_decoration_brush = anyrender::Paint::from(text_decoration_color);
-                let text_decoration_line = text_styles.text_decoration_line;
-                let has_underline = text_decoration_line.contains(TextDecorationLine::UNDERLINE);
-                let has_strikethrough =
-                    text_decoration_line.contains(TextDecorationLine::LINE_THROUGH);
-
-                scene.draw_glyphs(
-                    font,
-                    font_size,
-                    true, // hint
-                    run.normalized_coords(),
-                    Fill::NonZero,
-                    &anyrender::Paint::from(text_color),
-                    1.0, // alpha
-                    transform,
-                    glyph_xform,
-                    glyph_run.positioned_glyphs().map(|glyph| anyrender::Glyph {
-                        id: glyph.id as _,
-                        x: glyph.x,
-                        y: glyph.y,
-                    }),
-                );
-
-                let mut draw_decoration_line =
-                    |offset: f32, size: f32, brush: &anyrender::Paint| {
-                        let x = glyph_run.offset() as f64;
-                        let w = glyph_run.advance() as f64;
-                        let y = (glyph_run.baseline() - offset + size / 2.0) as f64;
-                        let line = kurbo::Line::new((x, y), (x + w, y));
-                        scene.stroke(&Stroke::new(size as f64), transform, brush, None, &line)
-                    };
-
-                if has_underline {
-                    let offset = metrics.underline_offset;
-                    let size = metrics.underline_size;
-
-                    // TODO: intercept line when crossing an descending character like "gqy"
-                    draw_decoration_line(offset, size, &text_decoration_brush);
-                }
-                if has_strikethrough {
-                    let offset = metrics.strikethrough_offset;
-                    let size = metrics.strikethrough_size;
+                stroke_glyph_run(scene, &glyph_run, doc, transform, None);
+            }
+        }
+    }
+}
+
+/// A marker (e.g. an ellipsis) to render in place of content which overflows the end of a line.
+/// Implements the `text-overflow` property.
+#[derive(Clone, Copy)]
+pub(crate) struct TextOverflowMarker<'a> {
+    /// The string to render at the end of overflowing lines
+    pub(crate) marker: &'a str,
+    /// The advance beyond which content is considered overflowing (in scaled pixels)
+    pub(crate) max_advance: f32,
+}
 
-                    draw_decoration_line(offset, size, &text_decoration_brush);
+/// Stroke the lines of an inline formatting context. In addition to what [`stroke_text`] does, this:
+///   - Renders a hyphen at the end of lines that were broken at a soft hyphen
+///   - Truncates lines that overflow and renders a `text-overflow` marker in their place
+pub(crate) fn stroke_inline_layout(
+    scene: &mut impl PaintScene,
+    text_layout: &TextLayout,
+    doc: &BaseDocument,
+    transform: Affine,
+    text_overflow: Option<TextOverflowMarker<'_>>,
+) {
+    for line in text_layout.layout.lines() {
+        let metrics = line.metrics();
+        let content_end = metrics.offset + metrics.advance - metrics.trailing_whitespace;
+
+        // Lines which fit within the container
+        let overflow = text_overflow.filter(|overflow| content_end > overflow.max_advance + 0.5);
+        let Some(overflow) = overflow else {
+            stroke_text(scene, std::iter::once(line), doc, transform);
+
+            let text_range = line.text_range();
+            let line_text = &text_layout.text[..text_range.end];
+            if line_text.ends_with(SOFT_HYPHEN) {
+                // Render a hyphen in the font of the last glyph run of the line
+                let last_run = line.items().filter_map(|item| match item {
+                    PositionedLayoutItem::GlyphRun(glyph_run) => Some(glyph_run),
+                    PositionedLayoutItem::InlineBox(_) => None,
+                });
+                if let Some(glyph_run) = last_run.last() {
+                    let x = glyph_run.offset() + glyph_run.advance();
+                    stroke_string(scene, &glyph_run, doc, transform, x, &['\u{2010}', '-']);
                 }
             }
+            continue;
+        };
+
+        // Lines which overflow the container: the marker is rendered using the font of the first glyph run
+        let Some(first_run) = line.items().find_map(|item| match item {
+            PositionedLayoutItem::GlyphRun(glyph_run) => Some(glyph_run),
+            PositionedLayoutItem::InlineBox(_) => None,
+        }) else {
+            stroke_text(scene, std::iter::once(line), doc, transform);
+            continue;
+        };
+
+        let marker_glyphs = shape_string(&first_run, overflow.marker);
+        let marker_width: f32 = marker_glyphs.iter().map(|(_, advance)| advance).sum();
+        let max_x = overflow.max_advance - marker_width;
+
+        let mut marker_x = metrics.offset;
+        for item in line.items() {
+            if let PositionedLayoutItem::GlyphRun(glyph_run) = item {
+                marker_x = marker_x.max(stroke_glyph_run(
+                    scene,
+                    &glyph_run,
+                    doc,
+                    transform,
+                    Some(max_x),
+                ));
+            }
+        }
+
+        draw_glyphs(scene, &first_run, doc, transform, marker_x, &marker_glyphs);
+    }
+}
+
+/// Stroke a glyph run (including text decorations), optionally omitting glyphs that extend past `max_x`.
+/// Returns the x coordinate of the end of the last glyph that was rendered.
+fn stroke_glyph_run(
+    scene: &mut impl PaintScene,
+    glyph_run: &GlyphRun<'_, TextBrush>,
+    doc: &BaseDocument,
+    transform: Affine,
+    max_x: Option<f32>,
+) -> f32 {
+    let run = glyph_run.run();
+    let font = run.font();
+    let font_size = run.font_size();
+    let metrics = run.metrics();
+    let style = glyph_run.style();
+    let synthesis = run.synthesis();
+    let glyph_xform = synthesis
+        .skew()
+        .map(|angle| Affine::skew(angle.to_radians().tan() as f64, 0.0));
+
+    // Styles
+    let styles = doc
+        .get_node(style.brush.id)
+        .unwrap()
+        .primary_styles()
+        .unwrap();
+    let itext_styles = styles.get_inherited_text();
+    let text_styles = styles.get_text();
+    let text_color = itext_styles.color.as_color_color();
+    let text_decoration_color = text_styles
+        .text_decoration_color
+        .as_absolute()
+        .map(ToColorColor::as_color_color)
+        .unwrap_or(text_color);
+    let text_decoration_brush = anyrender::Paint::from(text_decoration_color);
+    let text_decoration_line = text_styles.text_decoration_line;
+    let has_underline = text_decoration_line.contains(TextDecorationLine::UNDERLINE);
+    let has_strikethrough = text_decoration_line.contains(TextDecorationLine::LINE_THROUGH);
+
+    let start_x = glyph_run.offset();
+    let fits = |glyph: &Glyph| max_x.is_none_or(|max_x| glyph.x + glyph.advance <= max_x);
+    let end_x = match max_x {
+        // When not truncating, decorations span the full advance of the run (including spaces)
+        None => start_x + glyph_run.advance(),
+        Some(_) => glyph_run
+            .positioned_glyphs()
+            .filter(fits)
+            .map(|glyph| glyph.x + glyph.advance)
+            .fold(start_x, f32::max),
+    };
+
+    scene.draw_glyphs(
+        font,
+        font_size,
+        true, // hint
+        run.normalized_coords(),
+        Fill::NonZero,
+        &anyrender::Paint::from(text_color),
+        1.0, // alpha
+        transform,
+        glyph_xform,
+        glyph_run
+            .positioned_glyphs()
+            .filter(fits)
+            .map(|glyph| anyrender::Glyph {
+                id: glyph.id as _,
+                x: glyph.x,
+                y: glyph.y,
+            }),
+    );
+
+    let mut draw_decoration_line = |offset: f32, size: f32, brush: &anyrender::Paint| {
+        let x = start_x as f64;
+        let w = (end_x - start_x) as f64;
+        let y = (glyph_run.baseline() - offset + size / 2.0) as f64;
+        let line = kurbo::Line::new((x, y), (x + w, y));
+        scene.stroke(&Stroke::new(size as f64), transform, brush, None, &line)
+    };
+
+    if has_underline {
+        let offset = metrics.underline_offset;
+        let size = metrics.underline_size;
+
+        // TODO: intercept line when crossing an descending character like "gqy"
+        draw_decoration_line(offset, size, &text_decoration_brush);
+    }
+    if has_strikethrough {
+        let offset = metrics.strikethrough_offset;
+        let size = metrics.strikethrough_size;
+
+        draw_decoration_line(offset, size, &text_decoration_brush);
+    }
+
+    end_x
+}
+
+/// Map a string to glyphs (and their advances) using the font of a glyph run.
+///
+/// This does not perform shaping, so it is only suitable for short strings such as
+/// an ellipsis or hyphen. Characters that are not present in the font are skipped.
+fn shape_string(glyph_run: &GlyphRun<'_, TextBrush>, text: &str) -> Vec<(GlyphId, f32)> {
+    let run = glyph_run.run();
+    let font = run.font();
+    let Ok(font_ref) = FontRef::from_index(font.data.as_ref(), font.index) else {
+        return Vec::new();
+    };
+
+    let coords: Vec<NormalizedCoord> = run
+        .normalized_coords()
+        .iter()
+        .map(|coord| NormalizedCoord::from_bits(*coord))
+        .collect();
+    let glyph_metrics =
+        font_ref.glyph_metrics(Size::new(run.font_size()), LocationRef::new(&coords));
+    let charmap = font_ref.charmap();
+
+    // Fallback to three full stops if the font does not contain an ellipsis character
+    let text = if text == "\u{2026}" && charmap.map('\u{2026}').is_none() {
+        "..."
+    } else {
+        text
+    };
+
+    text.chars()
+        .filter_map(|c| charmap.map(c))
+        .map(|glyph_id| {
+            (
+                glyph_id,
+                glyph_metrics.advance_width(glyph_id).unwrap_or(0.0),
+            )
+        })
+        .collect()
+}
+
+/// Render the first character in `candidates` which is supported by the glyph run's font at the position `x`
+fn stroke_string(
+    scene: &mut impl PaintScene,
+    glyph_run: &GlyphRun<'_, TextBrush>,
+    doc: &BaseDocument,
+    transform: Affine,
+    x: f32,
+    candidates: &[char],
+) {
+    for c in candidates {
+        let glyphs = shape_string(glyph_run, c.encode_utf8(&mut [0; 4]));
+        if !glyphs.is_empty() {
+            draw_glyphs(scene, glyph_run, doc, transform, x, &glyphs);
+            return;
         }
     }
 }
 
+/// Draw a sequence of glyphs using the font and styles of a glyph run, starting at position `x` on the run's baseline
+fn draw_glyphs(
+    scene: &mut impl PaintScene,
+    glyph_run: &GlyphRun<'_, TextBrush>,
+    doc: &BaseDocument,
+    transform: Affine,
+    x: f32,
+    glyphs: &[(GlyphId, f32)],
+) {
+    let run = glyph_run.run();
+    let styles = doc
+        .get_node(glyph_run.style().brush.id)
+        .unwrap()
+        .primary_styles()
+        .unwrap();
+    let text_color = styles.get_inherited_text().color.as_color_color();
+
+    let y = glyph_run.baseline();
+    let mut x = x;
+    scene.draw_glyphs(
+        run.font(),
+        run.font_size(),
+        true, // hint
+        run.normalized_coords(),
+        Fill::NonZero,
+        &anyrender::Paint::from(text_color),
+        1.0, // alpha
+        transform,
+        None,
+        glyphs.iter().map(|(glyph_id, advance)| {
+            let glyph = anyrender::Glyph {
+                id: glyph_id.to_u32(),
+                x,
+                y,
+            };
+            x += advance;
+            glyph
+        }),
+    );
+}
+
 /// Draw selection highlight rectangles for the given byte range in a layout.
 /// Uses Parley's Selection type for accurate geometry calculation.
 pub(crate) fn draw_text_selection(
@@ -108,3 +313,59 @@ pub(crate) fn draw_text_selection(
         scene.fill(Fill::NonZero, transform, SELECTION_COLOR, None, &rect);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use anyrender::recording::{RenderCommand, Scene};
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use crate::paint_scene;
+
+    /// The x positions of the glyphs in each glyph run painted for a document
+    fn painted_glyph_runs(html: &str) -> Vec<Vec<f32>> {
+        let config = DocumentConfig {
+            viewport: Some(Viewport::new(400, 300, 1.0, ColorScheme::Light)),
+            ..Default::default()
+        };
+        let mut doc = HtmlDocument::from_html(html, config);
+        doc.resolve(0.0);
+
+        let mut scene = Scene::new();
+        paint_scene(&mut scene, &doc, 1.0, 400, 300, 0, 0);
+        scene
+            .commands
+            .into_iter()
+            .filter_map(|command| match command {
+                RenderCommand::GlyphRun(run) => Some(run.glyphs.iter().map(|g| g.x).collect()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_text_overflow_ellipsis() {
+        let html = |text_overflow: &str| {
+            format!(
+                r#"<div style="width: 100px; overflow: hidden; white-space: nowrap; text-overflow: {text_overflow}">
+                    Some text which overflows its container
+                </div>"#
+            )
+        };
+        let clipped = painted_glyph_runs(&html("clip"));
+        let truncated = painted_glyph_runs(&html("ellipsis"));
+
+        // Clipped lines are painted in full, whereas truncated lines are painted up to the marker
+        assert_eq!(clipped.len(), 1);
+        assert_eq!(truncated.len(), 2);
+        assert!(truncated[0].len() < clipped[0].len());
+
+        // The marker is painted within the content box, after the truncated content
+        let [marker_x] = truncated[1][..] else {
+            panic!("expected a single marker glyph, got {:?}", truncated[1]);
+        };
+        assert!(marker_x < 100.0);
+        assert!(marker_x > *truncated[0].last().unwrap());
+    }
+}
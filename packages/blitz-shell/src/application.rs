@@ -55,6 +55,11 @@ impl<Rend: WindowRenderer> BlitzApplication<Rend> {
                     window.request_redraw();
                 }
             }
+            BlitzShellEvent::FrameReady { window_id } => {
+                if let Some(window) = self.windows.get_mut(&window_id) {
+                    window.frame_ready();
+                }
+            }
 
             #[cfg(feature = "accessibility")]
             BlitzShellEvent::Accessibility { window_id, data } => {
@@ -72,6 +77,11 @@ impl<Rend: WindowRenderer> BlitzApplication<Rend> {
                     }
                 }
             }
+            BlitzShellEvent::ShowContextMenu { window_id, menu } => {
+                if let Some(window) = self.windows.get_mut(&window_id) {
+                    window.show_context_menu(*menu);
+                }
+            }
             BlitzShellEvent::Embedder(_) => {
                 // Do nothing. Should be handled by embedders (if required).
             }
@@ -0,0 +1,184 @@
+//! Context menus rendered as an overlay document on top of a window's document
+
+use anyrender::PaintScene;
+use blitz_dom::{Attribute, BaseDocument, DocumentConfig, qual_name};
+use blitz_paint::paint_scene;
+use blitz_traits::shell::{ContextMenu, ContextMenuCommand, ContextMenuItem, Viewport};
+
+const CONTEXT_MENU_CSS: &str = r#"
+html, body { margin: 0; padding: 0; width: 100%; height: 100%; }
+.menu {
+    position: absolute;
+    min-width: 180px;
+    padding: 4px 0;
+    background: #fff;
+    color: #222;
+    border: 1px solid #c8c8c8;
+    border-radius: 6px;
+    box-shadow: 0 4px 12px rgba(0, 0, 0, 0.2);
+    font-family: system-ui, sans-serif;
+    font-size: 13px;
+}
+.item { padding: 4px 16px; white-space: nowrap; cursor: default; }
+.item:hover { background: #2f6fde; color: #fff; }
+.item.disabled, .item.disabled:hover { background: transparent; color: #a0a0a0; }
+.separator { height: 1px; margin: 4px 0; background: #e0e0e0; }
+@media (prefers-color-scheme: dark) {
+    .menu { background: #2b2b2b; color: #eee; border-color: #444; }
+    .item.disabled, .item.disabled:hover { color: #777; }
+    .separator { background: #444; }
+}
+"#;
+
+/// Distance (in CSS pixels) the menu is kept from the edges of the viewport
+const EDGE_MARGIN: f32 = 4.0;
+
+/// A context menu shown by the shell in response to [`ShellProvider::show_context_menu`]
+///
+/// [`ShellProvider::show_context_menu`]: blitz_traits::shell::ShellProvider::show_context_menu
+pub(crate) struct ContextMenuOverlay {
+    doc: BaseDocument,
+    /// The element containing the menu items
+    menu_id: usize,
+    /// The enabled items of the open menu (if any)
+    items: Option<Vec<(usize, ContextMenuCommand)>>,
+}
+
+impl ContextMenuOverlay {
+    pub(crate) fn new() -> Self {
+        let mut doc = BaseDocument::new(DocumentConfig::default());
+        doc.add_user_agent_stylesheet(CONTEXT_MENU_CSS);
+
+        let mut mutr = doc.mutate();
+        let html_id = mutr.create_element(qual_name!("html", html), vec![]);
+        let body_id = mutr.create_element(qual_name!("body", html), vec![]);
+        let menu_id = mutr.create_element(qual_name!("div", html), vec![class_attr("menu")]);
+        mutr.append_children(mutr.doc.root_node().id, &[html_id]);
+        mutr.append_children(html_id, &[body_id]);
+        mutr.append_children(body_id, &[menu_id]);
+        mutr.set_style_property(menu_id, "display", "none");
+        drop(mutr);
+
+        Self {
+            doc,
+            menu_id,
+            items: None,
+        }
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.items.is_some()
+    }
+
+    /// Show `menu` (replacing any menu which is already open)
+    pub(crate) fn show(&mut self, menu: ContextMenu, viewport: Viewport) {
+        self.doc.set_viewport(viewport);
+
+        let menu_id = self.menu_id;
+        let mut items = Vec::new();
+        let mut mutr = self.doc.mutate();
+        mutr.remove_and_drop_all_children(menu_id);
+        for item in menu.items {
+            let item_id = match item {
+                ContextMenuItem::Command { command, enabled } => {
+                    let class = if enabled { "item" } else { "item disabled" };
+                    let item_id =
+                        mutr.create_element(qual_name!("div", html), vec![class_attr(class)]);
+                    let text_id = mutr.create_text_node(command.label());
+                    mutr.append_children(item_id, &[text_id]);
+                    if enabled {
+                        items.push((item_id, command));
+                    }
+                    item_id
+                }
+                ContextMenuItem::Separator => {
+                    mutr.create_element(qual_name!("div", html), vec![class_attr("separator")])
+                }
+            };
+            mutr.append_children(menu_id, &[item_id]);
+        }
+        mutr.set_style_property(menu_id, "display", "block");
+        mutr.set_style_property(menu_id, "left", &format!("{}px", menu.x));
+        mutr.set_style_property(menu_id, "top", &format!("{}px", menu.y));
+        drop(mutr);
+        self.doc.resolve(0.0);
+
+        // Keep the menu within the viewport, opening it up and/or to the left of the pointer if
+        // there isn't enough space below and/or to the right of it.
+        let viewport = self.doc.viewport();
+        let viewport_width = viewport.window_size.0 as f32 / viewport.scale();
+        let viewport_height = viewport.window_size.1 as f32 / viewport.scale();
+        let size = self.doc.get_node(menu_id).unwrap().final_layout.size;
+        let mut x = menu.x;
+        let mut y = menu.y;
+        if x + size.width > viewport_width - EDGE_MARGIN {
+            x = (x - size.width).max(EDGE_MARGIN);
+        }
+        if y + size.height > viewport_height - EDGE_MARGIN {
+            y = (y - size.height).max(EDGE_MARGIN);
+        }
+        let mut mutr = self.doc.mutate();
+        mutr.set_style_property(menu_id, "left", &format!("{x}px"));
+        mutr.set_style_property(menu_id, "top", &format!("{y}px"));
+        drop(mutr);
+        self.doc.resolve(0.0);
+
+        self.items = Some(items);
+    }
+
+    pub(crate) fn close(&mut self) {
+        if self.items.take().is_some() {
+            self.doc.clear_hover();
+            self.doc
+                .mutate()
+                .set_style_property(self.menu_id, "display", "none");
+        }
+    }
+
+    /// Update the hovered item. Returns true if it changed.
+    pub(crate) fn set_hover_to(&mut self, x: f32, y: f32) -> bool {
+        let changed = self.doc.set_hover_to(x, y);
+        if changed {
+            self.doc.resolve(0.0);
+        }
+        changed
+    }
+
+    /// Whether the point (in CSS pixels relative to the viewport) is within the menu
+    pub(crate) fn contains(&self, x: f32, y: f32) -> bool {
+        self.doc
+            .hit(x, y)
+            .is_some_and(|hit| self.doc.node_chain(hit.node_id).contains(&self.menu_id))
+    }
+
+    /// The command of the enabled item at the point (if any)
+    pub(crate) fn command_at(&self, x: f32, y: f32) -> Option<ContextMenuCommand> {
+        let chain = self.doc.node_chain(self.doc.hit(x, y)?.node_id);
+        let items = self.items.as_ref()?;
+        items
+            .iter()
+            .find(|(item_id, _)| chain.contains(item_id))
+            .map(|(_, command)| command.clone())
+    }
+
+    pub(crate) fn paint(
+        &self,
+        scene: &mut impl PaintScene,
+        scale: f64,
+        width: u32,
+        height: u32,
+        x_offset: u32,
+        y_offset: u32,
+    ) {
+        if self.is_open() {
+            paint_scene(scene, &self.doc, scale, width, height, x_offset, y_offset);
+        }
+    }
+}
+
+fn class_attr(value: &str) -> Attribute {
+    Attribute {
+        name: qual_name!("class"),
+        value: value.to_string(),
+    }
+}
@@ -1,5 +1,6 @@
 use blitz_traits::navigation::NavigationOptions;
 use blitz_traits::net::NetWaker;
+use blitz_traits::shell::ContextMenu;
 use futures_util::task::ArcWake;
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::{any::Any, sync::Arc};
@@ -18,6 +19,11 @@ pub enum BlitzShellEvent {
         doc_id: usize,
     },
 
+    /// A frame resolved and painted off the main thread is ready to be presented
+    FrameReady {
+        window_id: WindowId,
+    },
+
     /// An accessibility event from `accesskit`.
     #[cfg(feature = "accessibility")]
     Accessibility {
@@ -25,6 +31,12 @@ pub enum BlitzShellEvent {
         data: Arc<AccessKitEvent>,
     },
 
+    /// Show a context menu requested by a window's document
+    ShowContextMenu {
+        window_id: WindowId,
+        menu: Box<ContextMenu>,
+    },
+
     /// An arbitary event from the Blitz embedder
     Embedder(Arc<dyn Any + Send + Sync>),
 
@@ -9,9 +9,11 @@
 //!  - `tracing`: Enables tracing support.
 
 mod application;
+mod context_menu;
 mod convert_events;
 mod event;
 mod net;
+mod pipeline;
 mod window;
 
 #[cfg(feature = "accessibility")]
@@ -37,7 +39,7 @@ pub use crate::net::DataUriNetProvider;
     )
 ))]
 use blitz_traits::shell::FileDialogFilter;
-use blitz_traits::shell::ShellProvider;
+use blitz_traits::shell::{ContextMenu, ShellProvider};
 use std::sync::Arc;
 use winit::cursor::{Cursor, CursorIcon};
 use winit::dpi::{LogicalPosition, LogicalSize};
@@ -86,10 +88,11 @@ pub fn current_android_app() -> android_activity::AndroidApp {
 
 pub struct BlitzShellProvider {
     window: Arc<dyn Window>,
+    proxy: BlitzShellProxy,
 }
 impl BlitzShellProvider {
-    pub fn new(window: Arc<dyn Window>) -> Self {
-        Self { window }
+    pub fn new(window: Arc<dyn Window>, proxy: BlitzShellProxy) -> Self {
+        Self { window, proxy }
     }
 }
 
@@ -120,6 +123,12 @@ impl ShellProvider for BlitzShellProvider {
             ),
         ));
     }
+    fn show_context_menu(&self, menu: ContextMenu) {
+        self.proxy.send_event(BlitzShellEvent::ShowContextMenu {
+            window_id: self.window.id(),
+            menu: Box::new(menu),
+        });
+    }
 
     #[cfg(all(
         feature = "clipboard",
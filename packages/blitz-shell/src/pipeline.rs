@@ -0,0 +1,238 @@
+//! Resolving and painting documents off the main thread
+//!
+//! Documents which expose their [`BaseDocument`] through [`Document::shared_state`] (such as
+//! [`SharedDocument`](blitz_dom::SharedDocument), which an `HtmlDocument` can be converted into)
+//! are resolved and painted into an
+//! [`anyrender::Scene`] on a worker thread. The window keeps presenting the last completed frame
+//! in the meantime: input received while a frame is in progress is queued (and replayed once the
+//! frame completes), and wheel gestures scroll the last frame so that scrolling stays responsive
+//! even when restyle or relayout is slow.
+
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use blitz_dom::{BaseDocument, Document};
+use blitz_paint::{PaintCache, paint_scene_cached};
+use kurbo::{Point, Rect};
+use winit::window::WindowId;
+
+use crate::{BlitzShellEvent, BlitzShellProxy};
+
+/// The parameters for producing a frame
+pub(crate) struct FrameRequest {
+    pub(crate) animation_time: f64,
+    pub(crate) x_offset: u32,
+    pub(crate) y_offset: u32,
+}
+
+/// A frame painted by the worker thread, along with the document state needed to composite it
+pub(crate) struct CompletedFrame {
+    pub(crate) scene: anyrender::Scene,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) scale: f64,
+    /// The viewport scroll position (in CSS pixels) the frame was painted at
+    pub(crate) viewport_scroll: Point,
+    pub(crate) max_viewport_scroll: Point,
+    /// Client rects of scrollable nodes (see [`BaseDocument::scrollable_node_rects`])
+    pub(crate) scrollable_node_rects: Vec<Rect>,
+    pub(crate) is_animating: bool,
+    /// Whether painting was blocked on critical resources (in which case the scene is empty)
+    pub(crate) is_blocked: bool,
+}
+
+impl CompletedFrame {
+    /// Whether a wheel gesture at `position` (in client CSS pixels) would scroll a node within
+    /// the document (rather than the viewport)
+    pub(crate) fn is_over_scrollable_node(&self, position: Point) -> bool {
+        self.scrollable_node_rects
+            .iter()
+            .any(|rect| rect.contains(position))
+    }
+
+    /// Clamp a viewport scroll position to the document's scroll range
+    pub(crate) fn clamp_viewport_scroll(&self, scroll: Point) -> Point {
+        Point::new(
+            scroll.x.clamp(0.0, self.max_viewport_scroll.x),
+            scroll.y.clamp(0.0, self.max_viewport_scroll.y),
+        )
+    }
+}
+
+pub(crate) struct FramePipeline {
+    sender: Option<Sender<FrameRequest>>,
+    completed: Arc<Mutex<Option<CompletedFrame>>>,
+    is_busy: bool,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FramePipeline {
+    /// Start a pipeline for a window's document, if it supports being resolved on another thread
+    pub(crate) fn for_window(
+        doc: &dyn Document,
+        window_id: WindowId,
+        proxy: BlitzShellProxy,
+    ) -> Option<Self> {
+        Self::new(doc, move || {
+            proxy.send_event(BlitzShellEvent::FrameReady { window_id })
+        })
+    }
+
+    /// Start a pipeline for the document, if it supports being resolved on another thread.
+    /// `on_frame_ready` is called (on the worker thread) whenever a frame completes.
+    pub(crate) fn new(
+        doc: &dyn Document,
+        on_frame_ready: impl Fn() + Send + 'static,
+    ) -> Option<Self> {
+        let doc = doc.shared_state()?;
+        let (sender, receiver) = channel::<FrameRequest>();
+        let completed = Arc::new(Mutex::new(None));
+
+        let worker_completed = completed.clone();
+        let thread = std::thread::Builder::new()
+            .name("blitz-frame".into())
+            .spawn(move || {
+                let mut paint_cache = PaintCache::new();
+                while let Ok(mut request) = receiver.recv() {
+                    // Skip requests which have already been superseded
+                    while let Ok(newer) = receiver.try_recv() {
+                        request = newer;
+                    }
+
+                    let frame = {
+                        let mut doc = doc.lock().unwrap();
+                        produce_frame(&mut doc, &mut paint_cache, &request)
+                    };
+                    *worker_completed.lock().unwrap() = Some(frame);
+                    on_frame_ready();
+                }
+            })
+            .ok()?;
+
+        Some(Self {
+            sender: Some(sender),
+            completed,
+            is_busy: false,
+            thread: Some(thread),
+        })
+    }
+
+    /// Whether the worker thread is producing a frame (in which case the document is locked)
+    pub(crate) fn is_busy(&self) -> bool {
+        self.is_busy
+    }
+
+    pub(crate) fn request_frame(&mut self, request: FrameRequest) {
+        if let Some(sender) = &self.sender
+            && sender.send(request).is_ok()
+        {
+            self.is_busy = true;
+        }
+    }
+
+    /// Take the most recently completed frame (if any)
+    pub(crate) fn take_completed(&mut self) -> Option<CompletedFrame> {
+        let frame = self.completed.lock().unwrap().take()?;
+        self.is_busy = false;
+        Some(frame)
+    }
+}
+
+impl Drop for FramePipeline {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker once it has finished its current frame
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn produce_frame(
+    doc: &mut BaseDocument,
+    paint_cache: &mut PaintCache,
+    request: &FrameRequest,
+) -> CompletedFrame {
+    doc.resolve(request.animation_time);
+
+    let (width, height) = doc.viewport().window_size;
+    let scale = doc.viewport().scale_f64();
+    let mut scene = anyrender::Scene::new();
+    paint_scene_cached(
+        &mut scene,
+        doc,
+        paint_cache,
+        scale,
+        width,
+        height,
+        request.x_offset,
+        request.y_offset,
+    );
+
+    let viewport_scroll = doc.viewport_scroll();
+    let max_viewport_scroll = doc.max_viewport_scroll();
+    CompletedFrame {
+        scene,
+        width,
+        height,
+        scale,
+        viewport_scroll: Point::new(viewport_scroll.x, viewport_scroll.y),
+        max_viewport_scroll: Point::new(max_viewport_scroll.x, max_viewport_scroll.y),
+        scrollable_node_rects: doc.scrollable_node_rects(),
+        is_animating: doc.is_animating(),
+        is_blocked: doc.has_pending_critical_resources(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use blitz_dom::{DocumentConfig, SharedDocument};
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    use super::*;
+
+    #[test]
+    fn test_produces_frame() {
+        let mut doc = BaseDocument::new(DocumentConfig {
+            viewport: Some(Viewport::new(400, 300, 2.0, ColorScheme::Light)),
+            ..Default::default()
+        });
+        let mut mutator = doc.mutate();
+        let root = mutator.create_element(blitz_dom::qual_name!("html", html), Vec::new());
+        mutator.append_children(0, &[root]);
+        drop(mutator);
+        let doc = SharedDocument::new(doc);
+
+        let (ready_sender, ready) = channel();
+        let mut pipeline = FramePipeline::new(&doc, move || {
+            let _ = ready_sender.send(());
+        })
+        .unwrap();
+        assert!(!pipeline.is_busy());
+        assert!(pipeline.take_completed().is_none());
+
+        pipeline.request_frame(FrameRequest {
+            animation_time: 0.0,
+            x_offset: 0,
+            y_offset: 0,
+        });
+        assert!(pipeline.is_busy());
+
+        ready.recv_timeout(Duration::from_secs(30)).unwrap();
+        let frame = pipeline.take_completed().unwrap();
+        assert!(!pipeline.is_busy());
+        assert_eq!((frame.width, frame.height), (400, 300));
+        assert_eq!(frame.scale, 2.0);
+        assert_eq!(frame.viewport_scroll, Point::ZERO);
+        assert!(!frame.is_blocked);
+
+        // The document was resolved by the worker thread. Layout is in CSS pixels, so the root
+        // is half the width of the window.
+        let inner = doc.inner();
+        assert_eq!(inner.get_node(root).unwrap().final_layout.size.width, 200.0);
+    }
+}
@@ -1,26 +1,30 @@
 use crate::BlitzShellProvider;
+use crate::context_menu::ContextMenuOverlay;
 use crate::convert_events::{
     button_source_to_blitz, color_scheme_to_theme, pointer_source_to_blitz,
     pointer_source_to_blitz_details, theme_to_color_scheme, winit_ime_to_blitz,
     winit_key_event_to_blitz, winit_modifiers_to_kbt_modifiers,
 };
 use crate::event::{BlitzShellProxy, create_waker};
-use anyrender::WindowRenderer;
+use crate::pipeline::{CompletedFrame, FramePipeline, FrameRequest};
+use anyrender::{PaintScene as _, WindowRenderer};
 use blitz_dom::Document;
-use blitz_paint::paint_scene;
+use blitz_paint::{PaintCache, paint_scene_cached};
 use blitz_traits::events::{
-    BlitzPointerEvent, BlitzPointerId, BlitzWheelDelta, BlitzWheelEvent, MouseEventButton,
-    MouseEventButtons, PointerCoords, PointerDetails, UiEvent,
+    BlitzDragEvent, BlitzPointerEvent, BlitzPointerId, BlitzWheelDelta, BlitzWheelEvent,
+    DataTransfer, MouseEventButton, MouseEventButtons, PointerCoords, PointerDetails, UiEvent,
 };
-use blitz_traits::shell::Viewport;
+use blitz_traits::shell::{ContextMenu, Viewport};
+use kurbo::{Affine, Point, Vec2};
 use winit::dpi::{LogicalPosition, PhysicalInsets, PhysicalPosition};
 use winit::keyboard::PhysicalKey;
 
 use std::any::Any;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::task::Waker;
 use std::time::Instant;
-use winit::event::{ButtonSource, ElementState, MouseButton};
+use winit::event::{ButtonSource, ElementState, MouseButton, MouseScrollDelta};
 use winit::event_loop::ActiveEventLoop;
 use winit::window::{Theme, WindowAttributes, WindowId};
 use winit::{event::Modifiers, event::WindowEvent, keyboard::KeyCode, window::Window};
@@ -82,6 +86,21 @@ pub struct View<Rend: WindowRenderer> {
     pub is_visible: bool,
     pub safe_area_insets: PhysicalInsets<u32>,
 
+    /// The context menu shown over the document (created when first shown)
+    context_menu: Option<ContextMenuOverlay>,
+
+    /// Drawing commands retained between frames, so that only changed content is repainted
+    paint_cache: PaintCache,
+
+    /// Resolves and paints the document off the main thread (if the document supports it)
+    pipeline: Option<FramePipeline>,
+    /// The last frame completed by the pipeline
+    presented_frame: Option<CompletedFrame>,
+    /// Events received while the pipeline was busy, to be handled once its frame completes
+    queued_events: Vec<WindowEvent>,
+    /// The viewport scroll position at which the presented frame is shown
+    compositor_scroll: Point,
+
     #[cfg(feature = "accessibility")]
     /// Accessibility adapter for `accesskit`.
     pub accessibility: AccessibilityState,
@@ -123,7 +142,7 @@ impl<Rend: WindowRenderer> View<Rend> {
         let viewport = Viewport::new(size.width, size.height, scale, color_scheme);
 
         // Create shell provider
-        let shell_provider = BlitzShellProvider::new(winit_window.clone());
+        let shell_provider = BlitzShellProvider::new(winit_window.clone(), proxy.clone());
 
         let mut doc = config.doc;
         let mut inner = doc.inner_mut();
@@ -140,6 +159,8 @@ impl<Rend: WindowRenderer> View<Rend> {
 
         drop(inner);
 
+        let pipeline = FramePipeline::for_window(&*doc, winit_window.id(), proxy.clone());
+
         Self {
             renderer: config.renderer,
             waker: None,
@@ -151,6 +172,12 @@ impl<Rend: WindowRenderer> View<Rend> {
             theme_override: None,
             buttons: MouseEventButtons::None,
             safe_area_insets,
+            context_menu: None,
+            paint_cache: PaintCache::new(),
+            pipeline,
+            presented_frame: None,
+            queued_events: Vec::new(),
+            compositor_scroll: Point::ZERO,
             pointer_pos: Default::default(),
             is_visible: winit_window.is_visible().unwrap_or(true),
             #[cfg(feature = "accessibility")]
@@ -162,6 +189,8 @@ impl<Rend: WindowRenderer> View<Rend> {
     }
 
     pub fn replace_document(&mut self, new_doc: Box<dyn Document>, retain_scroll_position: bool) {
+        self.close_context_menu();
+
         let inner = self.doc.inner();
         let scroll = inner.viewport_scroll();
         let viewport = inner.viewport().clone();
@@ -169,6 +198,10 @@ impl<Rend: WindowRenderer> View<Rend> {
         drop(inner);
 
         self.doc = new_doc;
+        self.paint_cache.clear();
+        self.pipeline = FramePipeline::for_window(&*self.doc, self.window_id(), self.proxy.clone());
+        self.presented_frame = None;
+        self.queued_events.clear();
 
         let mut inner = self.doc.inner_mut();
         inner.set_viewport(viewport);
@@ -233,10 +266,25 @@ impl<Rend: WindowRenderer> View<Rend> {
             panic!("Renderer failed to resume");
         };
 
-        // Render
+        // Render (the new surface has no previous frame to reuse)
         let insets = self.safe_area_insets.to_logical(scale);
+        let context_menu = self.context_menu.as_ref();
+        let paint_cache = &mut self.paint_cache;
+        paint_cache.clear();
         self.renderer.render(|scene| {
-            paint_scene(scene, &inner, scale, width, height, insets.left, insets.top)
+            paint_scene_cached(
+                scene,
+                &inner,
+                paint_cache,
+                scale,
+                width,
+                height,
+                insets.left,
+                insets.top,
+            );
+            if let Some(menu) = context_menu {
+                menu.paint(scene, scale, width, height, insets.left, insets.top);
+            }
         });
 
         // Set waker
@@ -249,16 +297,16 @@ impl<Rend: WindowRenderer> View<Rend> {
     }
 
     pub fn poll(&mut self) -> bool {
+        // The document is locked by the pipeline. It is polled again once the frame completes.
+        if self.pipeline.as_ref().is_some_and(FramePipeline::is_busy) {
+            return false;
+        }
+
         if let Some(waker) = &self.waker {
             let cx = std::task::Context::from_waker(waker);
             if self.doc.poll(Some(cx)) {
                 #[cfg(feature = "accessibility")]
-                {
-                    let inner = self.doc.inner();
-                    if inner.has_changes() {
-                        self.accessibility.update_tree(&inner);
-                    }
-                }
+                self.update_accessibility_tree();
 
                 self.request_redraw();
                 return true;
@@ -279,6 +327,11 @@ impl<Rend: WindowRenderer> View<Rend> {
     pub fn redraw(&mut self) {
         #[cfg(target_os = "ios")]
         self.ios_request_redraw.set(false);
+        if self.pipeline.is_some() {
+            self.redraw_threaded();
+            return;
+        }
+
         let animation_time = self.current_animation_time();
         let is_visible = self.is_visible;
 
@@ -292,18 +345,185 @@ impl<Rend: WindowRenderer> View<Rend> {
         let insets = self.safe_area_insets.to_logical(scale);
 
         if !is_blocked && is_visible {
+            let context_menu = self.context_menu.as_ref();
+            let paint_cache = &mut self.paint_cache;
             self.renderer.render(|scene| {
-                paint_scene(scene, &inner, scale, width, height, insets.left, insets.top)
+                paint_scene_cached(
+                    scene,
+                    &inner,
+                    paint_cache,
+                    scale,
+                    width,
+                    height,
+                    insets.left,
+                    insets.top,
+                );
+                if let Some(menu) = context_menu {
+                    menu.paint(scene, scale, width, height, insets.left, insets.top);
+                }
             });
         }
 
         drop(inner);
 
+        // Deliver observer notifications generated by the layout pass
+        self.doc.flush_pending_events();
+
         if !is_blocked && is_visible && is_animating {
             self.request_redraw();
         }
     }
 
+    /// Request a frame from the pipeline (unless one is already in progress), and present the last
+    /// completed frame in the meantime
+    fn redraw_threaded(&mut self) {
+        if !self.is_visible {
+            return;
+        }
+
+        if !self.pipeline.as_ref().is_some_and(FramePipeline::is_busy) {
+            let animation_time = self.current_animation_time();
+            let inner = self.doc.inner();
+            let scale = inner.viewport().scale_f64();
+            let scroll = inner.viewport_scroll();
+            drop(inner);
+
+            // The document may have been scrolled since the last frame was painted
+            self.compositor_scroll = Point::new(scroll.x, scroll.y);
+
+            let insets = self.safe_area_insets.to_logical(scale);
+            if let Some(pipeline) = &mut self.pipeline {
+                pipeline.request_frame(FrameRequest {
+                    animation_time,
+                    x_offset: insets.left,
+                    y_offset: insets.top,
+                });
+            }
+        }
+
+        self.present_frame();
+    }
+
+    /// Present a frame completed by the pipeline, then handle the events which were queued while
+    /// it was in progress
+    pub fn frame_ready(&mut self) {
+        let Some(frame) = self
+            .pipeline
+            .as_mut()
+            .and_then(FramePipeline::take_completed)
+        else {
+            return;
+        };
+        let is_blocked = frame.is_blocked;
+        let is_animating = frame.is_animating;
+        if !is_blocked {
+            self.presented_frame = Some(frame);
+        }
+
+        // The accessibility adapter belongs to the UI thread, so the tree is updated from the
+        // document resolved by the worker here rather than on the worker thread
+        #[cfg(feature = "accessibility")]
+        self.update_accessibility_tree();
+
+        for event in std::mem::take(&mut self.queued_events) {
+            self.handle_winit_event(event);
+        }
+
+        // Deliver observer notifications generated by the layout pass
+        self.doc.flush_pending_events();
+        self.poll();
+
+        let scroll = self.doc.inner().viewport_scroll();
+        self.compositor_scroll = Point::new(scroll.x, scroll.y);
+        self.present_frame();
+
+        if !is_blocked && is_animating {
+            self.request_redraw();
+        }
+    }
+
+    /// Present the last frame completed by the pipeline, offset to the compositor's scroll position
+    fn present_frame(&mut self) {
+        let Some(frame) = &self.presented_frame else {
+            return;
+        };
+
+        let offset: Vec2 = (frame.viewport_scroll - self.compositor_scroll) * frame.scale;
+        let context_menu = self.context_menu.as_ref();
+
+        let insets = self.safe_area_insets.to_logical(frame.scale);
+        self.renderer.render(|scene| {
+            scene.append_scene(frame.scene.clone(), Affine::translate(offset));
+            if let Some(menu) = context_menu {
+                menu.paint(
+                    scene,
+                    frame.scale,
+                    frame.width,
+                    frame.height,
+                    insets.left,
+                    insets.top,
+                );
+            }
+        });
+    }
+
+    /// Queue an event received while the pipeline is busy (and the document is locked)
+    fn queue_event(&mut self, event: WindowEvent) {
+        match &event {
+            WindowEvent::PointerMoved { position, .. } => {
+                self.pointer_pos = *position;
+                // Only the latest pointer position matters
+                if matches!(
+                    self.queued_events.last(),
+                    Some(WindowEvent::PointerMoved { .. })
+                ) {
+                    self.queued_events.pop();
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => self.composite_wheel(*delta),
+            _ => {}
+        }
+        self.queued_events.push(event);
+    }
+
+    /// Scroll the presented frame in response to a wheel gesture received while the pipeline is
+    /// busy. The document itself is scrolled once the (queued) gesture is handled.
+    fn composite_wheel(&mut self, delta: MouseScrollDelta) {
+        let Some(frame) = &self.presented_frame else {
+            return;
+        };
+
+        let (x, y) = match delta {
+            // Matches the line height used by `blitz-dom` for wheel events
+            MouseScrollDelta::LineDelta(x, y) => (x as f64 * 20.0, y as f64 * 20.0),
+            MouseScrollDelta::PixelDelta(pos) => (pos.x, pos.y),
+        };
+
+        // Nodes under the pointer scroll before the viewport does
+        let pointer: LogicalPosition<f64> = self.pointer_pos.to_logical(frame.scale);
+        let client = Point::new(
+            pointer.x - self.safe_area_insets.left as f64 / frame.scale,
+            pointer.y - self.safe_area_insets.top as f64 / frame.scale,
+        );
+        if frame.is_over_scrollable_node(client) {
+            return;
+        }
+
+        self.compositor_scroll =
+            frame.clamp_viewport_scroll(self.compositor_scroll - Vec2::new(x, y));
+        self.present_frame();
+    }
+
+    /// A drag event for files dragged over the window from another application
+    fn drag_event(&self, position: PhysicalPosition<f64>, paths: Vec<PathBuf>) -> BlitzDragEvent {
+        BlitzDragEvent {
+            coords: self.pointer_coords(position),
+            buttons: self.buttons,
+            mods: winit_modifiers_to_kbt_modifiers(self.keyboard_modifiers.state()),
+            data_transfer: DataTransfer::from_files(paths),
+        }
+    }
+
     pub fn pointer_coords(&self, position: PhysicalPosition<f64>) -> PointerCoords {
         let inner = self.doc.inner();
         let scale = inner.viewport().scale_f64();
@@ -349,12 +569,107 @@ impl<Rend: WindowRenderer> View<Rend> {
         }
     }
 
+    /// Update the accessibility tree if the document changed
+    #[cfg(feature = "accessibility")]
+    fn update_accessibility_tree(&mut self) {
+        let inner = self.doc.inner();
+        if inner.has_changes() {
+            let start = Instant::now();
+            self.accessibility.update_tree(&inner);
+            inner
+                .profiler()
+                .record_phase(blitz_dom::profiler::FramePhase::Accessibility, start);
+        }
+    }
+
     #[cfg(feature = "accessibility")]
     pub fn build_accessibility_tree(&mut self) {
         let inner = self.doc.inner();
         self.accessibility.update_tree(&inner);
     }
 
+    /// Show a context menu over the document (see [`ShellProvider::show_context_menu`])
+    ///
+    /// [`ShellProvider::show_context_menu`]: blitz_traits::shell::ShellProvider::show_context_menu
+    pub fn show_context_menu(&mut self, menu: ContextMenu) {
+        let viewport = self.doc.inner().viewport().clone();
+        self.context_menu
+            .get_or_insert_with(ContextMenuOverlay::new)
+            .show(menu, viewport);
+        self.request_redraw();
+    }
+
+    pub fn close_context_menu(&mut self) {
+        if let Some(menu) = &mut self.context_menu
+            && menu.is_open()
+        {
+            menu.close();
+            // Repaint the area that was covered by the menu
+            self.paint_cache.clear();
+            self.request_redraw();
+        }
+    }
+
+    /// Handle input while a context menu is open. Returns true if the event was consumed
+    /// (and so should not be passed to the document).
+    fn handle_context_menu_event(&mut self, event: &WindowEvent) -> bool {
+        if !self
+            .context_menu
+            .as_ref()
+            .is_some_and(|menu| menu.is_open())
+        {
+            return false;
+        }
+
+        match event {
+            WindowEvent::PointerMoved { position, .. } => {
+                self.pointer_pos = *position;
+                let coords = self.pointer_coords(*position);
+                let menu = self.context_menu.as_mut().unwrap();
+                if menu.set_hover_to(coords.client_x, coords.client_y) {
+                    self.request_redraw();
+                }
+                true
+            }
+            WindowEvent::PointerButton {
+                state, position, ..
+            } => {
+                self.pointer_pos = *position;
+                let coords = self.pointer_coords(*position);
+                let menu = self.context_menu.as_mut().unwrap();
+                if !menu.contains(coords.client_x, coords.client_y) {
+                    // Clicking outside of the menu closes it, and is passed through to the document
+                    self.close_context_menu();
+                    return false;
+                }
+                if *state == ElementState::Released
+                    && let Some(command) = menu.command_at(coords.client_x, coords.client_y)
+                {
+                    self.close_context_menu();
+                    self.doc
+                        .handle_ui_event(UiEvent::ContextMenuCommand(command));
+                }
+                true
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.state.is_pressed()
+                    && event.physical_key == PhysicalKey::Code(KeyCode::Escape)
+                {
+                    self.close_context_menu();
+                }
+                true
+            }
+            WindowEvent::MouseWheel { .. }
+            | WindowEvent::SurfaceResized(_)
+            | WindowEvent::ScaleFactorChanged { .. }
+            | WindowEvent::Focused(false) => {
+                self.close_context_menu();
+                false
+            }
+            _ => false,
+        }
+    }
+
     #[cfg(target_os = "macos")]
     pub fn handle_apple_standard_keybinding(&mut self, command: &str) {
         use blitz_traits::SmolStr;
@@ -363,11 +678,24 @@ impl<Rend: WindowRenderer> View<Rend> {
     }
 
     pub fn handle_winit_event(&mut self, event: WindowEvent) {
+        // The document is locked while the pipeline is producing a frame, so events are handled
+        // once the frame is complete
+        if self.pipeline.as_ref().is_some_and(FramePipeline::is_busy)
+            && !matches!(event, WindowEvent::RedrawRequested)
+        {
+            self.queue_event(event);
+            return;
+        }
+
         // Update accessibility focus and window size state in response to a Winit WindowEvent
         #[cfg(feature = "accessibility")]
         self.accessibility
             .process_window_event(&*self.window, &event);
 
+        if self.handle_context_menu_event(&event) {
+            return;
+        }
+
         match event {
             WindowEvent::Destroyed => {}
             WindowEvent::ActivationTokenDone { .. } => {},
@@ -552,10 +880,26 @@ impl<Rend: WindowRenderer> View<Rend> {
             WindowEvent::PanGesture { .. } => {},
             WindowEvent::DoubleTapGesture { .. } => {},
             WindowEvent::RotationGesture { .. } => {},
-            WindowEvent::DragEntered { .. } => {},
-            WindowEvent::DragMoved { .. } => {},
-            WindowEvent::DragDropped { .. } => {},
-            WindowEvent::DragLeft { .. } => {},
+            WindowEvent::DragEntered { paths, position } => {
+                let event = self.drag_event(position, paths);
+                self.doc.handle_ui_event(UiEvent::DragEnter(event));
+                self.request_redraw();
+            },
+            WindowEvent::DragMoved { position } => {
+                let event = self.drag_event(position, Vec::new());
+                self.doc.handle_ui_event(UiEvent::DragOver(event));
+                self.request_redraw();
+            },
+            WindowEvent::DragDropped { paths, position } => {
+                let event = self.drag_event(position, paths);
+                self.doc.handle_ui_event(UiEvent::Drop(event));
+                self.request_redraw();
+            },
+            WindowEvent::DragLeft { position } => {
+                let event = self.drag_event(position.unwrap_or(self.pointer_pos), Vec::new());
+                self.doc.handle_ui_event(UiEvent::DragLeave(event));
+                self.request_redraw();
+            },
         }
     }
 }
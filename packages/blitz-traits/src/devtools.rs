@@ -9,6 +9,8 @@ pub struct DevtoolSettings {
     /// Render browser-style colored overlay showing the content-box,
     /// padding, border, and margin of the hovered element
     pub highlight_hover: bool,
+    /// Node whose box model is highlighted by a remote inspector (regardless of hover)
+    pub highlighted_node: Option<usize>,
 }
 
 impl DevtoolSettings {
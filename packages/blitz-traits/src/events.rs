@@ -1,11 +1,16 @@
 //! Types to represent UI and DOM events
 
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use bitflags::bitflags;
 use keyboard_types::{Code, Key, Location, Modifiers};
 use smol_str::SmolStr;
 
+use crate::shell::ContextMenuCommand;
+
 #[derive(Default)]
 pub struct EventState {
     cancelled: bool,
@@ -64,6 +69,18 @@ pub enum UiEvent {
     KeyDown(BlitzKeyEvent),
     Ime(BlitzImeEvent),
     AppleStandardKeybinding(SmolStr),
+    /// A command chosen from a context menu shown with [`ShellProvider::show_context_menu`]
+    ///
+    /// [`ShellProvider::show_context_menu`]: crate::shell::ShellProvider::show_context_menu
+    ContextMenuCommand(ContextMenuCommand),
+    /// Data (such as files) dragged from another application entered the window
+    DragEnter(BlitzDragEvent),
+    /// Data dragged from another application moved within the window
+    DragOver(BlitzDragEvent),
+    /// Data dragged from another application left the window without being dropped
+    DragLeave(BlitzDragEvent),
+    /// Data dragged from another application was dropped onto the window
+    Drop(BlitzDragEvent),
 }
 impl UiEvent {
     pub fn discriminant(&self) -> u8 {
@@ -124,6 +141,7 @@ pub enum DomEventKind {
     MouseOut,
 
     Scroll,
+    ScrollEnd,
     Wheel,
 
     Click,
@@ -141,6 +159,17 @@ pub enum DomEventKind {
     FocusIn,
     FocusOut,
 
+    Visible,
+    Resize,
+
+    DragStart,
+    Drag,
+    DragEnter,
+    DragOver,
+    DragLeave,
+    Drop,
+    DragEnd,
+
     AppleStandardKeybinding,
 }
 impl DomEventKind {
@@ -169,6 +198,7 @@ impl FromStr for DomEventKind {
             "mouseout" => Ok(Self::MouseOut),
 
             "scroll" => Ok(Self::Scroll),
+            "scrollend" => Ok(Self::ScrollEnd),
             "wheel" => Ok(Self::Wheel),
 
             "click" => Ok(Self::Click),
@@ -185,6 +215,17 @@ impl FromStr for DomEventKind {
             "blur" => Ok(Self::Blur),
             "focusin" => Ok(Self::FocusIn),
             "focusout" => Ok(Self::FocusOut),
+
+            "visible" => Ok(Self::Visible),
+            "resize" => Ok(Self::Resize),
+
+            "dragstart" => Ok(Self::DragStart),
+            "drag" => Ok(Self::Drag),
+            "dragenter" => Ok(Self::DragEnter),
+            "dragover" => Ok(Self::DragOver),
+            "dragleave" => Ok(Self::DragLeave),
+            "drop" => Ok(Self::Drop),
+            "dragend" => Ok(Self::DragEnd),
             _ => Err(()),
         }
     }
@@ -210,6 +251,7 @@ pub enum DomEventData {
     MouseOut(BlitzPointerEvent),
 
     Scroll(BlitzScrollEvent),
+    ScrollEnd(BlitzScrollEvent),
     Wheel(BlitzWheelEvent),
 
     Click(BlitzPointerEvent),
@@ -227,6 +269,17 @@ pub enum DomEventData {
     FocusIn(BlitzFocusEvent),
     FocusOut(BlitzFocusEvent),
 
+    Visible(BlitzIntersectionEvent),
+    Resize(BlitzResizeEvent),
+
+    DragStart(BlitzDragEvent),
+    Drag(BlitzDragEvent),
+    DragEnter(BlitzDragEvent),
+    DragOver(BlitzDragEvent),
+    DragLeave(BlitzDragEvent),
+    Drop(BlitzDragEvent),
+    DragEnd(BlitzDragEvent),
+
     AppleStandardKeybinding(SmolStr),
 }
 impl DomEventData {
@@ -259,6 +312,7 @@ impl DomEventData {
             Self::MouseOut { .. } => "mouseout",
 
             Self::Scroll { .. } => "scroll",
+            Self::ScrollEnd { .. } => "scrollend",
             Self::Wheel { .. } => "wheel",
 
             Self::Click { .. } => "click",
@@ -276,6 +330,17 @@ impl DomEventData {
             Self::FocusIn { .. } => "focusin",
             Self::FocusOut { .. } => "focusout",
 
+            Self::Visible { .. } => "visible",
+            Self::Resize { .. } => "resize",
+
+            Self::DragStart { .. } => "dragstart",
+            Self::Drag { .. } => "drag",
+            Self::DragEnter { .. } => "dragenter",
+            Self::DragOver { .. } => "dragover",
+            Self::DragLeave { .. } => "dragleave",
+            Self::Drop { .. } => "drop",
+            Self::DragEnd { .. } => "dragend",
+
             Self::AppleStandardKeybinding { .. } => "applekeybinding",
         }
     }
@@ -299,6 +364,7 @@ impl DomEventData {
             Self::MouseOut { .. } => DomEventKind::MouseOut,
 
             Self::Scroll { .. } => DomEventKind::Scroll,
+            Self::ScrollEnd { .. } => DomEventKind::ScrollEnd,
             Self::Wheel { .. } => DomEventKind::Wheel,
 
             Self::Click { .. } => DomEventKind::Click,
@@ -316,6 +382,17 @@ impl DomEventData {
             Self::FocusIn { .. } => DomEventKind::FocusIn,
             Self::FocusOut { .. } => DomEventKind::FocusOut,
 
+            Self::Visible { .. } => DomEventKind::Visible,
+            Self::Resize { .. } => DomEventKind::Resize,
+
+            Self::DragStart { .. } => DomEventKind::DragStart,
+            Self::Drag { .. } => DomEventKind::Drag,
+            Self::DragEnter { .. } => DomEventKind::DragEnter,
+            Self::DragOver { .. } => DomEventKind::DragOver,
+            Self::DragLeave { .. } => DomEventKind::DragLeave,
+            Self::Drop { .. } => DomEventKind::Drop,
+            Self::DragEnd { .. } => DomEventKind::DragEnd,
+
             Self::AppleStandardKeybinding { .. } => DomEventKind::AppleStandardKeybinding,
         }
     }
@@ -339,6 +416,7 @@ impl DomEventData {
             Self::MouseOut { .. } => true,
 
             Self::Scroll { .. } => false,
+            Self::ScrollEnd { .. } => false,
             Self::Wheel { .. } => true,
 
             Self::Click { .. } => true,
@@ -356,6 +434,17 @@ impl DomEventData {
             Self::FocusIn { .. } => false,
             Self::FocusOut { .. } => false,
 
+            Self::Visible { .. } => false,
+            Self::Resize { .. } => false,
+
+            Self::DragStart { .. } => true,
+            Self::Drag { .. } => true,
+            Self::DragEnter { .. } => true,
+            Self::DragOver { .. } => true,
+            Self::DragLeave { .. } => false,
+            Self::Drop { .. } => true,
+            Self::DragEnd { .. } => false,
+
             Self::AppleStandardKeybinding { .. } => true,
         }
     }
@@ -379,6 +468,7 @@ impl DomEventData {
             Self::MouseOut { .. } => true,
 
             Self::Scroll { .. } => false,
+            Self::ScrollEnd { .. } => false,
             Self::Wheel { .. } => true,
 
             Self::Click { .. } => true,
@@ -396,6 +486,17 @@ impl DomEventData {
             Self::FocusIn { .. } => true,
             Self::FocusOut { .. } => true,
 
+            Self::Visible { .. } => false,
+            Self::Resize { .. } => false,
+
+            Self::DragStart { .. } => true,
+            Self::Drag { .. } => true,
+            Self::DragEnter { .. } => true,
+            Self::DragOver { .. } => true,
+            Self::DragLeave { .. } => true,
+            Self::Drop { .. } => true,
+            Self::DragEnd { .. } => true,
+
             Self::AppleStandardKeybinding { .. } => false,
         }
     }
@@ -533,6 +634,191 @@ impl BlitzWheelEvent {
     }
 }
 
+/// A rectangle in CSS pixels
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BlitzRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A change in the intersection of an observed node with its intersection root
+#[derive(Clone, Debug)]
+pub struct BlitzIntersectionEvent {
+    /// The node's border box (relative to the viewport)
+    pub bounding_client_rect: BlitzRect,
+    /// The visible portion of the node's border box (relative to the viewport)
+    pub intersection_rect: BlitzRect,
+    /// The bounds of the intersection root, expanded by the root margin (relative to the viewport)
+    pub root_bounds: BlitzRect,
+    /// The proportion of the node's border box that is visible (from 0.0 to 1.0)
+    pub intersection_ratio: f64,
+    pub is_intersecting: bool,
+    pub time: SystemTime,
+}
+
+/// A change in the size of an observed node
+#[derive(Clone, Debug)]
+pub struct BlitzResizeEvent {
+    pub border_box_width: f64,
+    pub border_box_height: f64,
+    pub content_box_width: f64,
+    pub content_box_height: f64,
+}
+
+/// A drag-and-drop event (see <https://html.spec.whatwg.org/multipage/dnd.html#dragevent>)
+#[derive(Clone, Debug)]
+pub struct BlitzDragEvent {
+    pub coords: PointerCoords,
+    pub buttons: MouseEventButtons,
+    pub mods: Modifiers,
+    /// The data being dragged. This is shared between all of the events of a drag operation.
+    pub data_transfer: DataTransfer,
+}
+
+impl BlitzDragEvent {
+    #[inline(always)]
+    pub fn page_x(&self) -> f32 {
+        self.coords.page_x
+    }
+    #[inline(always)]
+    pub fn page_y(&self) -> f32 {
+        self.coords.page_y
+    }
+}
+
+/// The operation a drop performs (see <https://html.spec.whatwg.org/multipage/dnd.html#dom-datatransfer-dropeffect>)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DropEffect {
+    #[default]
+    None,
+    Copy,
+    Move,
+    Link,
+}
+
+impl DropEffect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Copy => "copy",
+            Self::Move => "move",
+            Self::Link => "link",
+        }
+    }
+}
+
+impl FromStr for DropEffect {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "none" => Ok(Self::None),
+            "copy" => Ok(Self::Copy),
+            "move" => Ok(Self::Move),
+            "link" => Ok(Self::Link),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The data being dragged in a drag-and-drop operation, keyed by format (e.g. `text/plain`),
+/// along with any files dragged from another application.
+///
+/// Clones share the same data, so changes made by an event handler (e.g. calling
+/// [`set_data`](Self::set_data) during `dragstart`) are visible to later events.
+///
+/// See <https://html.spec.whatwg.org/multipage/dnd.html#the-datatransfer-interface>
+#[derive(Clone, Debug, Default)]
+pub struct DataTransfer(Arc<Mutex<DataTransferData>>);
+
+#[derive(Debug, Default)]
+struct DataTransferData {
+    items: Vec<(String, String)>,
+    files: Vec<PathBuf>,
+    drop_effect: DropEffect,
+}
+
+impl DataTransfer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a `DataTransfer` for files dragged from another application
+    pub fn from_files(files: Vec<PathBuf>) -> Self {
+        let data_transfer = Self::default();
+        data_transfer.0.lock().unwrap().files = files;
+        data_transfer
+    }
+
+    pub fn get_data(&self, format: &str) -> Option<String> {
+        let format = format.to_ascii_lowercase();
+        let data = self.0.lock().unwrap();
+        data.items
+            .iter()
+            .find(|(item_format, _)| *item_format == format)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn set_data(&self, format: &str, value: &str) {
+        let format = format.to_ascii_lowercase();
+        let mut data = self.0.lock().unwrap();
+        match data
+            .items
+            .iter_mut()
+            .find(|(item_format, _)| *item_format == format)
+        {
+            Some((_, item_value)) => *item_value = value.to_string(),
+            None => data.items.push((format, value.to_string())),
+        }
+    }
+
+    /// Remove the data for `format`, or all data if `format` is `None`
+    pub fn clear_data(&self, format: Option<&str>) {
+        let mut data = self.0.lock().unwrap();
+        match format {
+            Some(format) => {
+                let format = format.to_ascii_lowercase();
+                data.items.retain(|(item_format, _)| *item_format != format);
+            }
+            None => data.items.clear(),
+        }
+    }
+
+    /// The formats for which there is data (plus `Files` if files are being dragged)
+    pub fn types(&self) -> Vec<String> {
+        let data = self.0.lock().unwrap();
+        let mut types: Vec<String> = data
+            .items
+            .iter()
+            .map(|(format, _)| format.clone())
+            .collect();
+        if !data.files.is_empty() {
+            types.push(String::from("Files"));
+        }
+        types
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().files.clone()
+    }
+
+    pub fn drop_effect(&self) -> DropEffect {
+        self.0.lock().unwrap().drop_effect
+    }
+
+    pub fn set_drop_effect(&self, drop_effect: DropEffect) {
+        self.0.lock().unwrap().drop_effect = drop_effect;
+    }
+}
+
+/// Two `DataTransfer`s are equal if they share the same data (i.e. belong to the same drag)
+impl PartialEq for DataTransfer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BlitzScrollEvent {
     pub scroll_top: f64,
@@ -33,6 +33,9 @@ pub struct NavigationOptions {
     pub method: Method,
 
     pub document_resource: Body,
+
+    /// Whether the embedder should open the URL in a new tab or window (if it supports them)
+    pub open_in_new_tab: bool,
 }
 
 impl NavigationOptions {
@@ -43,6 +46,7 @@ impl NavigationOptions {
             source_document,
             method: Method::GET,
             document_resource: Body::Empty,
+            open_in_new_tab: false,
         }
     }
     pub fn set_document_resource(mut self, document_resource: Body) -> Self {
@@ -55,6 +59,11 @@ impl NavigationOptions {
         self
     }
 
+    pub fn set_open_in_new_tab(mut self, open_in_new_tab: bool) -> Self {
+        self.open_in_new_tab = open_in_new_tab;
+        self
+    }
+
     pub fn into_request(self) -> Request {
         Request {
             url: self.url,
@@ -41,6 +41,11 @@ pub trait ShellProvider: Send + Sync + 'static {
         let _ = filter;
         vec![]
     }
+    /// Show a context menu. The shell should dispatch the chosen command (if any) back to the
+    /// document as a [`UiEvent::ContextMenuCommand`](crate::events::UiEvent::ContextMenuCommand).
+    fn show_context_menu(&self, menu: ContextMenu) {
+        let _ = menu;
+    }
 }
 
 pub struct DummyShellProvider;
@@ -126,3 +131,51 @@ pub struct FileDialogFilter {
     pub name: String,
     pub extensions: Vec<String>,
 }
+
+/// A context menu built by the document (e.g. in response to a right click)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextMenu {
+    /// The x position of the menu in CSS pixels, relative to the viewport
+    pub x: f32,
+    /// The y position of the menu in CSS pixels, relative to the viewport
+    pub y: f32,
+    pub items: Vec<ContextMenuItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextMenuItem {
+    Command {
+        command: ContextMenuCommand,
+        enabled: bool,
+    },
+    Separator,
+}
+
+/// A command which can be chosen from a [`ContextMenu`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextMenuCommand {
+    Cut,
+    Copy,
+    Paste,
+    SelectAll,
+    OpenLinkInNewTab(String),
+    CopyLink(String),
+    OpenImageInNewTab(String),
+    CopyImageAddress(String),
+}
+
+impl ContextMenuCommand {
+    /// A human-readable label for the command
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Cut => "Cut",
+            Self::Copy => "Copy",
+            Self::Paste => "Paste",
+            Self::SelectAll => "Select All",
+            Self::OpenLinkInNewTab(_) => "Open Link in New Tab",
+            Self::CopyLink(_) => "Copy Link",
+            Self::OpenImageInNewTab(_) => "Open Image in New Tab",
+            Self::CopyImageAddress(_) => "Copy Image Address",
+        }
+    }
+}
@@ -118,7 +118,8 @@ fn launch_internal(
             net_provider: Some(net_provider),
             ..Default::default()
         },
-    );
+    )
+    .into_shared();
     let renderer = WindowRenderer::new();
     let window = WindowConfig::new(Box::new(doc) as _, renderer);
 
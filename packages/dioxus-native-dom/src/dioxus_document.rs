@@ -1,8 +1,9 @@
 //! Integration between Dioxus and Blitz
 use crate::NodeId;
 use crate::events::{
-    BlitzKeyboardData, NativeConverter, NativeFocusData, NativeFormData, NativePointerData,
-    NativeScrollData, NativeWheelData, NodeHandle,
+    BlitzKeyboardData, NativeConverter, NativeDragData, NativeFocusData, NativeFormData,
+    NativePointerData, NativeResizeData, NativeScrollData, NativeVisibleData, NativeWheelData,
+    NodeHandle,
 };
 use crate::mutation_writer::{DioxusState, MutationWriter};
 use crate::qual_name;
@@ -248,6 +249,18 @@ impl Document for DioxusDocument {
         let mut driver = EventDriver::new(&mut self.inner, handler);
         driver.handle_ui_event(event);
     }
+
+    fn flush_pending_events(&mut self) {
+        if !self.inner.borrow().has_pending_events() {
+            return;
+        }
+        let handler = DioxusEventHandler {
+            vdom: &mut self.vdom,
+            vdom_state: &mut self.vdom_state,
+        };
+        let mut driver = EventDriver::new(&mut self.inner, handler);
+        driver.flush_pending_events();
+    }
 }
 
 pub struct DioxusEventHandler<'v> {
@@ -292,14 +305,32 @@ impl EventHandler for DioxusEventHandler<'_> {
                 Some(wrap_event_data(NativePointerData(mevent.clone())))
             }
 
-            DomEventData::Scroll(sevent) => Some(wrap_event_data(NativeScrollData(sevent.clone()))),
+            DomEventData::Scroll(sevent) | DomEventData::ScrollEnd(sevent) => {
+                Some(wrap_event_data(NativeScrollData(sevent.clone())))
+            }
             DomEventData::Wheel(wevent) => Some(wrap_event_data(NativeWheelData(wevent.clone()))),
 
+            DomEventData::DragStart(devent)
+            | DomEventData::Drag(devent)
+            | DomEventData::DragEnter(devent)
+            | DomEventData::DragOver(devent)
+            | DomEventData::DragLeave(devent)
+            | DomEventData::Drop(devent)
+            | DomEventData::DragEnd(devent) => {
+                let drag_data = NativeDragData::new(&doc.inner(), event.target, devent.clone());
+                Some(wrap_event_data(drag_data))
+            }
+
             DomEventData::Focus(_) => Some(wrap_event_data(NativeFocusData)),
             DomEventData::Blur(_) => Some(wrap_event_data(NativeFocusData)),
             DomEventData::FocusIn(_) => Some(wrap_event_data(NativeFocusData)),
             DomEventData::FocusOut(_) => Some(wrap_event_data(NativeFocusData)),
 
+            DomEventData::Visible(vevent) => {
+                Some(wrap_event_data(NativeVisibleData(vevent.clone())))
+            }
+            DomEventData::Resize(revent) => Some(wrap_event_data(NativeResizeData(revent.clone()))),
+
             DomEventData::KeyDown(kevent)
             | DomEventData::KeyUp(kevent)
             | DomEventData::KeyPress(kevent) => {
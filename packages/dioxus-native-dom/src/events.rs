@@ -1,15 +1,19 @@
 use blitz_dom::{BaseDocument, Node};
 use blitz_traits::events::{
-    BlitzKeyEvent, BlitzPointerEvent, BlitzPointerId, BlitzScrollEvent, BlitzWheelDelta,
-    BlitzWheelEvent, MouseEventButton,
+    BlitzDragEvent, BlitzIntersectionEvent, BlitzKeyEvent, BlitzPointerEvent, BlitzPointerId,
+    BlitzRect, BlitzResizeEvent, BlitzScrollEvent, BlitzWheelDelta, BlitzWheelEvent, DataTransfer,
+    MouseEventButton,
 };
+use bytes::Bytes;
+use dioxus_core::CapturedError;
 use dioxus_html::{
     AnimationData, CancelData, ClipboardData, CompositionData, DragData, FocusData, FormData,
-    FormValue, HasFileData, HasFocusData, HasFormData, HasKeyboardData, HasMouseData,
-    HasPointerData, HasScrollData, HasWheelData, HtmlEventConverter, ImageData, KeyboardData,
-    MediaData, MountedData, MountedError, MountedResult, MouseData, PlatformEventData, PointerData,
-    RenderedElementBacking, ResizeData, ScrollBehavior, ScrollData, ScrollToOptions, SelectionData,
-    ToggleData, TouchData, TransitionData, VisibleData, WheelData,
+    FormValue, HasDataTransferData, HasDragData, HasFileData, HasFocusData, HasFormData,
+    HasKeyboardData, HasMouseData, HasPointerData, HasResizeData, HasScrollData, HasVisibleData,
+    HasWheelData, HtmlEventConverter, ImageData, KeyboardData, MediaData, MountedData,
+    MountedError, MountedResult, MouseData, PlatformEventData, PointerData, RenderedElementBacking,
+    ResizeData, ResizeResult, ScrollBehavior, ScrollData, ScrollToOptions, SelectionData,
+    ToggleData, TouchData, TransitionData, VisibleData, VisibleResult, WheelData,
     geometry::{
         ClientPoint, ElementPoint, PagePoint, PixelsRect, PixelsSize, PixelsVector2D, ScreenPoint,
         WheelDelta,
@@ -20,14 +24,17 @@ use dioxus_html::{
         InteractionElementOffset, InteractionLocation, ModifiersInteraction, PointerInteraction,
     },
 };
+use futures_util::Stream;
 use keyboard_types::{Code, Key, Location, Modifiers};
 use std::{
     any::Any,
     cell::{Ref, RefCell, RefMut},
     fmt::Display,
     future::Future,
+    path::PathBuf,
     pin::Pin,
     rc::Rc,
+    time::SystemTime,
 };
 
 use crate::NodeId;
@@ -75,8 +82,8 @@ impl HtmlEventConverter for NativeConverter {
         unimplemented!("todo: convert_composition_data in dioxus-native. requires support in blitz")
     }
 
-    fn convert_drag_data(&self, _event: &PlatformEventData) -> DragData {
-        unimplemented!("todo: convert_drag_data in dioxus-native. requires support in blitz")
+    fn convert_drag_data(&self, event: &PlatformEventData) -> DragData {
+        event.downcast::<NativeDragData>().unwrap().clone().into()
     }
 
     fn convert_image_data(&self, _event: &PlatformEventData) -> ImageData {
@@ -123,12 +130,16 @@ impl HtmlEventConverter for NativeConverter {
         event.downcast::<NativeWheelData>().unwrap().clone().into()
     }
 
-    fn convert_resize_data(&self, _event: &PlatformEventData) -> ResizeData {
-        unimplemented!("todo: convert_resize_data in dioxus-native. requires support in blitz")
+    fn convert_resize_data(&self, event: &PlatformEventData) -> ResizeData {
+        event.downcast::<NativeResizeData>().unwrap().clone().into()
     }
 
-    fn convert_visible_data(&self, _event: &PlatformEventData) -> VisibleData {
-        unimplemented!("todo: convert_visible_data in dioxus-native. requires support in blitz")
+    fn convert_visible_data(&self, event: &PlatformEventData) -> VisibleData {
+        event
+            .downcast::<NativeVisibleData>()
+            .unwrap()
+            .clone()
+            .into()
     }
 }
 
@@ -512,3 +523,277 @@ impl InteractionLocation for NativeWheelData {
         PagePoint::new(self.0.page_x() as f64, self.0.page_y() as f64)
     }
 }
+
+#[derive(Clone)]
+pub struct NativeDragData {
+    pub(crate) event: BlitzDragEvent,
+    /// The position of the pointer relative to the padding edge of the event's target
+    pub(crate) element_coordinates: ElementPoint,
+}
+
+impl NativeDragData {
+    pub(crate) fn new(doc: &BaseDocument, target: usize, event: BlitzDragEvent) -> Self {
+        let element_coordinates =
+            element_coordinates(doc, target, event.coords.client_x, event.coords.client_y);
+        Self {
+            event,
+            element_coordinates,
+        }
+    }
+}
+
+impl HasDragData for NativeDragData {
+    fn as_any(&self) -> &dyn Any {
+        self as &dyn Any
+    }
+}
+
+impl HasFileData for NativeDragData {
+    fn files(&self) -> Vec<dioxus_html::FileData> {
+        self.event
+            .data_transfer
+            .files()
+            .into_iter()
+            .map(|path| dioxus_html::FileData::new(LocalFileData(path)))
+            .collect()
+    }
+}
+
+impl HasDataTransferData for NativeDragData {
+    fn data_transfer(&self) -> dioxus_html::DataTransfer {
+        dioxus_html::DataTransfer::new(NativeDataTransfer(self.event.data_transfer.clone()))
+    }
+}
+
+/// Exposes a drag's data to Dioxus. Shares the data with the drag, so changes are visible to later
+/// drag events.
+struct NativeDataTransfer(DataTransfer);
+
+impl dioxus_html::NativeDataTransfer for NativeDataTransfer {
+    fn get_data(&self, format: &str) -> Option<String> {
+        self.0.get_data(format)
+    }
+
+    fn set_data(&self, format: &str, data: &str) -> Result<(), String> {
+        self.0.set_data(format, data);
+        Ok(())
+    }
+
+    fn clear_data(&self, format: Option<&str>) -> Result<(), String> {
+        self.0.clear_data(format);
+        Ok(())
+    }
+
+    // TODO: support effectAllowed. Until then every effect is allowed.
+    fn effect_allowed(&self) -> String {
+        String::from("all")
+    }
+
+    fn set_effect_allowed(&self, _effect: &str) {}
+
+    fn drop_effect(&self) -> String {
+        self.0.drop_effect().as_str().to_string()
+    }
+
+    fn set_drop_effect(&self, effect: &str) {
+        // Invalid values are ignored
+        if let Ok(effect) = effect.parse() {
+            self.0.set_drop_effect(effect);
+        }
+    }
+
+    fn files(&self) -> Vec<dioxus_html::FileData> {
+        self.0
+            .files()
+            .into_iter()
+            .map(|path| dioxus_html::FileData::new(LocalFileData(path)))
+            .collect()
+    }
+}
+
+impl HasMouseData for NativeDragData {
+    fn as_any(&self) -> &dyn Any {
+        self as &dyn Any
+    }
+}
+
+impl PointerInteraction for NativeDragData {
+    fn trigger_button(&self) -> Option<MouseButton> {
+        None
+    }
+
+    fn held_buttons(&self) -> MouseButtonSet {
+        dioxus_html::input_data::decode_mouse_button_set(self.event.buttons.bits() as u16)
+    }
+}
+
+impl ModifiersInteraction for NativeDragData {
+    fn modifiers(&self) -> Modifiers {
+        self.event.mods
+    }
+}
+
+impl InteractionElementOffset for NativeDragData {
+    fn element_coordinates(&self) -> ElementPoint {
+        self.element_coordinates
+    }
+}
+
+impl InteractionLocation for NativeDragData {
+    fn client_coordinates(&self) -> ClientPoint {
+        ClientPoint::new(
+            self.event.coords.client_x as f64,
+            self.event.coords.client_y as f64,
+        )
+    }
+
+    fn screen_coordinates(&self) -> ScreenPoint {
+        ScreenPoint::new(
+            self.event.coords.screen_x as f64,
+            self.event.coords.screen_y as f64,
+        )
+    }
+
+    fn page_coordinates(&self) -> PagePoint {
+        PagePoint::new(self.event.page_x() as f64, self.event.page_y() as f64)
+    }
+}
+
+/// The position of a point (in client coordinates) relative to the padding edge of `node_id`
+fn element_coordinates(
+    doc: &BaseDocument,
+    node_id: usize,
+    client_x: f32,
+    client_y: f32,
+) -> ElementPoint {
+    let (Some(rect), Some(node)) = (doc.get_client_bounding_rect(node_id), doc.get_node(node_id))
+    else {
+        return ElementPoint::new(0.0, 0.0);
+    };
+    let border = node.unrounded_layout.border;
+    ElementPoint::new(
+        client_x as f64 - rect.x - border.left as f64,
+        client_y as f64 - rect.y - border.top as f64,
+    )
+}
+
+/// A file from the local filesystem (e.g. one dropped onto the window)
+struct LocalFileData(PathBuf);
+
+impl dioxus_html::NativeFileData for LocalFileData {
+    fn name(&self) -> String {
+        self.0
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn size(&self) -> u64 {
+        std::fs::metadata(&self.0)
+            .map(|meta| meta.len())
+            .unwrap_or(0)
+    }
+
+    fn last_modified(&self) -> u64 {
+        std::fs::metadata(&self.0)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.0.clone()
+    }
+
+    fn content_type(&self) -> Option<String> {
+        None
+    }
+
+    fn read_bytes(&self) -> Pin<Box<dyn Future<Output = Result<Bytes, CapturedError>> + 'static>> {
+        let path = self.0.clone();
+        Box::pin(async move { Ok(Bytes::from(std::fs::read(path)?)) })
+    }
+
+    fn read_string(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<String, CapturedError>> + 'static>> {
+        let path = self.0.clone();
+        Box::pin(async move { Ok(std::fs::read_to_string(path)?) })
+    }
+
+    fn byte_stream(
+        &self,
+    ) -> Pin<Box<dyn Stream<Item = Result<Bytes, CapturedError>> + Send + 'static>> {
+        let path = self.0.clone();
+        Box::pin(futures_util::stream::once(async move {
+            Ok(Bytes::from(std::fs::read(path)?))
+        }))
+    }
+
+    fn inner(&self) -> &dyn Any {
+        &self.0
+    }
+}
+
+fn to_pixels_rect(rect: BlitzRect) -> PixelsRect {
+    PixelsRect::new(
+        Point2D::new(rect.x, rect.y),
+        Size2D::new(rect.width, rect.height),
+    )
+}
+
+#[derive(Clone)]
+pub struct NativeVisibleData(pub(crate) BlitzIntersectionEvent);
+impl HasVisibleData for NativeVisibleData {
+    fn as_any(&self) -> &dyn Any {
+        self as &dyn Any
+    }
+
+    fn get_bounding_client_rect(&self) -> VisibleResult<PixelsRect> {
+        Ok(to_pixels_rect(self.0.bounding_client_rect))
+    }
+
+    fn get_intersection_ratio(&self) -> VisibleResult<f64> {
+        Ok(self.0.intersection_ratio)
+    }
+
+    fn get_intersection_rect(&self) -> VisibleResult<PixelsRect> {
+        Ok(to_pixels_rect(self.0.intersection_rect))
+    }
+
+    fn is_intersecting(&self) -> VisibleResult<bool> {
+        Ok(self.0.is_intersecting)
+    }
+
+    fn get_root_bounds(&self) -> VisibleResult<PixelsRect> {
+        Ok(to_pixels_rect(self.0.root_bounds))
+    }
+
+    fn get_time(&self) -> VisibleResult<SystemTime> {
+        Ok(self.0.time)
+    }
+}
+
+#[derive(Clone)]
+pub struct NativeResizeData(pub(crate) BlitzResizeEvent);
+impl HasResizeData for NativeResizeData {
+    fn as_any(&self) -> &dyn Any {
+        self as &dyn Any
+    }
+
+    fn get_border_box_size(&self) -> ResizeResult<PixelsSize> {
+        Ok(PixelsSize::new(
+            self.0.border_box_width,
+            self.0.border_box_height,
+        ))
+    }
+
+    fn get_content_box_size(&self) -> ResizeResult<PixelsSize> {
+        Ok(PixelsSize::new(
+            self.0.content_box_width,
+            self.0.content_box_height,
+        ))
+    }
+}
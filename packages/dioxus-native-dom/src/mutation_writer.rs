@@ -1,6 +1,7 @@
 //! Integration between Dioxus and Blitz
+use crate::sub_document::SubDocumentSource;
 use crate::{NodeId, SubDocumentAttr, qual_name, trace};
-use blitz_dom::{BaseDocument, Document as _, DocumentMutator};
+use blitz_dom::{BaseDocument, DocumentMutator, IntersectionObserverOptions};
 use blitz_traits::events::DomEventKind;
 use dioxus_core::{
     AttributeValue, ElementId, Template, TemplateAttribute, TemplateNode, WriteMutations,
@@ -18,7 +19,7 @@ pub struct DioxusState {
     /// Mapping from vdom ElementId -> rdom NodeId
     pub(crate) node_id_mapping: Vec<Option<NodeId>>,
     /// Count of each handler type
-    pub(crate) event_handler_counts: [u32; 32],
+    pub(crate) event_handler_counts: [u32; 64],
     /// Mounted events queued as elements are mounted
     pub(crate) queued_mounted_events: Vec<ElementId>,
 }
@@ -30,7 +31,7 @@ impl DioxusState {
             templates: FxHashMap::default(),
             stack: vec![root_id],
             node_id_mapping: vec![Some(root_id)],
-            event_handler_counts: [0; 32],
+            event_handler_counts: [0; 64],
             queued_mounted_events: Vec::new(),
         }
     }
@@ -204,14 +205,21 @@ impl WriteMutations for MutationWriter<'_> {
         if local_name == "__webview_document" {
             match value {
                 AttributeValue::Any(sub_doc_attr) => {
-                    if let Some(sub_doc_attr) =
-                        sub_doc_attr.as_any().downcast_ref::<SubDocumentAttr>()
-                        && let Some(mut sub_document) = sub_doc_attr.take_document()
-                    {
-                        sub_document
-                            .inner_mut()
-                            .set_shell_provider(self.docm.doc.shell_provider.clone());
-                        self.docm.set_sub_document(node_id, sub_document as _);
+                    let source = sub_doc_attr
+                        .as_any()
+                        .downcast_ref::<SubDocumentAttr>()
+                        .and_then(SubDocumentAttr::take_source);
+                    match source {
+                        Some(SubDocumentSource::Document(mut sub_document)) => {
+                            sub_document
+                                .inner_mut()
+                                .set_shell_provider(self.docm.doc.shell_provider.clone());
+                            self.docm.set_sub_document(node_id, sub_document);
+                        }
+                        Some(SubDocumentSource::Loader(loader)) => {
+                            self.docm.set_sub_document_loader(node_id, loader);
+                        }
+                        None => {}
                     }
                 }
                 _ => self.docm.remove_sub_document(node_id),
@@ -285,13 +293,32 @@ impl WriteMutations for MutationWriter<'_> {
             let idx = kind.discriminant() as usize;
             self.state.event_handler_counts[idx] += 1;
         }
+
+        // Visible and resize events are generated by observing the element
+        let node_id = self.state.element_to_node_id(id);
+        match name {
+            "visible" => self
+                .docm
+                .doc
+                .observe_intersection(node_id, IntersectionObserverOptions::default()),
+            "resize" => self.docm.doc.observe_resize(node_id),
+            _ => {}
+        }
     }
 
-    fn remove_event_listener(&mut self, name: &'static str, _id: ElementId) {
+    fn remove_event_listener(&mut self, name: &'static str, id: ElementId) {
         if let Ok(kind) = DomEventKind::from_str(name) {
             let idx = kind.discriminant() as usize;
             self.state.event_handler_counts[idx] -= 1;
         }
+
+        if let Some(node_id) = self.state.try_element_to_node_id(id) {
+            match name {
+                "visible" => self.docm.doc.unobserve_intersection(node_id),
+                "resize" => self.docm.doc.unobserve_resize(node_id),
+                _ => {}
+            }
+        }
     }
 }
 
@@ -1,29 +1,59 @@
 use std::{cell::RefCell, rc::Rc};
 
-use blitz_dom::{BaseDocument, PlainDocument};
+use blitz_dom::{BaseDocument, Document, PlainDocument, SubDocumentLoader};
 use dioxus_core::{AttributeValue, IntoAttributeValue};
 
+pub(crate) enum SubDocumentSource {
+    Document(Box<dyn Document>),
+    Loader(SubDocumentLoader),
+}
+
 // Hack to get write-once semantics for an attribute
 #[derive(Clone)]
 pub struct SubDocumentAttr {
-    id: usize,
-    doc: Rc<RefCell<Option<Box<PlainDocument>>>>,
+    /// The id of the wrapped document (`None` until a lazy document has been created)
+    id: Option<usize>,
+    doc: Rc<RefCell<Option<SubDocumentSource>>>,
 }
 
 impl SubDocumentAttr {
     pub fn new(doc: BaseDocument) -> Self {
+        Self::from_document(Box::new(PlainDocument(doc)))
+    }
+    /// Wrap an existing document (such as one previously taken out of a `web-view` element)
+    pub fn from_document(doc: Box<dyn Document>) -> Self {
         let id = doc.id();
-        let wrapped = Rc::new(RefCell::new(Some(Box::new(PlainDocument(doc)))));
-        Self { id, doc: wrapped }
+        let wrapped = Rc::new(RefCell::new(Some(SubDocumentSource::Document(doc))));
+        Self {
+            id: Some(id),
+            doc: wrapped,
+        }
+    }
+    /// Create the document with `loader` when it is needed. If the `web-view` element has
+    /// `loading="lazy"` this is deferred until the element is near the viewport.
+    pub fn lazy(loader: impl FnOnce() -> Box<dyn Document> + Send + 'static) -> Self {
+        let loader: SubDocumentLoader = Box::new(loader);
+        let wrapped = Rc::new(RefCell::new(Some(SubDocumentSource::Loader(loader))));
+        Self {
+            id: None,
+            doc: wrapped,
+        }
+    }
+    /// The id of the wrapped document, if it isn't created lazily
+    pub fn id(&self) -> Option<usize> {
+        self.id
     }
-    pub fn take_document(&self) -> Option<Box<PlainDocument>> {
+    pub(crate) fn take_source(&self) -> Option<SubDocumentSource> {
         self.doc.borrow_mut().take()
     }
 }
 
 impl PartialEq for SubDocumentAttr {
     fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+        match (self.id, other.id) {
+            (Some(id), Some(other_id)) => id == other_id,
+            _ => Rc::ptr_eq(&self.doc, &other.doc),
+        }
     }
 }
 
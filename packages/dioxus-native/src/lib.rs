@@ -229,6 +229,23 @@ pub fn launch_cfg_with_props<P: Clone + 'static, M: 'static>(
             ..Default::default()
         },
     );
+
+    // Start a remote inspector if requested (e.g. `BLITZ_DEVTOOLS_ADDR=127.0.0.1:9222`)
+    #[cfg(feature = "devtools-server")]
+    #[cfg(not(target_arch = "wasm32"))]
+    let doc = {
+        use blitz_dom::Document as _;
+        let mut doc = doc;
+        if let Ok(addr) = std::env::var("BLITZ_DEVTOOLS_ADDR") {
+            let _result = doc.inner_mut().start_devtools_server(addr.as_str());
+            #[cfg(feature = "tracing")]
+            match _result {
+                Ok(addr) => tracing::info!("Devtools server listening on ws://{addr}"),
+                Err(err) => tracing::error!("Failed to start devtools server on {addr}: {err}"),
+            }
+        }
+        doc
+    };
     #[cfg(any(
         feature = "vello",
         all(
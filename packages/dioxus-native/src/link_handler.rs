@@ -2,15 +2,30 @@ use blitz_traits::{
     navigation::{NavigationOptions, NavigationProvider},
     net::Method,
 };
+use webbrowser::{Browser, BrowserOptions};
 
 pub(crate) struct DioxusNativeNavigationProvider;
 
 impl NavigationProvider for DioxusNativeNavigationProvider {
     fn navigate_to(&self, options: NavigationOptions) {
-        if options.method == Method::GET
-            && matches!(options.url.scheme(), "http" | "https" | "mailto")
-            && let Err(_err) = webbrowser::open(options.url.as_str())
+        if options.method != Method::GET
+            || !matches!(options.url.scheme(), "http" | "https" | "mailto")
         {
+            return;
+        }
+
+        // Dioxus Native apps don't have tabs of their own, so links are always opened in the
+        // system browser. Links opened in new tabs ask it for a new tab (or window) explicitly.
+        let result = if options.open_in_new_tab {
+            webbrowser::open_browser_with_options(
+                Browser::Default,
+                options.url.as_str(),
+                BrowserOptions::new().with_target_hint("_blank"),
+            )
+        } else {
+            webbrowser::open(options.url.as_str())
+        };
+        if let Err(_err) = result {
             #[cfg(feature = "tracing")]
             tracing::error!("Failed to open URL: {}", _err);
         }
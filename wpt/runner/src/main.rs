@@ -3,34 +3,31 @@ use anyrender::ImageRenderer as _;
 use anyrender_vello::VelloImageRenderer;
 #[cfg(feature = "cpu")]
 use anyrender_vello_cpu::VelloCpuImageRenderer as VelloImageRenderer;
-use atomic_float::AtomicF64;
 use blitz_dom::net::Resource;
 use blitz_traits::navigation::{DummyNavigationProvider, NavigationProvider};
 use blitz_traits::shell::{ColorScheme, Viewport};
-use panic_backtrace::StashedPanicInfo;
+use panic_backtrace::PanicReport;
 use parley::FontContext;
+use regression::Baseline;
 use report::{generate_expectations, generate_report};
 use supports_hyperlinks::supports_hyperlinks;
 use terminal_link::Link;
-use test_runners::{SubtestResult, process_test_file};
-use thread_local::ThreadLocal;
+use test_runners::{HarnessShims, RefMetadataParser, SubtestResult, process_test_file};
 use url::Url;
 
-use rayon::prelude::*;
 use regex::Regex;
 
 use bitflags::bitflags;
 use log::{error, info};
 use owo_colors::OwoColorize;
-use std::cell::RefCell;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{BufWriter, Write, stdout};
 use std::panic::{AssertUnwindSafe, catch_unwind};
 use std::path::{self, Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::AtomicU32;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{Duration, Instant, SystemTime};
 use std::{env, fs};
 
@@ -38,7 +35,9 @@ mod test_runners;
 
 mod net_provider;
 mod panic_backtrace;
+mod regression;
 mod report;
+mod worker;
 
 use net_provider::WptNetProvider;
 
@@ -55,7 +54,7 @@ const HEIGHT: u32 = 600;
 const SCALE: f64 = 1.0;
 
 bitflags! {
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Serialize, Deserialize)]
     pub struct TestFlags : u32 {
         const USES_FLOAT = 0b00000001;
         const USES_INTRINSIC_SIZE = 0b00000010;
@@ -68,10 +67,11 @@ bitflags! {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 enum TestKind {
     Ref,
     Attr,
+    Harness,
     Unknown,
 }
 
@@ -80,17 +80,19 @@ impl Display for TestKind {
         match self {
             TestKind::Ref => f.write_str("REF"),
             TestKind::Attr => f.write_str("ATT"),
+            TestKind::Harness => f.write_str("HAR"),
             TestKind::Unknown => f.write_str("UNK"),
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 enum TestStatus {
     Pass,
     Fail,
     Skip,
     Crash,
+    Timeout,
 }
 
 impl TestStatus {
@@ -100,11 +102,23 @@ impl TestStatus {
             TestStatus::Fail => "FAIL",
             TestStatus::Skip => "SKIP",
             TestStatus::Crash => "CRASH",
+            TestStatus::Timeout => "TIMEOUT",
+        }
+    }
+
+    /// How bad the status is. Used to pick which result to report for a flaky test.
+    fn severity(&self) -> u8 {
+        match self {
+            TestStatus::Pass => 0,
+            TestStatus::Skip => 1,
+            TestStatus::Fail => 2,
+            TestStatus::Timeout => 3,
+            TestStatus::Crash => 4,
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 struct SubtestCounts {
     pass: u32,
     total: u32,
@@ -171,6 +185,67 @@ fn filter_path(p: &Path) -> bool {
     !(is_ref | is_support_file | is_blocked | is_dir)
 }
 
+/// Options passed as `--name=value` arguments. All other arguments are test suites.
+struct Options {
+    /// `--baseline=<path>`: A `wpt_expectations.txt` or `wptreport.json` to compare results against
+    baseline: Option<PathBuf>,
+    /// `--timeout=<seconds>`: How long a test may run before it is killed
+    timeout: Duration,
+    /// `--retries=<count>`: How many times to retry tests which crash, time out or regress
+    retries: u32,
+    /// `--shard=<index>/<count>`: Only run every `count`th test, starting with the `index`th (1-based)
+    shard: Option<(usize, usize)>,
+    /// `--jobs=<count>`: The number of worker processes to run tests in
+    jobs: usize,
+    /// `--worker`: Run as a worker process (used internally)
+    worker: bool,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut options = Options {
+            baseline: None,
+            timeout: Duration::from_secs(10),
+            retries: 1,
+            shard: None,
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            worker: false,
+        };
+
+        for arg in std::env::args().skip(1).filter(|arg| arg.starts_with('-')) {
+            let (name, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            let invalid = || format!("Invalid value for {name}: {value}");
+            match name {
+                "--baseline" => options.baseline = Some(PathBuf::from(value)),
+                "--timeout" => {
+                    let seconds: f64 = value.parse().map_err(|_| invalid())?;
+                    options.timeout = Duration::from_secs_f64(seconds);
+                }
+                "--retries" => options.retries = value.parse().map_err(|_| invalid())?,
+                "--jobs" => {
+                    options.jobs = value.parse().map_err(|_| invalid())?;
+                    if options.jobs == 0 {
+                        return Err(invalid());
+                    }
+                }
+                "--shard" => {
+                    let (index, count) = value.split_once('/').ok_or_else(invalid)?;
+                    let index: usize = index.parse().map_err(|_| invalid())?;
+                    let count: usize = count.parse().map_err(|_| invalid())?;
+                    if index == 0 || index > count {
+                        return Err(invalid());
+                    }
+                    options.shard = Some((index, count));
+                }
+                "--worker" => options.worker = true,
+                _ => return Err(format!("Unknown option: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
 fn collect_tests(wpt_dir: &Path) -> Vec<PathBuf> {
     let mut test_paths = Vec::new();
 
@@ -204,6 +279,10 @@ fn collect_tests(wpt_dir: &Path) -> Vec<PathBuf> {
         }
     }
 
+    // Sort tests so that sharding is deterministic
+    test_paths.sort();
+    test_paths.dedup();
+
     test_paths
 }
 
@@ -232,7 +311,8 @@ struct ThreadCtx {
     buffers: Buffers,
 
     // Things that aren't really thread-specifc, but are convenient to store here
-    reftest_re: Regex,
+    ref_metadata_parser: RefMetadataParser,
+    harness_shims: HarnessShims,
     attrtest_re: Regex,
     float_re: Regex,
     intrinsic_re: Regex,
@@ -247,6 +327,7 @@ struct ThreadCtx {
     dummy_base_url: Url,
 }
 
+#[derive(Serialize, Deserialize)]
 struct TestResult {
     name: String,
     kind: TestKind,
@@ -255,10 +336,32 @@ struct TestResult {
     subtest_counts: SubtestCounts,
     subtest_results: Vec<SubtestResult>,
     duration: Duration,
-    panic_info: Option<StashedPanicInfo>,
+    panic_info: Option<PanicReport>,
+    /// Other statuses seen when the test was retried
+    flaky_statuses: Vec<TestStatus>,
 }
 
 impl TestResult {
+    /// Merge the result of retrying a test into this result. The worse of the two results is
+    /// kept (so a flaky failure is still reported as a failure), and the other status is recorded
+    /// as flaky.
+    fn merge_retry(&mut self, retry: TestResult) {
+        if retry.status == self.status {
+            return;
+        }
+
+        let mut flaky_statuses = std::mem::take(&mut self.flaky_statuses);
+        let other_status = if retry.status.severity() > self.status.severity() {
+            std::mem::replace(self, retry).status
+        } else {
+            retry.status
+        };
+        if !flaky_statuses.contains(&other_status) {
+            flaky_statuses.push(other_status);
+        }
+        self.flaky_statuses = flaky_statuses;
+    }
+
     fn print_to(&self, mut out: impl Write) {
         let result_str = if supports_hyperlinks() {
             let url = format!("https://wpt.live/{}", &self.name);
@@ -290,7 +393,9 @@ impl TestResult {
             }
             TestStatus::Fail => write!(out, "{}", result_str.red()).unwrap(),
             TestStatus::Skip => write!(out, "{}", result_str.bright_black()).unwrap(),
-            TestStatus::Crash => write!(out, "{}", result_str.bright_magenta()).unwrap(),
+            TestStatus::Crash | TestStatus::Timeout => {
+                write!(out, "{}", result_str.bright_magenta()).unwrap()
+            }
         };
 
         // Write test kind
@@ -334,6 +439,13 @@ impl TestResult {
             write!(out, "{}", ")".bright_black()).unwrap();
         }
 
+        // Write flaky marker
+        if !self.flaky_statuses.is_empty() {
+            let statuses: Vec<_> = self.flaky_statuses.iter().map(|s| s.as_str()).collect();
+            let marker = format!(" (flaky: {})", statuses.join(", "));
+            write!(out, "{}", marker.yellow()).unwrap();
+        }
+
         // Newline
         writeln!(out).unwrap();
 
@@ -341,227 +453,231 @@ impl TestResult {
             if let Some(panic_msg) = &panic_info.message {
                 writeln!(out, "{panic_msg}").unwrap();
             }
-            writeln!(
-                out,
-                "Panicked at {}:{}:{}",
-                panic_info.file, panic_info.line, panic_info.column
-            )
-            .unwrap();
-
-            if let Some(trimmed_backtrace) = panic_backtrace::trim_backtrace(&panic_info.backtrace)
-            {
+            if let Some(location) = &panic_info.location {
+                writeln!(out, "Panicked at {location}").unwrap();
+            }
+            if let Some(trimmed_backtrace) = &panic_info.backtrace {
                 writeln!(out, "Backtrace:\n{trimmed_backtrace}").unwrap();
             }
         }
     }
 }
 
+fn create_thread_ctx(wpt_dir: &Path, out_dir: &Path) -> ThreadCtx {
+    let renderer = VelloImageRenderer::new(WIDTH, HEIGHT);
+    let font_ctx = FontContext::default();
+    let test_buffer = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    let ref_buffer = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    let viewport = Viewport::new(
+        (WIDTH as f64 * SCALE).floor() as u32,
+        (HEIGHT as f64 * SCALE).floor() as u32,
+        SCALE as f32,
+        ColorScheme::Light,
+    );
+    let net_provider = Arc::new(WptNetProvider::new(wpt_dir));
+    let ref_metadata_parser = RefMetadataParser::new();
+    let harness_shims = HarnessShims::default();
+
+    let float_re = Regex::new(r#"float:"#).unwrap();
+    let intrinsic_re = Regex::new(r#"(width|height): ?(min|max|fit)-content"#).unwrap();
+    let calc_re = Regex::new(r#"calc\("#).unwrap();
+    let direction_re = Regex::new(r#"direction:|directionRTL"#).unwrap();
+    let writing_mode_re = Regex::new(r#"writing-mode:|vertical(RL|LR)"#).unwrap();
+    let subgrid_re = Regex::new(r#"subgrid"#).unwrap();
+    let masonry_re = Regex::new(r#"masonry"#).unwrap();
+    let script_re = Regex::new(r#"<script|onload="#).unwrap();
+
+    let attrtest_re =
+        Regex::new(r#"checkLayout\(\s*['"]([^'"]*)['"]\s*(,\s*(true|false))?\)"#).unwrap();
+
+    let dummy_base_url = Url::parse("http://dummy.local").unwrap();
+    let navigation_provider = Arc::new(DummyNavigationProvider);
+
+    ThreadCtx {
+        viewport,
+        net_provider,
+        renderer,
+        font_ctx,
+        buffers: Buffers {
+            test_buffer,
+            ref_buffer,
+        },
+        ref_metadata_parser,
+        harness_shims,
+        attrtest_re,
+        float_re,
+        intrinsic_re,
+        calc_re,
+        direction_re,
+        writing_mode_re,
+        subgrid_re,
+        masonry_re,
+        script_re,
+        out_dir: out_dir.to_path_buf(),
+        wpt_dir: wpt_dir.to_path_buf(),
+        dummy_base_url,
+        navigation_provider,
+    }
+}
+
+/// Run a single test, catching any panics
+fn run_test(ctx: &mut ThreadCtx, relative_path: String) -> TestResult {
+    // Clear any pending requests to avoid failed requests from a previous test interfering with subsequent tests
+    ctx.net_provider.reset();
+
+    let start = Instant::now();
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        panic_backtrace::backtrace_cutoff(|| process_test_file(ctx, &relative_path))
+    }));
+    let (kind, flags, status, subtest_counts, panic_info, subtest_results) = match result {
+        Ok((kind, flags, status, subtest_counts, subtest_results)) => {
+            (kind, flags, status, subtest_counts, None, subtest_results)
+        }
+        Err(_) => {
+            let panic_info = panic_backtrace::take_stashed_panic_info().map(PanicReport::from);
+            (
+                TestKind::Unknown,
+                TestFlags::empty(),
+                TestStatus::Crash,
+                SubtestCounts::ZERO_OF_ZERO,
+                panic_info,
+                Vec::new(),
+            )
+        }
+    };
+
+    TestResult {
+        name: relative_path,
+        kind,
+        flags,
+        status,
+        subtest_counts,
+        subtest_results,
+        duration: start.elapsed(),
+        panic_info,
+        flaky_statuses: Vec::new(),
+    }
+}
+
+/// Whether a test should be retried to check whether its result is flaky
+fn should_retry(result: &TestResult, baseline: Option<&Baseline>) -> bool {
+    matches!(result.status, TestStatus::Crash | TestStatus::Timeout)
+        || baseline.is_some_and(|baseline| baseline.is_regression(result))
+}
+
 fn main() {
     env_logger::init();
     std::panic::set_hook(Box::new(panic_backtrace::stash_panic_handler));
 
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(err) => {
+            error!("{err}");
+            std::process::exit(2);
+        }
+    };
+
     let wpt_dir = path::absolute(env::var("WPT_DIR").expect("WPT_DIR is not set")).unwrap();
+    let cargo_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = cargo_dir.parent().unwrap().join("output");
+
+    if options.worker {
+        worker::run_worker(create_thread_ctx(&wpt_dir, &out_dir));
+        return;
+    }
+
     info!("WPT_DIR: {}", wpt_dir.display());
     if !wpt_dir.exists() {
         error!(
             "WPT_DIR does not exist. This should be set to a local copy of https://github.com/web-platform-tests/wpt."
         );
     }
-    let test_paths = collect_tests(&wpt_dir);
+    let mut test_paths = collect_tests(&wpt_dir);
+    if let Some((index, shard_count)) = options.shard {
+        test_paths = test_paths
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| idx % shard_count == index - 1)
+            .map(|(_, path)| path)
+            .collect();
+        println!("Running shard {index}/{shard_count}");
+    }
+    let test_paths: Vec<String> = test_paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(&wpt_dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace("\\", "/")
+        })
+        .collect();
     let count = test_paths.len();
 
-    let cargo_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
-    let out_dir = cargo_dir.parent().unwrap().join("output");
+    // Load the baseline before clearing the output directory as it may be the output of a previous run
+    let baseline = options
+        .baseline
+        .as_ref()
+        .map(|path| match Baseline::load(path) {
+            Ok(baseline) => baseline,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(2);
+            }
+        });
+
     if fs::exists(&out_dir).unwrap() {
         fs::remove_dir_all(&out_dir).unwrap();
     }
     fs::create_dir(&out_dir).unwrap();
 
-    let pass_count = AtomicU32::new(0);
-    let fail_count = AtomicU32::new(0);
-    let skip_count = AtomicU32::new(0);
-    let crash_count = AtomicU32::new(0);
-
-    let subtest_count = AtomicU32::new(0);
-    let subtest_pass_count = AtomicU32::new(0);
-    let subtest_fail_count = AtomicU32::new(0);
-
-    let fractional_pass_count = AtomicF64::new(0.0);
-
-    let masonry_fail_count = AtomicU32::new(0);
-    let subgrid_fail_count = AtomicU32::new(0);
-    let writing_mode_fail_count = AtomicU32::new(0);
-    let direction_fail_count = AtomicU32::new(0);
-    let float_fail_count = AtomicU32::new(0);
-    let calc_fail_count = AtomicU32::new(0);
-    let intrinsic_size_fail_count = AtomicU32::new(0);
-    let script_fail_count = AtomicU32::new(0);
-    let other_fail_count = AtomicU32::new(0);
     let start = Instant::now();
     let start_timestamp = unix_timestamp();
 
     let num = AtomicU32::new(0);
+    let mut results = worker::run_tests(&test_paths, options.jobs, options.timeout, |result| {
+        let num = num.fetch_add(1, Ordering::Relaxed) + 1;
+
+        // Print status line
+        let mut out = stdout().lock();
+        write!(out, "[{num}/{count}] ").unwrap();
+        result.print_to(out);
+    });
+
+    // Retry tests which crashed, timed out or regressed to detect flaky tests
+    for attempt in 1..=options.retries {
+        let retry_indices: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| should_retry(result, baseline.as_ref()))
+            .map(|(idx, _)| idx)
+            .collect();
+        if retry_indices.is_empty() {
+            break;
+        }
 
-    let base_font_context = parley::FontContext::default();
-
-    let thread_state: ThreadLocal<RefCell<ThreadCtx>> = ThreadLocal::new();
-
-    let mut results: Vec<TestResult> = test_paths
-        .into_par_iter()
-        .map(|path| {
-            let mut ctx = thread_state
-                .get_or(|| {
-                    let renderer = VelloImageRenderer::new(WIDTH, HEIGHT);
-                    let font_ctx = base_font_context.clone();
-                    let test_buffer = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
-                    let ref_buffer = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
-                    let viewport = Viewport::new(
-                        (WIDTH as f64 * SCALE).floor() as u32,
-                        (HEIGHT as f64 * SCALE).floor() as u32,
-                        SCALE as f32,
-                        ColorScheme::Light,
-                    );
-                    let net_provider = Arc::new(WptNetProvider::new(&wpt_dir));
-                    let reftest_re =
-                        Regex::new(r#"<link\s+rel=['"]?match['"]?\s+href=['"]([^'"]+)['"]"#)
-                            .unwrap();
-
-                    let float_re = Regex::new(r#"float:"#).unwrap();
-                    let intrinsic_re =
-                        Regex::new(r#"(width|height): ?(min|max|fit)-content"#).unwrap();
-                    let calc_re = Regex::new(r#"calc\("#).unwrap();
-                    let direction_re = Regex::new(r#"direction:|directionRTL"#).unwrap();
-                    let writing_mode_re = Regex::new(r#"writing-mode:|vertical(RL|LR)"#).unwrap();
-                    let subgrid_re = Regex::new(r#"subgrid"#).unwrap();
-                    let masonry_re = Regex::new(r#"masonry"#).unwrap();
-                    let script_re = Regex::new(r#"<script|onload="#).unwrap();
-
-                    let attrtest_re =
-                        Regex::new(r#"checkLayout\(\s*['"]([^'"]*)['"]\s*(,\s*(true|false))?\)"#)
-                            .unwrap();
-
-                    let dummy_base_url = Url::parse("http://dummy.local").unwrap();
-                    let navigation_provider = Arc::new(DummyNavigationProvider);
-
-                    RefCell::new(ThreadCtx {
-                        viewport,
-                        net_provider,
-                        renderer,
-                        font_ctx,
-                        buffers: Buffers {
-                            test_buffer,
-                            ref_buffer,
-                        },
-                        reftest_re,
-                        attrtest_re,
-                        float_re,
-                        intrinsic_re,
-                        calc_re,
-                        direction_re,
-                        writing_mode_re,
-                        subgrid_re,
-                        masonry_re,
-                        script_re,
-                        out_dir: out_dir.clone(),
-                        wpt_dir: wpt_dir.clone(),
-                        dummy_base_url,
-                        navigation_provider,
-                    })
-                })
-                .borrow_mut();
-
-            // Clear any pending requests to avoid failed requests from a previous test interfering with subsequent tests
-            ctx.net_provider.reset();
-
+        println!(
+            "\nRetrying {} tests (attempt {attempt}/{})\n",
+            retry_indices.len(),
+            options.retries
+        );
+        let retry_paths: Vec<String> = retry_indices
+            .iter()
+            .map(|&idx| results[idx].name.clone())
+            .collect();
+        let retry_count = retry_paths.len();
+        let num = AtomicU32::new(0);
+        let retried = worker::run_tests(&retry_paths, options.jobs, options.timeout, |result| {
             let num = num.fetch_add(1, Ordering::Relaxed) + 1;
-
-            let relative_path = path
-                .strip_prefix(&ctx.wpt_dir)
-                .unwrap()
-                .to_string_lossy()
-                .replace("\\", "/");
-
-            let start = Instant::now();
-
-            let result = catch_unwind(AssertUnwindSafe(|| {
-                panic_backtrace::backtrace_cutoff(|| process_test_file(&mut ctx, &relative_path))
-            }));
-            let (kind, flags, status, subtest_counts, panic_info, subtest_results) = match result {
-                Ok((kind, flags, status, subtest_counts, subtest_results)) => {
-                    (kind, flags, status, subtest_counts, None, subtest_results)
-                }
-                Err(_) => {
-                    let panic_info = panic_backtrace::take_stashed_panic_info();
-                    (
-                        TestKind::Unknown,
-                        TestFlags::empty(),
-                        TestStatus::Crash,
-                        SubtestCounts::ZERO_OF_ZERO,
-                        panic_info,
-                        Vec::new(),
-                    )
-                }
-            };
-
-            // Bump counts
-            match status {
-                TestStatus::Pass => pass_count.fetch_add(1, Ordering::Relaxed),
-                TestStatus::Fail => {
-                    if flags.contains(TestFlags::USES_MASONRY) {
-                        masonry_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_SUBGRID) {
-                        subgrid_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_WRITING_MODE) {
-                        writing_mode_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_DIRECTION) {
-                        direction_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_INTRINSIC_SIZE) {
-                        intrinsic_size_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_CALC) {
-                        calc_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if flags.contains(TestFlags::USES_FLOAT) {
-                        float_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else if kind == TestKind::Ref && flags.contains(TestFlags::USES_SCRIPT) {
-                        script_fail_count.fetch_add(1, Ordering::Relaxed);
-                    } else {
-                        other_fail_count.fetch_add(1, Ordering::Relaxed);
-                    }
-                    fail_count.fetch_add(1, Ordering::Relaxed)
-                }
-                TestStatus::Skip => skip_count.fetch_add(1, Ordering::Relaxed),
-                TestStatus::Crash => crash_count.fetch_add(1, Ordering::Relaxed),
-            };
-
-            // Bump fractional count
-            fractional_pass_count.fetch_add(subtest_counts.pass_fraction(), Ordering::Relaxed);
-
-            // Bump subtest counts
-            subtest_count.fetch_add(subtest_counts.total, Ordering::Relaxed);
-            subtest_pass_count.fetch_add(subtest_counts.pass, Ordering::Relaxed);
-            subtest_fail_count.fetch_add(
-                subtest_counts.total - subtest_counts.pass,
-                Ordering::Relaxed,
-            );
-
-            let result = TestResult {
-                name: relative_path,
-                kind,
-                flags,
-                status,
-                subtest_counts,
-                subtest_results,
-                duration: start.elapsed(),
-                panic_info,
-            };
-
-            // Print status line
             let mut out = stdout().lock();
-            write!(out, "[{num}/{count}] ").unwrap();
+            write!(out, "[retry {num}/{retry_count}] ").unwrap();
             result.print_to(out);
+        });
 
-            result
-        })
-        .collect();
+        for (idx, retry) in retry_indices.into_iter().zip(retried) {
+            results[idx].merge_retry(retry);
+        }
+    }
 
     let end_timestamp = unix_timestamp();
 
@@ -577,27 +693,70 @@ fn main() {
     }
     drop(out);
 
-    let pass_count = pass_count.load(Ordering::SeqCst);
-    let fail_count = fail_count.load(Ordering::SeqCst);
-    let crash_count = crash_count.load(Ordering::SeqCst);
-    let skip_count = skip_count.load(Ordering::SeqCst);
+    let mut pass_count = 0;
+    let mut fail_count = 0;
+    let mut skip_count = 0;
+    let mut crash_count = 0;
+    let mut timeout_count = 0;
+
+    let mut subtest_count = 0;
+    let mut subtest_pass_count = 0;
+
+    let mut fractional_pass_count = 0.0;
+
+    let mut masonry_fail_count = 0;
+    let mut subgrid_fail_count = 0;
+    let mut writing_mode_fail_count = 0;
+    let mut direction_fail_count = 0;
+    let mut float_fail_count = 0;
+    let mut calc_fail_count = 0;
+    let mut intrinsic_size_fail_count = 0;
+    let mut script_fail_count = 0;
+    let mut other_fail_count = 0;
+
+    for result in &results {
+        let flags = result.flags;
+
+        // Bump counts
+        match result.status {
+            TestStatus::Pass => pass_count += 1,
+            TestStatus::Fail => {
+                if flags.contains(TestFlags::USES_MASONRY) {
+                    masonry_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_SUBGRID) {
+                    subgrid_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_WRITING_MODE) {
+                    writing_mode_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_DIRECTION) {
+                    direction_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_INTRINSIC_SIZE) {
+                    intrinsic_size_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_CALC) {
+                    calc_fail_count += 1;
+                } else if flags.contains(TestFlags::USES_FLOAT) {
+                    float_fail_count += 1;
+                } else if result.kind == TestKind::Ref && flags.contains(TestFlags::USES_SCRIPT) {
+                    script_fail_count += 1;
+                } else {
+                    other_fail_count += 1;
+                }
+                fail_count += 1;
+            }
+            TestStatus::Skip => skip_count += 1,
+            TestStatus::Crash => crash_count += 1,
+            TestStatus::Timeout => timeout_count += 1,
+        };
 
-    let run_count = pass_count + fail_count + crash_count;
-    let count = count as u32;
+        // Bump fractional count
+        fractional_pass_count += result.subtest_counts.pass_fraction();
 
-    let fractional_pass_count = fractional_pass_count.load(Ordering::SeqCst);
-    let subtest_count = subtest_count.load(Ordering::SeqCst);
-    let subtest_pass_count = subtest_pass_count.load(Ordering::SeqCst);
+        // Bump subtest counts
+        subtest_count += result.subtest_counts.total;
+        subtest_pass_count += result.subtest_counts.pass;
+    }
 
-    let subgrid_fail_count = subgrid_fail_count.load(Ordering::SeqCst);
-    let masonry_fail_count = masonry_fail_count.load(Ordering::SeqCst);
-    let writing_mode_fail_count = writing_mode_fail_count.load(Ordering::SeqCst);
-    let direction_fail_count = direction_fail_count.load(Ordering::SeqCst);
-    let float_fail_count = float_fail_count.load(Ordering::SeqCst);
-    let calc_fail_count = calc_fail_count.load(Ordering::SeqCst);
-    let intrinsic_size_fail_count = intrinsic_size_fail_count.load(Ordering::SeqCst);
-    let script_fail_count = script_fail_count.load(Ordering::SeqCst);
-    let other_fail_count = other_fail_count.load(Ordering::SeqCst);
+    let run_count = pass_count + fail_count + crash_count + timeout_count;
+    let count = count as u32;
 
     fn as_percent(amount: u32, out_of: u32) -> f32 {
         (amount as f32 / out_of as f32) * 100.0
@@ -613,6 +772,8 @@ fn main() {
     let fail_percent_total = as_percent(fail_count, count);
     let crash_percent_run = as_percent(crash_count, run_count);
     let crash_percent_total = as_percent(crash_count, count);
+    let timeout_percent_run = as_percent(timeout_count, run_count);
+    let timeout_percent_total = as_percent(timeout_count, count);
 
     let subtest_pass_percent = as_percent(subtest_pass_count, subtest_count);
 
@@ -634,6 +795,9 @@ fn main() {
     println!(
         "{crash_count:>4} tests CRASHED ({crash_percent_run:.2}% of run; {crash_percent_total:.2}% of found)"
     );
+    println!(
+        "{timeout_count:>4} tests TIMED OUT ({timeout_percent_run:.2}% of run; {timeout_percent_total:.2}% of found)"
+    );
     println!(
         "{pass_count:>4} tests PASSED ({pass_percent_run:.2}% of run; {pass_percent_total:.2}% of found)"
     );
@@ -661,6 +825,27 @@ fn main() {
         println!("{masonry_fail_count:>4} use masonry (M)");
     }
 
+    // Compare against baseline
+    let regression_count = baseline.map(|baseline| {
+        let comparison = regression::compare(&baseline, &results);
+        regression::print_comparison(&comparison);
+
+        let regression_count = comparison.regression_count();
+        if regression_count > 0 {
+            let html_path = out_dir.join("regressions.html");
+            fs::write(
+                &html_path,
+                regression::generate_regressions_html(&comparison, &out_dir),
+            )
+            .unwrap();
+            println!(
+                "\n{regression_count} regressions. See {}",
+                html_path.display()
+            );
+        }
+        regression_count
+    });
+
     // Generate wpt_expectations.txt
     let expectations = generate_expectations(&results);
     let expectations_path = out_dir.join("wpt_expectations.txt");
@@ -682,4 +867,43 @@ fn main() {
         report_path,
         write_report_start.elapsed().as_millis()
     );
+
+    if regression_count.is_some_and(|count| count > 0) {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(status: TestStatus) -> TestResult {
+        TestResult {
+            name: String::from("test.html"),
+            kind: TestKind::Ref,
+            flags: TestFlags::empty(),
+            status,
+            subtest_counts: SubtestCounts::ZERO_OF_ONE,
+            subtest_results: Vec::new(),
+            duration: Duration::ZERO,
+            panic_info: None,
+            flaky_statuses: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn retries_keep_the_worst_status() {
+        let mut test = result(TestStatus::Fail);
+        test.merge_retry(result(TestStatus::Pass));
+        assert!(test.status == TestStatus::Fail);
+        assert!(test.flaky_statuses == [TestStatus::Pass]);
+
+        test.merge_retry(result(TestStatus::Crash));
+        assert!(test.status == TestStatus::Crash);
+        assert!(test.flaky_statuses == [TestStatus::Pass, TestStatus::Fail]);
+
+        test.merge_retry(result(TestStatus::Crash));
+        test.merge_retry(result(TestStatus::Pass));
+        assert!(test.flaky_statuses == [TestStatus::Pass, TestStatus::Fail]);
+    }
 }
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::backtrace::{Backtrace, BacktraceStatus};
 use std::{cell::Cell, panic::PanicHookInfo};
 
@@ -17,6 +18,35 @@ pub struct StashedPanicInfo {
     pub backtrace: Backtrace,
 }
 
+/// A summary of why a test crashed or timed out, which can be sent from a worker process
+#[derive(Serialize, Deserialize)]
+pub struct PanicReport {
+    pub message: Option<String>,
+    /// The source location of the panic (if the test panicked)
+    pub location: Option<String>,
+    pub backtrace: Option<String>,
+}
+
+impl PanicReport {
+    pub fn from_message(message: String) -> Self {
+        Self {
+            message: Some(message),
+            location: None,
+            backtrace: None,
+        }
+    }
+}
+
+impl From<StashedPanicInfo> for PanicReport {
+    fn from(info: StashedPanicInfo) -> Self {
+        Self {
+            message: info.message,
+            location: Some(format!("{}:{}:{}", info.file, info.line, info.column)),
+            backtrace: trim_backtrace(&info.backtrace),
+        }
+    }
+}
+
 pub fn stash_panic_handler(info: &PanicHookInfo) {
     let backtrace = Backtrace::force_capture();
     let payload = info.payload();
@@ -0,0 +1,423 @@
+//! Comparison of a test run against a baseline from a previous run
+//!
+//! The baseline can either be a `wpt_expectations.txt` file or a `wptreport.json` file as
+//! generated by a previous run of the runner.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+
+use crate::{TestKind, TestResult, TestStatus};
+
+/// The result of a single test in the baseline run
+struct BaselineResult {
+    status: TestStatus,
+    /// The number of subtests which passed
+    subtest_pass_count: u32,
+}
+
+pub struct Baseline {
+    results: HashMap<String, BaselineResult>,
+}
+
+impl Baseline {
+    /// Load a baseline from a file. Files with a `.json` extension are parsed as a `wptreport.json`,
+    /// and any other file is parsed as a `wpt_expectations.txt`.
+    pub fn load(path: &Path) -> Result<Baseline, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read baseline {}: {err}", path.display()))?;
+
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        let results = if is_json {
+            parse_wpt_report(&contents)?
+        } else {
+            parse_expectations(&contents)?
+        };
+
+        Ok(Baseline { results })
+    }
+}
+
+fn parse_expectations(contents: &str) -> Result<HashMap<String, BaselineResult>, String> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.split(' ');
+            let (Some(name), Some(status)) = (parts.next(), parts.next()) else {
+                return Err(format!("Invalid expectation line: {line}"));
+            };
+            let subtests = parts.next().unwrap_or_default();
+            let subtest_pass_count = subtests.chars().filter(|c| *c == 'Y').count() as u32;
+            let result = BaselineResult {
+                status: parse_status(status)?,
+                subtest_pass_count,
+            };
+            Ok((name.to_string(), result))
+        })
+        .collect()
+}
+
+fn parse_wpt_report(contents: &str) -> Result<HashMap<String, BaselineResult>, String> {
+    let report: serde_json::Value =
+        serde_json::from_str(contents).map_err(|err| format!("Invalid wptreport.json: {err}"))?;
+    let results = report["results"]
+        .as_array()
+        .ok_or_else(|| String::from("Invalid wptreport.json: missing results"))?;
+
+    results
+        .iter()
+        .filter_map(|result| {
+            // Tests in wptreport.json files generated by wpt.fyi are prefixed with a slash
+            let name = result["test"].as_str()?.trim_start_matches('/');
+            let status = result["status"].as_str()?;
+            let subtests = result["subtests"].as_array();
+            let subtest_count = subtests.map_or(0, |subtests| subtests.len() as u32);
+            let subtest_pass_count = subtests
+                .map(|subtests| {
+                    subtests
+                        .iter()
+                        .filter(|subtest| subtest["status"].as_str() == Some("PASS"))
+                        .count() as u32
+                })
+                .unwrap_or(0);
+            let status = match status {
+                // The harness ran to completion, so the result depends on the subtests
+                "OK" if subtest_pass_count == subtest_count => Ok(TestStatus::Pass),
+                "OK" => Ok(TestStatus::Fail),
+                status => parse_status(status),
+            };
+            let result = status.map(|status| BaselineResult {
+                status,
+                subtest_pass_count,
+            });
+            Some(result.map(|result| (name.to_string(), result)))
+        })
+        .collect()
+}
+
+/// Parse a test status from a `wpt_expectations.txt` or `wptreport.json` file
+fn parse_status(status: &str) -> Result<TestStatus, String> {
+    match status {
+        "PASS" => Ok(TestStatus::Pass),
+        "FAIL" | "ERROR" | "PRECONDITION_FAILED" => Ok(TestStatus::Fail),
+        "SKIP" | "NOTRUN" => Ok(TestStatus::Skip),
+        "CRASH" => Ok(TestStatus::Crash),
+        "TIMEOUT" => Ok(TestStatus::Timeout),
+        _ => Err(format!("Invalid test status: {status}")),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum ChangeKind {
+    NewlyCrashing,
+    NewlyTimingOut,
+    NewlyFailing,
+    NewlySkipped,
+    FewerSubtestsPassing,
+    NewlyPassing,
+}
+
+impl ChangeKind {
+    fn is_regression(self) -> bool {
+        !matches!(self, ChangeKind::NewlyPassing)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ChangeKind::NewlyCrashing => "newly crashing",
+            ChangeKind::NewlyTimingOut => "newly timing out",
+            ChangeKind::NewlyFailing => "newly failing",
+            ChangeKind::NewlySkipped => "newly skipped",
+            ChangeKind::FewerSubtestsPassing => "fewer subtests passing",
+            ChangeKind::NewlyPassing => "newly passing",
+        }
+    }
+}
+
+struct Change<'a> {
+    kind: ChangeKind,
+    test: &'a TestResult,
+    baseline_status: TestStatus,
+}
+
+pub struct Comparison<'a> {
+    /// Changes grouped by suite (e.g. "css/css-flexbox")
+    suites: BTreeMap<String, Vec<Change<'a>>>,
+}
+
+impl Comparison<'_> {
+    pub fn regression_count(&self) -> usize {
+        self.changes()
+            .filter(|change| change.kind.is_regression())
+            .count()
+    }
+
+    fn changes(&self) -> impl Iterator<Item = &Change<'_>> {
+        self.suites.values().flatten()
+    }
+}
+
+/// The suite that a test belongs to. This is the first two components of the test's path.
+fn suite_name(test_name: &str) -> String {
+    test_name.split('/').take(2).collect::<Vec<_>>().join("/")
+}
+
+impl Baseline {
+    /// Whether a test's result is worse than its result in the baseline
+    pub fn is_regression(&self, test: &TestResult) -> bool {
+        self.classify(test)
+            .is_some_and(|(kind, _)| kind.is_regression())
+    }
+
+    /// Determine how a test's result has changed from the baseline (if at all). Also returns the
+    /// baseline status of the test.
+    fn classify(&self, test: &TestResult) -> Option<(ChangeKind, TestStatus)> {
+        // Tests that are not in the baseline are treated as if they previously failed
+        let (baseline_status, baseline_subtest_pass_count) = self
+            .results
+            .get(&test.name)
+            .map(|result| (result.status, result.subtest_pass_count))
+            .unwrap_or((TestStatus::Fail, 0));
+
+        let subtest_pass_count = test
+            .subtest_results
+            .iter()
+            .filter(|subtest| subtest.status == TestStatus::Pass)
+            .count() as u32;
+
+        let kind = match test.status {
+            status if status == baseline_status => {
+                if subtest_pass_count < baseline_subtest_pass_count {
+                    ChangeKind::FewerSubtestsPassing
+                } else {
+                    return None;
+                }
+            }
+            TestStatus::Pass => ChangeKind::NewlyPassing,
+            TestStatus::Crash => ChangeKind::NewlyCrashing,
+            TestStatus::Timeout => ChangeKind::NewlyTimingOut,
+            TestStatus::Fail if baseline_status == TestStatus::Pass => ChangeKind::NewlyFailing,
+            TestStatus::Skip if baseline_status == TestStatus::Pass => ChangeKind::NewlySkipped,
+            TestStatus::Fail | TestStatus::Skip => return None,
+        };
+
+        Some((kind, baseline_status))
+    }
+}
+
+pub fn compare<'a>(baseline: &'a Baseline, results: &'a [TestResult]) -> Comparison<'a> {
+    let mut suites: BTreeMap<String, Vec<Change<'a>>> = BTreeMap::new();
+
+    for test in results {
+        let Some((kind, baseline_status)) = baseline.classify(test) else {
+            continue;
+        };
+        suites
+            .entry(suite_name(&test.name))
+            .or_default()
+            .push(Change {
+                kind,
+                test,
+                baseline_status,
+            });
+    }
+
+    for changes in suites.values_mut() {
+        changes.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.test.name.cmp(&b.test.name)));
+    }
+
+    Comparison { suites }
+}
+
+pub fn print_comparison(comparison: &Comparison) {
+    println!("\n{}", "Changes from baseline".bold());
+    println!("=====================\n");
+
+    if comparison.suites.is_empty() {
+        println!("No changes");
+        return;
+    }
+
+    for (suite, changes) in &comparison.suites {
+        println!("{}", suite.bold());
+        for change in changes {
+            let line = format!(
+                "  {} {} ({} -> {})",
+                change.kind.label(),
+                change.test.name,
+                change.baseline_status.as_str(),
+                change.test.status.as_str(),
+            );
+            if change.kind.is_regression() {
+                println!("{}", line.red());
+            } else {
+                println!("{}", line.green());
+            }
+        }
+    }
+
+    let mut counts: BTreeMap<ChangeKind, usize> = BTreeMap::new();
+    for change in comparison.changes() {
+        *counts.entry(change.kind).or_default() += 1;
+    }
+    println!();
+    for (kind, count) in counts {
+        println!("{count:>4} tests {}", kind.label());
+    }
+}
+
+/// Generate an HTML page showing the test, reference and diff images for each regression.
+///
+/// Image paths are relative to the output directory, so the page should be written there.
+pub fn generate_regressions_html(comparison: &Comparison, out_dir: &Path) -> String {
+    let mut out = String::new();
+    out.push_str(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>WPT regressions</title>\n<style>\n",
+        "body { font-family: sans-serif; }\n",
+        ".images { display: flex; gap: 8px; }\n",
+        ".images figure { margin: 0; }\n",
+        ".images img { width: 400px; border: 1px solid #ccc; }\n",
+        "</style>\n</head>\n<body>\n<h1>WPT regressions</h1>\n",
+    ));
+
+    for (suite, changes) in &comparison.suites {
+        let regressions: Vec<_> = changes
+            .iter()
+            .filter(|change| change.kind.is_regression())
+            .collect();
+        if regressions.is_empty() {
+            continue;
+        }
+
+        writeln!(out, "<h2>{}</h2>", escape_html(suite)).unwrap();
+        for change in regressions {
+            let name = escape_html(&change.test.name);
+            writeln!(
+                out,
+                "<h3><a href=\"https://wpt.live/{name}\">{name}</a> ({}: {} &rarr; {})</h3>",
+                change.kind.label(),
+                change.baseline_status.as_str(),
+                change.test.status.as_str(),
+            )
+            .unwrap();
+            // Only reftests produce screenshots
+            if change.test.kind == TestKind::Ref {
+                out.push_str("<div class=\"images\">\n");
+                let mut images = vec![(String::from("Test"), String::from("-test.png"))];
+                images.extend(
+                    reference_image_suffixes(out_dir, &change.test.name)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, suffix)| (format!("Ref {}", idx + 1), suffix)),
+                );
+                // A diff is only written for failed match comparisons
+                let diff_suffix = String::from("-diff.png");
+                if out_dir
+                    .join(format!("{}{diff_suffix}", change.test.name))
+                    .exists()
+                {
+                    images.push((String::from("Diff"), diff_suffix));
+                }
+                for (label, suffix) in images {
+                    writeln!(
+                        out,
+                        "<figure><img src=\"{name}{suffix}\" alt=\"{label}\"><figcaption>{label}</figcaption></figure>",
+                    )
+                    .unwrap();
+                }
+                out.push_str("</div>\n");
+            }
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// The suffixes of the images of a reftest's references which were rendered. The first reference
+/// is written to `-ref.png` and subsequent references in a chain to `-ref-N.png`.
+fn reference_image_suffixes(out_dir: &Path, test_name: &str) -> Vec<String> {
+    std::iter::once(String::from("-ref.png"))
+        .chain((1..).map(|n| format!("-ref-{n}.png")))
+        .take_while(|suffix| out_dir.join(format!("{test_name}{suffix}")).exists())
+        .collect()
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SubtestCounts, TestFlags};
+    use std::time::Duration;
+
+    fn result(name: &str, status: TestStatus) -> TestResult {
+        TestResult {
+            name: name.to_string(),
+            kind: TestKind::Ref,
+            flags: TestFlags::empty(),
+            status,
+            subtest_counts: SubtestCounts::ONE_OF_ONE,
+            subtest_results: Vec::new(),
+            duration: Duration::ZERO,
+            panic_info: None,
+            flaky_statuses: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_report_statuses() {
+        let report = r#"{"results": [
+            {"test": "/a.html", "status": "OK", "subtests": [{"status": "PASS"}]},
+            {"test": "/b.html", "status": "OK", "subtests": [{"status": "PASS"}, {"status": "FAIL"}]},
+            {"test": "/c.html", "status": "ERROR", "subtests": []},
+            {"test": "/d.html", "status": "TIMEOUT", "subtests": []}
+        ]}"#;
+        let results = parse_wpt_report(report).unwrap();
+        assert!(results["a.html"].status == TestStatus::Pass);
+        assert!(results["b.html"].status == TestStatus::Fail);
+        assert_eq!(results["b.html"].subtest_pass_count, 1);
+        assert!(results["c.html"].status == TestStatus::Fail);
+        assert!(results["d.html"].status == TestStatus::Timeout);
+
+        assert!(parse_wpt_report(r#"{"results": [{"test": "/e.html", "status": "?"}]}"#).is_err());
+        assert!(parse_expectations("a.html PASS\nb.html MAYBE").is_err());
+    }
+
+    #[test]
+    fn classifies_changes() {
+        let baseline = Baseline {
+            results: parse_expectations("pass.html PASS\nfail.html FAIL\ntimeout.html TIMEOUT\n")
+                .unwrap(),
+        };
+        let kind = |name: &str, status: TestStatus| {
+            baseline
+                .classify(&result(name, status))
+                .map(|(kind, _)| kind)
+        };
+
+        assert!(kind("pass.html", TestStatus::Pass).is_none());
+        assert!(kind("pass.html", TestStatus::Fail) == Some(ChangeKind::NewlyFailing));
+        assert!(kind("pass.html", TestStatus::Crash) == Some(ChangeKind::NewlyCrashing));
+        assert!(kind("pass.html", TestStatus::Skip) == Some(ChangeKind::NewlySkipped));
+        assert!(kind("fail.html", TestStatus::Skip).is_none());
+        assert!(kind("fail.html", TestStatus::Pass) == Some(ChangeKind::NewlyPassing));
+        assert!(kind("timeout.html", TestStatus::Timeout).is_none());
+        assert!(kind("timeout.html", TestStatus::Fail).is_none());
+        // Tests missing from the baseline are treated as previously failing
+        assert!(kind("new.html", TestStatus::Fail).is_none());
+        assert!(kind("new.html", TestStatus::Timeout) == Some(ChangeKind::NewlyTimingOut));
+        assert!(baseline.is_regression(&result("pass.html", TestStatus::Fail)));
+    }
+}
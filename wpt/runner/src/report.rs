@@ -61,6 +61,7 @@ fn convert_status(status: TestStatus) -> wpt_report::TestStatus {
         TestStatus::Fail => wpt_report::TestStatus::Fail,
         TestStatus::Skip => wpt_report::TestStatus::Skip,
         TestStatus::Crash => wpt_report::TestStatus::Crash,
+        TestStatus::Timeout => wpt_report::TestStatus::Timeout,
     }
 }
 
@@ -69,7 +70,7 @@ fn convert_subtest_status(status: TestStatus) -> wpt_report::SubtestStatus {
         TestStatus::Pass => wpt_report::SubtestStatus::Pass,
         TestStatus::Fail => wpt_report::SubtestStatus::Fail,
         TestStatus::Skip => wpt_report::SubtestStatus::Skip,
-        TestStatus::Crash => unreachable!(),
+        TestStatus::Crash | TestStatus::Timeout => unreachable!(),
     }
 }
 
@@ -86,7 +87,11 @@ pub fn generate_report(
             status: convert_status(test.status),
             duration: test.duration.as_millis() as i64,
             message: test.panic_info.and_then(|info| info.message),
-            known_intermittent: Vec::new(),
+            known_intermittent: test
+                .flaky_statuses
+                .iter()
+                .map(|status| status.as_str().to_string())
+                .collect(),
             subsuite: String::new(),
             subtests: test
                 .subtest_results
@@ -127,7 +132,7 @@ pub fn generate_expectations(results: &[TestResult]) -> String {
                 TestStatus::Pass => 'Y',
                 TestStatus::Fail => 'N',
                 TestStatus::Skip => '.',
-                TestStatus::Crash => unreachable!(),
+                TestStatus::Crash | TestStatus::Timeout => unreachable!(),
             };
             out.push(c);
         }
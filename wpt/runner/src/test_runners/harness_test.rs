@@ -0,0 +1,486 @@
+//! Support for `testharness.js` tests without a script engine
+//!
+//! Many CSS tests are written using shared helper functions (e.g. `test_computed_value` from
+//! `/css/support/computed-testcommon.js`). Rather than executing script, we find calls to known
+//! helpers in the test source and translate each call into an equivalent assertion against the DOM.
+//! Each family of helpers is implemented by a [`HarnessShim`].
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use blitz_dom::{BaseDocument, local_name};
+use regex::Regex;
+
+use super::attr_test::check_node_layout;
+use super::{SubtestResult, parse_and_resolve_document};
+use crate::{SubtestCounts, TestStatus, ThreadCtx};
+
+/// A literal argument to a helper function call
+#[derive(Clone, Debug)]
+pub enum JsArg {
+    String(String),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+impl JsArg {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            JsArg::String(value) => Some(value),
+            JsArg::Bool(_) | JsArg::Array(_) => None,
+        }
+    }
+
+    /// Interpret the argument as a list of acceptable values. Helpers accept either a single
+    /// string or an array of strings for expected values.
+    fn as_values(&self) -> Vec<String> {
+        match self {
+            JsArg::String(value) => vec![value.clone()],
+            JsArg::Bool(value) => vec![value.to_string()],
+            JsArg::Array(values) => values.clone(),
+        }
+    }
+}
+
+/// The outcome of translating a single helper function call
+pub struct ShimSubtest {
+    /// The name that `testharness.js` would give the subtest
+    pub name: String,
+    pub result: Result<(), String>,
+}
+
+/// A declarative translation of a family of `testharness.js` helper functions
+pub trait HarnessShim: Send {
+    /// The names of the helper functions that this shim can translate
+    fn functions(&self) -> &'static [&'static str];
+
+    /// Run the assertions for a single call to one of the helper functions. Most helpers define
+    /// a single subtest, but some (such as `checkLayout`) define one for each element they check.
+    ///
+    /// Returns `None` if the call's arguments are not understood.
+    fn run(
+        &self,
+        doc: &mut BaseDocument,
+        function: &str,
+        args: &[JsArg],
+    ) -> Option<Vec<ShimSubtest>>;
+}
+
+/// The set of shims used to run `testharness.js` tests
+pub struct HarnessShims {
+    shims: Vec<Box<dyn HarnessShim>>,
+    call_re: Regex,
+}
+
+impl Default for HarnessShims {
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(ComputedValueShim),
+            Box::new(ParsingShim),
+            Box::new(LayoutShim),
+        ])
+    }
+}
+
+impl HarnessShims {
+    pub fn new(shims: Vec<Box<dyn HarnessShim>>) -> Self {
+        let functions: Vec<&str> = shims
+            .iter()
+            .flat_map(|shim| shim.functions().iter().copied())
+            .collect();
+        let call_re = Regex::new(&format!(r#"\b({})\s*\("#, functions.join("|"))).unwrap();
+        Self { shims, call_re }
+    }
+
+    /// Whether a test source contains any calls which can be translated by a shim
+    pub fn handles(&self, html: &str) -> bool {
+        html.contains("/resources/testharness.js") && !self.find_calls(html).is_empty()
+    }
+
+    /// Find calls to helper functions. Arguments are `None` if they could not be parsed.
+    fn find_calls<'a>(&self, html: &'a str) -> Vec<(&'a str, Option<Vec<JsArg>>)> {
+        self.call_re
+            .captures_iter(html)
+            .filter_map(|captures| {
+                let call = captures.get(0).unwrap();
+                let function = captures.get(1).unwrap().as_str();
+
+                // Skip function definitions and method calls
+                let preceding = html[..call.start()].trim_end();
+                if preceding.ends_with("function") || preceding.ends_with('.') {
+                    return None;
+                }
+
+                Some((function, parse_call_args(&html[call.end()..])))
+            })
+            .collect()
+    }
+
+    fn shim_for(&self, function: &str) -> &dyn HarnessShim {
+        self.shims
+            .iter()
+            .find(|shim| shim.functions().contains(&function))
+            .map(|shim| &**shim)
+            .unwrap()
+    }
+}
+
+pub fn process_harness_test(
+    ctx: &mut ThreadCtx,
+    html: &str,
+    relative_path: &str,
+) -> (TestStatus, SubtestCounts, Vec<SubtestResult>) {
+    let mut document = parse_and_resolve_document(ctx, html, relative_path);
+    let calls = ctx.harness_shims.find_calls(html);
+
+    let subtest_results: Vec<SubtestResult> = calls
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, (function, args))| {
+            let subtests = args.as_ref().and_then(|args| {
+                ctx.harness_shims
+                    .shim_for(function)
+                    .run(&mut document, function, args)
+            });
+            subtests.unwrap_or_else(|| {
+                vec![ShimSubtest {
+                    name: format!("{function} {}", idx + 1),
+                    result: Err(format!("Unsupported arguments to {function}")),
+                }]
+            })
+        })
+        .map(|subtest| {
+            let (status, errors) = match subtest.result {
+                Ok(()) => (TestStatus::Pass, Vec::new()),
+                Err(err) => (TestStatus::Fail, vec![err]),
+            };
+            SubtestResult {
+                name: subtest.name,
+                status,
+                errors,
+            }
+        })
+        .collect();
+
+    let subtest_counts = SubtestCounts {
+        pass: subtest_results
+            .iter()
+            .filter(|result| matches!(result.status, TestStatus::Pass))
+            .count() as u32,
+        total: subtest_results.len() as u32,
+    };
+
+    let status = subtest_counts.as_status();
+    (status, subtest_counts, subtest_results)
+}
+
+/// Translates `test_computed_value` from `/css/support/computed-testcommon.js`
+struct ComputedValueShim;
+
+impl HarnessShim for ComputedValueShim {
+    fn functions(&self) -> &'static [&'static str] {
+        &["test_computed_value"]
+    }
+
+    fn run(
+        &self,
+        doc: &mut BaseDocument,
+        _function: &str,
+        args: &[JsArg],
+    ) -> Option<Vec<ShimSubtest>> {
+        let property = args.first()?.as_str()?;
+        let specified = args.get(1)?.as_str()?;
+        let expected = match args.get(2) {
+            Some(arg) => arg.as_values(),
+            None => vec![specified.to_string()],
+        };
+
+        let result = (|| {
+            let target = doc
+                .get_element_by_id("target")
+                .ok_or_else(|| String::from("No element with id \"target\""))?;
+
+            doc.remove_style_property(target, property);
+            doc.set_style_property(target, property, specified);
+            let is_set = doc.style_property_value(target, property).is_some();
+            doc.resolve(0.0);
+            let actual = doc.get_node(target).unwrap().computed_style_value(property);
+            doc.remove_style_property(target, property);
+
+            if !is_set {
+                return Err(format!(
+                    "assert_not_equals: property should be set got disallowed value \"\" ({specified})"
+                ));
+            }
+            let actual = actual.ok_or_else(|| format!("Unsupported property: {property}"))?;
+            assert_in_values(&actual, &expected)
+        })();
+
+        Some(vec![ShimSubtest {
+            name: format!("Property {property} value '{specified}'"),
+            result,
+        }])
+    }
+}
+
+/// Translates `test_valid_value` and `test_invalid_value` from `/css/support/parsing-testcommon.js`
+struct ParsingShim;
+
+impl HarnessShim for ParsingShim {
+    fn functions(&self) -> &'static [&'static str] {
+        &["test_valid_value", "test_invalid_value"]
+    }
+
+    fn run(
+        &self,
+        doc: &mut BaseDocument,
+        function: &str,
+        args: &[JsArg],
+    ) -> Option<Vec<ShimSubtest>> {
+        let property = args.first()?.as_str()?;
+        let value = args.get(1)?.as_str()?;
+
+        // The helpers use an element which is not in the document. As no layout is involved we
+        // can use any element, so use the root element.
+        let target = doc.root_element().id;
+        doc.remove_style_property(target, property);
+        doc.set_style_property(target, property, value);
+        let actual = doc.style_property_value(target, property);
+        doc.remove_style_property(target, property);
+
+        let (name, result) = if function == "test_valid_value" {
+            let expected = match args.get(2) {
+                Some(arg) => arg.as_values(),
+                None => vec![value.to_string()],
+            };
+            let result = match actual {
+                Some(actual) => assert_in_values(&actual, &expected),
+                None => Err(String::from(
+                    "assert_not_equals: property should be set got disallowed value \"\"",
+                )),
+            };
+            let name = format!("e.style['{property}'] = \"{value}\" should set the property value");
+            (name, result)
+        } else {
+            let result = match actual {
+                Some(actual) => Err(format!("assert_equals: expected \"\" but got \"{actual}\"")),
+                None => Ok(()),
+            };
+            let name =
+                format!("e.style['{property}'] = \"{value}\" should not set the property value");
+            (name, result)
+        };
+
+        Some(vec![ShimSubtest { name, result }])
+    }
+}
+
+/// Translates `checkLayout` from `/resources/check-layout-th.js`, which checks the layout of the
+/// elements matching a selector (and of their descendants) against their `data-expected-*` and
+/// `data-offset-*` attributes. Each matching element is a separate subtest.
+struct LayoutShim;
+
+impl HarnessShim for LayoutShim {
+    fn functions(&self) -> &'static [&'static str] {
+        &["checkLayout"]
+    }
+
+    fn run(
+        &self,
+        doc: &mut BaseDocument,
+        _function: &str,
+        args: &[JsArg],
+    ) -> Option<Vec<ShimSubtest>> {
+        let selector = args.first()?.as_str()?;
+        let node_ids = doc.query_selector_all(selector).ok()?;
+
+        let subtests = node_ids
+            .iter()
+            .enumerate()
+            .map(|(idx, node_id)| {
+                let title = match doc.get_node(*node_id).unwrap().attr(local_name!("title")) {
+                    Some(title) if !title.is_empty() => format!(": {title}"),
+                    _ => String::new(),
+                };
+                let mut errors = Vec::new();
+                doc.iter_subtree_mut(*node_id, |node_id, doc| {
+                    errors.extend(check_node_layout(doc.get_node(node_id).unwrap()));
+                });
+                ShimSubtest {
+                    name: format!("{selector} {}{title}", idx + 1),
+                    result: if errors.is_empty() {
+                        Ok(())
+                    } else {
+                        Err(errors.join("\n"))
+                    },
+                }
+            })
+            .collect();
+        Some(subtests)
+    }
+}
+
+fn assert_in_values(actual: &str, expected: &[String]) -> Result<(), String> {
+    match expected {
+        [expected] if expected != actual => Err(format!(
+            "assert_equals: expected \"{expected}\" but got \"{actual}\""
+        )),
+        _ if !expected.iter().any(|value| value == actual) => Err(format!(
+            "assert_in_array: value \"{actual}\" not in array {expected:?}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Parse the arguments of a function call, starting immediately after the opening parenthesis.
+///
+/// Returns `None` unless every argument is a string literal, a boolean literal or an array of string
+/// literals.
+fn parse_call_args(input: &str) -> Option<Vec<JsArg>> {
+    let mut chars = input.chars().peekable();
+    let mut args = Vec::new();
+
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.peek()? {
+            ')' => return Some(args),
+            '[' => {
+                chars.next();
+                let mut items = Vec::new();
+                loop {
+                    skip_whitespace(&mut chars);
+                    if chars.next_if_eq(&']').is_some() {
+                        break;
+                    }
+                    items.push(parse_string(&mut chars)?);
+                    skip_whitespace(&mut chars);
+                    chars.next_if_eq(&',');
+                }
+                args.push(JsArg::Array(items));
+            }
+            't' | 'f' => {
+                let mut literal = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    literal.push(c);
+                }
+                match literal.as_str() {
+                    "true" => args.push(JsArg::Bool(true)),
+                    "false" => args.push(JsArg::Bool(false)),
+                    _ => return None,
+                }
+            }
+            _ => args.push(JsArg::String(parse_string(&mut chars)?)),
+        }
+
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            ')' => return Some(args),
+            _ => return None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// Parse a JavaScript string literal (including template literals without substitutions)
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let quote = chars.next_if(|c| matches!(c, '"' | '\'' | '`'))?;
+    let mut value = String::new();
+
+    loop {
+        match chars.next()? {
+            c if c == quote => return Some(value),
+            '$' if quote == '`' && chars.peek() == Some(&'{') => return None,
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = if chars.next_if_eq(&'{').is_some() {
+                        chars.by_ref().take_while(|c| *c != '}').collect()
+                    } else {
+                        chars.by_ref().take(4).collect()
+                    };
+                    value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                // Line continuation
+                '\n' => {}
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    fn strings(args: Option<Vec<JsArg>>) -> Option<Vec<Vec<String>>> {
+        args.map(|args| args.iter().map(JsArg::as_values).collect())
+    }
+
+    #[test]
+    fn parses_literal_arguments() {
+        assert_eq!(
+            strings(parse_call_args(
+                r#""width", `calc(1px)`, ['1px', "2\u0070x"]);"#
+            )),
+            Some(vec![
+                vec![String::from("width")],
+                vec![String::from("calc(1px)")],
+                vec![String::from("1px"), String::from("2px")],
+            ])
+        );
+        assert_eq!(
+            strings(parse_call_args("'.grid', false)")),
+            Some(vec![
+                vec![String::from(".grid")],
+                vec![String::from("false")]
+            ])
+        );
+        assert_eq!(strings(parse_call_args(") ")), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_non_literal_arguments() {
+        assert!(parse_call_args("property, '1px')").is_none());
+        assert!(parse_call_args("'width', `${value}px`)").is_none());
+        assert!(parse_call_args("'.grid', falsy)").is_none());
+        assert!(parse_call_args("'unterminated").is_none());
+    }
+
+    fn check_layout(html: &str) -> Vec<Result<(), String>> {
+        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
+        document.as_mut().resolve(0.0);
+        let subtests = LayoutShim
+            .run(
+                document.as_mut(),
+                "checkLayout",
+                &[JsArg::String(String::from(".test"))],
+            )
+            .unwrap();
+        subtests.into_iter().map(|subtest| subtest.result).collect()
+    }
+
+    #[test]
+    fn checks_expected_layout() {
+        let results = check_layout(
+            r#"<body style="margin: 0">
+            <div class="test" style="width: 100px; border: 5px solid" data-expected-width="110">
+                <div style="margin-left: 10px; height: 20px"
+                    data-offset-x="10" data-offset-y="0" data-expected-height="20"></div>
+            </div>
+            <div class="test" style="width: 50px; height: 10px" data-expected-width="60"></div>
+            <div class="test" data-expected-scroll-width="0"></div>
+            </body>"#,
+        );
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+    }
+}
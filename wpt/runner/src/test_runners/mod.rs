@@ -1,17 +1,23 @@
 use std::{fs, sync::Arc, time::Instant};
 
-use blitz_dom::{BaseDocument, DocumentConfig};
+use blitz_dom::{BaseDocument, DocumentConfig, local_name, qual_name};
 use blitz_html::HtmlDocument;
 use log::info;
+use serde::{Deserialize, Serialize};
 
 use crate::{SubtestCounts, TestFlags, TestKind, TestStatus, ThreadCtx};
 
 mod attr_test;
+mod harness_test;
+mod ref_metadata;
 mod ref_test;
 
 pub use attr_test::process_attr_test;
+pub use harness_test::{HarnessShims, process_harness_test};
+pub use ref_metadata::RefMetadataParser;
 pub use ref_test::process_ref_test;
 
+#[derive(Serialize, Deserialize)]
 pub struct SubtestResult {
     pub name: String,
     pub status: TestStatus,
@@ -60,16 +66,13 @@ pub fn process_test_file(
     }
 
     // Ref Test
-    let reference = ctx
-        .reftest_re
-        .captures(&file_contents)
-        .and_then(|captures| captures.get(1).map(|href| href.as_str().to_string()));
-    if let Some(reference) = reference {
+    let ref_metadata = ctx.ref_metadata_parser.parse(&file_contents);
+    if !ref_metadata.references.is_empty() {
         let counts = process_ref_test(
             ctx,
             relative_path,
             file_contents.as_str(),
-            reference.as_str(),
+            ref_metadata,
             &mut flags,
         );
 
@@ -78,16 +81,15 @@ pub fn process_test_file(
     }
 
     // Attr Test
-    let mut matches = ctx.attrtest_re.captures_iter(&file_contents);
-    let first = matches.next();
-    let second = matches.next();
-    if first.is_some() && second.is_none() {
-        // TODO: handle tests with multiple calls to checkLayout.
-        #[allow(clippy::unnecessary_unwrap)]
-        let captures = first.unwrap();
-        let selector = captures.get(1).unwrap().as_str().to_string();
-        drop(matches);
-
+    // TODO: handle tests with multiple calls to checkLayout.
+    let attr_selector = {
+        let mut matches = ctx.attrtest_re.captures_iter(&file_contents);
+        match (matches.next(), matches.next()) {
+            (Some(captures), None) => Some(captures.get(1).unwrap().as_str().to_string()),
+            _ => None,
+        }
+    };
+    if let Some(selector) = attr_selector {
         println!("{selector}");
 
         let (status, counts, results) =
@@ -96,6 +98,12 @@ pub fn process_test_file(
         return (TestKind::Attr, flags, status, counts, results);
     }
 
+    // testharness.js Test
+    if ctx.harness_shims.handles(&file_contents) {
+        let (status, counts, results) = process_harness_test(ctx, &file_contents, relative_path);
+        return (TestKind::Harness, flags, status, counts, results);
+    }
+
     // TODO: Handle other test formats.
     (
         TestKind::Unknown,
@@ -119,6 +127,9 @@ fn parse_and_resolve_document(
             font_ctx: Some(ctx.font_ctx.clone()),
             net_provider: Some(Arc::clone(&ctx.net_provider) as _),
             navigation_provider: Some(Arc::clone(&ctx.navigation_provider)),
+            // The net provider counts a request as done once its handler returns, so images must
+            // be decoded before then for the test to see them
+            sync_image_decoding: true,
             ..Default::default()
         },
     );
@@ -143,5 +154,25 @@ fn parse_and_resolve_document(
 
     document.as_mut().resolve(0.0);
 
+    // Reftests can delay the screenshot by adding a `reftest-wait` class to the root element, which
+    // is expected to be removed by script once the test is ready. As we do not run script, we instead
+    // remove the class ourselves once the document has finished loading.
+    let root = document.as_ref().root_element();
+    let root_id = root.id;
+    if let Some(class) = root.attr(local_name!("class"))
+        && class.split_ascii_whitespace().any(|c| c == "reftest-wait")
+    {
+        let class = class
+            .split_ascii_whitespace()
+            .filter(|c| *c != "reftest-wait")
+            .collect::<Vec<_>>()
+            .join(" ");
+        document
+            .as_mut()
+            .mutate()
+            .set_attribute(root_id, qual_name!("class"), &class);
+        document.as_mut().resolve(0.0);
+    }
+
     document.into()
 }
@@ -0,0 +1,287 @@
+//! Parsing of reftest metadata: `<link rel=match|mismatch>` references and `<meta name=fuzzy>` annotations
+//!
+//! See <https://web-platform-tests.org/writing-tests/reftests.html>
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use regex::Regex;
+use url::Url;
+
+/// Whether a reference is expected to render identically to the file that links to it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RefRelation {
+    /// `<link rel=match>`. Rendered as `==`.
+    Match,
+    /// `<link rel=mismatch>`. Rendered as `!=`.
+    Mismatch,
+}
+
+impl RefRelation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefRelation::Match => "==",
+            RefRelation::Mismatch => "!=",
+        }
+    }
+}
+
+/// A reference linked from a test (or from another reference)
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub href: String,
+    pub relation: RefRelation,
+}
+
+/// The allowed difference between a test and a reference
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyRange {
+    /// The allowed range for the maximum difference in any colour channel of any pixel
+    pub max_difference: RangeInclusive<u8>,
+    /// The allowed range for the number of pixels which differ
+    pub total_pixels: RangeInclusive<u32>,
+}
+
+impl FuzzyRange {
+    /// No difference is allowed
+    pub const EXACT: FuzzyRange = FuzzyRange {
+        max_difference: 0..=0,
+        total_pixels: 0..=0,
+    };
+
+    pub fn allows(&self, max_difference: u8, total_pixels: u32) -> bool {
+        self.max_difference.contains(&max_difference) && self.total_pixels.contains(&total_pixels)
+    }
+}
+
+/// A `<meta name=fuzzy>` annotation
+#[derive(Clone, Debug)]
+pub struct FuzzyAnnotation {
+    /// The reference that this annotation applies to. Applies to all references if `None`.
+    pub href: Option<String>,
+    pub range: FuzzyRange,
+}
+
+/// Reftest metadata extracted from a single file
+#[derive(Clone, Debug, Default)]
+pub struct RefMetadata {
+    pub references: Vec<Reference>,
+    pub fuzzy: Vec<FuzzyAnnotation>,
+}
+
+impl RefMetadata {
+    /// Find the fuzzy range that applies when comparing against the reference at `reference_url`.
+    /// Annotation hrefs are resolved against `file_url`, the URL of the file containing the
+    /// metadata.
+    ///
+    /// Annotations that explicitly name the reference take precedence over those that don't.
+    pub fn fuzzy_for(&self, file_url: &Url, reference_url: &Url) -> Option<&FuzzyRange> {
+        let explicit = self.fuzzy.iter().find(|annotation| {
+            annotation
+                .href
+                .as_deref()
+                .and_then(|fuzzy_href| file_url.join(fuzzy_href).ok())
+                .is_some_and(|fuzzy_url| fuzzy_url == *reference_url)
+        });
+        let general = || {
+            self.fuzzy
+                .iter()
+                .find(|annotation| annotation.href.is_none())
+        };
+        explicit
+            .or_else(general)
+            .map(|annotation| &annotation.range)
+    }
+}
+
+/// Regexes used to extract reftest metadata from HTML source.
+pub struct RefMetadataParser {
+    link_re: Regex,
+    meta_re: Regex,
+    attr_re: Regex,
+}
+
+impl Default for RefMetadataParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RefMetadataParser {
+    pub fn new() -> Self {
+        Self {
+            link_re: Regex::new(r#"(?i)<link\b[^>]*>"#).unwrap(),
+            meta_re: Regex::new(r#"(?i)<meta\b[^>]*>"#).unwrap(),
+            attr_re: Regex::new(r#"([a-zA-Z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#)
+                .unwrap(),
+        }
+    }
+
+    fn attrs<'a>(&self, tag: &'a str) -> HashMap<String, &'a str> {
+        self.attr_re
+            .captures_iter(tag)
+            .map(|captures| {
+                let name = captures.get(1).unwrap().as_str().to_ascii_lowercase();
+                let value = captures
+                    .get(2)
+                    .or_else(|| captures.get(3))
+                    .or_else(|| captures.get(4))
+                    .map(|value| value.as_str())
+                    .unwrap_or_default();
+                (name, value)
+            })
+            .collect()
+    }
+
+    pub fn parse(&self, html: &str) -> RefMetadata {
+        let references = self
+            .link_re
+            .find_iter(html)
+            .filter_map(|tag| {
+                let attrs = self.attrs(tag.as_str());
+                let relation = match attrs.get("rel")?.to_ascii_lowercase().as_str() {
+                    "match" => RefRelation::Match,
+                    "mismatch" => RefRelation::Mismatch,
+                    _ => return None,
+                };
+                let href = attrs.get("href")?.to_string();
+                Some(Reference { href, relation })
+            })
+            .collect();
+
+        let fuzzy = self
+            .meta_re
+            .find_iter(html)
+            .filter_map(|tag| {
+                let attrs = self.attrs(tag.as_str());
+                if !attrs.get("name")?.eq_ignore_ascii_case("fuzzy") {
+                    return None;
+                }
+                parse_fuzzy_content(attrs.get("content")?)
+            })
+            .collect();
+
+        RefMetadata { references, fuzzy }
+    }
+}
+
+/// Parse the content of a `<meta name=fuzzy>` tag.
+///
+/// The format is `[<ref url>:]<max difference>;<total pixels>` where each value is either a
+/// number or a range `<min>-<max>`, and may optionally be prefixed with `maxDifference=` and
+/// `totalPixels=` respectively.
+pub fn parse_fuzzy_content(content: &str) -> Option<FuzzyAnnotation> {
+    let (href, ranges) = match content.split_once(':') {
+        Some((href, ranges)) => (Some(href.trim().to_string()), ranges),
+        None => (None, content),
+    };
+
+    let mut max_difference = None;
+    let mut total_pixels = None;
+    for (idx, part) in ranges.split(';').map(str::trim).enumerate() {
+        let (name, value) = match part.split_once('=') {
+            Some((name, value)) => (Some(name.trim()), value.trim()),
+            None => (None, part),
+        };
+        match (name, idx) {
+            (Some("maxDifference"), _) | (None, 0) => {
+                let (min, max) = parse_range(value)?;
+                max_difference = Some(u8::try_from(min).ok()?..=u8::try_from(max).ok()?);
+            }
+            (Some("totalPixels"), _) | (None, 1) => {
+                let (min, max) = parse_range(value)?;
+                total_pixels = Some(min..=max);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(FuzzyAnnotation {
+        href,
+        range: FuzzyRange {
+            max_difference: max_difference?,
+            total_pixels: total_pixels?,
+        },
+    })
+}
+
+fn parse_range(value: &str) -> Option<(u32, u32)> {
+    match value.split_once('-') {
+        Some((min, max)) => Some((min.trim().parse().ok()?, max.trim().parse().ok()?)),
+        None => {
+            // A single value is an exact value rather than a range
+            let value = value.trim().parse().ok()?;
+            Some((value, value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fuzzy_content() {
+        let annotation = parse_fuzzy_content("10;300").unwrap();
+        assert_eq!(annotation.href, None);
+        assert_eq!(annotation.range.max_difference, 10..=10);
+        assert_eq!(annotation.range.total_pixels, 300..=300);
+
+        let annotation =
+            parse_fuzzy_content("ref.html:totalPixels=5-10;maxDifference=0-2").unwrap();
+        assert_eq!(annotation.href.as_deref(), Some("ref.html"));
+        assert_eq!(annotation.range.max_difference, 0..=2);
+        assert_eq!(annotation.range.total_pixels, 5..=10);
+
+        assert!(parse_fuzzy_content("300;10").is_none());
+        assert!(parse_fuzzy_content("10").is_none());
+        assert!(parse_fuzzy_content("a;b").is_none());
+    }
+
+    #[test]
+    fn parses_references_and_annotations() {
+        let metadata = RefMetadataParser::new().parse(
+            r#"<link rel="match" href="a-ref.html">
+            <LINK REL=mismatch HREF='b-ref.html'>
+            <link rel=stylesheet href="style.css">
+            <meta name="fuzzy" content="0-1;0-20">
+            <meta name=fuzzy content="a-ref.html:5;10">
+            <meta name="viewport" content="width=device-width">"#,
+        );
+
+        let references: Vec<_> = metadata
+            .references
+            .iter()
+            .map(|reference| (reference.href.as_str(), reference.relation))
+            .collect();
+        assert_eq!(
+            references,
+            [
+                ("a-ref.html", RefRelation::Match),
+                ("b-ref.html", RefRelation::Mismatch)
+            ]
+        );
+        assert_eq!(metadata.fuzzy.len(), 2);
+    }
+
+    #[test]
+    fn resolves_fuzzy_hrefs() {
+        let metadata = RefMetadataParser::new().parse(
+            r#"<meta name="fuzzy" content="0-1;0-20">
+            <meta name="fuzzy" content="../ref.html:5;10">"#,
+        );
+        let file_url = Url::parse("http://dummy.local/css/test/test.html").unwrap();
+        let fuzzy_for = |reference: &str| {
+            metadata
+                .fuzzy_for(&file_url, &Url::parse(reference).unwrap())
+                .unwrap()
+                .total_pixels
+                .clone()
+        };
+
+        assert_eq!(fuzzy_for("http://dummy.local/css/ref.html"), 10..=10);
+        // A reference which merely has the same file name doesn't match the explicit annotation
+        assert_eq!(fuzzy_for("http://dummy.local/css/test/ref.html"), 0..=20);
+        assert_eq!(fuzzy_for("http://dummy.local/other/css/ref.html"), 0..=20);
+    }
+}
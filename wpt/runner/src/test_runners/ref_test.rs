@@ -1,6 +1,8 @@
 use anyrender::{ImageRenderer as _, PaintScene as _};
 use blitz_paint::paint_scene;
 use image::{ImageBuffer, ImageFormat};
+use log::{info, warn};
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
@@ -8,48 +10,64 @@ use std::path::Path;
 use url::Url;
 
 use super::parse_and_resolve_document;
+use super::ref_metadata::{FuzzyRange, RefMetadata, RefRelation, Reference};
 use crate::{BufferKind, HEIGHT, SCALE, SubtestCounts, TestFlags, ThreadCtx, WIDTH};
 
-#[allow(clippy::too_many_arguments)]
+/// The maximum length of a chain of references. Guards against reference cycles.
+const MAX_REF_CHAIN_DEPTH: usize = 8;
+
+/// The difference between two rendered images
+#[derive(Copy, Clone, Debug)]
+struct ImageDifference {
+    /// The maximum difference in any colour channel of any pixel
+    max_difference: u8,
+    /// The number of pixels which differ
+    total_pixels: u32,
+}
+
+impl ImageDifference {
+    fn compute(lhs: &[u8], rhs: &[u8]) -> Self {
+        let mut max_difference = 0;
+        let mut total_pixels = 0;
+        for (lhs_pixel, rhs_pixel) in lhs.chunks_exact(4).zip(rhs.chunks_exact(4)) {
+            let pixel_difference = lhs_pixel
+                .iter()
+                .zip(rhs_pixel)
+                .map(|(lhs, rhs)| lhs.abs_diff(*rhs))
+                .max()
+                .unwrap_or(0);
+            if pixel_difference > 0 {
+                total_pixels += 1;
+                max_difference = max_difference.max(pixel_difference);
+            }
+        }
+
+        Self {
+            max_difference,
+            total_pixels,
+        }
+    }
+
+    /// Whether the images should be considered to match given the allowed fuzziness.
+    /// Identical images always match.
+    fn matches(&self, fuzzy: &FuzzyRange) -> bool {
+        self.total_pixels == 0 || fuzzy.allows(self.max_difference, self.total_pixels)
+    }
+}
+
+/// A file which has been rendered as part of a reftest (either the test itself or a reference)
+struct RenderedFile {
+    buffer: Vec<u8>,
+    metadata: RefMetadata,
+}
+
 pub fn process_ref_test(
     ctx: &mut ThreadCtx,
     test_relative_path: &str,
     test_html: &str,
-    ref_file: &str,
+    test_metadata: RefMetadata,
     flags: &mut TestFlags,
 ) -> SubtestCounts {
-    let ref_url: Url = ctx
-        .dummy_base_url
-        .join(test_relative_path)
-        .unwrap()
-        .join(ref_file)
-        .unwrap();
-    let ref_relative_path = ref_url.path().strip_prefix('/').unwrap().to_string();
-    let ref_path = ctx.wpt_dir.join(&ref_relative_path);
-    let ref_html = fs::read_to_string(ref_path).expect("Ref file not found.");
-
-    if ctx.float_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_FLOAT;
-    }
-    if ctx.intrinsic_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_INTRINSIC_SIZE;
-    }
-    if ctx.calc_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_CALC;
-    }
-    if ctx.direction_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_DIRECTION;
-    }
-    if ctx.writing_mode_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_WRITING_MODE;
-    }
-    if ctx.subgrid_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_SUBGRID;
-    }
-    if ctx.masonry_re.is_match(&ref_html) {
-        *flags |= TestFlags::USES_MASONRY;
-    }
-
     let test_out_path = ctx
         .out_dir
         .join(format!("{}{}", test_relative_path, "-test.png"));
@@ -61,31 +79,149 @@ pub fn process_ref_test(
         test_html,
     );
 
-    let ref_out_path = ctx
-        .out_dir
-        .join(format!("{}{}", test_relative_path, "-ref.png"));
-    render_html_to_buffer(
-        ctx,
-        BufferKind::Ref,
-        &ref_relative_path,
-        &ref_out_path,
-        &ref_html,
-    );
-
     let image_is_blank = ctx.buffers.test_buffer.iter().all(|x| *x == 0);
     if image_is_blank {
         return SubtestCounts::ZERO_OF_ONE;
     }
 
-    if ctx.buffers.test_buffer == ctx.buffers.ref_buffer {
-        return SubtestCounts::ONE_OF_ONE;
+    let mut files: HashMap<String, RenderedFile> = HashMap::new();
+    let mut stack: Vec<(String, Reference, usize)> = test_metadata
+        .references
+        .iter()
+        .rev()
+        .map(|reference| (test_relative_path.to_string(), reference.clone(), 0))
+        .collect();
+    files.insert(
+        test_relative_path.to_string(),
+        RenderedFile {
+            buffer: ctx.buffers.test_buffer.clone(),
+            metadata: test_metadata,
+        },
+    );
+
+    // Depth-first search of the reference graph. As in wptrunner, the test passes if there is a path
+    // from the test to a reference with no references of its own where every comparison passes.
+    // Multiple references are therefore treated as alternatives.
+    let mut has_written_diff = false;
+    while let Some((lhs_path, reference, depth)) = stack.pop() {
+        let rhs_path = resolve_ref_path(ctx, &lhs_path, &reference.href);
+
+        if !files.contains_key(&rhs_path) {
+            let ref_path = ctx.wpt_dir.join(&rhs_path);
+            // A missing reference fails the comparison (other references may still pass)
+            let ref_html = match fs::read_to_string(&ref_path) {
+                Ok(ref_html) => ref_html,
+                Err(err) => {
+                    warn!("Failed to read reference {}: {err}", ref_path.display());
+                    continue;
+                }
+            };
+            update_flags(ctx, &ref_html, flags);
+
+            // The first reference is written to "-ref.png", and subsequent ones to "-ref-N.png"
+            let ref_suffix = match files.len() {
+                1 => String::new(),
+                n => format!("-{}", n - 1),
+            };
+            let ref_out_path = ctx
+                .out_dir
+                .join(format!("{test_relative_path}-ref{ref_suffix}.png"));
+            render_html_to_buffer(ctx, BufferKind::Ref, &rhs_path, &ref_out_path, &ref_html);
+
+            let metadata = ctx.ref_metadata_parser.parse(&ref_html);
+            let buffer = ctx.buffers.ref_buffer.clone();
+            files.insert(rhs_path.clone(), RenderedFile { buffer, metadata });
+        }
+
+        let lhs = &files[&lhs_path];
+        let rhs = &files[&rhs_path];
+
+        // Fuzzy annotations in the file containing the reference take precedence, but annotations
+        // in the test itself apply to every comparison.
+        let rhs_url = file_url(ctx, &rhs_path);
+        let fuzzy = lhs
+            .metadata
+            .fuzzy_for(&file_url(ctx, &lhs_path), &rhs_url)
+            .or_else(|| {
+                files[test_relative_path]
+                    .metadata
+                    .fuzzy_for(&file_url(ctx, test_relative_path), &rhs_url)
+            })
+            .unwrap_or(&FuzzyRange::EXACT);
+        let difference = ImageDifference::compute(&lhs.buffer, &rhs.buffer);
+        let passed = match reference.relation {
+            RefRelation::Match => difference.matches(fuzzy),
+            RefRelation::Mismatch => !difference.matches(fuzzy),
+        };
+
+        info!(
+            "{lhs_path} {} {rhs_path}: {} (max difference: {}, differing pixels: {})",
+            reference.relation.as_str(),
+            if passed { "PASS" } else { "FAIL" },
+            difference.max_difference,
+            difference.total_pixels,
+        );
+
+        if passed {
+            let next_references = &rhs.metadata.references;
+            if next_references.is_empty() || depth + 1 >= MAX_REF_CHAIN_DEPTH {
+                return SubtestCounts::ONE_OF_ONE;
+            }
+            stack.extend(
+                next_references
+                    .iter()
+                    .rev()
+                    .map(|reference| (rhs_path.clone(), reference.clone(), depth + 1)),
+            );
+        } else if reference.relation == RefRelation::Match && !has_written_diff {
+            has_written_diff = true;
+            write_diff_image(ctx, test_relative_path, &lhs.buffer, &rhs.buffer);
+        }
     }
 
-    let test_image = ImageBuffer::from_raw(WIDTH, HEIGHT, ctx.buffers.test_buffer.clone()).unwrap();
-    let ref_image = ImageBuffer::from_raw(WIDTH, HEIGHT, ctx.buffers.ref_buffer.clone()).unwrap();
+    SubtestCounts::ZERO_OF_ONE
+}
+
+/// Resolve a reference's href relative to the file which links to it
+fn resolve_ref_path(ctx: &ThreadCtx, linking_relative_path: &str, href: &str) -> String {
+    let ref_url = file_url(ctx, linking_relative_path).join(href).unwrap();
+    ref_url.path().strip_prefix('/').unwrap().to_string()
+}
+
+/// The URL that a file is rendered at
+fn file_url(ctx: &ThreadCtx, relative_path: &str) -> Url {
+    ctx.dummy_base_url.join(relative_path).unwrap()
+}
+
+fn update_flags(ctx: &ThreadCtx, ref_html: &str, flags: &mut TestFlags) {
+    if ctx.float_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_FLOAT;
+    }
+    if ctx.intrinsic_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_INTRINSIC_SIZE;
+    }
+    if ctx.calc_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_CALC;
+    }
+    if ctx.direction_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_DIRECTION;
+    }
+    if ctx.writing_mode_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_WRITING_MODE;
+    }
+    if ctx.subgrid_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_SUBGRID;
+    }
+    if ctx.masonry_re.is_match(ref_html) {
+        *flags |= TestFlags::USES_MASONRY;
+    }
+}
 
-    let diff = dify::diff::get_results(test_image, ref_image, 0.1f32, true, None, &None, &None);
+fn write_diff_image(ctx: &ThreadCtx, test_relative_path: &str, lhs: &[u8], rhs: &[u8]) {
+    let lhs_image = ImageBuffer::from_raw(WIDTH, HEIGHT, lhs.to_vec()).unwrap();
+    let rhs_image = ImageBuffer::from_raw(WIDTH, HEIGHT, rhs.to_vec()).unwrap();
 
+    let diff = dify::diff::get_results(lhs_image, rhs_image, 0.0f32, true, None, &None, &None);
     if let Some(diff) = diff {
         let path = ctx
             .out_dir
@@ -93,9 +229,6 @@ pub fn process_ref_test(
         let parent = path.parent().unwrap();
         fs::create_dir_all(parent).unwrap();
         diff.1.save_with_format(path, ImageFormat::Png).unwrap();
-        SubtestCounts::ZERO_OF_ONE
-    } else {
-        SubtestCounts::ONE_OF_ONE
     }
 }
 
@@ -0,0 +1,236 @@
+//! Running tests in worker subprocesses
+//!
+//! Each worker is a copy of this binary run with `--worker`. The parent writes the relative path of
+//! a test to the worker's stdin, and the worker runs the test and writes the serialized result to
+//! its stdout. Running tests in a separate process allows us to enforce a timeout (by killing the
+//! worker) and to recover from crashes which cannot be caught with `catch_unwind` (such as a stack
+//! overflow).
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::panic_backtrace::PanicReport;
+use crate::{SubtestCounts, TestFlags, TestKind, TestResult, TestStatus, ThreadCtx, run_test};
+
+/// Prefix for lines of worker output that contain a test result. Any other output (e.g. from
+/// `println!` calls within the tests) is ignored.
+const RESULT_PREFIX: &str = "WPT_RESULT:";
+/// Line written by a worker once it has initialised and is ready to run tests
+const READY_LINE: &str = "WPT_READY";
+/// How long to wait for a worker to initialise. This is much longer than the test timeout as
+/// initialisation (e.g. loading system fonts) may be slow.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The main loop of a worker process. Runs each test path read from stdin until stdin is closed.
+pub fn run_worker(mut ctx: ThreadCtx) {
+    println!("{READY_LINE}");
+
+    for relative_path in std::io::stdin().lock().lines() {
+        let relative_path = relative_path.unwrap();
+        let result = run_test(&mut ctx, relative_path);
+        let json = serde_json::to_string(&result).unwrap();
+
+        let mut out = std::io::stdout().lock();
+        writeln!(out, "{RESULT_PREFIX}{json}").unwrap();
+        out.flush().unwrap();
+    }
+}
+
+enum WorkerError {
+    Timeout,
+    Crashed(Option<ExitStatus>),
+    /// The worker process could not be started (or did not become ready)
+    Spawn(String),
+    /// The worker wrote a result which could not be deserialized
+    InvalidResult(String),
+}
+
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    results: Receiver<String>,
+}
+
+impl Worker {
+    fn spawn() -> Result<Worker, WorkerError> {
+        let exe = std::env::current_exe().map_err(|err| WorkerError::Spawn(err.to_string()))?;
+        let mut child = Command::new(exe)
+            .arg("--worker")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| WorkerError::Spawn(format!("Failed to spawn worker process: {err}")))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // Read output on a separate thread so that we can wait for results with a timeout
+        let (sender, results) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                let is_protocol_line = line == READY_LINE || line.starts_with(RESULT_PREFIX);
+                if is_protocol_line && sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let worker = Worker {
+            child,
+            stdin,
+            results,
+        };
+
+        // Wait for the worker to initialise
+        match worker.results.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(_) => Ok(worker),
+            Err(err) => {
+                let message = match err {
+                    RecvTimeoutError::Timeout => {
+                        format!("Worker did not start within {}s", STARTUP_TIMEOUT.as_secs())
+                    }
+                    RecvTimeoutError::Disconnected => String::from("Worker exited during startup"),
+                };
+                worker.kill();
+                Err(WorkerError::Spawn(message))
+            }
+        }
+    }
+
+    fn run_test(
+        &mut self,
+        relative_path: &str,
+        timeout: Duration,
+    ) -> Result<TestResult, WorkerError> {
+        if writeln!(self.stdin, "{relative_path}").is_err() {
+            return Err(WorkerError::Crashed(self.child.try_wait().ok().flatten()));
+        }
+
+        match self.results.recv_timeout(timeout) {
+            Ok(line) => {
+                let json = line.strip_prefix(RESULT_PREFIX).unwrap_or(&line);
+                serde_json::from_str(json).map_err(|err| {
+                    WorkerError::InvalidResult(format!("Invalid result from worker: {err}"))
+                })
+            }
+            Err(RecvTimeoutError::Timeout) => Err(WorkerError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(WorkerError::Crashed(self.child.wait().ok()))
+            }
+        }
+    }
+
+    fn kill(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    /// Close the worker's stdin (which signals it to exit) and wait for it to exit
+    fn shutdown(self) {
+        let Worker {
+            mut child, stdin, ..
+        } = self;
+        drop(stdin);
+        let _ = child.wait();
+    }
+}
+
+/// Run tests in a pool of worker processes.
+///
+/// `on_result` is called (from multiple threads) as each test completes. Results are returned
+/// in the same order as `test_paths`.
+pub fn run_tests(
+    test_paths: &[String],
+    worker_count: usize,
+    timeout: Duration,
+    on_result: impl Fn(&TestResult) + Sync,
+) -> Vec<TestResult> {
+    let next_idx = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<TestResult>>> =
+        Mutex::new(test_paths.iter().map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count.min(test_paths.len()) {
+            scope.spawn(|| {
+                let mut worker: Option<Worker> = None;
+                loop {
+                    let idx = next_idx.fetch_add(1, Ordering::Relaxed);
+                    let Some(relative_path) = test_paths.get(idx) else {
+                        break;
+                    };
+
+                    let start = Instant::now();
+                    let result = match worker.take().map_or_else(Worker::spawn, Ok) {
+                        Ok(mut current) => {
+                            let result = current.run_test(relative_path, timeout);
+                            if result.is_ok() {
+                                worker = Some(current);
+                            } else {
+                                // The worker is no longer usable. A new one will be spawned for the next test.
+                                current.kill();
+                            }
+                            result
+                        }
+                        Err(err) => Err(err),
+                    };
+
+                    let result = match result {
+                        Ok(result) => result,
+                        Err(err) => {
+                            let (status, message) = match err {
+                                WorkerError::Timeout => (
+                                    TestStatus::Timeout,
+                                    format!("Test timed out after {}s", timeout.as_secs_f32()),
+                                ),
+                                WorkerError::Crashed(exit_status) => (
+                                    TestStatus::Crash,
+                                    match exit_status {
+                                        Some(exit_status) => {
+                                            format!("Worker process exited with {exit_status}")
+                                        }
+                                        None => String::from("Worker process exited"),
+                                    },
+                                ),
+                                WorkerError::Spawn(message)
+                                | WorkerError::InvalidResult(message) => {
+                                    (TestStatus::Crash, message)
+                                }
+                            };
+                            TestResult {
+                                name: relative_path.clone(),
+                                kind: TestKind::Unknown,
+                                flags: TestFlags::empty(),
+                                status,
+                                subtest_counts: SubtestCounts::ZERO_OF_ZERO,
+                                subtest_results: Vec::new(),
+                                duration: start.elapsed(),
+                                panic_info: Some(PanicReport::from_message(message)),
+                                flaky_statuses: Vec::new(),
+                            }
+                        }
+                    };
+
+                    on_result(&result);
+                    results.lock().unwrap()[idx] = Some(result);
+                }
+
+                if let Some(worker) = worker {
+                    worker.shutdown();
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect()
+}